@@ -12,7 +12,8 @@
 
 use std::env;
 
-use async_std::io;
+use async_std::io::{self, Read, Write};
+use async_std::prelude::*;
 use async_std::task;
 use async_tungstenite::async_std::connect_async;
 use async_tungstenite::{ByteReader, ByteWriter};
@@ -30,12 +31,26 @@ async fn run() {
     let (write, read) = ws_stream.split();
     let byte_writer = ByteWriter::new(write);
     let byte_reader = ByteReader::new(read);
-    let stdin_to_ws = task::spawn(io::copy(io::stdin(), byte_writer));
+    let stdin_to_ws = task::spawn(forward(io::stdin(), byte_writer));
     let ws_to_stdout = task::spawn(io::copy(byte_reader, io::stdout()));
     stdin_to_ws.await.unwrap();
     ws_to_stdout.await.unwrap();
 }
 
+// Unlike `io::copy`, this flushes after every read, as `ByteWriter` only sends
+// data on flush.
+async fn forward(mut reader: impl Read + Unpin, mut writer: impl Write + Unpin) -> io::Result<()> {
+    let mut buf = vec![0; 4096];
+    loop {
+        let n = reader.read(&mut buf).await?;
+        if n == 0 {
+            return Ok(());
+        }
+        writer.write_all(&buf[..n]).await?;
+        writer.flush().await?;
+    }
+}
+
 fn main() {
     task::block_on(run())
 }
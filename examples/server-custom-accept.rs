@@ -117,7 +117,7 @@ async fn handle_request(
             .get(CONNECTION)
             .and_then(|h| h.to_str().ok())
             .map(|h| {
-                h.split(|c| c == ' ' || c == ',')
+                h.split([' ', ','])
                     .any(|p| p.eq_ignore_ascii_case(upgrade.to_str().unwrap()))
             })
             .unwrap_or(false)
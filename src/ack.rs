@@ -0,0 +1,320 @@
+//! Application level acknowledgements of sent messages.
+//!
+//! Some protocols tag every message with a sequence number and expect the
+//! peer to acknowledge each of them. [`split`] wraps the two halves of a
+//! [websocket](WebSocketStream) so that [`AckSender::send`] resolves once the
+//! peer acknowledged the message, while [`AckReceiver`] takes the
+//! acknowledgements out of the incoming messages.
+//!
+//! The encoding of sequence numbers and acknowledgements is up to the
+//! protocol and is provided as closures:
+//!
+//! ```no_run
+//! # async fn test(stream: async_tungstenite::WebSocketStream<async_std::net::TcpStream>) {
+//! use async_tungstenite::ack;
+//! use futures::prelude::*;
+//! use tungstenite::Message;
+//!
+//! let (sender, mut receiver) = ack::split(
+//!     stream,
+//!     |seq, msg| Message::text(format!("{} {}", seq, msg.to_text().unwrap())),
+//!     |msg| msg.to_text().ok()?.strip_prefix("ack ")?.parse().ok(),
+//! );
+//!
+//! // Acknowledgements are only processed while the receiver is polled.
+//! async_std::task::spawn(async move {
+//!     while let Some(msg) = receiver.next().await {
+//!         // ...
+//!     }
+//! });
+//!
+//! sender.send(Message::text("hello")).await.unwrap();
+//! # }
+//! ```
+use std::collections::HashMap;
+use std::fmt;
+use std::future::{poll_fn, Future};
+use std::pin::Pin;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::task::{Context, Poll, Waker};
+
+use futures_core::{FusedStream, Stream};
+use futures_io::{AsyncRead, AsyncWrite};
+use tungstenite::{Error as WsError, Message};
+
+use crate::deadline::race;
+use crate::{WebSocketReceiver, WebSocketSender, WebSocketStream};
+
+type Encode = Box<dyn Fn(u64, Message) -> Message + Send + Sync>;
+type ParseAck = Box<dyn FnMut(&Message) -> Option<u64> + Send>;
+
+/// Splits `stream` into halves that track acknowledgements.
+///
+/// `encode` turns a message and its sequence number into the message that is
+/// sent, `parse_ack` returns the acknowledged sequence number if an incoming
+/// message is an acknowledgement. Sequence numbers start at `0`.
+pub fn split<S, E, P>(
+    stream: WebSocketStream<S>,
+    encode: E,
+    parse_ack: P,
+) -> (AckSender<S>, AckReceiver<S>)
+where
+    E: Fn(u64, Message) -> Message + Send + Sync + 'static,
+    P: FnMut(&Message) -> Option<u64> + Send + 'static,
+{
+    let (sender, receiver) = stream.split();
+    let pending = Arc::new(Pending::default());
+    let sender = AckSender {
+        sender,
+        pending: pending.clone(),
+        encode: Box::new(encode),
+    };
+    let receiver = AckReceiver {
+        receiver,
+        pending,
+        parse_ack: Box::new(parse_ack),
+    };
+    (sender, receiver)
+}
+
+/// Error of [`AckSender::send`].
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum AckError {
+    /// Sending the message failed.
+    Ws(WsError),
+    /// The message was not acknowledged in time.
+    Timeout,
+    /// The connection ended or the [`AckReceiver`] was dropped before the
+    /// message was acknowledged.
+    Closed,
+}
+
+impl From<WsError> for AckError {
+    fn from(err: WsError) -> Self {
+        AckError::Ws(err)
+    }
+}
+
+impl fmt::Display for AckError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AckError::Ws(err) => write!(f, "failed to send message: {}", err),
+            AckError::Timeout => f.write_str("message was not acknowledged in time"),
+            AckError::Closed => f.write_str("connection ended before acknowledgement"),
+        }
+    }
+}
+
+impl std::error::Error for AckError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            AckError::Ws(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+struct Pending {
+    state: Mutex<State>,
+}
+
+#[derive(Debug, Default)]
+struct State {
+    next_seq: u64,
+    outstanding: HashMap<u64, Slot>,
+    closed: bool,
+}
+
+#[derive(Debug, Default)]
+struct Slot {
+    acked: bool,
+    waker: Option<Waker>,
+}
+
+impl Pending {
+    fn lock(&self) -> MutexGuard<'_, State> {
+        self.state.lock().expect("lock outstanding acks")
+    }
+
+    fn ack(&self, seq: u64) {
+        // Acknowledgements of messages nobody waits for anymore are ignored.
+        if let Some(slot) = self.lock().outstanding.get_mut(&seq) {
+            slot.acked = true;
+            if let Some(waker) = slot.waker.take() {
+                waker.wake();
+            }
+        }
+    }
+
+    fn close(&self) {
+        let mut state = self.lock();
+        state.closed = true;
+        for slot in state.outstanding.values_mut() {
+            if let Some(waker) = slot.waker.take() {
+                waker.wake();
+            }
+        }
+    }
+}
+
+/// Removes the slot of a message that is not waited for anymore.
+struct Outstanding<'a> {
+    pending: &'a Pending,
+    seq: u64,
+}
+
+impl Drop for Outstanding<'_> {
+    fn drop(&mut self) {
+        self.pending.lock().outstanding.remove(&self.seq);
+    }
+}
+
+/// The sender half returned by [`split`].
+pub struct AckSender<S> {
+    sender: WebSocketSender<S>,
+    pending: Arc<Pending>,
+    encode: Encode,
+}
+
+impl<S> AckSender<S> {
+    /// Sends `msg` and waits until the peer acknowledged it.
+    ///
+    /// Returns the sequence number the message was sent with. Dropping the
+    /// returned future stops waiting for the acknowledgement.
+    pub async fn send(&self, msg: Message) -> Result<u64, AckError>
+    where
+        S: AsyncRead + AsyncWrite + Unpin,
+    {
+        let seq = {
+            let mut state = self.pending.lock();
+            if state.closed {
+                return Err(AckError::Closed);
+            }
+            let seq = state.next_seq;
+            state.next_seq += 1;
+            state.outstanding.insert(seq, Slot::default());
+            seq
+        };
+        let _outstanding = Outstanding {
+            pending: &self.pending,
+            seq,
+        };
+
+        self.sender.send((self.encode)(seq, msg)).await?;
+
+        poll_fn(|cx| {
+            let mut state = self.pending.lock();
+            let closed = state.closed;
+            let slot = state
+                .outstanding
+                .get_mut(&seq)
+                .expect("outstanding message");
+            if slot.acked {
+                Poll::Ready(Ok(seq))
+            } else if closed {
+                Poll::Ready(Err(AckError::Closed))
+            } else {
+                slot.waker = Some(cx.waker().clone());
+                Poll::Pending
+            }
+        })
+        .await
+    }
+
+    /// Like [`send`](Self::send), but fails with [`AckError::Timeout`] once
+    /// `timer` resolves.
+    ///
+    /// `timer` is a future of the runtime in use, see [timers](crate#timers).
+    pub async fn send_with_timeout<T>(&self, msg: Message, timer: T) -> Result<u64, AckError>
+    where
+        S: AsyncRead + AsyncWrite + Unpin,
+        T: Future<Output = ()>,
+    {
+        race(self.send(msg), timer)
+            .await
+            .unwrap_or(Err(AckError::Timeout))
+    }
+
+    /// Returns the number of messages that wait for their acknowledgement.
+    pub fn outstanding(&self) -> usize {
+        self.pending.lock().outstanding.len()
+    }
+
+    /// Returns the underlying sender, e.g. for messages that are not
+    /// acknowledged or to close the connection.
+    pub fn sender(&self) -> &WebSocketSender<S> {
+        &self.sender
+    }
+}
+
+impl<S> fmt::Debug for AckSender<S> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("AckSender")
+            .field("pending", &self.pending)
+            .finish_non_exhaustive()
+    }
+}
+
+/// The receiver half returned by [`split`].
+///
+/// Yields all incoming messages except acknowledgements. Acknowledgements are
+/// only processed while this is polled, so it has to be polled for
+/// [`AckSender::send`] to make progress. Outstanding sends fail with
+/// [`AckError::Closed`] once the stream ended or this is dropped.
+pub struct AckReceiver<S> {
+    receiver: WebSocketReceiver<S>,
+    pending: Arc<Pending>,
+    parse_ack: ParseAck,
+}
+
+impl<S> Stream for AckReceiver<S>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    type Item = Result<Message, WsError>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let me = self.get_mut();
+        loop {
+            let item = match Pin::new(&mut me.receiver).poll_next(cx) {
+                Poll::Ready(item) => item,
+                Poll::Pending => return Poll::Pending,
+            };
+            if let Some(Ok(ref msg)) = item {
+                if let Some(seq) = (me.parse_ack)(msg) {
+                    me.pending.ack(seq);
+                    continue;
+                }
+            }
+            if me.receiver.is_terminated() {
+                me.pending.close();
+            }
+            return Poll::Ready(item);
+        }
+    }
+}
+
+impl<S> FusedStream for AckReceiver<S>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    fn is_terminated(&self) -> bool {
+        self.receiver.is_terminated()
+    }
+}
+
+impl<S> Drop for AckReceiver<S> {
+    fn drop(&mut self) {
+        self.pending.close();
+    }
+}
+
+impl<S> fmt::Debug for AckReceiver<S> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("AckReceiver")
+            .field("pending", &self.pending)
+            .finish_non_exhaustive()
+    }
+}
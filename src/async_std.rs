@@ -1,14 +1,19 @@
 //! `async-std` integration.
+use std::future::Future;
+
 use tungstenite::client::IntoClientRequest;
 use tungstenite::handshake::client::{Request, Response};
+use tungstenite::http::HeaderValue;
 use tungstenite::protocol::WebSocketConfig;
-use tungstenite::Error;
+use tungstenite::{Error, Message};
 
-use async_std::net::TcpStream;
+use async_std::net::{TcpStream, ToSocketAddrs};
 
-use super::{domain, port, WebSocketStream};
+use super::{
+    authorize_retry, domain, port, with_protocols, ConnectError, Heartbeat, HeartbeatHandle,
+    WebSocketSender, WebSocketStream,
+};
 
-#[cfg(feature = "async-native-tls")]
 use futures_io::{AsyncRead, AsyncWrite};
 
 #[cfg(feature = "async-native-tls")]
@@ -86,7 +91,31 @@ pub(crate) mod async_native_tls {
         // Make sure we check domain and mode first. URL must be valid.
         let mode = uri_mode(request.uri())?;
 
-        let stream = wrap_stream(stream, domain, connector, mode).await?;
+        let stream = wrap_stream(stream, domain, connector, mode)
+            .await
+            .map_err(crate::ConnectError::tls)?;
+        client_async_with_config(request, stream, config).await
+    }
+
+    /// Performs a TLS handshake on an already connected stream, with `domain` as
+    /// the server name, and then the WebSocket handshake over it.
+    ///
+    /// See [TLS over an existing stream](crate#tls-over-an-existing-stream).
+    pub async fn client_async_tls_upgrade<R, S>(
+        stream: S,
+        request: R,
+        domain: &str,
+        connector: Option<AsyncTlsConnector>,
+        config: Option<WebSocketConfig>,
+    ) -> Result<(WebSocketStream<AutoStream<S>>, Response), Error>
+    where
+        R: IntoClientRequest + Unpin,
+        S: 'static + AsyncRead + AsyncWrite + Unpin,
+        AutoStream<S>: Unpin,
+    {
+        let stream = wrap_stream(stream, domain.to_owned(), connector, Mode::Tls)
+            .await
+            .map_err(crate::ConnectError::tls)?;
         client_async_with_config(request, stream, config).await
     }
 }
@@ -153,15 +182,17 @@ pub use self::dummy_tls::client_async_tls_with_connector_and_config;
 #[cfg(not(any(feature = "async-tls", feature = "async-native-tls")))]
 use self::dummy_tls::AutoStream;
 
-#[cfg(all(feature = "async-tls", not(feature = "async-native-tls")))]
-pub use crate::async_tls::client_async_tls_with_connector_and_config;
 #[cfg(all(feature = "async-tls", not(feature = "async-native-tls")))]
 use crate::async_tls::AutoStream;
 #[cfg(all(feature = "async-tls", not(feature = "async-native-tls")))]
+pub use crate::async_tls::{client_async_tls_upgrade, client_async_tls_with_connector_and_config};
+#[cfg(all(feature = "async-tls", not(feature = "async-native-tls")))]
 type Connector = real_async_tls::TlsConnector;
 
 #[cfg(feature = "async-native-tls")]
-pub use self::async_native_tls::client_async_tls_with_connector_and_config;
+pub use self::async_native_tls::{
+    client_async_tls_upgrade, client_async_tls_with_connector_and_config,
+};
 #[cfg(feature = "async-native-tls")]
 use self::async_native_tls::{AutoStream, Connector};
 
@@ -220,6 +251,90 @@ where
 /// Type alias for the stream type of the `connect_async()` functions.
 pub type ConnectStream = ClientStream<TcpStream>;
 
+/// Resolves `host` to the addresses to connect to.
+async fn resolve(host: &str, port: u16) -> Result<Vec<std::net::SocketAddr>, Error> {
+    let addrs = match (host, port).to_socket_addrs().await {
+        Ok(addrs) => addrs.collect::<Vec<_>>(),
+        Err(source) => {
+            return Err(ConnectError::Dns {
+                host: host.to_owned(),
+                source,
+            }
+            .into())
+        }
+    };
+    if addrs.is_empty() {
+        return Err(ConnectError::Dns {
+            host: host.to_owned(),
+            source: std::io::ErrorKind::NotFound.into(),
+        }
+        .into());
+    }
+    Ok(addrs)
+}
+
+/// Resolves `host` and connects to the first address that accepts the connection.
+async fn connect_tcp(host: &str, port: u16) -> Result<TcpStream, Error> {
+    connect_tcp_addr(host, port).await.map(|(stream, _)| stream)
+}
+
+/// Like `connect_tcp`, but also returns the address that accepted the connection.
+async fn connect_tcp_addr(
+    host: &str,
+    port: u16,
+) -> Result<(TcpStream, std::net::SocketAddr), Error> {
+    let mut last_err = None;
+    for addr in resolve(host, port).await? {
+        match TcpStream::connect(addr).await {
+            Ok(stream) => return Ok((stream, addr)),
+            Err(err) => last_err = Some(err),
+        }
+    }
+    Err(ConnectError::Connect {
+        host: host.to_owned(),
+        port,
+        source: last_err.expect("no addresses"),
+    }
+    .into())
+}
+
+#[cfg(feature = "socket2")]
+/// Like `connect_tcp`, but creates the sockets itself and passes them to
+/// `configure` before connecting.
+async fn connect_tcp_with<F>(host: &str, port: u16, configure: F) -> Result<TcpStream, Error>
+where
+    F: Fn(&socket2::Socket) -> std::io::Result<()>,
+{
+    let mut last_err = None;
+    for addr in resolve(host, port).await? {
+        match connect_socket(addr, &configure).await {
+            Ok(stream) => return Ok(stream),
+            Err(err) => last_err = Some(err),
+        }
+    }
+    Err(ConnectError::Connect {
+        host: host.to_owned(),
+        port,
+        source: last_err.expect("no addresses"),
+    }
+    .into())
+}
+
+#[cfg(feature = "socket2")]
+async fn connect_socket<F>(addr: std::net::SocketAddr, configure: &F) -> std::io::Result<TcpStream>
+where
+    F: Fn(&socket2::Socket) -> std::io::Result<()>,
+{
+    let socket = socket2::Socket::new(
+        socket2::Domain::for_address(addr),
+        socket2::Type::STREAM,
+        Some(socket2::Protocol::TCP),
+    )?;
+    configure(&socket)?;
+    let stream = super::connect_nonblocking(socket, addr).await?;
+    Ok(TcpStream::from(stream.into_inner()?))
+}
+
 /// Connect to a given URL.
 ///
 /// Accepts any request that implements [`IntoClientRequest`], which is often just `&str`, but can
@@ -261,11 +376,163 @@ where
     let domain = domain(&request)?;
     let port = port(&request)?;
 
-    let try_socket = TcpStream::connect((domain.as_str(), port)).await;
-    let socket = try_socket.map_err(Error::Io)?;
+    let socket = connect_tcp(&domain, port).await?;
     client_async_tls_with_connector_and_config(request, socket, None, config).await
 }
 
+/// Connect to a given URL and return the address that was connected to.
+///
+/// If the host resolves to several addresses, they are tried in order and
+/// the one that accepted the connection is returned, e.g. for logging.
+pub async fn connect_async_with_addr<R>(
+    request: R,
+) -> Result<
+    (
+        WebSocketStream<ConnectStream>,
+        Response,
+        std::net::SocketAddr,
+    ),
+    Error,
+>
+where
+    R: IntoClientRequest + Unpin,
+{
+    let request: Request = request.into_client_request()?;
+
+    let domain = domain(&request)?;
+    let port = port(&request)?;
+
+    let (socket, addr) = connect_tcp_addr(&domain, port).await?;
+    let (stream, response) =
+        client_async_tls_with_connector_and_config(request, socket, None, None).await?;
+    Ok((stream, response, addr))
+}
+
+#[cfg(feature = "socket2")]
+/// Connect to a given URL, letting `configure` set options on the socket
+/// before it connects.
+///
+/// `configure` is called with the socket of every address that is tried. This
+/// gives full control over the socket, e.g. to set the type of service or to
+/// bind to a local address, without dedicated options for each of them.
+///
+/// ```no_run
+/// # async fn test() {
+/// use async_tungstenite::async_std::connect_async_with_socket_config;
+///
+/// let (stream, _) = connect_async_with_socket_config("wss://api.example.com", None, |socket| {
+///     socket.set_tcp_nodelay(true)?;
+///     socket.set_tos_v4(0x10)
+/// })
+/// .await
+/// .unwrap();
+/// # }
+/// ```
+pub async fn connect_async_with_socket_config<R, F>(
+    request: R,
+    config: Option<WebSocketConfig>,
+    configure: F,
+) -> Result<(WebSocketStream<ConnectStream>, Response), Error>
+where
+    R: IntoClientRequest + Unpin,
+    F: Fn(&socket2::Socket) -> std::io::Result<()>,
+{
+    let request: Request = request.into_client_request()?;
+
+    let domain = domain(&request)?;
+    let port = port(&request)?;
+
+    let socket = connect_tcp_with(&domain, port, configure).await?;
+    client_async_tls_with_connector_and_config(request, socket, None, config).await
+}
+
+/// Connect to a given URL, answering an authentication challenge of the server.
+///
+/// If the server rejects the upgrade with `401 Unauthorized`, `authorize` is called with its
+/// response, which carries the `WWW-Authenticate` challenge. If it returns a value for the
+/// `Authorization` header, the handshake is retried once on a new connection with that header
+/// added to the request. Otherwise the original error is returned.
+pub async fn connect_async_with_auth<R, F>(
+    request: R,
+    config: Option<WebSocketConfig>,
+    authorize: F,
+) -> Result<(WebSocketStream<ConnectStream>, Response), Error>
+where
+    R: IntoClientRequest + Unpin,
+    F: FnOnce(&Response) -> Option<HeaderValue>,
+{
+    let request: Request = request.into_client_request()?;
+
+    match connect_async_with_config(request.clone(), config).await {
+        Err(err) => match authorize_retry(&request, &err, authorize) {
+            Some(request) => connect_async_with_config(request, config).await,
+            None => Err(err),
+        },
+        res => res,
+    }
+}
+
+/// Connect to a given URL, offering the subprotocols `protocols` in order of
+/// preference.
+///
+/// The server's choice is available via
+/// [`WebSocketStream::selected_protocol`]. Any `Sec-WebSocket-Protocol` header
+/// of `request` is replaced.
+///
+/// ```no_run
+/// # async fn test() {
+/// use async_tungstenite::async_std::connect_async_with_protocols;
+///
+/// let (stream, _) = connect_async_with_protocols("wss://api.example.com", &["v2", "v1"])
+///     .await
+///     .unwrap();
+/// println!("speaking {:?}", stream.selected_protocol());
+/// # }
+/// ```
+pub async fn connect_async_with_protocols<R>(
+    request: R,
+    protocols: &[&str],
+) -> Result<(WebSocketStream<ConnectStream>, Response), Error>
+where
+    R: IntoClientRequest + Unpin,
+{
+    let request = with_protocols(request.into_client_request()?, protocols)?;
+    connect_async_with_config(request, None).await
+}
+
+/// Connect to a given URL and send `initial` as the first message, e.g. to
+/// authenticate or subscribe.
+///
+/// If sending the message fails, the connection is closed before the error is
+/// returned.
+pub async fn connect_and_send<R>(
+    request: R,
+    initial: Message,
+) -> Result<(WebSocketStream<ConnectStream>, Response), Error>
+where
+    R: IntoClientRequest + Unpin,
+{
+    let (mut stream, response) = connect_async(request).await?;
+    if let Err(err) = stream.send(initial).await {
+        let _ = stream.close(None).await;
+        return Err(err);
+    }
+    Ok((stream, response))
+}
+
+/// Runs `heartbeat` on `sender` with the timer of `async-std`.
+///
+/// See [`WebSocketSender::heartbeat`].
+pub fn heartbeat<S>(
+    sender: &WebSocketSender<S>,
+    heartbeat: Heartbeat,
+) -> (HeartbeatHandle, impl Future<Output = Result<(), Error>>)
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    sender.heartbeat(heartbeat, async_std::task::sleep)
+}
+
 #[cfg(any(feature = "async-tls", feature = "async-native-tls"))]
 /// Connect to a given URL using the provided TLS connector.
 pub async fn connect_async_with_tls_connector<R>(
@@ -293,7 +560,39 @@ where
     let domain = domain(&request)?;
     let port = port(&request)?;
 
-    let try_socket = TcpStream::connect((domain.as_str(), port)).await;
-    let socket = try_socket.map_err(Error::Io)?;
+    let socket = connect_tcp(&domain, port).await?;
     client_async_tls_with_connector_and_config(request, socket, connector, config).await
 }
+
+#[cfg(any(feature = "async-tls", feature = "async-native-tls"))]
+/// Connect to a given URL, using `server_name` instead of the URL's host
+/// for the TLS handshake of `wss://` URLs.
+///
+/// This allows e.g. connecting to an IP address while presenting and
+/// verifying a specific server name. The `Host` header still uses the URL's
+/// host. With `None` this behaves like
+/// [`connect_async_with_tls_connector_and_config`]. Whether SNI is sent at
+/// all is configured on the connector.
+pub async fn connect_async_with_server_name<R>(
+    request: R,
+    server_name: Option<&str>,
+    connector: Option<Connector>,
+    config: Option<WebSocketConfig>,
+) -> Result<(WebSocketStream<ConnectStream>, Response), Error>
+where
+    R: IntoClientRequest + Unpin,
+{
+    let request: Request = request.into_client_request()?;
+
+    let domain = domain(&request)?;
+    let port = port(&request)?;
+    let mode = tungstenite::client::uri_mode(request.uri())?;
+
+    let socket = connect_tcp(&domain, port).await?;
+    match (server_name, mode) {
+        (Some(server_name), tungstenite::stream::Mode::Tls) => {
+            client_async_tls_upgrade(socket, request, server_name, connector, config).await
+        }
+        _ => client_async_tls_with_connector_and_config(request, socket, connector, config).await,
+    }
+}
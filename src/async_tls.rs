@@ -34,7 +34,7 @@ where
         Mode::Plain => Ok(StreamSwitcher::Plain(socket)),
         Mode::Tls => {
             let stream = {
-                let connector = connector.unwrap_or_else(AsyncTlsConnector::new);
+                let connector = connector.unwrap_or_default();
                 connector.connect(&domain, socket).await?
             };
             Ok(StreamSwitcher::Tls(stream))
@@ -112,6 +112,30 @@ where
     // Make sure we check domain and mode first. URL must be valid.
     let mode = uri_mode(request.uri())?;
 
-    let stream = wrap_stream(stream, domain, connector, mode).await?;
+    let stream = wrap_stream(stream, domain, connector, mode)
+        .await
+        .map_err(crate::ConnectError::tls)?;
+    client_async_with_config(request, stream, config).await
+}
+
+/// Performs a TLS handshake on an already connected stream, with `domain` as
+/// the server name, and then the WebSocket handshake over it.
+///
+/// See [TLS over an existing stream](crate#tls-over-an-existing-stream).
+pub async fn client_async_tls_upgrade<R, S>(
+    stream: S,
+    request: R,
+    domain: &str,
+    connector: Option<AsyncTlsConnector>,
+    config: Option<WebSocketConfig>,
+) -> Result<(WebSocketStream<ClientStream<S>>, Response), Error>
+where
+    R: IntoClientRequest + Unpin,
+    S: 'static + AsyncRead + AsyncWrite + Unpin,
+    AutoStream<S>: Unpin,
+{
+    let stream = wrap_stream(stream, domain.to_owned(), connector, Mode::Tls)
+        .await
+        .map_err(crate::ConnectError::tls)?;
     client_async_with_config(request, stream, config).await
 }
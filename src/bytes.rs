@@ -2,9 +2,9 @@
 //! a [`WebSocketStream`](crate::WebSocketStream) or a [`WebSocketSender`](crate::WebSocketSender).
 
 use std::{
-    fmt, io,
+    fmt, io, mem,
     pin::Pin,
-    task::{Context, Poll},
+    task::{ready, Context, Poll},
 };
 
 use futures_core::stream::Stream;
@@ -13,13 +13,38 @@ use crate::{tungstenite::Bytes, Message, WsError};
 
 /// Treat a websocket [sender](Sender) as an `AsyncWrite` implementation.
 ///
-/// Every write sends a binary message. If you want to group writes together, consider wrapping
-/// this with a `BufWriter`.
+/// Writes are collected in a buffer and sent as a single message on flush, so all writes between
+/// two flushes end up in one frame. Once the buffer reaches the
+/// [flush threshold](Self::set_flush_threshold), its data is sent as a message of its own without
+/// waiting for the flush.
+///
+/// Messages are binary by default, see [`FrameKind`] for sending text.
 pub struct ByteWriter<S> {
     sender: S,
+    buffer: Vec<u8>,
+    threshold: usize,
+    kind: FrameKind,
     state: State,
 }
 
+/// The default [flush threshold](ByteWriter::set_flush_threshold), which matches the default
+/// maximum frame size of tungstenite.
+const DEFAULT_FLUSH_THRESHOLD: usize = 16 << 20;
+
+/// The type of messages a [`ByteWriter`] sends.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum FrameKind {
+    /// Binary messages.
+    #[default]
+    Binary,
+    /// Text messages.
+    ///
+    /// The data is only validated as UTF-8 when it is sent, as a multi-byte sequence may be split
+    /// across writes. If the data written since the last flush is not valid UTF-8, it is
+    /// discarded and flushing fails with an error that wraps [`WsError::Utf8`].
+    Text,
+}
+
 impl<S> ByteWriter<S> {
     /// Create a new `ByteWriter` from a [sender](Sender) that accepts a websocket [`Message`].
     #[inline(always)]
@@ -29,15 +54,139 @@ impl<S> ByteWriter<S> {
     {
         Self {
             sender,
+            buffer: Vec::new(),
+            threshold: DEFAULT_FLUSH_THRESHOLD,
+            kind: FrameKind::Binary,
             state: State::Open,
         }
     }
 
+    /// Create a new `ByteWriter` that sends text messages.
+    ///
+    /// This is a shorthand for [`new`](Self::new) and
+    /// [`set_frame_kind(FrameKind::Text)`](Self::set_frame_kind).
+    pub fn new_text(sender: S) -> Self
+    where
+        S: Sender,
+    {
+        let mut writer = Self::new(sender);
+        writer.set_frame_kind(FrameKind::Text);
+        writer
+    }
+
+    /// Sets the type of the messages that are sent, starting with the next flush.
+    pub fn set_frame_kind(&mut self, kind: FrameKind) {
+        self.kind = kind;
+    }
+
+    /// Returns the type of the messages that are sent.
+    pub fn frame_kind(&self) -> FrameKind {
+        self.kind
+    }
+
+    /// Sets the number of buffered bytes after which the data is sent without waiting for a flush.
+    ///
+    /// A write is accepted only in part if it would take the buffer past the threshold, so no
+    /// message is larger than this. For text messages a multi-byte sequence that is still
+    /// incomplete is kept for the next message. The default is 16 MiB.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `threshold` is zero.
+    pub fn set_flush_threshold(&mut self, threshold: usize) {
+        assert!(threshold > 0, "flush threshold must not be zero");
+        self.threshold = threshold;
+    }
+
+    /// Returns the number of buffered bytes after which the data is sent without waiting for a
+    /// flush.
+    pub fn flush_threshold(&self) -> usize {
+        self.threshold
+    }
+
     /// Get the underlying [sender](Sender) back.
+    ///
+    /// Data that was written but not flushed yet is discarded.
     #[inline(always)]
     pub fn into_inner(self) -> S {
         self.sender
     }
+
+    /// Returns the number of bytes that were written but not flushed yet.
+    pub fn buffered(&self) -> usize {
+        self.buffer.len()
+    }
+
+    fn poll_write_buffered(
+        &mut self,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<Result<usize, WsError>>
+    where
+        S: Sender + Unpin,
+    {
+        if self.buffer.len() >= self.threshold {
+            ready!(self.poll_send_buffered(cx, false))?;
+        }
+        // An incomplete UTF-8 sequence kept back may already fill a tiny threshold.
+        let n = buf
+            .len()
+            .min(self.threshold.saturating_sub(self.buffer.len()).max(1));
+        self.buffer.extend_from_slice(&buf[..n]);
+        Poll::Ready(Ok(n))
+    }
+
+    /// Sends the buffered data as one message.
+    ///
+    /// Unless `complete` is set, an incomplete UTF-8 sequence at the end of text data stays in the
+    /// buffer.
+    fn poll_send_buffered(
+        &mut self,
+        cx: &mut Context<'_>,
+        complete: bool,
+    ) -> Poll<Result<(), WsError>>
+    where
+        S: Sender + Unpin,
+    {
+        if !self.buffer.is_empty() {
+            ready!(Pin::new(&mut self.sender).poll_ready(cx))?;
+            let mut data = mem::take(&mut self.buffer);
+            if self.kind == FrameKind::Text && !complete {
+                if let Err(e) = std::str::from_utf8(&data) {
+                    if e.error_len().is_none() {
+                        self.buffer = data.split_off(e.valid_up_to());
+                    }
+                }
+                if data.is_empty() {
+                    mem::swap(&mut self.buffer, &mut data);
+                    return Poll::Ready(Ok(()));
+                }
+            }
+            let msg = match self.kind {
+                FrameKind::Binary => Message::binary(data),
+                FrameKind::Text => Message::text(String::from_utf8(data)?),
+            };
+            Pin::new(&mut self.sender).start_send(msg)?;
+        }
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_flush_buffered(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), WsError>>
+    where
+        S: Sender + Unpin,
+    {
+        ready!(self.poll_send_buffered(cx, true))?;
+        <S as private::SealedSender>::poll_flush(Pin::new(&mut self.sender), cx)
+    }
+
+    fn poll_close_buffered(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), WsError>>
+    where
+        S: Sender + Unpin,
+    {
+        ready!(self.poll_send_buffered(cx, true))?;
+        let msg = self.state.close();
+        <S as private::SealedSender>::poll_close(Pin::new(&mut self.sender), cx, msg)
+    }
 }
 
 impl<S> fmt::Debug for ByteWriter<S>
@@ -47,6 +196,9 @@ where
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         f.debug_struct("ByteWriter")
             .field("sender", &self.sender)
+            .field("buffered", &self.buffer.len())
+            .field("threshold", &self.threshold)
+            .field("kind", &self.kind)
             .field("state", &"..")
             .finish()
     }
@@ -86,11 +238,10 @@ pub(crate) mod private {
     use super::*;
 
     pub trait SealedSender {
-        fn poll_write(
-            self: Pin<&mut Self>,
-            cx: &mut Context<'_>,
-            buf: &[u8],
-        ) -> Poll<Result<usize, WsError>>;
+        fn poll_ready(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), WsError>>;
+
+        #[allow(clippy::result_large_err)]
+        fn start_send(self: Pin<&mut Self>, msg: Message) -> Result<(), WsError>;
 
         fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), WsError>>;
 
@@ -109,17 +260,12 @@ impl<S> private::SealedSender for S
 where
     S: futures_util::Sink<Message, Error = WsError> + Unpin,
 {
-    fn poll_write(
-        mut self: Pin<&mut Self>,
-        cx: &mut Context<'_>,
-        buf: &[u8],
-    ) -> Poll<Result<usize, WsError>> {
-        use std::task::ready;
+    fn poll_ready(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), WsError>> {
+        <S as futures_util::Sink<_>>::poll_ready(self, cx)
+    }
 
-        ready!(self.as_mut().poll_ready(cx))?;
-        let len = buf.len();
-        self.start_send(Message::binary(buf.to_owned()))?;
-        Poll::Ready(Ok(len))
+    fn start_send(self: Pin<&mut Self>, msg: Message) -> Result<(), WsError> {
+        <S as futures_util::Sink<_>>::start_send(self, msg)
     }
 
     fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), WsError>> {
@@ -140,24 +286,21 @@ where
     S: Sender + Unpin,
 {
     fn poll_write(
-        mut self: Pin<&mut Self>,
+        self: Pin<&mut Self>,
         cx: &mut Context<'_>,
         buf: &[u8],
     ) -> Poll<io::Result<usize>> {
-        <S as private::SealedSender>::poll_write(Pin::new(&mut self.sender), cx, buf)
+        self.get_mut()
+            .poll_write_buffered(cx, buf)
             .map_err(convert_err)
     }
 
-    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
-        <S as private::SealedSender>::poll_flush(Pin::new(&mut self.sender), cx)
-            .map_err(convert_err)
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        self.get_mut().poll_flush_buffered(cx).map_err(convert_err)
     }
 
     fn poll_close(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
-        let me = self.get_mut();
-        let msg = me.state.close();
-        <S as private::SealedSender>::poll_close(Pin::new(&mut me.sender), cx, msg)
-            .map_err(convert_err)
+        self.get_mut().poll_close_buffered(cx).map_err(convert_err)
     }
 }
 
@@ -167,24 +310,21 @@ where
     S: Sender + Unpin,
 {
     fn poll_write(
-        mut self: Pin<&mut Self>,
+        self: Pin<&mut Self>,
         cx: &mut Context<'_>,
         buf: &[u8],
     ) -> Poll<io::Result<usize>> {
-        <S as private::SealedSender>::poll_write(Pin::new(&mut self.sender), cx, buf)
+        self.get_mut()
+            .poll_write_buffered(cx, buf)
             .map_err(convert_err)
     }
 
-    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
-        <S as private::SealedSender>::poll_flush(Pin::new(&mut self.sender), cx)
-            .map_err(convert_err)
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        self.get_mut().poll_flush_buffered(cx).map_err(convert_err)
     }
 
     fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
-        let me = self.get_mut();
-        let msg = me.state.close();
-        <S as private::SealedSender>::poll_close(Pin::new(&mut me.sender), cx, msg)
-            .map_err(convert_err)
+        self.get_mut().poll_close_buffered(cx).map_err(convert_err)
     }
 }
 
@@ -193,11 +333,18 @@ where
 /// This also works with any other `Stream` of `Message`, such as a `SplitStream`.
 ///
 /// Each read will only return data from one message. If you want to combine data from multiple
-/// messages into one read, consider wrapping this in a `BufReader`.
+/// messages into one read, consider wrapping this in a `BufReader`. Only text and binary messages
+/// are read, pings, pongs and close frames are skipped.
+///
+/// By default the messages form one continuous stream of bytes, see
+/// [`with_message_boundaries`](Self::with_message_boundaries) for telling them apart.
 #[derive(Debug)]
 pub struct ByteReader<S> {
     stream: S,
     bytes: Option<Bytes>,
+    message_boundaries: bool,
+    // Set once all data of a message was read and the boundary wasn't reported yet.
+    at_boundary: bool,
 }
 
 impl<S> ByteReader<S> {
@@ -207,8 +354,20 @@ impl<S> ByteReader<S> {
         Self {
             stream,
             bytes: None,
+            message_boundaries: false,
+            at_boundary: false,
         }
     }
+
+    /// Report the end of every message as a read of 0 bytes.
+    ///
+    /// Reading continues with the next message afterwards, so e.g. `read_to_end` returns the data
+    /// of one message at a time. The end of the stream can't be told apart from a boundary by the
+    /// read alone, but reading past it returns 0 bytes again.
+    pub fn with_message_boundaries(mut self, enabled: bool) -> Self {
+        self.message_boundaries = enabled;
+        self
+    }
 }
 
 fn poll_read_helper<S>(
@@ -219,17 +378,26 @@ fn poll_read_helper<S>(
 where
     S: Stream<Item = Result<Message, WsError>> + Unpin,
 {
+    if s.at_boundary {
+        s.at_boundary = false;
+        return Poll::Ready(Ok(Some(Bytes::new())));
+    }
     Poll::Ready(Ok(Some(match s.bytes {
-        None => match Pin::new(&mut s.stream).poll_next(cx) {
-            Poll::Pending => return Poll::Pending,
-            Poll::Ready(None) => return Poll::Ready(Ok(None)),
-            Poll::Ready(Some(Err(e))) => return Poll::Ready(Err(convert_err(e))),
-            Poll::Ready(Some(Ok(msg))) => {
-                let bytes = msg.into_data();
-                if bytes.len() > buf_len {
-                    s.bytes.insert(bytes).split_to(buf_len)
-                } else {
-                    bytes
+        None => loop {
+            match Pin::new(&mut s.stream).poll_next(cx) {
+                Poll::Pending => return Poll::Pending,
+                Poll::Ready(None) => return Poll::Ready(Ok(None)),
+                Poll::Ready(Some(Err(e))) => return Poll::Ready(Err(convert_err(e))),
+                // Control messages carry no data of the stream.
+                Poll::Ready(Some(Ok(msg))) if !msg.is_text() && !msg.is_binary() => {}
+                Poll::Ready(Some(Ok(msg))) => {
+                    let bytes = msg.into_data();
+                    if bytes.len() > buf_len {
+                        break s.bytes.insert(bytes).split_to(buf_len);
+                    }
+                    // An empty message is its own boundary.
+                    s.at_boundary = s.message_boundaries && !bytes.is_empty();
+                    break bytes;
                 }
             }
         },
@@ -237,6 +405,7 @@ where
         Some(ref mut bytes) => {
             let bytes = bytes.clone();
             s.bytes = None;
+            s.at_boundary = s.message_boundaries;
             bytes
         }
     })))
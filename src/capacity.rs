@@ -0,0 +1,87 @@
+//! Bounded sending for the [sender](crate::WebSocketSender) part of a
+//! [websocket](crate::WebSocketStream).
+use std::sync::{Mutex, MutexGuard};
+use std::task::{Context, Poll, Waker};
+
+use tungstenite::Error as WsError;
+
+/// Limits how many sends may be in progress at once.
+#[derive(Debug)]
+pub(crate) struct Capacity(Mutex<State>);
+
+#[derive(Debug)]
+struct State {
+    available: usize,
+    waiting: Vec<Waker>,
+    /// Whether the receiver was dropped, which fails waiting sends.
+    closed: bool,
+}
+
+impl Capacity {
+    pub(crate) fn new(capacity: usize) -> Self {
+        Capacity(Mutex::new(State {
+            available: capacity,
+            waiting: Vec::new(),
+            closed: false,
+        }))
+    }
+
+    fn lock(&self) -> MutexGuard<'_, State> {
+        self.0.lock().expect("lock send capacity")
+    }
+
+    /// Waits until a send may start.
+    pub(crate) async fn acquire(&self) -> Result<Permit<'_>, WsError> {
+        std::future::poll_fn(|cx| self.poll_acquire(cx)).await?;
+        Ok(Permit(self))
+    }
+
+    /// Takes room for one send, which has to be given back with
+    /// [`release`](Self::release).
+    pub(crate) fn poll_acquire(&self, cx: &mut Context<'_>) -> Poll<Result<(), WsError>> {
+        let mut state = self.lock();
+        if state.available > 0 {
+            state.available -= 1;
+            return Poll::Ready(Ok(()));
+        }
+        if state.closed {
+            return Poll::Ready(Err(WsError::Io(std::io::Error::new(
+                std::io::ErrorKind::BrokenPipe,
+                "receiver was dropped",
+            ))));
+        }
+        if !state.waiting.iter().any(|w| w.will_wake(cx.waker())) {
+            state.waiting.push(cx.waker().clone());
+        }
+        Poll::Pending
+    }
+
+    /// Gives back the room taken by [`poll_acquire`](Self::poll_acquire).
+    pub(crate) fn release(&self) {
+        let mut state = self.lock();
+        state.available += 1;
+        wake_all(&mut state);
+    }
+
+    /// Fails all sends that are waiting for capacity.
+    pub(crate) fn close(&self) {
+        let mut state = self.lock();
+        state.closed = true;
+        wake_all(&mut state);
+    }
+}
+
+fn wake_all(state: &mut State) {
+    for waker in state.waiting.drain(..) {
+        waker.wake();
+    }
+}
+
+/// Room for one send, given back when dropped.
+pub(crate) struct Permit<'a>(&'a Capacity);
+
+impl Drop for Permit<'_> {
+    fn drop(&mut self) {
+        self.0.release();
+    }
+}
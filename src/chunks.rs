@@ -0,0 +1,377 @@
+//! Incoming messages as a stream of chunks, see [`WebSocketStream::chunks`].
+//!
+//! tungstenite only hands out complete messages, so the payload of data
+//! frames is taken out of the bytes read from the underlying stream before
+//! tungstenite sees them. Control frames are passed on unchanged, so Pings,
+//! Pongs and the close handshake are still handled by tungstenite.
+use std::collections::VecDeque;
+use std::io::{self, Cursor};
+use std::mem;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use futures_core::Stream;
+use futures_io::{AsyncRead, AsyncWrite};
+use tungstenite::error::ProtocolError;
+use tungstenite::protocol::frame::{
+    coding::{CloseCode, Data as OpData, OpCode},
+    FrameHeader,
+};
+use tungstenite::protocol::Role;
+use tungstenite::{Bytes, Error as WsError};
+
+use crate::{FrameKind, WebSocketStream};
+
+/// The longest possible frame header.
+const MAX_HEADER_SIZE: usize = 14;
+
+/// A part of an incoming text or binary message, see
+/// [`WebSocketStream::chunks`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MessageChunk {
+    kind: FrameKind,
+    data: Bytes,
+    fin: bool,
+}
+
+impl MessageChunk {
+    /// The type of the message this chunk belongs to.
+    pub fn kind(&self) -> FrameKind {
+        self.kind
+    }
+
+    /// The payload of this chunk.
+    ///
+    /// Chunks of a text message may split a multi-byte UTF-8 sequence, only
+    /// the message as a whole is valid UTF-8.
+    pub fn data(&self) -> &Bytes {
+        &self.data
+    }
+
+    /// Returns the payload of this chunk.
+    pub fn into_data(self) -> Bytes {
+        self.data
+    }
+
+    /// Whether this is the last chunk of the message.
+    pub fn is_final(&self) -> bool {
+        self.fin
+    }
+}
+
+/// The data frame that is currently read.
+#[derive(Debug)]
+struct DataFrame {
+    kind: FrameKind,
+    mask: Option<[u8; 4]>,
+    /// Number of payload bytes read so far, to continue the mask.
+    offset: usize,
+    fin: bool,
+}
+
+/// Splits the bytes read from the underlying stream into control frames for
+/// tungstenite and chunks of the payload of data frames.
+#[derive(Debug)]
+pub(crate) struct Demux {
+    role: Role,
+    accept_unmasked: bool,
+    /// Bytes of a frame header that is not complete yet.
+    header: Vec<u8>,
+    /// Payload bytes of the current frame that were not read yet.
+    remaining: u64,
+    /// The current frame unless it is a control frame.
+    frame: Option<DataFrame>,
+    /// The type of the fragmented message that is in progress.
+    message: Option<FrameKind>,
+    /// Unmasked payload of the current frame that is not part of a chunk yet.
+    payload: Vec<u8>,
+    /// The end of the text read so far if it is an incomplete UTF-8 sequence.
+    utf8: Vec<u8>,
+    /// Control frames to pass on to tungstenite.
+    pub(crate) control: VecDeque<u8>,
+    pub(crate) chunks: VecDeque<MessageChunk>,
+    /// Set once the data frames violate the protocol.
+    pub(crate) error: Option<WsError>,
+}
+
+impl Demux {
+    pub(crate) fn new(role: Role, accept_unmasked: bool) -> Self {
+        Demux {
+            role,
+            accept_unmasked,
+            header: Vec::new(),
+            remaining: 0,
+            frame: None,
+            message: None,
+            payload: Vec::new(),
+            utf8: Vec::new(),
+            control: VecDeque::new(),
+            chunks: VecDeque::new(),
+            error: None,
+        }
+    }
+
+    /// Splits `buf`, which must directly follow the bytes of the previous
+    /// call.
+    #[allow(clippy::result_large_err)]
+    pub(crate) fn feed(&mut self, buf: &[u8]) -> Result<(), WsError> {
+        let mut pos = 0;
+        while pos < buf.len() {
+            if self.remaining > 0 {
+                let take = std::cmp::min(self.remaining, (buf.len() - pos) as u64) as usize;
+                let bytes = &buf[pos..pos + take];
+                match self.frame {
+                    Some(ref mut frame) => {
+                        let start = self.payload.len();
+                        self.payload.extend_from_slice(bytes);
+                        if let Some(mask) = frame.mask {
+                            for (i, byte) in self.payload[start..].iter_mut().enumerate() {
+                                *byte ^= mask[(frame.offset + i) % 4];
+                            }
+                        }
+                        frame.offset += take;
+                    }
+                    None => self.control.extend(bytes),
+                }
+                self.remaining -= take as u64;
+                pos += take;
+                if self.remaining == 0 {
+                    self.end_frame()?;
+                }
+                continue;
+            }
+
+            let previous = self.header.len();
+            let take = std::cmp::min(MAX_HEADER_SIZE - previous, buf.len() - pos);
+            self.header.extend_from_slice(&buf[pos..pos + take]);
+
+            let mut cursor = Cursor::new(&self.header);
+            match FrameHeader::parse(&mut cursor)? {
+                Some((header, length)) => {
+                    let header_len = cursor.position() as usize;
+                    pos += header_len - previous;
+                    self.start_frame(header, length, header_len)?;
+                    self.header.clear();
+                    if self.remaining == 0 {
+                        self.end_frame()?;
+                    }
+                }
+                None => pos += take,
+            }
+        }
+
+        // Whatever was read of the current frame is handed out right away.
+        if let Some(ref frame) = self.frame {
+            if !self.payload.is_empty() {
+                self.emit(frame.kind, false)?;
+            }
+        }
+        Ok(())
+    }
+
+    #[allow(clippy::result_large_err)]
+    fn start_frame(
+        &mut self,
+        header: FrameHeader,
+        length: u64,
+        header_len: usize,
+    ) -> Result<(), WsError> {
+        self.remaining = length;
+        let opcode = match header.opcode {
+            OpCode::Data(opcode) => opcode,
+            OpCode::Control(_) => {
+                self.control.extend(&self.header[..header_len]);
+                self.frame = None;
+                return Ok(());
+            }
+        };
+
+        if header.rsv1 || header.rsv2 || header.rsv3 {
+            return Err(ProtocolError::NonZeroReservedBits.into());
+        }
+        match (self.role, header.mask) {
+            (Role::Server, None) if !self.accept_unmasked => {
+                return Err(ProtocolError::UnmaskedFrameFromClient.into())
+            }
+            (Role::Client, Some(_)) => return Err(ProtocolError::MaskedFrameFromServer.into()),
+            _ => {}
+        }
+        let kind = match (opcode, self.message) {
+            (OpData::Continue, Some(kind)) => kind,
+            (OpData::Continue, None) => return Err(ProtocolError::UnexpectedContinueFrame.into()),
+            (OpData::Text | OpData::Binary, Some(_)) => {
+                return Err(ProtocolError::ExpectedFragment(opcode).into())
+            }
+            (OpData::Text, None) => FrameKind::Text,
+            (OpData::Binary, None) => FrameKind::Binary,
+            (OpData::Reserved(opcode), _) => {
+                return Err(ProtocolError::UnknownDataFrameType(opcode).into())
+            }
+        };
+        self.message = if header.is_final { None } else { Some(kind) };
+        self.frame = Some(DataFrame {
+            kind,
+            mask: header.mask,
+            offset: 0,
+            fin: header.is_final,
+        });
+        Ok(())
+    }
+
+    #[allow(clippy::result_large_err)]
+    fn end_frame(&mut self) -> Result<(), WsError> {
+        match self.frame.take() {
+            Some(frame) if frame.fin || !self.payload.is_empty() => {
+                self.emit(frame.kind, frame.fin)
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Turns the payload read so far into a chunk.
+    #[allow(clippy::result_large_err)]
+    fn emit(&mut self, kind: FrameKind, fin: bool) -> Result<(), WsError> {
+        let data = mem::take(&mut self.payload);
+        if kind == FrameKind::Text {
+            self.check_utf8(&data, fin)?;
+        }
+        self.chunks.push_back(MessageChunk {
+            kind,
+            data: data.into(),
+            fin,
+        });
+        Ok(())
+    }
+
+    /// Validates the text of a message piece by piece.
+    #[allow(clippy::result_large_err)]
+    fn check_utf8(&mut self, data: &[u8], fin: bool) -> Result<(), WsError> {
+        let mut text = mem::take(&mut self.utf8);
+        text.extend_from_slice(data);
+        match std::str::from_utf8(&text) {
+            Ok(_) => Ok(()),
+            // The sequence may be completed by the next chunk.
+            Err(err) if err.error_len().is_none() && !fin => {
+                self.utf8 = text.split_off(err.valid_up_to());
+                Ok(())
+            }
+            Err(err) => Err(err.into()),
+        }
+    }
+}
+
+/// A [`WebSocketStream`] that yields incoming messages in chunks, see
+/// [`WebSocketStream::chunks`].
+#[derive(Debug)]
+pub struct Chunks<S> {
+    stream: WebSocketStream<S>,
+    /// Set if `chunks` was called in the middle of a message.
+    late: bool,
+}
+
+impl<S> Chunks<S> {
+    /// Returns a shared reference to the websocket.
+    pub fn get_ref(&self) -> &WebSocketStream<S> {
+        &self.stream
+    }
+
+    /// Returns a mutable reference to the websocket, e.g. to send messages.
+    ///
+    /// Reading from it directly doesn't yield any text or binary messages.
+    pub fn get_mut(&mut self) -> &mut WebSocketStream<S> {
+        &mut self.stream
+    }
+}
+
+impl<S> WebSocketStream<S> {
+    /// Turns the websocket into a stream of the chunks of incoming text and
+    /// binary messages.
+    ///
+    /// Messages are not buffered until they are complete. Every chunk holds
+    /// the payload that was read from the connection at once, so a large
+    /// message arrives as many chunks, the last of which is
+    /// [final](MessageChunk::is_final). The memory used for incoming data is
+    /// bounded by the
+    /// [`read_buffer_size`](tungstenite::protocol::WebSocketConfig::read_buffer_size)
+    /// instead of the message size, and the size limits of the configuration
+    /// don't apply to messages. Frames are still checked against the
+    /// protocol, e.g. a new message must not start before the previous one is
+    /// complete, and the text of a message must be valid UTF-8.
+    ///
+    /// Control messages are handled as they are read: Pings are answered and
+    /// a Close frame completes the close handshake. They are not yielded, and
+    /// the stream ends once the connection is closed.
+    ///
+    /// This has to be called before the first message was read, as
+    /// tungstenite may already buffer the data that follows it. If a text or
+    /// binary message was read in part or is buffered, the stream yields an
+    /// error and the connection is closed with code 1011 (internal error).
+    /// Unless the frames are followed for one of the frame based limits, e.g.
+    /// [`set_max_fragments_per_message`](WebSocketStream::set_max_fragments_per_message),
+    /// any data read before counts as such.
+    pub fn chunks(mut self) -> Chunks<S> {
+        let late = !self.inner.get_ref().frames().between_messages();
+        let demux = Demux::new(self.role, self.get_config().accept_unmasked_frames);
+        self.inner.get_mut().set_demux(demux);
+        Chunks { stream: self, late }
+    }
+}
+
+impl<S> Stream for Chunks<S>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    type Item = Result<MessageChunk, WsError>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+        let ws = &mut this.stream;
+        if mem::take(&mut this.late) {
+            return Poll::Ready(Some(Err(started_late(ws))));
+        }
+        loop {
+            if let Some(chunk) = ws.inner.get_mut().demux_mut().chunks.pop_front() {
+                ws.last_activity = ws.clock.now();
+                return Poll::Ready(Some(Ok(chunk)));
+            }
+            // Reading stops as soon as chunks are available.
+            match ws.poll_next(cx) {
+                Poll::Pending if ws.inner.get_mut().demux_mut().chunks.is_empty() => {
+                    return Poll::Pending
+                }
+                Poll::Ready(Some(Ok(msg))) if msg.is_text() || msg.is_binary() => {
+                    return Poll::Ready(Some(Err(started_late(ws))));
+                }
+                Poll::Pending | Poll::Ready(Some(Ok(_))) => {}
+                Poll::Ready(Some(Err(err))) => {
+                    let err = match ws.inner.get_mut().demux_mut().error.take() {
+                        Some(err) => {
+                            let (code, reason) = match err {
+                                WsError::Utf8(_) => (CloseCode::Invalid, "Invalid UTF-8"),
+                                _ => (CloseCode::Protocol, "Protocol violation"),
+                            };
+                            ws.fail_with_close(code, reason);
+                            err
+                        }
+                        None => err,
+                    };
+                    return Poll::Ready(Some(Err(err)));
+                }
+                Poll::Ready(None) => return Poll::Ready(None),
+            }
+        }
+    }
+}
+
+/// Fails the connection because data was read before it was streamed in
+/// chunks.
+fn started_late<S>(ws: &mut WebSocketStream<S>) -> WsError
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    ws.fail_with_close(CloseCode::Error, "Internal error");
+    WsError::Io(io::Error::new(
+        io::ErrorKind::Other,
+        "data was read before WebSocketStream::chunks was called",
+    ))
+}
@@ -0,0 +1,70 @@
+//! Time source for everything time related in `WebSocketStream`.
+//!
+//! Normally this is the system's monotonic clock. With the `test-clock`
+//! feature a [`TestClock`] can be installed instead, which only advances when
+//! told to and allows testing time dependent behaviour deterministically.
+use std::time::Instant;
+
+#[cfg(feature = "test-clock")]
+use std::{
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+#[derive(Debug, Clone, Default)]
+pub(crate) enum Clock {
+    #[default]
+    System,
+    #[cfg(feature = "test-clock")]
+    Test(TestClock),
+}
+
+impl Clock {
+    pub(crate) fn now(&self) -> Instant {
+        match self {
+            Clock::System => Instant::now(),
+            #[cfg(feature = "test-clock")]
+            Clock::Test(clock) => clock.now(),
+        }
+    }
+}
+
+/// A manually driven clock for tests.
+///
+/// The clock starts at the time it was created and only moves forward when
+/// [`advance`](Self::advance) is called. Clones share the same time, so a clone
+/// can be installed on a stream with
+/// [`WebSocketStream::set_clock`](crate::WebSocketStream::set_clock) while the
+/// test keeps another one to drive it.
+#[cfg(feature = "test-clock")]
+#[derive(Debug, Clone)]
+pub struct TestClock {
+    now: Arc<Mutex<Instant>>,
+}
+
+#[cfg(feature = "test-clock")]
+impl TestClock {
+    /// Creates a new clock starting at the current time.
+    pub fn new() -> Self {
+        Self {
+            now: Arc::new(Mutex::new(Instant::now())),
+        }
+    }
+
+    /// Returns the current time of this clock.
+    pub fn now(&self) -> Instant {
+        *self.now.lock().expect("lock test clock")
+    }
+
+    /// Moves the clock forward by `duration`.
+    pub fn advance(&self, duration: Duration) {
+        *self.now.lock().expect("lock test clock") += duration;
+    }
+}
+
+#[cfg(feature = "test-clock")]
+impl Default for TestClock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
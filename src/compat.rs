@@ -1,6 +1,6 @@
 #[allow(unused_imports)]
 use log::*;
-use std::io::{Read, Write};
+use std::io::{IoSlice, Read, Write};
 use std::pin::Pin;
 use std::task::{Context, Poll, Waker};
 
@@ -10,13 +10,25 @@ use futures_task::{waker_ref, ArcWake};
 use std::sync::Arc;
 use tungstenite::Error as WsError;
 
+use crate::chunks::Demux;
+use crate::counters::ConnectionMetrics;
+use crate::frames::FrameTracker;
+use crate::prepared::{PreparedFrame, Staged};
+use crate::tap::Tap;
+
 pub(crate) enum ContextWaker {
     Read,
     Write,
 }
 
+/// Adapts an async stream to the blocking `Read` and `Write` traits that
+/// tungstenite expects, see [`WebSocketStream::with_raw`](crate::WebSocketStream::with_raw).
+///
+/// Reads and writes that would block return
+/// [`WouldBlock`](std::io::ErrorKind::WouldBlock) instead and wake the task
+/// that last polled the stream once they can make progress.
 #[derive(Debug)]
-pub(crate) struct AllowStd<S> {
+pub struct AllowStd<S> {
     inner: S,
     // We have the problem that external read operations (i.e. the Stream impl)
     // can trigger both read (AsyncRead) and write (AsyncWrite) operations on
@@ -43,6 +55,35 @@ pub(crate) struct AllowStd<S> {
     // (and handshaking), the write_waker slots for the Sink impl.
     write_waker_proxy: Arc<WakerProxy>,
     read_waker_proxy: Arc<WakerProxy>,
+    // Follows the frames read after the handshake for the frame based limits.
+    frames: FrameTracker,
+    // Bytes that were already read from the stream, e.g. together with the
+    // handshake response, and are returned by the next reads.
+    unread: Vec<u8>,
+    // A prepared frame that is written before anything else, so that it is
+    // completed even if sending it was cancelled.
+    staged: Option<Staged>,
+    // Bytes of the last write that the stream didn't accept. tungstenite
+    // always writes its whole buffer, so this is what is still buffered.
+    unwritten: usize,
+    // Bytes that tungstenite added to its write buffer since the last write,
+    // see `buffer`.
+    buffered: usize,
+    // Everything read while recording, e.g. the raw handshake response.
+    #[cfg(feature = "handshake")]
+    recorded: Option<Vec<u8>>,
+    // Set during the handshake, whose bytes are not frames.
+    #[cfg(feature = "handshake")]
+    handshaking: bool,
+    // The response to a client handshake read so far, see `hold_back`.
+    #[cfg(feature = "handshake")]
+    head: Option<Vec<u8>>,
+    // Only set while incoming messages are streamed in chunks.
+    demux: Option<Box<Demux>>,
+    // Observes the raw bytes, see `WebSocketStream::set_wire_tap`.
+    tap: Option<Tap>,
+    // Shared with the halves of a split stream.
+    metrics: Arc<ConnectionMetrics>,
 }
 
 // Internal trait used only in the Handshake module for registering
@@ -68,6 +109,20 @@ impl<S> AllowStd<S> {
             inner,
             write_waker_proxy: Default::default(),
             read_waker_proxy: Default::default(),
+            frames: Default::default(),
+            unread: Vec::new(),
+            staged: None,
+            unwritten: 0,
+            buffered: 0,
+            #[cfg(feature = "handshake")]
+            recorded: None,
+            #[cfg(feature = "handshake")]
+            handshaking: false,
+            #[cfg(feature = "handshake")]
+            head: None,
+            demux: None,
+            tap: None,
+            metrics: Default::default(),
         };
 
         // Register the handshake waker as read waker for both proxies,
@@ -98,6 +153,104 @@ impl<S> AllowStd<S> {
             }
         }
     }
+
+    // Wakes the task waiting for reads, e.g. to let it notice a state change
+    // caused by a write.
+    pub(crate) fn wake_reader(&self) {
+        self.read_waker_proxy.read_waker.wake();
+    }
+
+    // Notes that tungstenite is about to add `len` bytes to its write buffer.
+    // It only writes them once the buffer exceeds `write_buffer_size`, and
+    // then always writes the whole buffer.
+    pub(crate) fn buffer(&mut self, len: usize) {
+        self.buffered += len;
+    }
+
+    // Takes back `len` bytes noted by `buffer` that tungstenite refused.
+    pub(crate) fn unbuffer(&mut self, len: usize) {
+        self.buffered = self.buffered.saturating_sub(len);
+    }
+
+    // Returns the number of bytes that tungstenite holds in its write buffer.
+    pub(crate) fn pending(&self) -> usize {
+        self.unwritten + self.buffered
+    }
+
+    // Starts keeping a copy of everything that is read.
+    #[cfg(feature = "handshake")]
+    pub(crate) fn start_recording(&mut self) {
+        self.recorded = Some(Vec::new());
+    }
+
+    // Stops recording and returns what was read since it started.
+    #[cfg(feature = "handshake")]
+    pub(crate) fn take_recorded(&mut self) -> Option<Vec<u8>> {
+        self.recorded.take()
+    }
+
+    // Stops passing the bytes that are read to the frame tracker until
+    // `finish_handshake` is called.
+    #[cfg(feature = "handshake")]
+    pub(crate) fn start_handshake(&mut self) {
+        self.handshaking = true;
+    }
+
+    // Holds back whatever follows the response to a client handshake, see
+    // `hold_back`.
+    #[cfg(feature = "handshake")]
+    pub(crate) fn start_client_handshake(&mut self) {
+        self.start_handshake();
+        self.head = Some(Vec::new());
+    }
+
+    #[cfg(feature = "handshake")]
+    pub(crate) fn finish_handshake(&mut self) {
+        self.handshaking = false;
+        self.head = None;
+    }
+
+    // Writes `frame` before anything that is written after it, see
+    // `WebSocketStream::send_prepared`.
+    pub(crate) fn stage(&mut self, frame: PreparedFrame) {
+        debug_assert!(self.staged.is_none(), "a frame is staged already");
+        self.staged = Some(Staged::new(frame));
+    }
+
+    // Makes the next reads return `part` before reading from the stream.
+    pub(crate) fn set_unread(&mut self, part: Vec<u8>) {
+        self.unread = part;
+    }
+
+    pub(crate) fn frames(&self) -> &FrameTracker {
+        &self.frames
+    }
+
+    pub(crate) fn frames_mut(&mut self) -> &mut FrameTracker {
+        &mut self.frames
+    }
+
+    // Starts passing only control frames on to tungstenite, see
+    // `WebSocketStream::chunks`.
+    pub(crate) fn set_demux(&mut self, demux: Demux) {
+        self.demux = Some(Box::new(demux));
+    }
+
+    pub(crate) fn demux_mut(&mut self) -> &mut Demux {
+        self.demux.as_mut().expect("not streaming chunks")
+    }
+
+    pub(crate) fn set_tap(&mut self, tap: Tap) {
+        self.tap = Some(tap);
+    }
+
+    pub(crate) fn clear_tap(&mut self) {
+        self.tap = None;
+    }
+
+    pub(crate) fn metrics(&self) -> &Arc<ConnectionMetrics> {
+        &self.metrics
+    }
 }
 
 // Proxy Waker that we pass to the internal AsyncRead/Write of the
@@ -151,6 +304,31 @@ where
     fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
         #[cfg(feature = "verbose-logging")]
         trace!("{}:{} Read.read", file!(), line!());
+        self.frames.check()?;
+        if self.demux.is_some() {
+            return self.read_control(buf);
+        }
+        let n = self.read_raw(buf)?;
+        #[cfg(feature = "handshake")]
+        if self.handshaking {
+            return Ok(n);
+        }
+        self.frames.feed(&buf[..n])
+    }
+}
+
+impl<S> AllowStd<S>
+where
+    S: AsyncRead + Unpin,
+{
+    // Reads what is left of `unread`, or from the underlying stream.
+    fn read_raw(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        if !self.unread.is_empty() {
+            let n = std::cmp::min(buf.len(), self.unread.len());
+            buf[..n].copy_from_slice(&self.unread[..n]);
+            self.unread.drain(..n);
+            return Ok(n);
+        }
         match self.with_context(ContextWaker::Read, |ctx, stream| {
             #[cfg(feature = "verbose-logging")]
             trace!(
@@ -160,7 +338,135 @@ where
             );
             stream.poll_read(ctx, buf)
         }) {
-            Poll::Ready(r) => r,
+            Poll::Ready(Ok(n)) => {
+                self.metrics.bytes_received(n);
+                if let Some(ref mut tap) = self.tap {
+                    tap.read(&buf[..n]);
+                }
+                #[cfg(feature = "handshake")]
+                if let Some(ref mut recorded) = self.recorded {
+                    recorded.extend_from_slice(&buf[..n]);
+                }
+                #[cfg(feature = "handshake")]
+                if self.head.is_some() {
+                    return Ok(self.hold_back(&buf[..n]));
+                }
+                Ok(n)
+            }
+            Poll::Ready(Err(e)) => Err(e),
+            Poll::Pending => Err(std::io::Error::from(std::io::ErrorKind::WouldBlock)),
+        }
+    }
+
+    // Returns how much of `buf`, which was read as part of the response to a
+    // client handshake, belongs to the response. tungstenite keeps the frames
+    // that follow a successful response in its own buffer, so they are held
+    // back to pass them through the frame tracker or the demultiplexer later.
+    // The body of other responses is passed on.
+    #[cfg(feature = "handshake")]
+    fn hold_back(&mut self, buf: &[u8]) -> usize {
+        let head = self.head.as_mut().expect("reading the handshake response");
+        let previous = head.len();
+        head.extend_from_slice(buf);
+        // The end of the response may be split across reads.
+        let from = previous.saturating_sub(3);
+        let end = match head[from..].windows(4).position(|w| w == b"\r\n\r\n") {
+            Some(pos) => from + pos + 4,
+            None => return buf.len(),
+        };
+        // The status code follows "HTTP/1.1 ".
+        let switching = head.get(9..12) == Some(&b"101"[..]);
+        self.head = None;
+        if !switching {
+            return buf.len();
+        }
+        let n = end - previous;
+        self.unread.extend_from_slice(&buf[n..]);
+        n
+    }
+
+    // Reads until there are control frames for tungstenite, while the payload
+    // of data frames is turned into chunks. Returns `WouldBlock` as long as
+    // chunks are waiting to be taken, so that memory use stays bounded.
+    fn read_control(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        loop {
+            let demux = self.demux_mut();
+            if !demux.control.is_empty() {
+                let n = std::cmp::min(buf.len(), demux.control.len());
+                for (dst, src) in buf.iter_mut().zip(demux.control.drain(..n)) {
+                    *dst = src;
+                }
+                return Ok(n);
+            }
+            if !demux.chunks.is_empty() {
+                return Err(std::io::Error::from(std::io::ErrorKind::WouldBlock));
+            }
+            if demux.error.is_some() {
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::InvalidData,
+                    "invalid data frame",
+                ));
+            }
+
+            let n = match self.read_raw(buf)? {
+                0 => return Ok(0),
+                n => n,
+            };
+            let demux = self.demux_mut();
+            if let Err(err) = demux.feed(&buf[..n]) {
+                demux.error = Some(err);
+            }
+        }
+    }
+}
+
+impl<S> AllowStd<S>
+where
+    S: AsyncWrite + Unpin,
+{
+    // Writes what is left of the staged frame.
+    fn write_staged(&mut self) -> std::io::Result<()> {
+        let mut staged = match self.staged.take() {
+            Some(staged) => staged,
+            None => return Ok(()),
+        };
+        loop {
+            match self.write_raw(&staged.remaining()) {
+                Ok(0) => {
+                    self.staged = Some(staged);
+                    return Err(std::io::ErrorKind::WriteZero.into());
+                }
+                Ok(n) => {
+                    if staged.advance(n) {
+                        return Ok(());
+                    }
+                }
+                Err(e) => {
+                    self.staged = Some(staged);
+                    return Err(e);
+                }
+            }
+        }
+    }
+
+    fn write_raw(&mut self, bufs: &[IoSlice<'_>]) -> std::io::Result<usize> {
+        match self.with_context(ContextWaker::Write, |ctx, stream| {
+            #[cfg(feature = "verbose-logging")]
+            trace!(
+                "{}:{} Write.with_context write_vectored -> poll_write_vectored",
+                file!(),
+                line!()
+            );
+            stream.poll_write_vectored(ctx, bufs)
+        }) {
+            Poll::Ready(Ok(n)) => {
+                self.metrics.bytes_sent(n);
+                if let Some(ref mut tap) = self.tap {
+                    tap.write_vectored(bufs, n);
+                }
+                Ok(n)
+            }
+            Poll::Ready(Err(e)) => Err(e),
             Poll::Pending => Err(std::io::Error::from(std::io::ErrorKind::WouldBlock)),
         }
     }
@@ -173,6 +479,13 @@ where
     fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
         #[cfg(feature = "verbose-logging")]
         trace!("{}:{} Write.write", file!(), line!());
+        self.buffered = 0;
+        if let Err(e) = self.write_staged() {
+            if e.kind() == std::io::ErrorKind::WouldBlock {
+                self.unwritten = buf.len();
+            }
+            return Err(e);
+        }
         match self.with_context(ContextWaker::Write, |ctx, stream| {
             #[cfg(feature = "verbose-logging")]
             trace!(
@@ -182,14 +495,40 @@ where
             );
             stream.poll_write(ctx, buf)
         }) {
-            Poll::Ready(r) => r,
-            Poll::Pending => Err(std::io::Error::from(std::io::ErrorKind::WouldBlock)),
+            Poll::Ready(Ok(n)) => {
+                self.unwritten = buf.len() - n;
+                self.metrics.bytes_sent(n);
+                if let Some(ref mut tap) = self.tap {
+                    tap.write(&buf[..n]);
+                }
+                Ok(n)
+            }
+            Poll::Ready(Err(e)) => Err(e),
+            Poll::Pending => {
+                self.unwritten = buf.len();
+                Err(std::io::Error::from(std::io::ErrorKind::WouldBlock))
+            }
         }
     }
 
+    fn write_vectored(&mut self, bufs: &[IoSlice<'_>]) -> std::io::Result<usize> {
+        #[cfg(feature = "verbose-logging")]
+        trace!("{}:{} Write.write_vectored", file!(), line!());
+        let len: usize = bufs.iter().map(|buf| buf.len()).sum();
+        self.buffered = 0;
+        let res = self.write_staged().and_then(|()| self.write_raw(bufs));
+        match res {
+            Ok(n) => self.unwritten = len - n,
+            Err(ref e) if e.kind() == std::io::ErrorKind::WouldBlock => self.unwritten = len,
+            Err(_) => {}
+        }
+        res
+    }
+
     fn flush(&mut self) -> std::io::Result<()> {
         #[cfg(feature = "verbose-logging")]
         trace!("{}:{} Write.flush", file!(), line!());
+        self.write_staged()?;
         match self.with_context(ContextWaker::Write, |ctx, stream| {
             #[cfg(feature = "verbose-logging")]
             trace!(
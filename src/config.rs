@@ -0,0 +1,162 @@
+//! Validated construction of a [`WebSocketConfig`].
+use std::fmt;
+
+use tungstenite::protocol::WebSocketConfig;
+
+/// Builder for a [`WebSocketConfig`].
+///
+/// Unlike filling in the fields of [`WebSocketConfig`] directly, this keeps
+/// compiling when `tungstenite` adds or reorganizes its options, and checks
+/// that the chosen limits are consistent with each other. Options that are
+/// not set keep the defaults of `tungstenite`.
+///
+/// ```
+/// use async_tungstenite::ConfigBuilder;
+///
+/// let config = ConfigBuilder::new()
+///     .max_message_size(Some(1 << 20))
+///     .max_frame_size(Some(1 << 16))
+///     .build()
+///     .unwrap();
+/// assert_eq!(config.max_message_size, Some(1 << 20));
+/// ```
+///
+/// The presets [`low_latency`](Self::low_latency) and
+/// [`high_throughput`](Self::high_throughput) can be tweaked further the same
+/// way:
+///
+/// ```
+/// use async_tungstenite::ConfigBuilder;
+///
+/// let config = ConfigBuilder::low_latency()
+///     .max_message_size(Some(1 << 16))
+///     .max_frame_size(Some(1 << 16))
+///     .build()
+///     .unwrap();
+/// assert_eq!(config.write_buffer_size, 0);
+/// ```
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ConfigBuilder {
+    config: WebSocketConfig,
+}
+
+impl ConfigBuilder {
+    /// Creates a builder with the default configuration.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Creates a builder preset for low latency.
+    ///
+    /// Sets [`write_buffer_size`](Self::write_buffer_size) to `0`, so that
+    /// every message is written to the stream as soon as it is sent, and
+    /// [`read_buffer_size`](Self::read_buffer_size) to 4 KiB. All other
+    /// options keep their defaults.
+    ///
+    /// Small writes are only sent out right away with Nagle's algorithm
+    /// disabled, which has to be done on the socket, e.g. with
+    /// `TcpStream::set_nodelay(true)`.
+    pub fn low_latency() -> Self {
+        Self::new().write_buffer_size(0).read_buffer_size(4 * 1024)
+    }
+
+    /// Creates a builder preset for high throughput.
+    ///
+    /// Sets [`write_buffer_size`](Self::write_buffer_size) and
+    /// [`read_buffer_size`](Self::read_buffer_size) to 1 MiB, so that many
+    /// messages are coalesced into few writes and reads. All other options
+    /// keep their defaults.
+    pub fn high_throughput() -> Self {
+        Self::new()
+            .write_buffer_size(1024 * 1024)
+            .read_buffer_size(1024 * 1024)
+    }
+
+    /// Sets the size of the buffer that incoming data is read into.
+    pub fn read_buffer_size(mut self, size: usize) -> Self {
+        self.config = self.config.read_buffer_size(size);
+        self
+    }
+
+    /// Sets the number of bytes that are buffered before outgoing data is
+    /// written to the underlying stream.
+    ///
+    /// `0` writes every message immediately.
+    pub fn write_buffer_size(mut self, size: usize) -> Self {
+        self.config = self.config.write_buffer_size(size);
+        self
+    }
+
+    /// Sets the maximum number of bytes that may be buffered for writing.
+    ///
+    /// Sending fails with an error once the buffer would grow larger. This
+    /// must be larger than the [`write_buffer_size`](Self::write_buffer_size).
+    pub fn max_write_buffer_size(mut self, size: usize) -> Self {
+        self.config = self.config.max_write_buffer_size(size);
+        self
+    }
+
+    /// Sets the maximum size of an incoming message, or `None` for no limit.
+    pub fn max_message_size(mut self, size: Option<usize>) -> Self {
+        self.config = self.config.max_message_size(size);
+        self
+    }
+
+    /// Sets the maximum size of a single incoming frame, or `None` for no
+    /// limit.
+    ///
+    /// This must not be larger than the
+    /// [`max_message_size`](Self::max_message_size).
+    pub fn max_frame_size(mut self, size: Option<usize>) -> Self {
+        self.config = self.config.max_frame_size(size);
+        self
+    }
+
+    /// Sets whether unmasked frames from clients are accepted by a server.
+    ///
+    /// The protocol requires clients to mask their frames, so this should
+    /// only be enabled for clients known to violate this.
+    pub fn accept_unmasked_frames(mut self, accept: bool) -> Self {
+        self.config = self.config.accept_unmasked_frames(accept);
+        self
+    }
+
+    /// Checks the options and returns the resulting configuration.
+    pub fn build(self) -> Result<WebSocketConfig, ConfigError> {
+        let config = self.config;
+        if config.max_write_buffer_size <= config.write_buffer_size {
+            return Err(ConfigError::WriteBufferTooLarge);
+        }
+        if let (Some(frame), Some(message)) = (config.max_frame_size, config.max_message_size) {
+            if frame > message {
+                return Err(ConfigError::FrameLargerThanMessage);
+            }
+        }
+        Ok(config)
+    }
+}
+
+/// Error returned by [`ConfigBuilder::build`] for inconsistent options.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum ConfigError {
+    /// The write buffer size is not smaller than the maximum write buffer size.
+    WriteBufferTooLarge,
+    /// The maximum frame size is larger than the maximum message size.
+    FrameLargerThanMessage,
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigError::WriteBufferTooLarge => {
+                f.write_str("write buffer size must be smaller than the maximum write buffer size")
+            }
+            ConfigError::FrameLargerThanMessage => {
+                f.write_str("maximum frame size must not exceed the maximum message size")
+            }
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {}
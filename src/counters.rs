@@ -0,0 +1,87 @@
+//! Per-connection counters, see [`WebSocketStream::metrics`](crate::WebSocketStream::metrics).
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use crate::telemetry::Direction;
+
+/// Counts the messages and bytes a single connection transferred.
+///
+/// The counters are updated with relaxed atomics while the connection is
+/// used and can be read at any time with [`snapshot`](Self::snapshot), also
+/// from another thread.
+#[derive(Debug, Default)]
+pub struct ConnectionMetrics {
+    messages_sent: AtomicU64,
+    messages_received: AtomicU64,
+    bytes_sent: AtomicU64,
+    bytes_received: AtomicU64,
+    pings_sent: AtomicU64,
+    pings_received: AtomicU64,
+    pongs_sent: AtomicU64,
+    pongs_received: AtomicU64,
+}
+
+/// The values of [`ConnectionMetrics`] at one point in time.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct MetricsSnapshot {
+    /// Messages sent, other than Pings and Pongs.
+    pub messages_sent: u64,
+    /// Messages received, other than Pings and Pongs.
+    pub messages_received: u64,
+    /// Bytes written to the underlying stream, including the handshake and
+    /// the frame headers.
+    pub bytes_sent: u64,
+    /// Bytes read from the underlying stream, including the handshake and
+    /// the frame headers.
+    pub bytes_received: u64,
+    /// Pings sent.
+    pub pings_sent: u64,
+    /// Pings received.
+    pub pings_received: u64,
+    /// Pongs sent. Replies that tungstenite sends to Pings on its own are not
+    /// counted.
+    pub pongs_sent: u64,
+    /// Pongs received.
+    pub pongs_received: u64,
+}
+
+impl ConnectionMetrics {
+    /// Reads all counters.
+    ///
+    /// The counters are read one after another, so a snapshot taken while
+    /// the connection is in use may e.g. include a message but not yet all
+    /// of its bytes.
+    pub fn snapshot(&self) -> MetricsSnapshot {
+        MetricsSnapshot {
+            messages_sent: self.messages_sent.load(Ordering::Relaxed),
+            messages_received: self.messages_received.load(Ordering::Relaxed),
+            bytes_sent: self.bytes_sent.load(Ordering::Relaxed),
+            bytes_received: self.bytes_received.load(Ordering::Relaxed),
+            pings_sent: self.pings_sent.load(Ordering::Relaxed),
+            pings_received: self.pings_received.load(Ordering::Relaxed),
+            pongs_sent: self.pongs_sent.load(Ordering::Relaxed),
+            pongs_received: self.pongs_received.load(Ordering::Relaxed),
+        }
+    }
+
+    /// Counts a message of `kind`, see [`MessageSample`](crate::telemetry::MessageSample).
+    pub(crate) fn message(&self, direction: Direction, kind: &str) {
+        let counter = match (direction, kind) {
+            (Direction::Sent, "ping") => &self.pings_sent,
+            (Direction::Sent, "pong") => &self.pongs_sent,
+            (Direction::Sent, _) => &self.messages_sent,
+            (Direction::Received, "ping") => &self.pings_received,
+            (Direction::Received, "pong") => &self.pongs_received,
+            (Direction::Received, _) => &self.messages_received,
+        };
+        counter.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn bytes_sent(&self, n: usize) {
+        self.bytes_sent.fetch_add(n as u64, Ordering::Relaxed);
+    }
+
+    pub(crate) fn bytes_received(&self, n: usize) {
+        self.bytes_received.fetch_add(n as u64, Ordering::Relaxed);
+    }
+}
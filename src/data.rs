@@ -0,0 +1,71 @@
+//! A stream of only the data messages of a [websocket](WebSocketStream).
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use futures_core::{FusedStream, Stream};
+use futures_io::{AsyncRead, AsyncWrite};
+use tungstenite::{Error as WsError, Message};
+
+use crate::WebSocketStream;
+
+/// A [`WebSocketStream`] that only yields [`Text`](Message::Text) and
+/// [`Binary`](Message::Binary) messages, see
+/// [`WebSocketStream::data_frames`].
+#[derive(Debug)]
+pub struct DataFrames<S> {
+    stream: WebSocketStream<S>,
+}
+
+impl<S> DataFrames<S> {
+    /// Returns a shared reference to the websocket.
+    pub fn get_ref(&self) -> &WebSocketStream<S> {
+        &self.stream
+    }
+
+    /// Returns a mutable reference to the websocket, e.g. to send messages.
+    pub fn get_mut(&mut self) -> &mut WebSocketStream<S> {
+        &mut self.stream
+    }
+
+    /// Returns the websocket.
+    pub fn into_inner(self) -> WebSocketStream<S> {
+        self.stream
+    }
+}
+
+impl<S> WebSocketStream<S> {
+    /// Turns the websocket into a stream of only the data messages.
+    ///
+    /// Control messages are still handled as they are read: Pings are
+    /// answered and a Close frame completes the close handshake. They are
+    /// just not yielded, and the stream ends once the connection is closed.
+    pub fn data_frames(self) -> DataFrames<S> {
+        DataFrames { stream: self }
+    }
+}
+
+impl<S> Stream for DataFrames<S>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    type Item = Result<Message, WsError>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let me = self.get_mut();
+        loop {
+            match Pin::new(&mut me.stream).poll_next(cx) {
+                Poll::Ready(Some(Ok(msg))) if !(msg.is_text() || msg.is_binary()) => {}
+                other => return other,
+            }
+        }
+    }
+}
+
+impl<S> FusedStream for DataFrames<S>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    fn is_terminated(&self) -> bool {
+        self.stream.is_terminated()
+    }
+}
@@ -0,0 +1,99 @@
+//! Deadlines for receiving and sending on a [`WebSocketStream`](crate::WebSocketStream).
+use std::fmt;
+use std::future::Future;
+use std::io;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use tungstenite::Error as WsError;
+
+use crate::exclusive::Exclusive;
+
+/// Which deadline of a stream passed.
+///
+/// Errors for passed deadlines are [`Io`](WsError::Io) errors of kind
+/// [`TimedOut`](io::ErrorKind::TimedOut) that carry this as inner error. Use
+/// [`of`](Self::of) to tell them apart from other errors.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DeadlineExceeded {
+    /// No message was received before the receive deadline.
+    Recv,
+    /// Pending data was not flushed before the send deadline.
+    Send,
+}
+
+impl DeadlineExceeded {
+    /// Returns which deadline passed if `err` was caused by one.
+    pub fn of(err: &WsError) -> Option<Self> {
+        match err {
+            WsError::Io(err) => err.get_ref()?.downcast_ref().copied(),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn into_error(self) -> WsError {
+        WsError::Io(io::Error::new(io::ErrorKind::TimedOut, self))
+    }
+}
+
+impl fmt::Display for DeadlineExceeded {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DeadlineExceeded::Recv => f.write_str("receive deadline exceeded"),
+            DeadlineExceeded::Send => f.write_str("send deadline exceeded"),
+        }
+    }
+}
+
+impl std::error::Error for DeadlineExceeded {}
+
+/// Runs `fut` until it completes or `timer` resolves, whichever happens first.
+///
+/// Returns `None` if the timer resolved first.
+pub(crate) async fn race<F, T>(fut: F, timer: T) -> Option<F::Output>
+where
+    F: Future,
+    T: Future<Output = ()>,
+{
+    let mut fut = Box::pin(fut);
+    let mut timer = Box::pin(timer);
+    std::future::poll_fn(|cx| {
+        if let Poll::Ready(res) = fut.as_mut().poll(cx) {
+            return Poll::Ready(Some(res));
+        }
+        timer.as_mut().poll(cx).map(|()| None)
+    })
+    .await
+}
+
+/// A timer future supplied by the user.
+pub(crate) struct Deadline(Exclusive<Pin<Box<dyn Future<Output = ()> + Send>>>);
+
+impl Deadline {
+    pub(crate) fn new<F>(timer: F) -> Self
+    where
+        F: Future<Output = ()> + Send + 'static,
+    {
+        Deadline(Exclusive::new(Box::pin(timer)))
+    }
+
+    /// Whether the deadline in `slot` passed, clearing it in that case.
+    ///
+    /// Registers `cx` for wakeup otherwise.
+    pub(crate) fn poll_passed(slot: &mut Option<Self>, cx: &mut Context<'_>) -> bool {
+        let passed = match slot {
+            Some(deadline) => deadline.0.get_mut().as_mut().poll(cx).is_ready(),
+            None => false,
+        };
+        if passed {
+            *slot = None;
+        }
+        passed
+    }
+}
+
+impl fmt::Debug for Deadline {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Deadline").finish_non_exhaustive()
+    }
+}
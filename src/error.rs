@@ -0,0 +1,164 @@
+//! Structured reasons for failed connection attempts.
+use std::error::Error as StdError;
+use std::fmt;
+use std::io;
+
+use tungstenite::error::ProtocolError;
+use tungstenite::handshake::client::Response;
+use tungstenite::Error as WsError;
+
+/// The reason why a client connection could not be established.
+///
+/// The connect and handshake functions return a [`tungstenite::Error`], in
+/// which many of these reasons look alike. Converting it into this type tells
+/// them apart:
+///
+/// ```
+/// use async_tungstenite::ConnectError;
+///
+/// fn report(err: tungstenite::Error) {
+///     match ConnectError::from(err) {
+///         ConnectError::Dns { host, .. } => eprintln!("Unknown host {}", host),
+///         ConnectError::Status(response) => eprintln!("Rejected with {}", response.status()),
+///         err => eprintln!("Failed to connect: {}", err),
+///     }
+/// }
+/// ```
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum ConnectError {
+    /// The host name could not be resolved.
+    Dns {
+        /// The host name.
+        host: String,
+        /// The error of the resolver.
+        source: io::Error,
+    },
+    /// No TCP connection could be established, e.g. because it was refused.
+    Connect {
+        /// The host name.
+        host: String,
+        /// The port.
+        port: u16,
+        /// The error of the last connection attempt.
+        source: io::Error,
+    },
+    /// The TLS handshake failed.
+    Tls(WsError),
+    /// The server answered with a status other than `101 Switching Protocols`.
+    Status(Box<Response>),
+    /// The server's answer was not a valid upgrade, e.g. because of missing or
+    /// wrong headers.
+    InvalidUpgrade(ProtocolError),
+    /// The attempt timed out.
+    Timeout(io::Error),
+    /// Any other failure.
+    Other(WsError),
+}
+
+impl ConnectError {
+    /// Marks I/O errors of a TLS handshake as such.
+    #[cfg_attr(
+        not(any(
+            feature = "async-tls",
+            feature = "async-native-tls",
+            feature = "tokio-native-tls",
+            feature = "tokio-rustls-manual-roots",
+            feature = "tokio-rustls-native-certs",
+            feature = "tokio-rustls-webpki-roots",
+            feature = "tokio-openssl"
+        )),
+        allow(dead_code)
+    )]
+    pub(crate) fn tls(err: WsError) -> WsError {
+        match err {
+            WsError::Io(source) => ConnectError::Tls(WsError::Io(source)).into(),
+            err => err,
+        }
+    }
+
+    /// Returns the start of the body of a [`Status`](Self::Status) response.
+    ///
+    /// The client handshake never reads past the response headers, so this
+    /// only holds the bytes that arrived together with them. Its size is
+    /// bounded by the handshake limits of `tungstenite`, which fail the
+    /// handshake after 64 KiB, however much the server sends.
+    pub fn body(&self) -> Option<&[u8]> {
+        match self {
+            ConnectError::Status(response) => response.body().as_deref(),
+            _ => None,
+        }
+    }
+
+    /// Truncates the body of a [`Status`](Self::Status) response to at most
+    /// `max` bytes, e.g. before logging or storing the error.
+    pub fn truncate_body(&mut self, max: usize) {
+        if let ConnectError::Status(response) = self {
+            if let Some(body) = response.body_mut() {
+                body.truncate(max);
+            }
+        }
+    }
+}
+
+impl From<WsError> for ConnectError {
+    fn from(err: WsError) -> Self {
+        match err {
+            WsError::Io(err) if err.get_ref().map_or(false, |e| e.is::<ConnectError>()) => *err
+                .into_inner()
+                .and_then(|e| e.downcast().ok())
+                .expect("checked above"),
+            WsError::Io(err) if err.kind() == io::ErrorKind::TimedOut => ConnectError::Timeout(err),
+            WsError::Tls(_) => ConnectError::Tls(err),
+            WsError::Http(response) => ConnectError::Status(Box::new(response)),
+            WsError::Protocol(err) => ConnectError::InvalidUpgrade(err),
+            err => ConnectError::Other(err),
+        }
+    }
+}
+
+impl From<ConnectError> for WsError {
+    fn from(err: ConnectError) -> Self {
+        let kind = match err {
+            ConnectError::Dns { ref source, .. }
+            | ConnectError::Connect { ref source, .. }
+            | ConnectError::Tls(WsError::Io(ref source))
+            | ConnectError::Timeout(ref source) => source.kind(),
+            _ => io::ErrorKind::Other,
+        };
+        WsError::Io(io::Error::new(kind, err))
+    }
+}
+
+impl fmt::Display for ConnectError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConnectError::Dns { host, source } => {
+                write!(f, "Failed to resolve {}: {}", host, source)
+            }
+            ConnectError::Connect { host, port, source } => {
+                write!(f, "Failed to connect to {}:{}: {}", host, port, source)
+            }
+            ConnectError::Tls(err) => write!(f, "TLS handshake failed: {}", err),
+            ConnectError::Status(response) => {
+                write!(f, "Unexpected HTTP status {}", response.status())
+            }
+            ConnectError::InvalidUpgrade(err) => write!(f, "Invalid upgrade response: {}", err),
+            ConnectError::Timeout(err) => write!(f, "Timed out: {}", err),
+            ConnectError::Other(err) => err.fmt(f),
+        }
+    }
+}
+
+impl StdError for ConnectError {
+    fn source(&self) -> Option<&(dyn StdError + 'static)> {
+        match self {
+            ConnectError::Dns { source, .. }
+            | ConnectError::Connect { source, .. }
+            | ConnectError::Timeout(source) => Some(source),
+            ConnectError::Tls(err) | ConnectError::Other(err) => Some(err),
+            ConnectError::InvalidUpgrade(err) => Some(err),
+            ConnectError::Status(_) => None,
+        }
+    }
+}
@@ -0,0 +1,19 @@
+//! Exclusive access to values that are not `Sync`.
+use std::sync::{Mutex, PoisonError};
+
+/// Holds a value that is only ever used through `&mut`, e.g. a callback or a
+/// timer supplied by the user.
+///
+/// The mutex keeps the stream `Sync` without requiring the value to be. It is
+/// never locked, as [`get_mut`](Self::get_mut) already has exclusive access.
+pub(crate) struct Exclusive<T>(Mutex<T>);
+
+impl<T> Exclusive<T> {
+    pub(crate) fn new(value: T) -> Self {
+        Exclusive(Mutex::new(value))
+    }
+
+    pub(crate) fn get_mut(&mut self) -> &mut T {
+        self.0.get_mut().unwrap_or_else(PoisonError::into_inner)
+    }
+}
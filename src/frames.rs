@@ -0,0 +1,349 @@
+//! Passive tracking of the frames read from the underlying stream.
+//!
+//! tungstenite only hands out complete messages, so limits that depend on
+//! individual frames are enforced here by following the frame headers in the
+//! bytes passing through `AllowStd`. The bytes themselves are never modified,
+//! but the payload of close frames can be kept for lenient decoding.
+use std::fmt;
+use std::io::{self, Cursor};
+use std::time::{Duration, Instant};
+
+use crate::clock::Clock;
+use crate::exclusive::Exclusive;
+use tungstenite::protocol::frame::{
+    coding::{CloseCode, Control as OpCtl, Data as OpData, OpCode},
+    FrameHeader,
+};
+
+/// The longest possible frame header.
+const MAX_HEADER_SIZE: usize = 14;
+
+/// Decides whether an incoming message is accepted, see
+/// [`WebSocketStream::set_message_admission`](crate::WebSocketStream::set_message_admission).
+pub(crate) struct Admission(Exclusive<AdmissionFn>);
+
+type AdmissionFn = Box<dyn FnMut(&MessageStart) -> bool + Send>;
+
+impl Admission {
+    pub(crate) fn new<F>(admission: F) -> Self
+    where
+        F: FnMut(&MessageStart) -> bool + Send + 'static,
+    {
+        Admission(Exclusive::new(Box::new(admission)))
+    }
+
+    fn admits(&mut self, start: &MessageStart) -> bool {
+        (self.0.get_mut())(start)
+    }
+}
+
+/// Limits the pings the peer may send, see
+/// [`WebSocketStream::set_ping_limit`](crate::WebSocketStream::set_ping_limit).
+#[derive(Debug)]
+pub(crate) struct PingLimit {
+    max: u32,
+    window: Duration,
+    /// Start of the current window and the pings received in it.
+    start: Instant,
+    count: u32,
+    pub(crate) clock: Clock,
+}
+
+impl PingLimit {
+    pub(crate) fn new(max: u32, window: Duration, clock: Clock) -> Self {
+        PingLimit {
+            max,
+            window,
+            start: clock.now(),
+            count: 0,
+            clock,
+        }
+    }
+
+    /// Counts a ping and returns whether it exceeds the limit.
+    fn exceeded(&mut self) -> bool {
+        let now = self.clock.now();
+        if now.saturating_duration_since(self.start) >= self.window {
+            self.start = now;
+            self.count = 0;
+        }
+        self.count += 1;
+        self.count > self.max
+    }
+}
+
+/// Follows the incoming frames for the frame based limits.
+///
+/// Parsing the frame headers is only started once a limit needs it, so that
+/// connections without limits don't pay for it.
+#[derive(Default)]
+pub(crate) struct FrameTracker {
+    /// Whether the frames are followed. Once started, this stays on.
+    tracking: bool,
+    /// Whether bytes were read before following the frames started.
+    untracked: bool,
+    /// Bytes of a frame header that is not complete yet.
+    header: Vec<u8>,
+    /// Payload bytes of the current frame that were not seen yet.
+    remaining: u64,
+    /// Number of frames of the current fragmented message.
+    fragments: usize,
+    /// Set once the stream can't be followed anymore, tungstenite reports the
+    /// actual error in that case.
+    broken: bool,
+    /// The limit that was violated.
+    violation: Option<Violation>,
+    /// Payload of the last close frame and its masking key, if kept.
+    close: Option<(Option<[u8; 4]>, Vec<u8>)>,
+    pub(crate) max_fragments: Option<usize>,
+    pub(crate) admission: Option<Admission>,
+    pub(crate) ping_limit: Option<PingLimit>,
+    /// Whether to keep the payload of close frames.
+    pub(crate) keep_close: bool,
+}
+
+impl fmt::Debug for FrameTracker {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("FrameTracker")
+            .field("tracking", &self.tracking)
+            .field("remaining", &self.remaining)
+            .field("fragments", &self.fragments)
+            .field("broken", &self.broken)
+            .field("violation", &self.violation)
+            .field("max_fragments", &self.max_fragments)
+            .field("admission", &self.admission.is_some())
+            .field("ping_limit", &self.ping_limit)
+            .field("keep_close", &self.keep_close)
+            .finish()
+    }
+}
+
+impl FrameTracker {
+    /// Follows the frames in `buf`, which must directly follow the bytes of
+    /// the previous call.
+    ///
+    /// Returns how many bytes of `buf` may be passed on. If a frame violates a
+    /// limit, only the bytes before it are passed on so that earlier messages
+    /// are still delivered, and [`check`](Self::check) fails from then on.
+    pub(crate) fn feed(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if !self.tracking {
+            if !self.needed() {
+                self.untracked |= !buf.is_empty();
+                return Ok(buf.len());
+            }
+            self.tracking = true;
+            // The frames read so far can't be followed anymore.
+            self.broken = self.untracked;
+        }
+
+        let mut pos = 0;
+        while pos < buf.len() && !self.broken {
+            if self.remaining > 0 {
+                let skip = std::cmp::min(self.remaining, (buf.len() - pos) as u64);
+                if let Some((_, ref mut payload)) = self.close {
+                    payload.extend_from_slice(&buf[pos..pos + skip as usize]);
+                }
+                self.remaining -= skip;
+                pos += skip as usize;
+                continue;
+            }
+
+            let previous = self.header.len();
+            let start = pos.saturating_sub(previous);
+            let take = std::cmp::min(MAX_HEADER_SIZE - previous, buf.len() - pos);
+            self.header.extend_from_slice(&buf[pos..pos + take]);
+
+            let mut cursor = Cursor::new(&self.header);
+            match FrameHeader::parse(&mut cursor) {
+                Ok(Some((header, length))) => {
+                    pos += cursor.position() as usize - previous;
+                    self.header.clear();
+                    self.remaining = length;
+                    self.close = match header.opcode {
+                        OpCode::Control(OpCtl::Close) if self.keep_close => {
+                            Some((header.mask, Vec::new()))
+                        }
+                        _ => None,
+                    };
+                    if !self.on_header(&header, length) {
+                        if start == 0 {
+                            self.check()?;
+                        }
+                        return Ok(start);
+                    }
+                }
+                Ok(None) => pos += take,
+                Err(_) => self.broken = true,
+            }
+        }
+
+        Ok(buf.len())
+    }
+
+    /// Returns the unmasked payload of the last frame if it is a complete
+    /// close frame.
+    pub(crate) fn take_close(&mut self) -> Option<Vec<u8>> {
+        if self.remaining > 0 {
+            return None;
+        }
+        let (mask, mut payload) = self.close.take()?;
+        if let Some(mask) = mask {
+            for (i, byte) in payload.iter_mut().enumerate() {
+                *byte ^= mask[i % 4];
+            }
+        }
+        Some(payload)
+    }
+
+    /// Whether the bytes seen so far end between two messages.
+    ///
+    /// Without following the frames, this is only known before anything was
+    /// read.
+    pub(crate) fn between_messages(&self) -> bool {
+        if !self.tracking {
+            return !self.untracked;
+        }
+        !self.broken && self.header.is_empty() && self.remaining == 0 && self.fragments == 0
+    }
+
+    /// Fails if a limit was violated before.
+    pub(crate) fn check(&self) -> io::Result<()> {
+        match self.violation {
+            Some(violation) => Err(io::Error::new(io::ErrorKind::InvalidData, violation)),
+            None => Ok(()),
+        }
+    }
+
+    /// Whether one of the limits needs the frames to be followed.
+    fn needed(&self) -> bool {
+        self.max_fragments.is_some()
+            || self.admission.is_some()
+            || self.ping_limit.is_some()
+            || self.keep_close
+    }
+
+    /// Returns `false` if the frame violates a limit.
+    fn on_header(&mut self, header: &FrameHeader, length: u64) -> bool {
+        if let OpCode::Data(opcode @ (OpData::Text | OpData::Binary)) = header.opcode {
+            if let Some(ref mut admission) = self.admission {
+                let start = MessageStart {
+                    opcode,
+                    fragmented: !header.is_final,
+                    frame_len: length,
+                };
+                if !admission.admits(&start) {
+                    self.violation = Some(Violation::Rejected);
+                    return false;
+                }
+            }
+        }
+
+        match header.opcode {
+            // The ping is not passed on, so tungstenite doesn't queue a Pong
+            // that would follow our Close frame.
+            OpCode::Control(OpCtl::Ping) => {
+                if let Some(ref mut limit) = self.ping_limit {
+                    if limit.exceeded() {
+                        self.violation = Some(Violation::TooManyPings { max: limit.max });
+                        return false;
+                    }
+                }
+            }
+            OpCode::Data(OpData::Continue) if self.fragments > 0 => {
+                self.fragments += 1;
+                if let Some(max) = self.max_fragments {
+                    if self.fragments > max {
+                        self.violation = Some(Violation::TooManyFragments { max });
+                        return false;
+                    }
+                }
+                if header.is_final {
+                    self.fragments = 0;
+                }
+            }
+            OpCode::Data(OpData::Text | OpData::Binary) if !header.is_final => {
+                self.fragments = 1;
+            }
+            _ => {}
+        }
+
+        true
+    }
+}
+
+/// The start of an incoming message, as reported to the admission callback
+/// of [`WebSocketStream::set_message_admission`](crate::WebSocketStream::set_message_admission).
+#[derive(Debug, Clone)]
+pub struct MessageStart {
+    opcode: OpData,
+    fragmented: bool,
+    frame_len: u64,
+}
+
+impl MessageStart {
+    /// Whether this is a text message.
+    pub fn is_text(&self) -> bool {
+        self.opcode == OpData::Text
+    }
+
+    /// Whether this is a binary message.
+    pub fn is_binary(&self) -> bool {
+        self.opcode == OpData::Binary
+    }
+
+    /// Whether more frames follow the first one.
+    pub fn is_fragmented(&self) -> bool {
+        self.fragmented
+    }
+
+    /// The payload length of the first frame, which is the length of the
+    /// whole message unless it is fragmented.
+    pub fn frame_len(&self) -> u64 {
+        self.frame_len
+    }
+}
+
+/// A limit that incoming messages violated.
+#[derive(Debug, Clone, Copy)]
+pub(crate) enum Violation {
+    /// The message was split into more frames than allowed.
+    TooManyFragments { max: usize },
+    /// The admission callback rejected the message.
+    Rejected,
+    /// The peer sent more pings within a window than allowed.
+    TooManyPings { max: u32 },
+}
+
+impl Violation {
+    /// Returns the violation `err` reports, if any.
+    pub(crate) fn of(err: &io::Error) -> Option<Violation> {
+        err.get_ref()
+            .and_then(|err| err.downcast_ref::<Violation>())
+            .copied()
+    }
+
+    /// The code and reason to close the connection with.
+    pub(crate) fn close_reason(&self) -> (CloseCode, &'static str) {
+        match self {
+            Violation::TooManyFragments { .. } => (CloseCode::Size, "Too many fragments"),
+            Violation::Rejected => (CloseCode::Policy, "Message rejected"),
+            Violation::TooManyPings { .. } => (CloseCode::Policy, "Too many pings"),
+        }
+    }
+}
+
+impl fmt::Display for Violation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Violation::TooManyFragments { max } => {
+                write!(f, "Message consists of more than {} fragments", max)
+            }
+            Violation::Rejected => f.write_str("Message rejected"),
+            Violation::TooManyPings { max } => {
+                write!(f, "Peer sent more than {} pings within the window", max)
+            }
+        }
+    }
+}
+
+impl std::error::Error for Violation {}
@@ -1,6 +1,8 @@
 use crate::compat::AllowStd;
 #[cfg(feature = "handshake")]
 use crate::compat::SetWaker;
+#[cfg(feature = "handshake")]
+use crate::telemetry;
 use crate::WebSocketStream;
 use futures_io::{AsyncRead, AsyncWrite};
 #[allow(unused_imports)]
@@ -9,7 +11,6 @@ use std::future::Future;
 use std::io::{Read, Write};
 use std::pin::Pin;
 use std::task::{Context, Poll};
-use tungstenite::WebSocket;
 #[cfg(feature = "handshake")]
 use tungstenite::{
     handshake::{
@@ -18,8 +19,9 @@ use tungstenite::{
     },
     ClientHandshake, ServerHandshake,
 };
+use tungstenite::{protocol::Role, WebSocket};
 
-pub(crate) async fn without_handshake<F, S>(stream: S, f: F) -> WebSocketStream<S>
+pub(crate) async fn without_handshake<F, S>(stream: S, role: Role, f: F) -> WebSocketStream<S>
 where
     F: FnOnce(AllowStd<S>) -> WebSocket<AllowStd<S>> + Unpin,
     S: AsyncRead + AsyncWrite + Unpin,
@@ -28,7 +30,7 @@ where
 
     let ws = start.await;
 
-    WebSocketStream::new(ws)
+    WebSocketStream::new(ws, role)
 }
 
 struct SkippedHandshakeFuture<F, S>(Option<SkippedHandshakeFutureInner<F, S>>);
@@ -96,6 +98,7 @@ where
 }
 
 #[cfg(feature = "handshake")]
+#[allow(clippy::result_large_err)]
 pub(crate) async fn client_handshake<F, S>(
     stream: S,
     f: F,
@@ -109,9 +112,16 @@ where
         > + Unpin,
     S: AsyncRead + AsyncWrite + Unpin,
 {
-    let result = handshake(stream, f).await?;
-    let (s, r) = result;
-    Ok((WebSocketStream::new(s), r))
+    let timer = telemetry::HandshakeTimer::start();
+    let result = handshake(stream, move |mut allow_std: AllowStd<S>| {
+        allow_std.start_client_handshake();
+        f(allow_std)
+    })
+    .await?;
+    timer.finish("client");
+    let (mut s, r) = result;
+    s.get_mut().finish_handshake();
+    Ok((WebSocketStream::new(s, Role::Client), r))
 }
 
 #[cfg(feature = "handshake")]
@@ -129,8 +139,11 @@ where
         > + Unpin,
     S: AsyncRead + AsyncWrite + Unpin,
 {
-    let s: WebSocket<AllowStd<S>> = handshake(stream, f).await?;
-    Ok(WebSocketStream::new(s))
+    let timer = telemetry::HandshakeTimer::start();
+    let mut s: WebSocket<AllowStd<S>> = handshake(stream, f).await?;
+    timer.finish("server");
+    s.get_mut().finish_handshake();
+    Ok(WebSocketStream::new(s, Role::Server))
 }
 
 #[cfg(feature = "handshake")]
@@ -148,7 +161,8 @@ where
         let inner = self.0.take().expect("future polled after completion");
         #[cfg(feature = "verbose-logging")]
         trace!("Setting ctx when starting handshake");
-        let stream = AllowStd::new(inner.stream, ctx.waker());
+        let mut stream = AllowStd::new(inner.stream, ctx.waker());
+        stream.start_handshake();
 
         match (inner.f)(stream) {
             Ok(r) => Poll::Ready(Ok(StartedHandshake::Done(r))),
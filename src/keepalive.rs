@@ -0,0 +1,307 @@
+//! Heartbeats for the [sender](WebSocketSender) part of a
+//! [websocket](crate::WebSocketStream).
+use std::fmt;
+use std::future::Future;
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use std::sync::Arc;
+use std::task::Poll;
+use std::time::Duration;
+
+use atomic_waker::AtomicWaker;
+use futures_io::{AsyncRead, AsyncWrite};
+use log::*;
+use tungstenite::protocol::{frame::coding::CloseCode, CloseFrame};
+use tungstenite::{error::ProtocolError, Error as WsError, Message};
+
+use crate::exclusive::Exclusive;
+use crate::WebSocketSender;
+
+/// When [heartbeats](Heartbeat) are sent.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum KeepAliveMode {
+    /// Send a heartbeat every interval regardless of other traffic.
+    ///
+    /// This is useful for regularly measuring the round-trip time, and for
+    /// servers that expect heartbeats regardless of other traffic.
+    #[default]
+    Always,
+    /// Only send a heartbeat once the connection was idle for a whole
+    /// interval.
+    ///
+    /// Any message sent or received resets the timer, so busy connections
+    /// don't send redundant heartbeats.
+    OnIdle,
+}
+
+/// A heartbeat that keeps a connection alive and checks whether the peer
+/// still answers.
+///
+/// Either protocol [pings](Self::ping) or, for servers that expect a data
+/// message instead, an application level [message](Self::message) is sent.
+/// Run it with [`WebSocketSender::heartbeat`], or with `heartbeat` of the
+/// runtime modules.
+pub struct Heartbeat {
+    interval: Duration,
+    mode: KeepAliveMode,
+    message: Option<MessageFn>,
+    reply: Option<ReplyMatcher>,
+    max_missed: Option<u32>,
+}
+
+type MessageFn = Box<dyn FnMut() -> Message + Send>;
+
+impl Heartbeat {
+    /// Sends an empty ping every `interval`, which the peer answers with a
+    /// pong.
+    pub fn ping(interval: Duration) -> Self {
+        Heartbeat {
+            interval,
+            mode: KeepAliveMode::default(),
+            message: None,
+            reply: None,
+            max_missed: None,
+        }
+    }
+
+    /// Sends the message returned by `message` every `interval`.
+    ///
+    /// Replies are only tracked once they can be recognized, see
+    /// [`expect_reply`](Self::expect_reply).
+    pub fn message<M>(interval: Duration, message: M) -> Self
+    where
+        M: FnMut() -> Message + Send + 'static,
+    {
+        Heartbeat {
+            message: Some(Box::new(message)),
+            ..Self::ping(interval)
+        }
+    }
+
+    /// Sets when heartbeats are sent, the default is
+    /// [`KeepAliveMode::Always`].
+    pub fn mode(mut self, mode: KeepAliveMode) -> Self {
+        self.mode = mode;
+        self
+    }
+
+    /// Recognizes replies to the heartbeats among the incoming messages.
+    ///
+    /// `reply` is called with every incoming message and returns whether it
+    /// is a reply. Replies are still passed on to the application. This
+    /// replaces counting pongs as replies to pings.
+    pub fn expect_reply<R>(mut self, reply: R) -> Self
+    where
+        R: FnMut(&Message) -> bool + Send + 'static,
+    {
+        self.reply = Some(ReplyMatcher(Exclusive::new(Box::new(reply))));
+        self
+    }
+
+    /// Closes the connection once `max` heartbeats in a row were not answered
+    /// before the next one was due.
+    pub fn max_missed(mut self, max: u32) -> Self {
+        self.max_missed = Some(max);
+        self
+    }
+}
+
+impl fmt::Debug for Heartbeat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Heartbeat")
+            .field("interval", &self.interval)
+            .field("mode", &self.mode)
+            .field("message", &self.message.is_some())
+            .field("reply", &self.reply.is_some())
+            .field("max_missed", &self.max_missed)
+            .finish()
+    }
+}
+
+/// Recognizes replies to a [`Heartbeat`] among the incoming messages.
+pub(crate) struct ReplyMatcher(Exclusive<ReplyFn>);
+
+type ReplyFn = Box<dyn FnMut(&Message) -> bool + Send>;
+
+impl ReplyMatcher {
+    pub(crate) fn matches(&mut self, msg: &Message) -> bool {
+        (self.0.get_mut())(msg)
+    }
+}
+
+impl fmt::Debug for ReplyMatcher {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("ReplyMatcher")
+    }
+}
+
+/// Whether sending failed because the connection is closed.
+fn is_closed(err: &WsError) -> bool {
+    matches!(
+        err,
+        WsError::AlreadyClosed
+            | WsError::ConnectionClosed
+            | WsError::Protocol(ProtocolError::SendAfterClosing)
+    )
+}
+
+/// Stops the heartbeat started with [`heartbeat`](WebSocketSender::heartbeat)
+/// when dropped.
+#[derive(Debug)]
+pub struct HeartbeatHandle {
+    state: Arc<HeartbeatState>,
+}
+
+#[derive(Debug, Default)]
+struct HeartbeatState {
+    stopped: AtomicBool,
+    waker: AtomicWaker,
+    /// Consecutive heartbeats that were not answered before the next was due.
+    missed: AtomicU32,
+}
+
+impl HeartbeatHandle {
+    /// Whether the peer answered the last heartbeat before the next one was
+    /// due.
+    ///
+    /// This is `true` until the first heartbeat could be checked, and for
+    /// message heartbeats whose replies are not recognized.
+    pub fn reply_seen(&self) -> bool {
+        self.missed_replies() == 0
+    }
+
+    /// Returns the number of consecutive heartbeats that were not answered
+    /// before the next one was due.
+    pub fn missed_replies(&self) -> u32 {
+        self.state.missed.load(Ordering::Relaxed)
+    }
+}
+
+impl Drop for HeartbeatHandle {
+    fn drop(&mut self) {
+        self.state.stopped.store(true, Ordering::Release);
+        self.state.waker.wake();
+    }
+}
+
+impl<S> WebSocketSender<S>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    /// Runs `heartbeat` and tracks whether the peer answers it.
+    ///
+    /// `sleep` returns a future of the runtime in use, see
+    /// [timers](crate#timers). For the same reason this spawns nothing,
+    /// unlike what a `spawn_heartbeat` would: the returned future has to be
+    /// spawned or polled alongside the receiver, which is needed to process
+    /// the peer's replies. It runs concurrently with other sends.
+    ///
+    /// The future holds a clone of this sender, so
+    /// [`reunite`](crate::WebSocketStream::reunite) fails with
+    /// [`OutstandingClones`](crate::ReuniteError::OutstandingClones) until the
+    /// future itself is dropped. Dropping the [`HeartbeatHandle`] only makes
+    /// it resolve, it has to be awaited or dropped as well.
+    ///
+    /// The future resolves to `Ok(())` once the [`HeartbeatHandle`] is dropped
+    /// or the connection is closed, and to an error if sending a heartbeat
+    /// fails. If more replies were missed than
+    /// [allowed](Heartbeat::max_missed), a Close frame with code 1001 (going
+    /// away) is sent and the future resolves to a
+    /// [`TimedOut`](std::io::ErrorKind::TimedOut) error.
+    pub fn heartbeat<F, Fut>(
+        &self,
+        heartbeat: Heartbeat,
+        mut sleep: F,
+    ) -> (HeartbeatHandle, impl Future<Output = Result<(), WsError>>)
+    where
+        F: FnMut(Duration) -> Fut,
+        Fut: Future<Output = ()>,
+    {
+        let Heartbeat {
+            interval,
+            mode,
+            mut message,
+            reply,
+            max_missed,
+        } = heartbeat;
+        // Pongs answer pings unless replies are recognized explicitly.
+        let matched = reply.is_some();
+        let tracked = matched || message.is_none();
+        if matched {
+            self.shared.lock().heartbeat_reply = reply;
+        }
+
+        let state = Arc::new(HeartbeatState::default());
+        let handle = HeartbeatHandle {
+            state: state.clone(),
+        };
+        let sender = self.clone();
+
+        let mut heartbeat = Box::pin(async move {
+            let mut replies = None;
+            loop {
+                let wait = match mode {
+                    KeepAliveMode::Always => interval,
+                    KeepAliveMode::OnIdle => {
+                        interval.saturating_sub(sender.shared.lock().idle_duration())
+                    }
+                };
+                if !wait.is_zero() {
+                    sleep(wait).await;
+                    if mode == KeepAliveMode::OnIdle {
+                        // Activity while sleeping pushes the next heartbeat out.
+                        continue;
+                    }
+                }
+
+                if tracked {
+                    let received = {
+                        let ws = sender.shared.lock();
+                        if matched {
+                            ws.heartbeat_replies
+                        } else {
+                            ws.pongs_received
+                        }
+                    };
+                    if replies == Some(received) {
+                        let missed = state.missed.fetch_add(1, Ordering::Relaxed) + 1;
+                        if max_missed.map_or(false, |max| missed >= max) {
+                            let frame = CloseFrame {
+                                code: CloseCode::Away,
+                                reason: "heartbeat timed out".into(),
+                            };
+                            if let Err(err) = sender.close(Some(frame)).await {
+                                debug!("websocket close error: {}", err);
+                            }
+                            return Err(WsError::Io(std::io::Error::new(
+                                std::io::ErrorKind::TimedOut,
+                                "heartbeat was not answered",
+                            )));
+                        }
+                    } else {
+                        state.missed.store(0, Ordering::Relaxed);
+                    }
+                    replies = Some(received);
+                }
+
+                let msg = match message {
+                    Some(ref mut message) => message(),
+                    None => Message::Ping(Default::default()),
+                };
+                match sender.send(msg).await {
+                    Ok(()) => {}
+                    Err(err) if is_closed(&err) => return Ok(()),
+                    Err(err) => return Err(err),
+                }
+            }
+        });
+        let stopped = handle.state.clone();
+        let future = std::future::poll_fn(move |cx| {
+            stopped.waker.register(cx.waker());
+            if stopped.stopped.load(Ordering::Acquire) {
+                return Poll::Ready(Ok(()));
+            }
+            heartbeat.as_mut().poll(cx)
+        });
+        (handle, future)
+    }
+}
@@ -30,9 +30,51 @@
 //!    implement TLS via [tokio-openssl](https://crates.io/crates/tokio-openssl).
 //!  * `gio-runtime`: Enables the `gio` module, which provides integration with
 //!    the [gio](https://www.gtk-rs.org) runtime.
+//!  * `smol-runtime`: Enables the `smol` module, which provides integration with
+//!    the [smol](https://crates.io/crates/smol) runtime. TLS is available via
+//!    the `async-tls` or `async-native-tls` features.
+//!  * `test-clock`: Enables [`TestClock`], a manually driven clock that can be
+//!    installed on a `WebSocketStream` for testing time dependent behaviour.
+//!  * `test-frames`: Enables `WebSocketStream::send_raw_close`, which writes
+//!    deliberately malformed close frames for conformance testing of peers.
+//!  * `tower`: Enables the `tower` module, which provides the server handshake
+//!    as a [tower](https://crates.io/crates/tower) `Service`.
+//!  * `socket2`: Enables the `socket` module, which provides socket level tuning
+//!    of the underlying TCP connection via [socket2](https://crates.io/crates/socket2).
+//!  * `metrics`: Emits counters and histograms for messages, bytes, errors,
+//!    handshake durations and ping round-trip times through the
+//!    [metrics](https://crates.io/crates/metrics) facade. The metric names
+//!    start with `websocket_`.
 //!
 //! Each WebSocket stream implements the required `Stream` and `Sink` traits,
 //! making the socket a stream of WebSocket messages coming in and going out.
+//!
+//! # TLS over an existing stream
+//!
+//! The `client_async_tls*` functions of the runtime modules decide from the
+//! URL scheme whether to use TLS and take the server name from its host.
+//! `client_async_tls_upgrade` instead always performs a TLS handshake on the
+//! given stream, with the `domain` it is passed as the server name, before
+//! the WebSocket handshake. This allows acquiring the connected socket
+//! separately, e.g. from a connection pool or through a proxy, while still
+//! verifying the certificate of the real server.
+//!
+//! It is available in the `async_std`, `smol` and `tokio` modules with their
+//! TLS features. The `gio` module doesn't offer it, as TLS is set up by
+//! `gio::SocketClient` while connecting there.
+//!
+//! # Timers
+//!
+//! As this crate is independent of any runtime, functions that wait for some
+//! time take their timer from the caller. A `timer` argument is a future of
+//! the runtime in use that resolves once the time is up, e.g.
+//! `tokio::time::sleep(duration)` or `async_std::task::sleep(duration)`. A
+//! `sleep` argument is a function that returns such a future for a given
+//! duration, e.g. `tokio::time::sleep` or `async_std::task::sleep`.
+//!
+//! For the same reason nothing is spawned: work that keeps running in the
+//! background, like [heartbeats](WebSocketSender::heartbeat), is returned as a
+//! future that the caller spawns or polls.
 
 #![deny(
     missing_docs,
@@ -44,8 +86,29 @@
 
 pub use tungstenite;
 
+mod capacity;
+mod chunks;
+pub use chunks::{Chunks, MessageChunk};
+mod clock;
 mod compat;
+pub use compat::AllowStd;
+mod counters;
+pub use counters::{ConnectionMetrics, MetricsSnapshot};
+mod data;
+pub use data::DataFrames;
+mod exclusive;
+mod frames;
+pub use frames::MessageStart;
 mod handshake;
+mod prepared;
+pub use prepared::PreparedFrame;
+mod priority;
+pub use priority::Priority;
+#[cfg(feature = "test-frames")]
+mod raw;
+mod tap;
+pub use tap::TapDirection;
+mod telemetry;
 
 #[cfg(any(
     feature = "async-tls",
@@ -59,13 +122,17 @@ mod handshake;
 pub mod stream;
 
 use std::{
+    fmt,
+    future::Future,
     io::{Read, Write},
     pin::Pin,
     sync::{Arc, Mutex, MutexGuard},
     task::{ready, Context, Poll},
+    time::{Duration, Instant},
 };
 
-use compat::{cvt, AllowStd, ContextWaker};
+use clock::Clock;
+use compat::{cvt, ContextWaker};
 use futures_core::stream::{FusedStream, Stream};
 use futures_io::{AsyncRead, AsyncWrite};
 use log::*;
@@ -76,11 +143,11 @@ use tungstenite::{
     handshake::{
         client::{ClientHandshake, Response},
         server::{Callback, NoCallback},
-        HandshakeError,
+        HandshakeError, HandshakeRole,
     },
 };
 use tungstenite::{
-    error::Error as WsError,
+    error::{CapacityError, Error as WsError, ProtocolError},
     protocol::{Message, Role, WebSocket, WebSocketConfig},
 };
 
@@ -90,14 +157,58 @@ pub mod async_std;
 pub mod async_tls;
 #[cfg(feature = "gio-runtime")]
 pub mod gio;
+#[cfg(feature = "smol-runtime")]
+pub mod smol;
+#[cfg(feature = "socket2")]
+pub mod socket;
 #[cfg(feature = "tokio-runtime")]
 pub mod tokio;
+#[cfg(feature = "tower")]
+pub mod tower;
+
+#[cfg(feature = "test-clock")]
+pub use clock::TestClock;
+
+#[cfg(feature = "handshake")]
+pub mod error;
+#[cfg(feature = "handshake")]
+pub use error::ConnectError;
+
+pub mod config;
+pub use config::{ConfigBuilder, ConfigError};
+
+pub mod deadline;
+use deadline::Deadline;
+pub use deadline::DeadlineExceeded;
+
+pub mod ack;
+pub use ack::{AckReceiver, AckSender};
 
 pub mod bytes;
 pub use bytes::ByteReader;
 pub use bytes::ByteWriter;
+pub use bytes::FrameKind;
+
+pub mod keepalive;
+pub use keepalive::{Heartbeat, HeartbeatHandle, KeepAliveMode};
+
+pub mod proxy;
+pub use proxy::proxy;
+
+pub mod rate_limit;
+pub use rate_limit::{AcceptRateLimit, RateLimitedSender};
 
-use tungstenite::protocol::CloseFrame;
+pub mod ring;
+pub use ring::RingReceiver;
+
+use tungstenite::protocol::{
+    frame::{
+        coding::{CloseCode, Data as OpData, OpCode},
+        Frame,
+    },
+    CloseFrame,
+};
+use tungstenite::Utf8Bytes;
 
 /// Creates a WebSocket handshake from a request and a stream.
 /// For convenience, the user may call this with a url string, a URL,
@@ -107,12 +218,13 @@ use tungstenite::protocol::CloseFrame;
 /// Internally, this custom creates a handshake representation and returns
 /// a future representing the resolution of the WebSocket handshake. The
 /// returned future will resolve to either `WebSocketStream<S>` or `Error`
-/// depending on whether the handshake is successful.
+/// depending on whether the handshake is successful. Dropping the future
+/// before that, e.g. to abort a slow handshake, drops `stream` right away.
 ///
 /// This is typically used for clients who have already established, for
 /// example, a TCP connection to the remote server.
 #[cfg(feature = "handshake")]
-pub async fn client_async<'a, R, S>(
+pub async fn client_async<R, S>(
     request: R,
     stream: S,
 ) -> Result<(WebSocketStream<S>, Response), WsError>
@@ -126,27 +238,300 @@ where
 /// The same as `client_async()` but the one can specify a websocket configuration.
 /// Please refer to `client_async()` for more details.
 #[cfg(feature = "handshake")]
-pub async fn client_async_with_config<'a, R, S>(
+pub async fn client_async_with_config<R, S>(
+    request: R,
+    stream: S,
+    config: Option<WebSocketConfig>,
+) -> Result<(WebSocketStream<S>, Response), WsError>
+where
+    R: IntoClientRequest + Unpin,
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    client_async_with_hook_and_config(request, stream, |_| {}, config).await
+}
+
+/// The same as `client_async()` but `hook` can modify the request right
+/// before it is sent.
+///
+/// The hook receives the request including the headers added by
+/// [`IntoClientRequest`], e.g. `Sec-WebSocket-Key`, so it can add headers
+/// derived from them or sign the complete request.
+///
+/// ```no_run
+/// # async fn test(tcp: async_std::net::TcpStream) {
+/// use async_tungstenite::client_async_with_hook;
+///
+/// let (stream, _) = client_async_with_hook("ws://example.com/", tcp, |request| {
+///     let key = request.headers()["Sec-WebSocket-Key"].clone();
+///     request.headers_mut().insert("X-Nonce", key);
+/// })
+/// .await
+/// .unwrap();
+/// # }
+/// ```
+#[cfg(feature = "handshake")]
+pub async fn client_async_with_hook<R, S, F>(
+    request: R,
+    stream: S,
+    hook: F,
+) -> Result<(WebSocketStream<S>, Response), WsError>
+where
+    R: IntoClientRequest + Unpin,
+    S: AsyncRead + AsyncWrite + Unpin,
+    F: FnOnce(&mut tungstenite::handshake::client::Request) + Unpin,
+{
+    client_async_with_hook_and_config(request, stream, hook, None).await
+}
+
+/// The same as `client_async_with_hook()` but the one can specify a websocket
+/// configuration.
+#[cfg(feature = "handshake")]
+#[allow(clippy::result_large_err)]
+pub async fn client_async_with_hook_and_config<R, S, F>(
     request: R,
     stream: S,
+    hook: F,
     config: Option<WebSocketConfig>,
 ) -> Result<(WebSocketStream<S>, Response), WsError>
 where
     R: IntoClientRequest + Unpin,
     S: AsyncRead + AsyncWrite + Unpin,
+    F: FnOnce(&mut tungstenite::handshake::client::Request) + Unpin,
 {
     let f = handshake::client_handshake(stream, move |allow_std| {
-        let request = request.into_client_request()?;
+        let mut request = request.into_client_request()?;
+        hook(&mut request);
         let cli_handshake = ClientHandshake::start(allow_std, request, config)?;
         cli_handshake.handshake()
     });
-    f.await.map_err(|e| match e {
-        HandshakeError::Failure(e) => e,
-        e => WsError::Io(std::io::Error::new(
-            std::io::ErrorKind::Other,
-            e.to_string(),
-        )),
-    })
+    let (mut stream, response) = f.await.map_err(handshake_error)?;
+    stream.selected_protocol = selected_protocol(response.headers());
+    Ok((stream, response))
+}
+
+/// Unwraps the error of a failed handshake.
+///
+/// The handshake futures resume interrupted handshakes themselves, so an
+/// `Interrupted` error never reaches the caller; should it anyway, it is
+/// reported as the `WouldBlock` it stands for.
+#[cfg(feature = "handshake")]
+fn handshake_error<Role: HandshakeRole>(err: HandshakeError<Role>) -> WsError {
+    match err {
+        HandshakeError::Failure(err) => err,
+        HandshakeError::Interrupted(_) => WsError::Io(std::io::ErrorKind::WouldBlock.into()),
+    }
+}
+
+/// The same as `client_async()` but also returns the raw bytes of the
+/// handshake response.
+///
+/// The bytes are the status line and headers exactly as they were received,
+/// including the empty line that ends them, e.g. to replay the response in a
+/// test harness. Data that followed the response is not part of them.
+#[cfg(feature = "handshake")]
+#[allow(clippy::result_large_err)]
+pub async fn client_async_with_raw_response<R, S>(
+    request: R,
+    stream: S,
+) -> Result<(WebSocketStream<S>, Response, Vec<u8>), WsError>
+where
+    R: IntoClientRequest + Unpin,
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    let f = handshake::client_handshake(stream, move |mut allow_std| {
+        allow_std.start_recording();
+        let cli_handshake =
+            ClientHandshake::start(allow_std, request.into_client_request()?, None)?;
+        cli_handshake.handshake()
+    });
+    let (mut stream, response) = f.await.map_err(handshake_error)?;
+    stream.selected_protocol = selected_protocol(response.headers());
+    let mut raw = stream
+        .inner
+        .get_mut()
+        .take_recorded()
+        .expect("recording started");
+    if let Some(end) = raw.windows(4).position(|w| w == b"\r\n\r\n") {
+        raw.truncate(end + 4);
+    }
+    Ok((stream, response, raw))
+}
+
+/// The same as `client_async()` but follows redirects of the server.
+///
+/// If the server answers the upgrade with `301`, `302`, `307` or `308`, the
+/// handshake is retried at the `Location` of the response, which must be a
+/// `ws` or `wss` URL or an absolute path. As this crate is independent of any
+/// runtime, `stream_factory` is called to connect to every URI, including the
+/// first one. Headers of `request` are carried over, except that
+/// `Authorization` and `Cookie` are dropped unless the redirect keeps both the
+/// scheme and the host, so credentials are never sent in plaintext after a
+/// redirect from `wss` to `ws`.
+///
+/// After `max_redirects` redirects, the last redirect response is returned as
+/// an [`Http`](WsError::Http) error.
+///
+/// ```no_run
+/// # async fn test() {
+/// use async_std::net::TcpStream;
+/// use async_tungstenite::client_async_with_redirects;
+///
+/// let (stream, _) = client_async_with_redirects(
+///     "ws://example.com/",
+///     |uri| {
+///         let addr = format!("{}:{}", uri.host().unwrap(), uri.port_u16().unwrap_or(80));
+///         async move { Ok(TcpStream::connect(addr).await?) }
+///     },
+///     5,
+/// )
+/// .await
+/// .unwrap();
+/// # }
+/// ```
+#[cfg(feature = "handshake")]
+pub async fn client_async_with_redirects<R, S, F, Fut>(
+    request: R,
+    mut stream_factory: F,
+    max_redirects: usize,
+) -> Result<(WebSocketStream<S>, Response), WsError>
+where
+    R: IntoClientRequest + Unpin,
+    S: AsyncRead + AsyncWrite + Unpin,
+    F: FnMut(&tungstenite::http::Uri) -> Fut,
+    Fut: Future<Output = Result<S, WsError>>,
+{
+    let mut request = request.into_client_request()?;
+    let mut redirects = 0;
+    loop {
+        let stream = stream_factory(request.uri()).await?;
+        match client_async(request.clone(), stream).await {
+            Err(WsError::Http(response)) if redirects < max_redirects => {
+                match redirect_request(&request, &response)? {
+                    Some(next) => {
+                        debug!("Following redirect to {}", next.uri());
+                        request = next;
+                        redirects += 1;
+                    }
+                    None => return Err(WsError::Http(response)),
+                }
+            }
+            res => return res,
+        }
+    }
+}
+
+/// Returns the request for following a redirect response of the server, if
+/// `response` is one.
+#[cfg(feature = "handshake")]
+#[allow(clippy::result_large_err)]
+fn redirect_request(
+    request: &tungstenite::handshake::client::Request,
+    response: &Response,
+) -> Result<Option<tungstenite::handshake::client::Request>, WsError> {
+    use tungstenite::http::{header, StatusCode, Uri};
+
+    match response.status() {
+        StatusCode::MOVED_PERMANENTLY
+        | StatusCode::FOUND
+        | StatusCode::TEMPORARY_REDIRECT
+        | StatusCode::PERMANENT_REDIRECT => {}
+        _ => return Ok(None),
+    }
+    let location = match response
+        .headers()
+        .get(header::LOCATION)
+        .and_then(|location| location.to_str().ok())
+    {
+        Some(location) => location,
+        None => return Ok(None),
+    };
+
+    let location: Uri = location
+        .parse()
+        .map_err(|e| WsError::HttpFormat(tungstenite::http::Error::from(e)))?;
+    let uri = if location.scheme().is_some() {
+        location
+    } else {
+        // An absolute path on the same host.
+        let mut parts = request.uri().clone().into_parts();
+        parts.path_and_query = location.into_parts().path_and_query;
+        Uri::from_parts(parts).map_err(|e| WsError::HttpFormat(e.into()))?
+    };
+    match uri.scheme_str() {
+        Some("ws") | Some("wss") => {}
+        _ => {
+            return Err(WsError::Url(
+                tungstenite::error::UrlError::UnsupportedUrlScheme,
+            ))
+        }
+    }
+
+    // Credentials are only sent to the same origin, and never in plaintext
+    // after a downgrade from wss to ws.
+    let same_origin =
+        uri.scheme() == request.uri().scheme() && uri.authority() == request.uri().authority();
+    let mut next = uri.into_client_request()?;
+    for name in request.headers().keys() {
+        let skip = name == header::HOST
+            || name == header::SEC_WEBSOCKET_KEY
+            || (!same_origin && (name == header::AUTHORIZATION || name == header::COOKIE));
+        if skip {
+            continue;
+        }
+        // Replaces the generated value, but keeps every value of a repeated
+        // header.
+        next.headers_mut().remove(name);
+        for value in request.headers().get_all(name) {
+            next.headers_mut().append(name, value.clone());
+        }
+    }
+    Ok(Some(next))
+}
+
+/// Returns the subprotocol in the `Sec-WebSocket-Protocol` header of a
+/// handshake response.
+#[cfg(feature = "handshake")]
+fn selected_protocol(headers: &tungstenite::http::HeaderMap) -> Option<String> {
+    let protocol = headers.get(tungstenite::http::header::SEC_WEBSOCKET_PROTOCOL)?;
+    protocol.to_str().ok().map(str::to_owned)
+}
+
+/// The same as `client_async()` but fails if the handshake did not complete
+/// before `timer` resolves.
+///
+/// `timer` is a future of the runtime in use, see [timers](crate#timers). On
+/// timeout, `stream` is dropped and an
+/// [`Io`](WsError::Io) error of kind
+/// [`TimedOut`](std::io::ErrorKind::TimedOut) is returned.
+#[cfg(feature = "handshake")]
+pub async fn client_async_with_timeout<R, S, T>(
+    request: R,
+    stream: S,
+    timer: T,
+) -> Result<(WebSocketStream<S>, Response), WsError>
+where
+    R: IntoClientRequest + Unpin,
+    S: AsyncRead + AsyncWrite + Unpin,
+    T: Future<Output = ()>,
+{
+    handshake_timeout(client_async(request, stream), timer).await
+}
+
+/// Checks that the `Sec-WebSocket-Accept` header of a handshake response
+/// matches the `Sec-WebSocket-Key` that was sent with the request.
+///
+/// tungstenite already performs this check during the handshake, so this is
+/// only useful for additional verification or to diagnose proxies that rewrite
+/// the header. The received value is available via
+/// `response.headers().get("Sec-WebSocket-Accept")`, the expected one via
+/// [`tungstenite::handshake::derive_accept_key`].
+#[cfg(feature = "handshake")]
+pub fn verify_accept(response: &Response, sent_key: &str) -> bool {
+    let expected = tungstenite::handshake::derive_accept_key(sent_key.as_bytes());
+    response
+        .headers()
+        .get("Sec-WebSocket-Accept")
+        .map_or(false, |accept| accept.as_bytes() == expected.as_bytes())
 }
 
 /// Accepts a new WebSocket connection with the provided stream.
@@ -168,6 +553,40 @@ where
     accept_hdr_async(stream, NoCallback).await
 }
 
+/// The same as `accept_async()` but fails if the handshake did not complete
+/// before `timer` resolves.
+///
+/// Servers accepting untrusted connections should use this, as a peer that
+/// stalls mid-handshake otherwise holds the connection forever. See
+/// [`client_async_with_timeout`] for details.
+#[cfg(feature = "handshake")]
+pub async fn accept_async_with_timeout<S, T>(
+    stream: S,
+    timer: T,
+) -> Result<WebSocketStream<S>, WsError>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+    T: Future<Output = ()>,
+{
+    handshake_timeout(accept_async(stream), timer).await
+}
+
+/// Races `handshake` against `timer`.
+#[cfg(feature = "handshake")]
+#[allow(clippy::result_large_err)]
+async fn handshake_timeout<F, T, R>(handshake: F, timer: T) -> Result<R, WsError>
+where
+    F: Future<Output = Result<R, WsError>>,
+    T: Future<Output = ()>,
+{
+    deadline::race(handshake, timer).await.unwrap_or_else(|| {
+        Err(WsError::Io(std::io::Error::new(
+            std::io::ErrorKind::TimedOut,
+            "handshake timed out",
+        )))
+    })
+}
+
 /// The same as `accept_async()` but the one can specify a websocket configuration.
 /// Please refer to `accept_async()` for more details.
 #[cfg(feature = "handshake")]
@@ -207,16 +626,97 @@ where
     S: AsyncRead + AsyncWrite + Unpin,
     C: Callback + Unpin,
 {
+    accept_hdr_async_with_request(stream, callback, config)
+        .await
+        .map(|(stream, _)| stream)
+}
+
+/// The same as `accept_async()` but also returns the handshake request.
+///
+/// The request carries everything the client sent besides the body, e.g. the
+/// path and query of its URI or its `Origin` header.
+///
+/// ```no_run
+/// # async fn test(tcp: async_std::net::TcpStream) {
+/// use async_tungstenite::accept_async_with_request;
+///
+/// let (stream, request) = accept_async_with_request(tcp).await.unwrap();
+/// println!("{} from {:?}", request.uri().path(), request.headers().get("Origin"));
+/// # }
+/// ```
+#[cfg(feature = "handshake")]
+pub async fn accept_async_with_request<S>(
+    stream: S,
+) -> Result<(WebSocketStream<S>, tungstenite::handshake::server::Request), WsError>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    accept_hdr_async_with_request(stream, NoCallback, None).await
+}
+
+/// The same as `accept_hdr_async_with_config()` but also returns the
+/// handshake request.
+#[cfg(feature = "handshake")]
+#[allow(clippy::result_large_err)]
+async fn accept_hdr_async_with_request<S, C>(
+    stream: S,
+    callback: C,
+    config: Option<WebSocketConfig>,
+) -> Result<(WebSocketStream<S>, tungstenite::handshake::server::Request), WsError>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+    C: Callback + Unpin,
+{
+    let recorded = Arc::new(Mutex::new(None));
+    let callback = RecordHeaders {
+        callback,
+        recorded: recorded.clone(),
+    };
     let f = handshake::server_handshake(stream, move |allow_std| {
         tungstenite::accept_hdr_with_config(allow_std, callback, config)
     });
-    f.await.map_err(|e| match e {
-        HandshakeError::Failure(e) => e,
-        e => WsError::Io(std::io::Error::new(
-            std::io::ErrorKind::Other,
-            e.to_string(),
-        )),
-    })
+    let mut stream = f.await.map_err(handshake_error)?;
+    let (request, protocol) = recorded
+        .lock()
+        .expect("lock request")
+        .take()
+        .expect("request recorded by callback");
+    stream.request_headers = Some(request.headers().clone());
+    stream.selected_protocol = protocol;
+    Ok((stream, request))
+}
+
+/// Remembers the request and the subprotocol selected by `callback`.
+#[cfg(feature = "handshake")]
+struct RecordHeaders<C> {
+    callback: C,
+    recorded: Arc<Mutex<Option<Recorded>>>,
+}
+
+/// The request and the selected subprotocol.
+#[cfg(feature = "handshake")]
+type Recorded = (tungstenite::handshake::server::Request, Option<String>);
+
+#[cfg(feature = "handshake")]
+impl<C: Callback> Callback for RecordHeaders<C> {
+    fn on_request(
+        self,
+        request: &tungstenite::handshake::server::Request,
+        response: tungstenite::handshake::server::Response,
+    ) -> Result<
+        tungstenite::handshake::server::Response,
+        tungstenite::handshake::server::ErrorResponse,
+    > {
+        let mut copy = tungstenite::handshake::server::Request::new(());
+        *copy.method_mut() = request.method().clone();
+        *copy.uri_mut() = request.uri().clone();
+        *copy.version_mut() = request.version();
+        *copy.headers_mut() = request.headers().clone();
+        let response = self.callback.on_request(request, response)?;
+        let protocol = selected_protocol(response.headers());
+        *self.recorded.lock().expect("lock request") = Some((copy, protocol));
+        Ok(response)
+    }
 }
 
 /// A wrapper around an underlying raw stream which implements the WebSocket
@@ -231,7 +731,10 @@ where
 #[derive(Debug)]
 pub struct WebSocketStream<S> {
     inner: WebSocket<AllowStd<S>>,
-    #[cfg(feature = "futures-03-sink")]
+    /// Whether this is the client or the server side, tungstenite doesn't
+    /// expose it.
+    role: Role,
+    /// A Close frame was queued by `poll_close`, which now only flushes it.
     closing: bool,
     ended: bool,
     /// Tungstenite is probably ready to receive more data.
@@ -239,6 +742,51 @@ pub struct WebSocketStream<S> {
     /// `false` once start_send hits `WouldBlock` errors.
     /// `true` initially and after `flush`ing.
     ready: bool,
+    /// Whether data messages received after we sent a Close frame, but before
+    /// the peer acknowledged it, are passed on to the user.
+    deliver_after_close: bool,
+    /// Whether a stream ending without TLS `close_notify` during the close
+    /// handshake is reported as an error.
+    strict_tls_close: bool,
+    lossy_close_reasons: bool,
+    /// Our reply to a Close frame with an invalid reason is being flushed.
+    lossy_closing: bool,
+    /// The peer started the close handshake.
+    closed_by_peer: bool,
+    /// The last Close frame received from the peer.
+    close_frame: Option<CloseFrame>,
+    /// Message returned by `peek` that `poll_next` returns next.
+    peeked: Option<Message>,
+    ignore_orphan_continuations: bool,
+    auto_pong: bool,
+    yield_pings: bool,
+    recv_deadline: Option<Deadline>,
+    send_deadline: Option<Deadline>,
+    /// Largest data message the peer accepts.
+    max_send_size: Option<usize>,
+    round_trip: telemetry::RoundTrip,
+    /// Headers of the handshake request if this is the server side.
+    #[cfg(feature = "handshake")]
+    request_headers: Option<tungstenite::http::HeaderMap>,
+    /// Subprotocol agreed on in the handshake.
+    #[cfg(feature = "handshake")]
+    selected_protocol: Option<String>,
+    /// Time of the last message that was sent or received.
+    last_activity: Instant,
+    /// Number of Pong frames received.
+    pongs_received: u64,
+    /// Maximum number of unanswered pings before the peer is considered dead.
+    pong_deadline: Option<u32>,
+    /// A fragmented message was started but not finished, see
+    /// `start_send_fragment`.
+    unfinished_message: bool,
+    /// Pings sent since the last Pong was received.
+    unanswered_pings: u32,
+    /// Recognizes replies to a heartbeat.
+    heartbeat_reply: Option<keepalive::ReplyMatcher>,
+    /// Number of heartbeat replies received.
+    heartbeat_replies: u64,
+    clock: Clock,
 }
 
 impl<S> WebSocketStream<S> {
@@ -248,7 +796,7 @@ impl<S> WebSocketStream<S> {
     where
         S: AsyncRead + AsyncWrite + Unpin,
     {
-        handshake::without_handshake(stream, move |allow_std| {
+        handshake::without_handshake(stream, role, move |allow_std| {
             WebSocket::from_raw_socket(allow_std, role, config)
         })
         .await
@@ -265,19 +813,68 @@ impl<S> WebSocketStream<S> {
     where
         S: AsyncRead + AsyncWrite + Unpin,
     {
-        handshake::without_handshake(stream, move |allow_std| {
-            WebSocket::from_partially_read(allow_std, part, role, config)
+        // Passing `part` on through the stream lets frame tracking see it.
+        handshake::without_handshake(stream, role, move |mut allow_std| {
+            allow_std.set_unread(part);
+            WebSocket::from_raw_socket(allow_std, role, config)
         })
         .await
     }
 
-    pub(crate) fn new(ws: WebSocket<AllowStd<S>>) -> Self {
+    /// Convert a raw socket into a server side WebSocketStream without
+    /// performing a handshake, using `headers` as the headers of the
+    /// handshake request.
+    ///
+    /// This is meant for testing server logic that depends on
+    /// [`request_headers`](Self::request_headers) with an in-memory stream,
+    /// or for connections whose handshake was performed elsewhere.
+    #[cfg(feature = "handshake")]
+    pub async fn from_raw_socket_with_request_headers(
+        stream: S,
+        headers: tungstenite::http::HeaderMap,
+        config: Option<WebSocketConfig>,
+    ) -> Self
+    where
+        S: AsyncRead + AsyncWrite + Unpin,
+    {
+        let mut stream = Self::from_raw_socket(stream, Role::Server, config).await;
+        stream.request_headers = Some(headers);
+        stream
+    }
+
+    pub(crate) fn new(ws: WebSocket<AllowStd<S>>, role: Role) -> Self {
         Self {
             inner: ws,
-            #[cfg(feature = "futures-03-sink")]
+            role,
             closing: false,
             ended: false,
             ready: true,
+            deliver_after_close: true,
+            strict_tls_close: false,
+            lossy_close_reasons: false,
+            lossy_closing: false,
+            closed_by_peer: false,
+            close_frame: None,
+            peeked: None,
+            ignore_orphan_continuations: false,
+            auto_pong: false,
+            yield_pings: false,
+            recv_deadline: None,
+            send_deadline: None,
+            max_send_size: None,
+            round_trip: Default::default(),
+            #[cfg(feature = "handshake")]
+            request_headers: None,
+            #[cfg(feature = "handshake")]
+            selected_protocol: None,
+            last_activity: Instant::now(),
+            pongs_received: 0,
+            pong_deadline: None,
+            unfinished_message: false,
+            unanswered_pings: 0,
+            heartbeat_reply: None,
+            heartbeat_replies: 0,
+            clock: Clock::default(),
         }
     }
 
@@ -294,6 +891,54 @@ impl<S> WebSocketStream<S> {
         f(&mut self.inner)
     }
 
+    /// Calls `f` with the underlying tungstenite [`WebSocket`], e.g. to use
+    /// functionality that is not exposed by this type.
+    ///
+    /// `f` runs without a task context. Reads and writes inside of it don't
+    /// wait but fail with a [`WouldBlock`](std::io::ErrorKind::WouldBlock)
+    /// error, and the wakeup for them goes to the task that last polled the
+    /// stream, so they must be retried by polling the stream instead, e.g. by
+    /// flushing it after [`WebSocket::write`]. Messages read by `f` are not
+    /// seen by the [`Stream`] implementation. If `f` completed the close
+    /// handshake, the stream ends on its next poll.
+    ///
+    /// ```
+    /// # async fn test<S>(mut stream: async_tungstenite::WebSocketStream<S>)
+    /// # where S: futures::AsyncRead + futures::AsyncWrite + Unpin {
+    /// use futures::SinkExt;
+    /// use tungstenite::Message;
+    ///
+    /// // Queue a message without flushing, then flush it.
+    /// stream.with_raw(|ws| ws.write(Message::text("hello"))).unwrap();
+    /// stream.flush().await.unwrap();
+    /// # }
+    /// ```
+    pub fn with_raw<R>(&mut self, f: impl FnOnce(&mut WebSocket<AllowStd<S>>) -> R) -> R {
+        let res = f(&mut self.inner);
+        // `f` may have queued data, so flush before assuming that more fits.
+        self.ready = false;
+        res
+    }
+
+    /// Returns the headers of the handshake request.
+    ///
+    /// Only available on the server side of connections accepted by this
+    /// crate, `None` otherwise.
+    #[cfg(feature = "handshake")]
+    pub fn request_headers(&self) -> Option<&tungstenite::http::HeaderMap> {
+        self.request_headers.as_ref()
+    }
+
+    /// Returns the subprotocol that was agreed on in the handshake, i.e. the
+    /// `Sec-WebSocket-Protocol` header of the handshake response.
+    ///
+    /// `None` if no subprotocol was selected or the connection was not
+    /// established by a handshake of this crate.
+    #[cfg(feature = "handshake")]
+    pub fn selected_protocol(&self) -> Option<&str> {
+        self.selected_protocol.as_deref()
+    }
+
     /// Returns a shared reference to the inner stream.
     pub fn get_ref(&self) -> &S
     where
@@ -315,131 +960,875 @@ impl<S> WebSocketStream<S> {
         self.inner.get_config()
     }
 
-    /// Close the underlying web socket
-    pub async fn close(&mut self, msg: Option<CloseFrame>) -> Result<(), WsError>
-    where
-        S: AsyncRead + AsyncWrite + Unpin,
-    {
-        self.send(Message::Close(msg)).await
+    /// Changes the configuration of the tungstenite stream, e.g. to raise the
+    /// limits for a peer that turned out to be trusted.
+    ///
+    /// Changes to [`write_buffer_size`](WebSocketConfig::write_buffer_size)
+    /// and [`max_write_buffer_size`](WebSocketConfig::max_write_buffer_size)
+    /// apply to the next message that is sent.
+    /// [`max_frame_size`](WebSocketConfig::max_frame_size) and
+    /// [`accept_unmasked_frames`](WebSocketConfig::accept_unmasked_frames)
+    /// apply from the next frame that is read, and
+    /// [`max_message_size`](WebSocketConfig::max_message_size) also to the
+    /// rest of a fragmented message that is partially read.
+    /// [`read_buffer_size`](WebSocketConfig::read_buffer_size) has no effect
+    /// after the stream was created.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the resulting configuration is invalid, e.g. if
+    /// `max_write_buffer_size` is not larger than `write_buffer_size`.
+    pub fn set_config(&mut self, f: impl FnOnce(&mut WebSocketConfig)) {
+        self.inner.set_config(f);
     }
 
-    /// Splits the websocket stream into separate
-    /// [sender](WebSocketSender) and [receiver](WebSocketReceiver) parts.
-    pub fn split(self) -> (WebSocketSender<S>, WebSocketReceiver<S>) {
-        let shared = Arc::new(Shared(Mutex::new(self)));
-        let sender = WebSocketSender {
-            shared: shared.clone(),
-        };
+    /// Returns how full the write buffer is, from `0.0` to `1.0`.
+    ///
+    /// This is the number of bytes that were queued but not written to the
+    /// underlying stream yet, relative to the
+    /// [`max_write_buffer_size`](WebSocketConfig::max_write_buffer_size).
+    /// It includes what is held back until
+    /// [`write_buffer_size`](WebSocketConfig::write_buffer_size) is reached,
+    /// but not the control frames that tungstenite queues on its own, e.g.
+    /// replies to pings.
+    ///
+    /// Sending fails with [`WriteBufferFull`](WsError::WriteBufferFull) if a
+    /// message doesn't fit into the rest of the buffer, so producers can slow
+    /// down as this approaches `1.0`. With the default unlimited buffer this
+    /// stays close to `0.0`.
+    pub fn write_buffer_occupancy(&self) -> f32 {
+        let max = self.inner.get_config().max_write_buffer_size;
+        if max == 0 {
+            return 1.0;
+        }
+        let pending = self.inner.get_ref().pending();
+        (pending as f64 / max as f64).min(1.0) as f32
+    }
 
-        let receiver = WebSocketReceiver { shared };
-        (sender, receiver)
+    /// Returns the last Close frame received from the peer.
+    ///
+    /// This stays available after the stream ended, e.g. to log the close
+    /// code and reason once the receive loop exited. `None` if the peer
+    /// didn't send a Close frame, e.g. because the connection was dropped, or
+    /// if its Close frame carried no code.
+    pub fn close_frame(&self) -> Option<&CloseFrame> {
+        self.close_frame.as_ref()
     }
 
-    /// Attempts to reunite the [sender](WebSocketSender) and [receiver](WebSocketReceiver)
-    /// parts back into a single stream. If both parts originate from the same
-    /// [`split`](WebSocketStream::split) call, returns `Ok` with the original stream.
-    /// Otherwise, returns `Err` containing the provided parts.
-    pub fn reunite(
-        sender: WebSocketSender<S>,
-        receiver: WebSocketReceiver<S>,
-    ) -> Result<Self, (WebSocketSender<S>, WebSocketReceiver<S>)> {
-        if sender.is_pair_of(&receiver) {
-            drop(receiver);
-            let stream = Arc::try_unwrap(sender.shared)
-                .ok()
-                .expect("reunite the stream")
-                .into_inner();
-
-            Ok(stream)
+    /// Returns whether the connection is open, closing or closed.
+    ///
+    /// The connection is closing as soon as either side sent a Close frame,
+    /// from then on no more messages can be sent. It is closed once the
+    /// stream ended, i.e. after the close handshake completed or the
+    /// connection failed.
+    pub fn state(&self) -> ConnectionState {
+        if self.ended {
+            ConnectionState::Closed
+        } else if !self.inner.can_write() {
+            ConnectionState::Closing
         } else {
-            Err((sender, receiver))
+            ConnectionState::Open
         }
     }
-}
 
-impl<S> WebSocketStream<S>
-where
-    S: AsyncRead + AsyncWrite + Unpin,
-{
-    fn poll_next(&mut self, cx: &mut Context<'_>) -> Poll<Option<Result<Message, WsError>>> {
-        #[cfg(feature = "verbose-logging")]
-        trace!("{}:{} WebSocketStream.poll_next", file!(), line!());
+    /// Returns the counters of the messages and bytes this connection
+    /// transferred.
+    ///
+    /// The counters are shared with the halves of a [split](Self::split)
+    /// stream.
+    pub fn metrics(&self) -> &ConnectionMetrics {
+        self.inner.get_ref().metrics()
+    }
 
-        // The connection has been closed or a critical error has occurred.
-        // We have already returned the error to the user, the `Stream` is unusable,
-        // so we assume that the stream has been "fused".
-        if self.ended {
-            return Poll::Ready(None);
-        }
+    /// Adopts the maximum message size the peer advertised in the handshake
+    /// response header `header`.
+    ///
+    /// Our [`max_message_size`](WebSocketConfig::max_message_size) is lowered
+    /// to the peer's limit if that is smaller, and sending a text or binary
+    /// message larger than the peer's limit fails with
+    /// [`CapacityError::MessageTooLong`] instead of sending a message the
+    /// peer would reject. Returns the peer's limit, or `None` if the header is
+    /// missing or not a number, in which case nothing changes.
+    #[cfg(feature = "handshake")]
+    pub fn respect_peer_max_message_size(
+        &mut self,
+        response: &Response,
+        header: &str,
+    ) -> Option<usize> {
+        let max = response
+            .headers()
+            .get(header)?
+            .to_str()
+            .ok()?
+            .trim()
+            .parse()
+            .ok()?;
+        self.inner.set_config(|config| {
+            config.max_message_size = Some(config.max_message_size.map_or(max, |own| own.min(max)));
+        });
+        self.max_send_size = Some(max);
+        Some(max)
+    }
 
-        match ready!(self.with_context(Some((ContextWaker::Read, cx)), |s| {
-            #[cfg(feature = "verbose-logging")]
-            trace!(
-                "{}:{} WebSocketStream.with_context poll_next -> read()",
-                file!(),
-                line!()
-            );
-            cvt(s.read())
-        })) {
-            Ok(v) => Poll::Ready(Some(Ok(v))),
-            Err(e) => {
-                self.ended = true;
-                if matches!(e, WsError::AlreadyClosed | WsError::ConnectionClosed) {
-                    Poll::Ready(None)
-                } else {
-                    Poll::Ready(Some(Err(e)))
-                }
-            }
-        }
+    /// Sets whether data messages that arrive after we sent a Close frame are
+    /// delivered.
+    ///
+    /// After sending a Close frame the peer may still send data until it
+    /// replies with its own Close frame. By default these messages are
+    /// yielded by the stream as usual, as RFC 6455 allows. When set to
+    /// `false`, text and binary messages received in this window are
+    /// discarded while control messages, including the peer's Close frame,
+    /// are still yielded.
+    pub fn set_deliver_after_close(&mut self, deliver: bool) {
+        self.deliver_after_close = deliver;
     }
 
-    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), WsError>> {
-        if self.ready {
-            return Poll::Ready(Ok(()));
-        }
+    /// Returns whether data messages that arrive after we sent a Close frame
+    /// are delivered.
+    ///
+    /// See [`set_deliver_after_close`](Self::set_deliver_after_close).
+    pub fn deliver_after_close(&self) -> bool {
+        self.deliver_after_close
+    }
 
-        // Currently blocked so try to flush the blockage away
-        self.with_context(Some((ContextWaker::Write, cx)), |s| cvt(s.flush()))
-            .map(|r| {
-                self.ready = true;
-                r
-            })
+    /// Sets whether a TLS connection ending without `close_notify` during the
+    /// close handshake is reported as an error.
+    ///
+    /// Some TLS implementations, e.g. rustls, report a peer closing the TCP
+    /// connection without sending `close_notify` as an
+    /// [`UnexpectedEof`](std::io::ErrorKind::UnexpectedEof) error. Many peers
+    /// do that after the WebSocket close handshake, so by default this is
+    /// treated as a normal end of the stream once either side sent a Close
+    /// frame. Enable strict mode to get the error instead, e.g. to detect
+    /// truncation attacks. Outside of the close handshake the error is always
+    /// returned.
+    pub fn set_strict_tls_close(&mut self, strict: bool) {
+        self.strict_tls_close = strict;
     }
 
-    fn start_send(&mut self, item: Message) -> Result<(), WsError> {
-        match self.with_context(None, |s| s.write(item)) {
-            Ok(()) => {
-                self.ready = true;
-                Ok(())
-            }
-            Err(WsError::Io(err)) if err.kind() == std::io::ErrorKind::WouldBlock => {
-                // the message was accepted and queued so not an error
-                // but `poll_ready` will now start trying to flush the block
-                self.ready = false;
-                Ok(())
+    /// Sets whether Pings are answered right away without involving the
+    /// application.
+    ///
+    /// tungstenite always queues a Pong for every Ping, but only writes it with
+    /// the next read or write, so an application that doesn't read again
+    /// soon after a Ping delays the Pong. With auto-pong the Pong is flushed
+    /// as soon as the Ping is read, and the Ping is not yielded by the stream
+    /// unless [`set_yield_pings`](Self::set_yield_pings) is enabled.
+    pub fn set_auto_pong(&mut self, auto_pong: bool) {
+        self.auto_pong = auto_pong;
+    }
+
+    /// Sets whether Pings are yielded by the stream with
+    /// [auto-pong](Self::set_auto_pong) enabled.
+    ///
+    /// Without auto-pong Pings are always yielded. Defaults to `false`.
+    pub fn set_yield_pings(&mut self, yield_pings: bool) {
+        self.yield_pings = yield_pings;
+    }
+
+    /// Sets whether Close frames whose reason is not valid UTF-8 are accepted.
+    ///
+    /// The protocol requires failing the connection in that case, so by
+    /// default the stream yields a [`Utf8`](WsError::Utf8) error and a normal
+    /// disconnect turns into an error. In lossy mode the reason is decoded
+    /// with replacement characters instead, the Close frame is answered and
+    /// the stream yields it as [`Message::Close`] before it ends.
+    ///
+    /// Like [`set_max_fragments_per_message`](Self::set_max_fragments_per_message),
+    /// this should be set before reading from the stream.
+    pub fn set_lossy_close_reasons(&mut self, lossy: bool) {
+        self.lossy_close_reasons = lossy;
+        self.inner.get_mut().frames_mut().keep_close = lossy;
+    }
+
+    /// Sets whether continuation frames that don't continue a message are
+    /// silently dropped.
+    ///
+    /// By default such an orphan continuation frame is a protocol violation
+    /// and the stream yields a
+    /// [`UnexpectedContinueFrame`](tungstenite::error::ProtocolError::UnexpectedContinueFrame)
+    /// error as required by RFC 6455. Enabling this allows interoperating with
+    /// broken peers that send them.
+    pub fn set_ignore_orphan_continuations(&mut self, ignore: bool) {
+        self.ignore_orphan_continuations = ignore;
+    }
+
+    /// Sets the maximum number of frames a single incoming message may consist
+    /// of.
+    ///
+    /// This protects against peers splitting messages into a huge number of
+    /// tiny frames, which the size limits of the [`WebSocketConfig`] don't
+    /// catch. If a message exceeds the limit, the connection is closed with
+    /// code 1009 (message too big) and the stream yields an
+    /// [`InvalidData`](std::io::ErrorKind::InvalidData) error. `None`, the
+    /// default, means no limit.
+    ///
+    /// The frames are only followed once one of the frame based limits is
+    /// set. If anything was read from the underlying stream before, they
+    /// can't be followed and the limits are not enforced, so this should be
+    /// set before reading from the stream.
+    pub fn set_max_fragments_per_message(&mut self, max: Option<usize>) {
+        self.inner.get_mut().frames_mut().max_fragments = max;
+    }
+
+    /// Limits the number of pings the peer may send to `max` per `window`.
+    ///
+    /// Every ping is answered with a pong, so a peer flooding the connection
+    /// with pings makes us send just as many pongs. If the peer exceeds the
+    /// limit, the ping is not answered, the connection is closed with code
+    /// 1008 (policy violation) and the stream yields an
+    /// [`InvalidData`](std::io::ErrorKind::InvalidData) error. `None`, the
+    /// default, means no limit.
+    ///
+    /// Like [`set_max_fragments_per_message`](Self::set_max_fragments_per_message),
+    /// this should be set before reading from the stream.
+    pub fn set_ping_limit(&mut self, limit: Option<(u32, Duration)>) {
+        self.inner.get_mut().frames_mut().ping_limit =
+            limit.map(|(max, window)| frames::PingLimit::new(max, window, self.clock.clone()));
+    }
+
+    /// Makes the stream fail once more than `max_missed` pings we sent went
+    /// unanswered.
+    ///
+    /// Every ping sent on this stream, also through the
+    /// [sender](WebSocketSender) after [`split`](Self::split), is counted
+    /// until a Pong is received. Any Pong resets the count, including
+    /// unsolicited ones, as they show that the peer is alive as well. The
+    /// ping that was sent last is counted while it is in flight, so
+    /// `max_missed` should be at least 1. Once the limit is exceeded, the
+    /// stream yields a [`TimedOut`](std::io::ErrorKind::TimedOut) error and
+    /// ends.
+    ///
+    /// Pongs are only received while the stream is read.
+    pub fn set_pong_deadline(&mut self, max_missed: u32) {
+        self.pong_deadline = Some(max_missed);
+    }
+
+    /// Sets a callback that decides whether an incoming message is accepted
+    /// before its payload is buffered.
+    ///
+    /// The callback is called with the type and the first frame's length once
+    /// the header of the first frame of a message is read, which allows
+    /// rejecting e.g. fragmented or large messages depending on the
+    /// application's state, beyond the static limits of the
+    /// [`WebSocketConfig`]. If it returns `false`, the connection is closed with
+    /// code 1008 (policy violation) and the stream yields an
+    /// [`InvalidData`](std::io::ErrorKind::InvalidData) error.
+    ///
+    /// Like [`set_max_fragments_per_message`](Self::set_max_fragments_per_message),
+    /// this should be set before reading from the stream.
+    pub fn set_message_admission<F>(&mut self, admission: F)
+    where
+        F: FnMut(&MessageStart) -> bool + std::marker::Send + 'static,
+    {
+        self.inner.get_mut().frames_mut().admission = Some(frames::Admission::new(admission));
+    }
+
+    /// Sets a deadline for receiving the next message.
+    ///
+    /// Once `timer` resolves while no message was received, the stream yields
+    /// an error for which [`DeadlineExceeded::of`] returns
+    /// [`Recv`](DeadlineExceeded::Recv). The deadline is cleared when a
+    /// message arrives or it passed, the stream stays usable in both cases.
+    ///
+    /// `timer` is a future of the runtime in use, see [timers](crate#timers).
+    pub fn set_recv_deadline<F>(&mut self, timer: F)
+    where
+        F: Future<Output = ()> + std::marker::Send + 'static,
+    {
+        self.recv_deadline = Some(Deadline::new(timer));
+    }
+
+    /// Clears the deadline set by [`set_recv_deadline`](Self::set_recv_deadline).
+    pub fn clear_recv_deadline(&mut self) {
+        self.recv_deadline = None;
+    }
+
+    /// Sets a deadline for flushing messages that are sent.
+    ///
+    /// Once `timer` resolves while flushing still waits for the underlying
+    /// stream, the flush fails with an error for which
+    /// [`DeadlineExceeded::of`] returns [`Send`](DeadlineExceeded::Send). The
+    /// deadline is cleared when a flush completes or it passed.
+    ///
+    /// See [`set_recv_deadline`](Self::set_recv_deadline) for the `timer`.
+    pub fn set_send_deadline<F>(&mut self, timer: F)
+    where
+        F: Future<Output = ()> + std::marker::Send + 'static,
+    {
+        self.send_deadline = Some(Deadline::new(timer));
+    }
+
+    /// Clears the deadline set by [`set_send_deadline`](Self::set_send_deadline).
+    pub fn clear_send_deadline(&mut self) {
+        self.send_deadline = None;
+    }
+
+    /// Returns the time since the last message was sent or received.
+    ///
+    /// Control messages like Ping and Pong count as activity too. This can be
+    /// used to find and close idle connections.
+    pub fn idle_duration(&self) -> Duration {
+        self.clock
+            .now()
+            .saturating_duration_since(self.last_activity)
+    }
+
+    /// Installs a [`TestClock`] as time source for this stream.
+    ///
+    /// All time dependent behaviour of the stream, e.g.
+    /// [`idle_duration`](Self::idle_duration), is based on this clock
+    /// afterwards.
+    #[cfg(feature = "test-clock")]
+    pub fn set_clock(&mut self, clock: TestClock) {
+        self.last_activity = clock.now();
+        self.clock = Clock::Test(clock);
+        if let Some(ref mut limit) = self.inner.get_mut().frames_mut().ping_limit {
+            limit.clock = self.clock.clone();
+        }
+    }
+
+    /// Close the underlying web socket
+    pub async fn close(&mut self, msg: Option<CloseFrame>) -> Result<(), WsError>
+    where
+        S: AsyncRead + AsyncWrite + Unpin,
+    {
+        self.send(Message::Close(msg)).await
+    }
+
+    /// Closes the connection on a best-effort basis, consuming the stream.
+    ///
+    /// Unlike [`close`](Self::close) this never fails, which makes it
+    /// suitable for cleanup paths where errors can't be propagated. This is
+    /// [`close_gracefully`](Self::close_gracefully) without a close reason,
+    /// except that failures are only logged.
+    ///
+    /// `timer` is a future of the runtime in use, see [timers](crate#timers).
+    pub async fn finalize<T>(mut self, timer: T)
+    where
+        S: AsyncRead + AsyncWrite + Unpin,
+        T: Future<Output = ()>,
+    {
+        if let Err(err) = self.close_gracefully(None, timer).await {
+            debug!("websocket finalize error: {}", err);
+        }
+    }
+
+    /// Closes the connection and waits for the close handshake to complete.
+    ///
+    /// Sends a Close frame with `msg` and reads until the peer replied with
+    /// its Close frame and the connection ended. Messages the peer sent before
+    /// it saw our Close frame are discarded. Returns `Ok(())` on a clean close,
+    /// also if the peer started closing first, and an
+    /// [`Io`](WsError::Io) error of kind
+    /// [`TimedOut`](std::io::ErrorKind::TimedOut) if `timer` resolves first.
+    ///
+    /// `timer` is a future of the runtime in use, see [timers](crate#timers).
+    #[allow(clippy::result_large_err)]
+    pub async fn close_gracefully<T>(
+        &mut self,
+        msg: Option<CloseFrame>,
+        timer: T,
+    ) -> Result<(), WsError>
+    where
+        S: AsyncRead + AsyncWrite + Unpin,
+        T: Future<Output = ()>,
+    {
+        let close = async {
+            match self.close(msg).await {
+                Ok(()) | Err(WsError::ConnectionClosed) => {}
+                Err(err) => return Err(err),
+            }
+            while let Some(res) = std::future::poll_fn(|cx| self.poll_next(cx)).await {
+                res?;
+            }
+            Ok(())
+        };
+        deadline::race(close, timer).await.unwrap_or_else(|| {
+            Err(WsError::Io(std::io::Error::new(
+                std::io::ErrorKind::TimedOut,
+                "close handshake timed out",
+            )))
+        })
+    }
+
+    /// Receives the next text or binary message.
+    ///
+    /// Unlike reading the stream with `next()`, this only returns application
+    /// messages. Control messages are handled on the way: replies to Pings
+    /// and Close frames are flushed right away, Pongs are skipped. Returns
+    /// `Ok(None)` once the connection was closed.
+    ///
+    /// This makes a single loop correct without splitting the stream:
+    ///
+    /// ```no_run
+    /// # async fn test(mut stream: async_tungstenite::WebSocketStream<async_std::net::TcpStream>) {
+    /// while let Some(msg) = stream.recv().await.unwrap() {
+    ///     stream.send(msg).await.unwrap();
+    /// }
+    /// # }
+    /// ```
+    pub async fn recv(&mut self) -> Result<Option<Message>, WsError>
+    where
+        S: AsyncRead + AsyncWrite + Unpin,
+    {
+        loop {
+            let msg = match std::future::poll_fn(|cx| self.poll_next(cx)).await {
+                Some(msg) => msg?,
+                None => return Ok(None),
+            };
+            match msg {
+                Message::Text(_) | Message::Binary(_) => return Ok(Some(msg)),
+                Message::Ping(_) | Message::Close(_) => {
+                    match std::future::poll_fn(|cx| self.poll_flush(cx)).await {
+                        Ok(()) | Err(WsError::ConnectionClosed) => {}
+                        Err(err) => return Err(err),
+                    }
+                }
+                Message::Pong(_) | Message::Frame(_) => {}
+            }
+        }
+    }
+
+    /// Polls for the next message without consuming it.
+    ///
+    /// The message is kept and returned again by the next call to this or to
+    /// `poll_next`. Errors are not kept, a peeked error is only returned by
+    /// this call and the stream ends afterwards like it does after an error
+    /// returned by `poll_next`.
+    pub fn poll_peek(&mut self, cx: &mut Context<'_>) -> Poll<Option<Result<&Message, WsError>>>
+    where
+        S: AsyncRead + AsyncWrite + Unpin,
+    {
+        if self.peeked.is_none() {
+            match ready!(self.poll_next(cx)) {
+                Some(Ok(msg)) => self.peeked = Some(msg),
+                Some(Err(err)) => return Poll::Ready(Some(Err(err))),
+                None => return Poll::Ready(None),
+            }
+        }
+        Poll::Ready(self.peeked.as_ref().map(Ok))
+    }
+
+    /// Returns the next message without consuming it.
+    ///
+    /// See [`poll_peek`](Self::poll_peek).
+    #[allow(clippy::result_large_err)]
+    pub async fn peek(&mut self) -> Option<Result<&Message, WsError>>
+    where
+        S: AsyncRead + AsyncWrite + Unpin,
+    {
+        let res =
+            std::future::poll_fn(|cx| self.poll_peek(cx).map(|res| res.map(|res| res.map(drop))))
+                .await;
+        match res {
+            Some(Ok(())) => self.peeked.as_ref().map(Ok),
+            Some(Err(err)) => Some(Err(err)),
+            None => None,
+        }
+    }
+
+    /// Splits the websocket stream into separate
+    /// [sender](WebSocketSender) and [receiver](WebSocketReceiver) parts.
+    pub fn split(self) -> (WebSocketSender<S>, WebSocketReceiver<S>) {
+        let shared = Arc::new(Shared::new(self));
+        let sender = WebSocketSender {
+            shared: shared.clone(),
+            sink_permit: false,
+        };
+
+        let receiver = WebSocketReceiver { shared };
+        (sender, receiver)
+    }
+
+    /// Splits the websocket stream like [`split`](Self::split), but at most
+    /// `capacity` sends through the [sender](WebSocketSender) and its clones
+    /// are in progress at once.
+    ///
+    /// This covers every way of sending, e.g. [`WebSocketSender::send`],
+    /// [`send_priority`](WebSocketSender::send_priority) or the `Sink`
+    /// implementation, where a send lasts from `poll_ready` until the next
+    /// flush. Further sends wait until one of them flushed its message, so many
+    /// tasks sending to a slow peer are slowed down as well instead of piling
+    /// up messages in memory. Sends that wait for capacity when the receiver
+    /// is dropped fail with an [`Io`](WsError::Io) error of kind
+    /// [`BrokenPipe`](std::io::ErrorKind::BrokenPipe).
+    ///
+    /// # Panics
+    ///
+    /// Panics if `capacity` is `0`.
+    pub fn split_with_capacity(
+        self,
+        capacity: usize,
+    ) -> (WebSocketSender<S>, WebSocketReceiver<S>) {
+        assert!(capacity > 0, "send capacity must not be 0");
+        let mut shared = Shared::new(self);
+        shared.capacity = Some(capacity::Capacity::new(capacity));
+        let shared = Arc::new(shared);
+        let sender = WebSocketSender {
+            shared: shared.clone(),
+            sink_permit: false,
+        };
+
+        let receiver = WebSocketReceiver { shared };
+        (sender, receiver)
+    }
+
+    /// Attempts to reunite the [sender](WebSocketSender) and [receiver](WebSocketReceiver)
+    /// parts back into a single stream. If both parts originate from the same
+    /// [`split`](WebSocketStream::split) call, returns `Ok` with the original stream.
+    /// Otherwise, returns a [`ReuniteError`] containing the provided parts.
+    ///
+    /// This also fails while clones of the [sender](WebSocketSender) are
+    /// alive, so drop them first.
+    pub fn reunite(
+        sender: WebSocketSender<S>,
+        receiver: WebSocketReceiver<S>,
+    ) -> Result<Self, ReuniteError<S>> {
+        if !sender.is_pair_of(&receiver) {
+            return Err(ReuniteError::NotPair(sender, receiver));
+        }
+        // The count can't grow concurrently, all clones were dropped once it
+        // is down to the two parts.
+        if sender.reference_count() > 2 {
+            return Err(ReuniteError::OutstandingClones(sender, receiver));
+        }
+        drop(receiver);
+        let shared = sender.shared.clone();
+        drop(sender);
+        let stream = Arc::try_unwrap(shared)
+            .ok()
+            .expect("reunite the stream")
+            .into_inner();
+
+        Ok(stream)
+    }
+}
+
+impl<S> WebSocketStream<S>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    fn poll_next(&mut self, cx: &mut Context<'_>) -> Poll<Option<Result<Message, WsError>>> {
+        #[cfg(feature = "verbose-logging")]
+        trace!("{}:{} WebSocketStream.poll_next", file!(), line!());
+
+        if let Some(msg) = self.peeked.take() {
+            return Poll::Ready(Some(Ok(msg)));
+        }
+
+        // The connection has been closed or a critical error has occurred.
+        // We have already returned the error to the user, the `Stream` is unusable,
+        // so we assume that the stream has been "fused".
+        if self.ended {
+            return Poll::Ready(None);
+        }
+
+        if self.lossy_closing {
+            let res = ready!(self.with_context(Some((ContextWaker::Read, cx)), |s| cvt(s.flush())));
+            if let Err(err) = res {
+                debug!("websocket close error: {}", err);
+            }
+            self.ended = true;
+            return Poll::Ready(None);
+        }
+
+        loop {
+            let could_write = self.inner.can_write();
+            let res = self.with_context(Some((ContextWaker::Read, cx)), |s| {
+                #[cfg(feature = "verbose-logging")]
+                trace!(
+                    "{}:{} WebSocketStream.with_context poll_next -> read()",
+                    file!(),
+                    line!()
+                );
+                cvt(s.read())
+            });
+            let res = match res {
+                Poll::Ready(res) => res,
+                Poll::Pending => {
+                    if self.pongs_missed() {
+                        self.ended = true;
+                        let err = WsError::Io(std::io::Error::new(
+                            std::io::ErrorKind::TimedOut,
+                            "peer stopped answering pings",
+                        ));
+                        telemetry::error(&err);
+                        return Poll::Ready(Some(Err(err)));
+                    }
+                    return if Deadline::poll_passed(&mut self.recv_deadline, cx) {
+                        Poll::Ready(Some(Err(DeadlineExceeded::Recv.into_error())))
+                    } else {
+                        Poll::Pending
+                    };
+                }
+            };
+            let res = match res {
+                Err(WsError::Utf8(err)) if self.lossy_close_reasons => match self.lossy_close() {
+                    Some(frame) => Ok(Message::Close(Some(frame))),
+                    None => Err(WsError::Utf8(err)),
+                },
+                res => res,
+            };
+            if let Ok(Message::Close(frame)) = &res {
+                self.closed_by_peer |= could_write;
+                if let Some(frame) = frame {
+                    self.close_frame = Some(frame.clone());
+                }
+            }
+            match res {
+                Ok(v)
+                    if !self.deliver_after_close
+                        && (v.is_text() || v.is_binary())
+                        && self.close_sent() =>
+                {
+                    self.last_activity = self.clock.now();
+                    trace!("Discarding data message received after sending Close");
+                }
+                Ok(v) => {
+                    self.last_activity = self.clock.now();
+                    self.recv_deadline = None;
+                    if v.is_pong() {
+                        self.pongs_received += 1;
+                        self.unanswered_pings = 0;
+                    }
+                    if let Some(ref mut reply) = self.heartbeat_reply {
+                        if reply.matches(&v) {
+                            self.heartbeat_replies += 1;
+                        }
+                    }
+                    telemetry::MessageSample::new(&v).record(
+                        telemetry::Direction::Received,
+                        &mut self.round_trip,
+                        self.inner.get_ref().metrics(),
+                    );
+                    if v.is_ping() && self.auto_pong {
+                        // Errors show up again with the next read.
+                        if let Poll::Ready(Err(err)) =
+                            self.with_context(Some((ContextWaker::Read, cx)), |s| cvt(s.flush()))
+                        {
+                            debug!("websocket auto-pong error: {}", err);
+                        }
+                        if !self.yield_pings {
+                            continue;
+                        }
+                    }
+                    return Poll::Ready(Some(Ok(v)));
+                }
+                Err(WsError::Protocol(ProtocolError::UnexpectedContinueFrame))
+                    if self.ignore_orphan_continuations =>
+                {
+                    trace!("Ignoring orphan continuation frame");
+                }
+                Err(e) => {
+                    if let WsError::Io(ref err) = e {
+                        if let Some(violation) = frames::Violation::of(err) {
+                            let (code, reason) = violation.close_reason();
+                            self.fail_with_close(code, reason);
+                        }
+                    }
+                    self.ended = true;
+                    return if matches!(e, WsError::AlreadyClosed | WsError::ConnectionClosed)
+                        || self.is_truncated_close(&e)
+                    {
+                        Poll::Ready(None)
+                    } else {
+                        telemetry::error(&e);
+                        Poll::Ready(Some(Err(e)))
+                    };
+                }
+            }
+        }
+    }
+
+    /// Whether more pings went unanswered than the pong deadline allows.
+    fn pongs_missed(&self) -> bool {
+        match self.pong_deadline {
+            Some(max_missed) => self.unanswered_pings > max_missed,
+            None => false,
+        }
+    }
+
+    /// Counts a sent ping towards the pong deadline.
+    fn ping_sent(&mut self) {
+        self.unanswered_pings = self.unanswered_pings.saturating_add(1);
+        if self.pongs_missed() {
+            // A reader waiting for data has to notice that the peer is dead.
+            self.inner.get_ref().wake_reader();
+        }
+    }
+
+    /// Answers a Close frame that tungstenite rejected for its reason.
+    ///
+    /// Returns the frame with the reason decoded lossily, or `None` if the
+    /// error wasn't caused by a Close frame.
+    fn lossy_close(&mut self) -> Option<CloseFrame> {
+        let payload = self.inner.get_mut().frames_mut().take_close()?;
+        if payload.len() < 2 {
+            return None;
+        }
+        let code = CloseCode::from(u16::from_be_bytes([payload[0], payload[1]]));
+        let reason = String::from_utf8_lossy(&payload[2..]).into_owned();
+
+        // tungstenite didn't process the frame, so reply like it would have.
+        // The peer already sent its Close frame, so the stream ends once the
+        // reply is flushed.
+        let reply = if code.is_allowed() {
+            code
+        } else {
+            CloseCode::Protocol
+        };
+        self.fail_with_close(reply, "");
+        self.lossy_closing = true;
+
+        Some(CloseFrame {
+            code,
+            reason: reason.into(),
+        })
+    }
+
+    /// Sends a Close frame for a connection we give up on.
+    ///
+    /// Errors are ignored, the frame stays queued if the stream would block.
+    #[allow(clippy::result_large_err)]
+    fn fail_with_close(&mut self, code: CloseCode, reason: &'static str)
+    where
+        AllowStd<S>: Read + Write,
+    {
+        let frame = CloseFrame {
+            code,
+            reason: Utf8Bytes::from_static(reason),
+        };
+        if let Err(err) = self.with_context(None, |s| s.close(Some(frame))) {
+            debug!("websocket close error: {}", err);
+        }
+    }
+
+    /// Whether `err` is a TLS stream ending without `close_notify` during a
+    /// close handshake that should be treated as a normal close.
+    fn is_truncated_close(&self, err: &WsError) -> bool {
+        // Writing is not possible anymore once either side sent a Close frame.
+        !self.strict_tls_close
+            && !self.inner.can_write()
+            && matches!(err, WsError::Io(err) if err.kind() == std::io::ErrorKind::UnexpectedEof)
+    }
+
+    /// Whether we sent a Close frame that the peer did not acknowledge yet.
+    fn close_sent(&self) -> bool {
+        // Reading is still possible but writing is not only between sending our
+        // Close frame and receiving the peer's.
+        self.inner.can_read() && !self.inner.can_write()
+    }
+
+    #[allow(clippy::result_large_err)]
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), WsError>> {
+        if self.ready {
+            return Poll::Ready(Ok(()));
+        }
+
+        // Currently blocked so try to flush the blockage away
+        self.poll_flush_until_deadline(cx).map(|r| {
+            self.ready = true;
+            r
+        })
+    }
+
+    #[allow(clippy::result_large_err)]
+    fn start_send(&mut self, item: Message) -> Result<(), WsError> {
+        if item.is_text() || item.is_binary() {
+            self.check_unfinished_message(&item)?;
+            self.check_send_size(item.len())?;
+        }
+        let sample = telemetry::MessageSample::new(&item);
+        let is_ping = item.is_ping();
+        let framed = framed_len(&item, self.role);
+        self.inner.get_mut().buffer(framed);
+        match self.with_context(None, |s| s.write(item)) {
+            Ok(()) => {
+                self.ready = true;
+                self.last_activity = self.clock.now();
+                sample.record(
+                    telemetry::Direction::Sent,
+                    &mut self.round_trip,
+                    self.inner.get_ref().metrics(),
+                );
+                if is_ping {
+                    self.ping_sent();
+                }
+                Ok(())
+            }
+            Err(WsError::Io(err)) if err.kind() == std::io::ErrorKind::WouldBlock => {
+                // the message was accepted and queued so not an error
+                // but `poll_ready` will now start trying to flush the block
+                self.ready = false;
+                self.last_activity = self.clock.now();
+                sample.record(
+                    telemetry::Direction::Sent,
+                    &mut self.round_trip,
+                    self.inner.get_ref().metrics(),
+                );
+                if is_ping {
+                    self.ping_sent();
+                }
+                Ok(())
             }
             Err(e) => {
                 self.ready = true;
+                self.inner.get_mut().unbuffer(framed);
+                let e = match e {
+                    // Sending raced with a Close frame of the peer that was
+                    // processed first.
+                    WsError::Protocol(ProtocolError::SendAfterClosing) | WsError::AlreadyClosed
+                        if self.closed_by_peer =>
+                    {
+                        WsError::ConnectionClosed
+                    }
+                    e => e,
+                };
                 debug!("websocket start_send error: {}", e);
+                telemetry::error(&e);
                 Err(e)
             }
         }
     }
 
+    #[allow(clippy::result_large_err)]
     fn poll_flush(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), WsError>> {
-        self.with_context(Some((ContextWaker::Write, cx)), |s| cvt(s.flush()))
-            .map(|r| {
-                self.ready = true;
-                match r {
-                    // WebSocket connection has just been closed. Flushing completed, not an error.
-                    Err(WsError::ConnectionClosed) => Ok(()),
-                    other => other,
-                }
-            })
+        self.poll_flush_until_deadline(cx).map(|r| {
+            self.ready = true;
+            match r {
+                // WebSocket connection has just been closed. Flushing completed, not an error.
+                Err(WsError::ConnectionClosed) => Ok(()),
+                other => other,
+            }
+        })
     }
 
-    #[cfg(feature = "futures-03-sink")]
-    fn poll_close(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), WsError>> {
+    /// Flushes, failing once the send deadline passed while still pending.
+    fn poll_flush_until_deadline(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), WsError>> {
+        match self.with_context(Some((ContextWaker::Write, cx)), |s| cvt(s.flush())) {
+            Poll::Ready(r) => {
+                self.send_deadline = None;
+                Poll::Ready(r)
+            }
+            Poll::Pending if Deadline::poll_passed(&mut self.send_deadline, cx) => {
+                Poll::Ready(Err(DeadlineExceeded::Send.into_error()))
+            }
+            Poll::Pending => Poll::Pending,
+        }
+    }
+
+    /// Sends a Close frame and flushes it, e.g. for manual state machines
+    /// that can't await [`close`](Self::close).
+    ///
+    /// This is what `Sink::poll_close` does, also without the
+    /// `futures-03-sink` feature. The first call queues a Close frame without
+    /// a code, and once it returned [`Poll::Pending`], later calls only flush
+    /// it. A connection that is closed already counts as success. Read the
+    /// stream afterwards to receive the peer's reply and complete the close
+    /// handshake.
+    #[allow(clippy::result_large_err)]
+    pub fn poll_close(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), WsError>> {
         self.ready = true;
         let res = if self.closing {
             // After queueing it, we call `flush` to drive the close handshake to completion.
@@ -464,92 +1853,393 @@ where
     }
 }
 
-impl<S> Stream for WebSocketStream<S>
-where
-    S: AsyncRead + AsyncWrite + Unpin,
-{
-    type Item = Result<Message, WsError>;
-
-    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
-        self.get_mut().poll_next(cx)
+impl<S> Stream for WebSocketStream<S>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    type Item = Result<Message, WsError>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.get_mut().poll_next(cx)
+    }
+}
+
+impl<S> FusedStream for WebSocketStream<S>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    fn is_terminated(&self) -> bool {
+        self.ended && self.peeked.is_none()
+    }
+}
+
+/// Whether `err` means that the connection was dropped without a close
+/// handshake, e.g. by a TCP reset.
+///
+/// A [`WebSocketStream`] ends with `None` after an orderly close. If the
+/// connection is dropped instead, it yields a final error for which this
+/// returns `true`, which allows e.g. counting unclean disconnects.
+pub fn is_abrupt_disconnect(err: &WsError) -> bool {
+    match err {
+        WsError::Io(err) => matches!(
+            err.kind(),
+            std::io::ErrorKind::ConnectionReset
+                | std::io::ErrorKind::ConnectionAborted
+                | std::io::ErrorKind::BrokenPipe
+                | std::io::ErrorKind::UnexpectedEof
+        ),
+        WsError::Protocol(ProtocolError::ResetWithoutClosingHandshake) => true,
+        _ => false,
+    }
+}
+
+#[cfg(feature = "futures-03-sink")]
+impl<S> futures_util::Sink<Message> for WebSocketStream<S>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    type Error = WsError;
+
+    fn poll_ready(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.get_mut().poll_ready(cx)
+    }
+
+    fn start_send(self: Pin<&mut Self>, item: Message) -> Result<(), Self::Error> {
+        self.get_mut().start_send(item)
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.get_mut().poll_flush(cx)
+    }
+
+    fn poll_close(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.get_mut().poll_close(cx)
+    }
+}
+
+#[cfg(not(feature = "futures-03-sink"))]
+impl<S> bytes::private::SealedSender for WebSocketStream<S>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    fn poll_ready(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), WsError>> {
+        self.get_mut().poll_ready(cx)
+    }
+
+    fn start_send(self: Pin<&mut Self>, msg: Message) -> Result<(), WsError> {
+        self.get_mut().start_send(msg)
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), WsError>> {
+        self.get_mut().poll_flush(cx)
+    }
+
+    fn poll_close(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        msg: &mut Option<Message>,
+    ) -> Poll<Result<(), WsError>> {
+        let me = self.get_mut();
+        send_helper(me, msg, cx)
+    }
+}
+
+impl<S> WebSocketStream<S> {
+    /// Simple send method to replace `futures_sink::Sink` (till v0.3).
+    ///
+    /// Text and binary messages are always written as a single frame.
+    /// Outgoing messages are never fragmented automatically, the
+    /// [`max_frame_size`](WebSocketConfig::max_frame_size) of the
+    /// configuration only limits incoming frames. To send a fragmented
+    /// message, use [`send_fragmented`](Self::send_fragmented).
+    ///
+    /// If the peer starts the close handshake, a message is either queued
+    /// before its Close frame is processed, in which case it is sent ahead of
+    /// our reply, or sending fails with
+    /// [`ConnectionClosed`](WsError::ConnectionClosed). Close frames are
+    /// processed while the stream is read. The same applies to
+    /// [`WebSocketSender::send`] and the `Sink` implementation.
+    ///
+    /// # Cancellation
+    ///
+    /// Once the message was queued, it is sent as a whole even if the future
+    /// is dropped before flushing finished: the rest of the frame stays
+    /// buffered and is written ahead of any later frame, with the next send
+    /// or flush. Dropping the future never leaves a partial frame on the
+    /// wire. If it is dropped before the message was queued, e.g. while
+    /// waiting for earlier data to be written, the message is lost, see
+    /// [`send_owned`](Self::send_owned) for getting it back.
+    pub async fn send(&mut self, msg: Message) -> Result<(), WsError>
+    where
+        S: AsyncRead + AsyncWrite + Unpin,
+    {
+        Send {
+            ws: self,
+            msg: Some(msg),
+        }
+        .await
+    }
+
+    /// Sends a message like [`send`](Self::send), with a future that hands
+    /// the message back if it was not queued yet.
+    ///
+    /// Poll the future by reference, e.g. in a `select!`, and call
+    /// [`SendOwned::into_unsent`] once it is abandoned.
+    pub fn send_owned(&mut self, msg: Message) -> SendOwned<'_, S> {
+        SendOwned {
+            send: Send {
+                ws: self,
+                msg: Some(msg),
+            },
+        }
+    }
+
+    /// Sends all messages and flushes them once at the end.
+    ///
+    /// Unlike calling [`send`](Self::send) for every message, this doesn't
+    /// flush after each message. Queued data is only written out early once
+    /// the write buffer fills up, and if the stream would block, it is
+    /// flushed before the remaining messages are queued.
+    pub async fn send_batch<I>(&mut self, msgs: I) -> Result<(), WsError>
+    where
+        S: AsyncRead + AsyncWrite + Unpin,
+        I: IntoIterator<Item = Message>,
+    {
+        for msg in msgs {
+            std::future::poll_fn(|cx| self.poll_ready(cx)).await?;
+            self.start_send(msg)?;
+        }
+
+        std::future::poll_fn(|cx| self.poll_flush(cx)).await
+    }
+
+    /// Flushes all buffered data and reports the progress.
+    ///
+    /// `progress` is called with the number of bytes that are still buffered
+    /// whenever it changes, and with `0` once flushing finished. This helps
+    /// diagnosing flushes, e.g. of a close, that hang because of a slow peer.
+    pub async fn flush_with_progress<F>(&mut self, mut progress: F) -> Result<(), WsError>
+    where
+        S: AsyncRead + AsyncWrite + Unpin,
+        F: FnMut(usize),
+    {
+        let mut last = None;
+        std::future::poll_fn(|cx| {
+            let res = self.poll_flush(cx);
+            let remaining = match res {
+                Poll::Ready(Ok(())) => 0,
+                _ => self.inner.get_ref().pending(),
+            };
+            if last != Some(remaining) {
+                last = Some(remaining);
+                progress(remaining);
+            }
+            res
+        })
+        .await
+    }
+
+    /// Sends a single binary message whose payload is the concatenation of
+    /// `bufs`.
+    ///
+    /// tungstenite needs the payload of a frame in one contiguous buffer, so
+    /// the slices are copied exactly once into a buffer of the final size.
+    /// This avoids intermediate copies when assembling a message from e.g.
+    /// a header and a body.
+    pub async fn send_vectored(&mut self, bufs: &[&[u8]]) -> Result<(), WsError>
+    where
+        S: AsyncRead + AsyncWrite + Unpin,
+    {
+        self.send(gather(bufs)).await
+    }
+
+    /// Sends a text or binary message split into frames of at most
+    /// `frame_size` bytes.
+    ///
+    /// Smaller frames e.g. let control frames be interleaved sooner with a
+    /// large latency-sensitive message. All frames are queued at once, so no
+    /// other data message ends up between them. If only some of them could
+    /// be queued, e.g. because the write buffer is full, the message can't be
+    /// completed and the connection is closed with code 1011 (internal
+    /// error). Other messages are sent unchanged.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `frame_size` is `0`.
+    pub async fn send_fragmented(&mut self, msg: Message, frame_size: usize) -> Result<(), WsError>
+    where
+        S: AsyncRead + AsyncWrite + Unpin,
+    {
+        let frames = fragment(msg, frame_size);
+        std::future::poll_fn(|cx| self.poll_ready(cx)).await?;
+        self.queue_fragments(frames)?;
+        std::future::poll_fn(|cx| self.poll_flush(cx)).await
+    }
+
+    /// Sends a message of type `kind` whose payload is streamed from
+    /// `chunks`, without buffering all of it.
+    ///
+    /// Every chunk is sent as one frame as soon as it is available, and an
+    /// empty final frame ends the message once `chunks` ends. Control frames,
+    /// e.g. Pongs, may be interleaved with the frames, which the protocol
+    /// allows. Nothing else can be sent until this returns. If it fails or
+    /// is dropped before it completes, the message remains unfinished and
+    /// sending another text or binary message fails with an
+    /// [`ExpectedFragment`](ProtocolError::ExpectedFragment) error, so the
+    /// connection should be closed.
+    ///
+    /// The payload of a text message has to be valid UTF-8 as a whole, the
+    /// peer fails the connection otherwise. Unlike with
+    /// [`send`](Self::send), the size of the message is not checked against
+    /// the limit of the peer.
+    pub async fn send_chunks<C>(&mut self, kind: FrameKind, chunks: C) -> Result<(), WsError>
+    where
+        S: AsyncRead + AsyncWrite + Unpin,
+        C: Stream<Item = Vec<u8>>,
+    {
+        let mut chunks = Box::pin(chunks);
+        let mut opcode = match kind {
+            FrameKind::Binary => OpData::Binary,
+            FrameKind::Text => OpData::Text,
+        };
+        loop {
+            let chunk = std::future::poll_fn(|cx| chunks.as_mut().poll_next(cx)).await;
+            let fin = chunk.is_none();
+            let frame = Frame::message(chunk.unwrap_or_default(), OpCode::Data(opcode), fin);
+            std::future::poll_fn(|cx| self.poll_ready(cx)).await?;
+            self.start_send_fragment(frame)?;
+            std::future::poll_fn(|cx| self.poll_flush(cx)).await?;
+            if fin {
+                return Ok(());
+            }
+            opcode = OpData::Continue;
+        }
+    }
+}
+
+/// Splits a data message into frames of at most `frame_size` bytes.
+fn fragment(msg: Message, frame_size: usize) -> Vec<Message> {
+    assert!(frame_size > 0, "frame size must not be zero");
+
+    let opcode = match msg {
+        Message::Text(_) => OpData::Text,
+        Message::Binary(_) => OpData::Binary,
+        msg => return vec![msg],
+    };
+    if msg.len() <= frame_size {
+        return vec![msg];
     }
-}
 
-impl<S> FusedStream for WebSocketStream<S>
-where
-    S: AsyncRead + AsyncWrite + Unpin,
-{
-    fn is_terminated(&self) -> bool {
-        self.ended
-    }
+    let data = msg.into_data();
+    let count = (data.len() + frame_size - 1) / frame_size;
+    (0..count)
+        .map(|i| {
+            let end = std::cmp::min((i + 1) * frame_size, data.len());
+            let opcode = if i == 0 { opcode } else { OpData::Continue };
+            Message::Frame(Frame::message(
+                data.slice(i * frame_size..end),
+                OpCode::Data(opcode),
+                i + 1 == count,
+            ))
+        })
+        .collect()
 }
 
-#[cfg(feature = "futures-03-sink")]
-impl<S> futures_util::Sink<Message> for WebSocketStream<S>
-where
-    S: AsyncRead + AsyncWrite + Unpin,
-{
-    type Error = WsError;
-
-    fn poll_ready(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
-        self.get_mut().poll_ready(cx)
-    }
-
-    fn start_send(self: Pin<&mut Self>, item: Message) -> Result<(), Self::Error> {
-        self.get_mut().start_send(item)
-    }
-
-    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
-        self.get_mut().poll_flush(cx)
-    }
+/// Returns the number of bytes that `msg` takes up in the write buffer once
+/// tungstenite turned it into a frame, which clients also mask.
+fn framed_len(msg: &Message, role: Role) -> usize {
+    let mask = if role == Role::Client { 4 } else { 0 };
+    let payload = match msg {
+        Message::Frame(frame) if frame.header().mask.is_some() => return frame.len(),
+        Message::Frame(frame) => return frame.len() + mask,
+        Message::Close(Some(frame)) => 2 + frame.reason.len(),
+        msg => msg.len(),
+    };
+    let extended = match payload {
+        0..=125 => 0,
+        126..=0xffff => 2,
+        _ => 8,
+    };
+    2 + extended + mask + payload
+}
 
-    fn poll_close(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
-        self.get_mut().poll_close(cx)
+/// Concatenates `bufs` into the payload of a binary message.
+fn gather(bufs: &[&[u8]]) -> Message {
+    let mut payload = Vec::with_capacity(bufs.iter().map(|buf| buf.len()).sum());
+    for buf in bufs {
+        payload.extend_from_slice(buf);
     }
+    Message::binary(payload)
 }
 
-#[cfg(not(feature = "futures-03-sink"))]
-impl<S> bytes::private::SealedSender for WebSocketStream<S>
+impl<S> WebSocketStream<S>
 where
     S: AsyncRead + AsyncWrite + Unpin,
 {
-    fn poll_write(
-        self: Pin<&mut Self>,
-        cx: &mut Context<'_>,
-        buf: &[u8],
-    ) -> Poll<Result<usize, WsError>> {
-        let me = self.get_mut();
-        ready!(me.poll_ready(cx))?;
-        let len = buf.len();
-        me.start_send(Message::binary(buf.to_owned()))?;
-        Poll::Ready(Ok(len))
+    /// Queues the frames returned by `fragment` without waiting in between,
+    /// so that nothing else can be queued between them.
+    #[allow(clippy::result_large_err)]
+    fn queue_fragments(&mut self, msgs: Vec<Message>) -> Result<(), WsError> {
+        self.check_send_size(msgs.iter().map(Message::len).sum())?;
+        // `start_send` accepts messages even while the stream is blocked.
+        for (i, msg) in msgs.into_iter().enumerate() {
+            let res = match msg {
+                Message::Frame(frame) => self.start_send_fragment(frame),
+                msg => self.start_send(msg),
+            };
+            if let Err(err) = res {
+                if i > 0 {
+                    // The message can't be completed anymore.
+                    self.fail_with_close(CloseCode::Error, "Internal error");
+                }
+                return Err(err);
+            }
+        }
+        Ok(())
     }
 
-    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), WsError>> {
-        self.get_mut().poll_flush(cx)
+    /// Queues a frame of a fragmented message, keeping track of whether the
+    /// message is complete.
+    #[allow(clippy::result_large_err)]
+    fn start_send_fragment(&mut self, frame: Frame) -> Result<(), WsError> {
+        let fin = frame.header().is_final;
+        if let OpCode::Data(opcode @ (OpData::Text | OpData::Binary)) = frame.header().opcode {
+            if self.unfinished_message {
+                return Err(ProtocolError::ExpectedFragment(opcode).into());
+            }
+        }
+        self.start_send(Message::Frame(frame))?;
+        self.unfinished_message = !fin;
+        Ok(())
     }
 
-    fn poll_close(
-        self: Pin<&mut Self>,
-        cx: &mut Context<'_>,
-        msg: &mut Option<Message>,
-    ) -> Poll<Result<(), WsError>> {
-        let me = self.get_mut();
-        send_helper(me, msg, cx)
+    /// Fails if `msg` would start a new message in the middle of a fragmented
+    /// one.
+    #[allow(clippy::result_large_err)]
+    fn check_unfinished_message(&self, msg: &Message) -> Result<(), WsError> {
+        if !self.unfinished_message {
+            return Ok(());
+        }
+        let opcode = if msg.is_text() {
+            OpData::Text
+        } else {
+            OpData::Binary
+        };
+        Err(ProtocolError::ExpectedFragment(opcode).into())
     }
-}
 
-impl<S> WebSocketStream<S> {
-    /// Simple send method to replace `futures_sink::Sink` (till v0.3).
-    pub async fn send(&mut self, msg: Message) -> Result<(), WsError>
-    where
-        S: AsyncRead + AsyncWrite + Unpin,
-    {
-        Send {
-            ws: self,
-            msg: Some(msg),
+    /// Fails if the peer doesn't accept messages of `size` bytes.
+    #[allow(clippy::result_large_err)]
+    fn check_send_size(&self, size: usize) -> Result<(), WsError> {
+        match self.max_send_size {
+            Some(max_size) if size > max_size => {
+                Err(WsError::Capacity(CapacityError::MessageTooLong {
+                    size,
+                    max_size,
+                }))
+            }
+            _ => Ok(()),
         }
-        .await
     }
 }
 
@@ -588,6 +2278,41 @@ where
     }
 }
 
+/// Future returned by [`WebSocketStream::send_owned`].
+#[must_use = "futures do nothing unless you `.await` or poll them"]
+pub struct SendOwned<'a, S> {
+    send: Send<&'a mut WebSocketStream<S>>,
+}
+
+impl<S> SendOwned<'_, S> {
+    /// Returns the message unless it was queued already.
+    ///
+    /// A queued message is sent even though the future is dropped, see
+    /// [`WebSocketStream::send`].
+    pub fn into_unsent(self) -> Option<Message> {
+        self.send.msg
+    }
+}
+
+impl<S> std::future::Future for SendOwned<'_, S>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    type Output = Result<(), WsError>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        Pin::new(&mut self.get_mut().send).poll(cx)
+    }
+}
+
+impl<S> fmt::Debug for SendOwned<'_, S> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SendOwned")
+            .field("msg", &self.send.msg)
+            .finish_non_exhaustive()
+    }
+}
+
 impl<S> std::future::Future for Send<&Shared<S>>
 where
     S: AsyncRead + AsyncWrite + Unpin,
@@ -602,17 +2327,47 @@ where
 }
 
 /// The sender part of a [websocket](WebSocketStream) stream.
+///
+/// Cloning it is cheap and allows sending from many tasks, all clones send
+/// on the same connection. The stream can only be
+/// [reunited](WebSocketStream::reunite) once all clones but one were dropped.
 #[derive(Debug)]
 pub struct WebSocketSender<S> {
     shared: Arc<Shared<S>>,
+    /// Whether the `Sink` took room for a send that wasn't flushed yet.
+    sink_permit: bool,
+}
+
+impl<S> Clone for WebSocketSender<S> {
+    fn clone(&self) -> Self {
+        WebSocketSender {
+            shared: self.shared.clone(),
+            sink_permit: false,
+        }
+    }
+}
+
+impl<S> Drop for WebSocketSender<S> {
+    fn drop(&mut self) {
+        self.release_sink_permit();
+    }
 }
 
 impl<S> WebSocketSender<S> {
     /// Send a message via [websocket](WebSocketStream).
+    ///
+    /// This can be called concurrently from multiple tasks. Each message is
+    /// queued as a whole while holding the lock of the shared stream, so the
+    /// frames of different messages are never interleaved on the wire.
+    ///
+    /// If the sender was created by
+    /// [`split_with_capacity`](WebSocketStream::split_with_capacity), this
+    /// first waits until there is room for another send.
     pub async fn send(&self, msg: Message) -> Result<(), WsError>
     where
         S: AsyncRead + AsyncWrite + Unpin,
     {
+        let _permit = self.shared.acquire().await?;
         Send {
             ws: &*self.shared,
             msg: Some(msg),
@@ -620,6 +2375,73 @@ impl<S> WebSocketSender<S> {
         .await
     }
 
+    /// Sends a message unless it became stale before the connection could
+    /// take it.
+    ///
+    /// While the connection can't accept more data, e.g. because the peer
+    /// reads slowly, the message waits at this layer. If it still waits once
+    /// `ttl` passed, it is dropped and this returns `Ok(false)` instead of
+    /// sending it, so a slow peer is not flooded with outdated data after a
+    /// stall. Expiry is checked whenever the connection becomes writable
+    /// again. Once the message was handed to the connection, it is sent
+    /// regardless of `ttl` and this returns `Ok(true)` after flushing.
+    pub async fn send_with_ttl(&self, msg: Message, ttl: Duration) -> Result<bool, WsError>
+    where
+        S: AsyncRead + AsyncWrite + Unpin,
+    {
+        let _permit = self.shared.acquire().await?;
+        let expires = self.shared.lock().clock.now() + ttl;
+        let mut msg = Some(msg);
+        std::future::poll_fn(|cx| {
+            let mut ws = self.shared.lock();
+            if msg.is_some() {
+                ready!(ws.poll_ready(cx))?;
+                let msg = msg.take().expect("unreachable");
+                if ws.clock.now() >= expires {
+                    trace!("Dropping message that expired before it could be sent");
+                    return Poll::Ready(Ok(false));
+                }
+                ws.start_send(msg)?;
+            }
+            ws.poll_flush(cx).map_ok(|()| true)
+        })
+        .await
+    }
+
+    /// Sends a single binary message whose payload is the concatenation of
+    /// `bufs`.
+    ///
+    /// See [`WebSocketStream::send_vectored`] for details.
+    pub async fn send_vectored(&self, bufs: &[&[u8]]) -> Result<(), WsError>
+    where
+        S: AsyncRead + AsyncWrite + Unpin,
+    {
+        self.send(gather(bufs)).await
+    }
+
+    /// Sends a text or binary message split into frames of at most
+    /// `frame_size` bytes.
+    ///
+    /// See [`WebSocketStream::send_fragmented`] for details.
+    pub async fn send_fragmented(&self, msg: Message, frame_size: usize) -> Result<(), WsError>
+    where
+        S: AsyncRead + AsyncWrite + Unpin,
+    {
+        let _permit = self.shared.acquire().await?;
+        // The lock is held from waiting for readiness until all frames are
+        // queued, so that no other sender can queue a message in between.
+        let mut frames = Some(fragment(msg, frame_size));
+        std::future::poll_fn(|cx| {
+            let mut ws = self.shared.lock();
+            if frames.is_some() {
+                ready!(ws.poll_ready(cx))?;
+                ws.queue_fragments(frames.take().expect("unreachable"))?;
+            }
+            ws.poll_flush(cx)
+        })
+        .await
+    }
+
     /// Close the underlying [websocket](WebSocketStream).
     pub async fn close(&self, msg: Option<CloseFrame>) -> Result<(), WsError>
     where
@@ -633,6 +2455,106 @@ impl<S> WebSocketSender<S> {
     pub fn is_pair_of(&self, other: &WebSocketReceiver<S>) -> bool {
         Arc::ptr_eq(&self.shared, &other.shared)
     }
+
+    /// Returns the number of live references to the shared stream.
+    ///
+    /// This counts the sender and receiver parts that are still alive,
+    /// including clones of the sender. The stream can only be
+    /// [reunited](WebSocketStream::reunite) while this is exactly `2`, so a
+    /// larger value points to a leaked reference.
+    pub fn reference_count(&self) -> usize {
+        Arc::strong_count(&self.shared)
+    }
+
+    /// Returns the state of the connection.
+    ///
+    /// See [`WebSocketStream::state`].
+    pub fn state(&self) -> ConnectionState {
+        self.shared.lock().state()
+    }
+
+    /// Returns the counters of the connection.
+    ///
+    /// See [`WebSocketStream::metrics`].
+    pub fn metrics(&self) -> &ConnectionMetrics {
+        &self.shared.metrics
+    }
+
+    /// Returns how full the write buffer is, from `0.0` to `1.0`.
+    ///
+    /// See [`WebSocketStream::write_buffer_occupancy`].
+    pub fn write_buffer_occupancy(&self) -> f32 {
+        self.shared.lock().write_buffer_occupancy()
+    }
+
+    /// Waits until a message can be sent or the connection is closed,
+    /// whichever happens first.
+    ///
+    /// Once either side sent a Close frame, no more messages can be sent and
+    /// this resolves to [`Readiness::Closed`] right away. This lets a sender
+    /// loop stop instead of spinning on a dead connection.
+    pub async fn ready_or_closed(&self) -> Result<Readiness, WsError>
+    where
+        S: AsyncRead + AsyncWrite + Unpin,
+    {
+        std::future::poll_fn(|cx| {
+            let mut ws = self.shared.lock();
+            if ws.ended || !ws.inner.can_write() {
+                return Poll::Ready(Ok(Readiness::Closed));
+            }
+            match ready!(ws.poll_ready(cx)) {
+                Ok(()) => Poll::Ready(Ok(Readiness::Writable)),
+                Err(WsError::AlreadyClosed | WsError::ConnectionClosed) => {
+                    Poll::Ready(Ok(Readiness::Closed))
+                }
+                Err(err) => Poll::Ready(Err(err)),
+            }
+        })
+        .await
+    }
+}
+
+impl<S> WebSocketSender<S> {
+    /// Takes room for a send before the `Sink` accepts a message, which is
+    /// kept until the messages were flushed.
+    fn poll_sink_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), WsError>>
+    where
+        S: AsyncRead + AsyncWrite + Unpin,
+    {
+        if let (Some(capacity), false) = (&self.shared.capacity, self.sink_permit) {
+            ready!(capacity.poll_acquire(cx))?;
+            self.sink_permit = true;
+        }
+        self.shared.lock().poll_ready(cx)
+    }
+
+    fn release_sink_permit(&mut self) {
+        if let (Some(capacity), true) = (&self.shared.capacity, self.sink_permit) {
+            capacity.release();
+            self.sink_permit = false;
+        }
+    }
+}
+
+/// The state of a connection, see [`WebSocketStream::state`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConnectionState {
+    /// Messages can be sent and received.
+    Open,
+    /// A Close frame was sent or received, but the close handshake is not
+    /// complete yet. Messages can still be received, but not sent.
+    Closing,
+    /// The stream ended.
+    Closed,
+}
+
+/// What [`WebSocketSender::ready_or_closed`] waited for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Readiness {
+    /// A message can be sent.
+    Writable,
+    /// The connection is closed or closing, no more messages can be sent.
+    Closed,
 }
 
 #[cfg(feature = "futures-03-sink")]
@@ -643,7 +2565,7 @@ where
     type Error = WsError;
 
     fn poll_ready(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
-        self.shared.lock().poll_ready(cx)
+        self.get_mut().poll_sink_ready(cx)
     }
 
     fn start_send(self: Pin<&mut Self>, item: Message) -> Result<(), Self::Error> {
@@ -651,11 +2573,17 @@ where
     }
 
     fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
-        self.shared.lock().poll_flush(cx)
+        let me = self.get_mut();
+        let res = ready!(me.shared.lock().poll_flush(cx));
+        me.release_sink_permit();
+        Poll::Ready(res)
     }
 
     fn poll_close(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
-        self.shared.lock().poll_close(cx)
+        let me = self.get_mut();
+        let res = ready!(me.shared.lock().poll_close(cx));
+        me.release_sink_permit();
+        Poll::Ready(res)
     }
 }
 
@@ -664,21 +2592,19 @@ impl<S> bytes::private::SealedSender for WebSocketSender<S>
 where
     S: AsyncRead + AsyncWrite + Unpin,
 {
-    fn poll_write(
-        self: Pin<&mut Self>,
-        cx: &mut Context<'_>,
-        buf: &[u8],
-    ) -> Poll<Result<usize, WsError>> {
-        let me = self.get_mut();
-        let mut ws = me.shared.lock();
-        ready!(ws.poll_ready(cx))?;
-        let len = buf.len();
-        ws.start_send(Message::binary(buf.to_owned()))?;
-        Poll::Ready(Ok(len))
+    fn poll_ready(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), WsError>> {
+        self.get_mut().poll_sink_ready(cx)
+    }
+
+    fn start_send(self: Pin<&mut Self>, msg: Message) -> Result<(), WsError> {
+        self.shared.lock().start_send(msg)
     }
 
     fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), WsError>> {
-        self.shared.lock().poll_flush(cx)
+        let me = self.get_mut();
+        let res = ready!(me.shared.lock().poll_flush(cx));
+        me.release_sink_permit();
+        Poll::Ready(res)
     }
 
     fn poll_close(
@@ -687,8 +2613,9 @@ where
         msg: &mut Option<Message>,
     ) -> Poll<Result<(), WsError>> {
         let me = self.get_mut();
-        let mut ws = me.shared.lock();
-        send_helper(&mut ws, msg, cx)
+        let res = ready!(send_helper(&mut me.shared.lock(), msg, cx));
+        me.release_sink_permit();
+        Poll::Ready(res)
     }
 }
 
@@ -704,6 +2631,52 @@ impl<S> WebSocketReceiver<S> {
     pub fn is_pair_of(&self, other: &WebSocketSender<S>) -> bool {
         Arc::ptr_eq(&self.shared, &other.shared)
     }
+
+    /// Returns the state of the connection.
+    ///
+    /// See [`WebSocketStream::state`].
+    pub fn state(&self) -> ConnectionState {
+        self.shared.lock().state()
+    }
+
+    /// Returns the counters of the connection.
+    ///
+    /// See [`WebSocketStream::metrics`].
+    pub fn metrics(&self) -> &ConnectionMetrics {
+        &self.shared.metrics
+    }
+
+    /// Returns the next message if one can be read without waiting.
+    ///
+    /// Returns `Ok(None)` if no complete message is available yet, e.g. to
+    /// drain everything that is already buffered from a manual event loop.
+    /// Errors end the stream the same way they do when polling it, afterwards
+    /// [`ConnectionClosed`](WsError::ConnectionClosed) is returned.
+    ///
+    /// This does not register for a wakeup once more data arrives, and it
+    /// replaces the wakeup of a task that is waiting for the next message
+    /// concurrently, so don't mix it with polling the receiver from another
+    /// task.
+    #[allow(clippy::result_large_err)]
+    pub fn try_recv(&self) -> Result<Option<Message>, WsError>
+    where
+        S: AsyncRead + AsyncWrite + Unpin,
+    {
+        let mut cx = Context::from_waker(futures_task::noop_waker_ref());
+        match self.shared.lock().poll_next(&mut cx) {
+            Poll::Ready(Some(res)) => res.map(Some),
+            Poll::Ready(None) => Err(WsError::ConnectionClosed),
+            Poll::Pending => Ok(None),
+        }
+    }
+}
+
+impl<S> Drop for WebSocketReceiver<S> {
+    fn drop(&mut self) {
+        if let Some(capacity) = &self.shared.capacity {
+            capacity.close();
+        }
+    }
 }
 
 impl<S> Stream for WebSocketReceiver<S>
@@ -726,27 +2699,148 @@ where
     }
 }
 
+/// Why [`WebSocketStream::reunite`] failed.
+///
+/// Both parts are handed back unchanged, see [`into_parts`](Self::into_parts).
+#[derive(Debug)]
+pub enum ReuniteError<S> {
+    /// The parts were split from different streams.
+    NotPair(WebSocketSender<S>, WebSocketReceiver<S>),
+    /// The parts belong together, but clones of the sender are still alive.
+    OutstandingClones(WebSocketSender<S>, WebSocketReceiver<S>),
+}
+
+impl<S> ReuniteError<S> {
+    /// Returns the parts that could not be reunited.
+    pub fn into_parts(self) -> (WebSocketSender<S>, WebSocketReceiver<S>) {
+        match self {
+            ReuniteError::NotPair(sender, receiver)
+            | ReuniteError::OutstandingClones(sender, receiver) => (sender, receiver),
+        }
+    }
+}
+
+impl<S> fmt::Display for ReuniteError<S> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ReuniteError::NotPair(..) => {
+                f.write_str("sender and receiver were split from different streams")
+            }
+            ReuniteError::OutstandingClones(sender, _) => write!(
+                f,
+                "clones of the sender are still alive ({} outstanding)",
+                sender.reference_count() - 2
+            ),
+        }
+    }
+}
+
+impl<S: fmt::Debug> std::error::Error for ReuniteError<S> {}
+
 #[derive(Debug)]
-struct Shared<S>(Mutex<WebSocketStream<S>>);
+struct Shared<S> {
+    ws: Mutex<WebSocketStream<S>>,
+    queue: Mutex<priority::Queue>,
+    capacity: Option<capacity::Capacity>,
+    /// The counters of the stream, which can be read without locking it.
+    metrics: Arc<ConnectionMetrics>,
+}
 
 impl<S> Shared<S> {
+    fn new(ws: WebSocketStream<S>) -> Self {
+        Shared {
+            metrics: ws.inner.get_ref().metrics().clone(),
+            ws: Mutex::new(ws),
+            queue: Default::default(),
+            capacity: None,
+        }
+    }
+
     fn lock(&self) -> MutexGuard<'_, WebSocketStream<S>> {
-        self.0.lock().expect("lock shared stream")
+        self.ws.lock().expect("lock shared stream")
+    }
+
+    /// Waits until there is room for another send, if the capacity is
+    /// limited.
+    async fn acquire(&self) -> Result<Option<capacity::Permit<'_>>, WsError> {
+        match &self.capacity {
+            Some(capacity) => capacity.acquire().await.map(Some),
+            None => Ok(None),
+        }
+    }
+
+    fn queue(&self) -> MutexGuard<'_, priority::Queue> {
+        self.queue.lock().expect("lock send queue")
     }
 
     fn into_inner(self) -> WebSocketStream<S> {
-        self.0.into_inner().expect("get shared stream")
+        self.ws.into_inner().expect("get shared stream")
+    }
+}
+
+#[cfg(any(
+    feature = "async-std-runtime",
+    feature = "tokio-runtime",
+    feature = "smol-runtime"
+))]
+/// Returns the request for retrying a handshake that the server rejected with
+/// an authentication challenge, if `authorize` provides credentials for it.
+pub(crate) fn authorize_retry<F>(
+    request: &tungstenite::handshake::client::Request,
+    err: &WsError,
+    authorize: F,
+) -> Option<tungstenite::handshake::client::Request>
+where
+    F: FnOnce(&Response) -> Option<tungstenite::http::HeaderValue>,
+{
+    match err {
+        WsError::Http(response)
+            if response.status() == tungstenite::http::StatusCode::UNAUTHORIZED =>
+        {
+            let value = authorize(response)?;
+            let mut request = request.clone();
+            request
+                .headers_mut()
+                .insert(tungstenite::http::header::AUTHORIZATION, value);
+            Some(request)
+        }
+        _ => None,
+    }
+}
+
+#[cfg(any(
+    feature = "async-std-runtime",
+    feature = "tokio-runtime",
+    feature = "smol-runtime"
+))]
+/// Sets the `Sec-WebSocket-Protocol` header of `request` to `protocols`, in
+/// order of preference.
+#[allow(clippy::result_large_err)]
+pub(crate) fn with_protocols(
+    mut request: tungstenite::handshake::client::Request,
+    protocols: &[&str],
+) -> Result<tungstenite::handshake::client::Request, WsError> {
+    let header = tungstenite::http::header::SEC_WEBSOCKET_PROTOCOL;
+    if protocols.is_empty() {
+        request.headers_mut().remove(header);
+    } else {
+        let value = tungstenite::http::HeaderValue::from_str(&protocols.join(", "))
+            .map_err(|e| WsError::HttpFormat(e.into()))?;
+        request.headers_mut().insert(header, value);
     }
+    Ok(request)
 }
 
 #[cfg(any(
     feature = "async-tls",
     feature = "async-std-runtime",
     feature = "tokio-runtime",
-    feature = "gio-runtime"
+    feature = "gio-runtime",
+    feature = "smol-runtime"
 ))]
 /// Get a domain from an URL.
 #[inline]
+#[allow(clippy::result_large_err)]
 pub(crate) fn domain(
     request: &tungstenite::handshake::client::Request,
 ) -> Result<String, tungstenite::Error> {
@@ -775,10 +2869,12 @@ pub(crate) fn domain(
 #[cfg(any(
     feature = "async-std-runtime",
     feature = "tokio-runtime",
-    feature = "gio-runtime"
+    feature = "gio-runtime",
+    feature = "smol-runtime"
 ))]
 /// Get the port from an URL.
 #[inline]
+#[allow(clippy::result_large_err)]
 pub(crate) fn port(
     request: &tungstenite::handshake::client::Request,
 ) -> Result<u16, tungstenite::Error> {
@@ -795,13 +2891,41 @@ pub(crate) fn port(
         ))
 }
 
+#[cfg(all(
+    feature = "socket2",
+    any(feature = "async-std-runtime", feature = "smol-runtime")
+))]
+/// Connects `socket` to `addr` without blocking the executor.
+///
+/// The connection is established once the socket becomes writable.
+pub(crate) async fn connect_nonblocking(
+    socket: socket2::Socket,
+    addr: std::net::SocketAddr,
+) -> std::io::Result<async_io::Async<std::net::TcpStream>> {
+    socket.set_nonblocking(true)?;
+    match socket.connect(&addr.into()) {
+        Ok(()) => {}
+        #[cfg(unix)]
+        Err(err) if err.raw_os_error() == Some(libc::EINPROGRESS) => {}
+        Err(err) if err.kind() == std::io::ErrorKind::WouldBlock => {}
+        Err(err) => return Err(err),
+    }
+    let stream = async_io::Async::new(std::net::TcpStream::from(socket))?;
+    stream.writable().await?;
+    match stream.get_ref().take_error()? {
+        None => Ok(stream),
+        Some(err) => Err(err),
+    }
+}
+
 #[cfg(test)]
 mod tests {
     #[cfg(any(
         feature = "async-tls",
         feature = "async-std-runtime",
         feature = "tokio-runtime",
-        feature = "gio-runtime"
+        feature = "gio-runtime",
+        feature = "smol-runtime"
     ))]
     #[test]
     fn domain_strips_ipv6_brackets() {
@@ -811,6 +2935,94 @@ mod tests {
         assert_eq!(crate::domain(&request).unwrap(), "::1");
     }
 
+    #[cfg(feature = "handshake")]
+    #[test]
+    fn redirect_keeps_credentials_only_for_same_origin() {
+        use tungstenite::client::IntoClientRequest;
+        use tungstenite::http::{header, HeaderValue, Response, StatusCode};
+
+        let mut request = "wss://example.com/a".into_client_request().unwrap();
+        let headers = request.headers_mut();
+        headers.insert(header::AUTHORIZATION, HeaderValue::from_static("secret"));
+        headers.append("x-tag", HeaderValue::from_static("one"));
+        headers.append("x-tag", HeaderValue::from_static("two"));
+
+        let redirect = |location| {
+            let response = Response::builder()
+                .status(StatusCode::FOUND)
+                .header(header::LOCATION, location)
+                .body(None)
+                .unwrap();
+            crate::redirect_request(&request, &response)
+                .unwrap()
+                .unwrap()
+        };
+
+        let next = redirect("/b");
+        assert_eq!(next.uri(), "wss://example.com/b");
+        assert_eq!(next.headers()[header::AUTHORIZATION], "secret");
+        let tags: Vec<_> = next.headers().get_all("x-tag").iter().collect();
+        assert_eq!(tags, ["one", "two"]);
+        assert_eq!(next.headers().get_all(header::UPGRADE).iter().count(), 1);
+
+        // Downgrading to plaintext drops the credentials.
+        let next = redirect("ws://example.com/b");
+        assert!(next.headers().get(header::AUTHORIZATION).is_none());
+        assert_eq!(next.headers().get_all("x-tag").iter().count(), 2);
+
+        let next = redirect("wss://other.example.com/b");
+        assert!(next.headers().get(header::AUTHORIZATION).is_none());
+    }
+
+    #[test]
+    fn config_builder_validates() {
+        use crate::{ConfigBuilder, ConfigError};
+
+        let err = ConfigBuilder::new()
+            .write_buffer_size(1024)
+            .max_write_buffer_size(1024)
+            .build();
+        assert_eq!(err.unwrap_err(), ConfigError::WriteBufferTooLarge);
+
+        let err = ConfigBuilder::new()
+            .max_message_size(Some(1024))
+            .max_frame_size(Some(2048))
+            .build();
+        assert_eq!(err.unwrap_err(), ConfigError::FrameLargerThanMessage);
+
+        let config = ConfigBuilder::new()
+            .max_message_size(None)
+            .max_frame_size(Some(2048))
+            .accept_unmasked_frames(true)
+            .build()
+            .unwrap();
+        assert_eq!(config.max_frame_size, Some(2048));
+        assert!(config.accept_unmasked_frames);
+
+        let config = ConfigBuilder::high_throughput().build().unwrap();
+        assert_eq!(config.write_buffer_size, 1024 * 1024);
+        assert!(config.max_write_buffer_size > config.write_buffer_size);
+    }
+
+    #[cfg(feature = "handshake")]
+    #[test]
+    fn verify_accept() {
+        use tungstenite::handshake::client::Response;
+
+        // Example from RFC 6455, section 1.3.
+        let key = "dGhlIHNhbXBsZSBub25jZQ==";
+        let mut response = Response::new(None);
+        response.headers_mut().insert(
+            "Sec-WebSocket-Accept",
+            "s3pPLMBiTxaQ9kYGzzhZRbK+xOo=".parse().unwrap(),
+        );
+        assert!(crate::verify_accept(&response, key));
+        assert!(!crate::verify_accept(&response, "AQIDBAUGBwgJCgsMDQ4PEC=="));
+
+        let response = Response::new(None);
+        assert!(!crate::verify_accept(&response, key));
+    }
+
     #[cfg(feature = "handshake")]
     #[test]
     fn requests_cannot_contain_invalid_uris() {
@@ -0,0 +1,168 @@
+//! Frames that are serialized once and sent on many connections.
+use std::convert::TryFrom;
+use std::io::IoSlice;
+use std::sync::Arc;
+
+use futures_io::{AsyncRead, AsyncWrite};
+use tungstenite::error::ProtocolError;
+use tungstenite::protocol::frame::{
+    coding::{Data as OpData, OpCode},
+    Frame,
+};
+use tungstenite::protocol::Role;
+use tungstenite::{Bytes, Error as WsError, Message, Utf8Bytes};
+
+use crate::{telemetry, FrameKind, WebSocketStream};
+
+/// A message that was serialized to an unmasked frame once, to send it on
+/// many connections with [`WebSocketStream::send_prepared`].
+///
+/// Cloning is cheap, the payload is shared.
+#[derive(Debug, Clone)]
+pub struct PreparedFrame {
+    header: Vec<u8>,
+    payload: Bytes,
+    message: Message,
+}
+
+impl PreparedFrame {
+    /// Serializes `msg` as a single frame.
+    pub fn new(msg: Message) -> Self {
+        let mut frame = match msg.clone() {
+            Message::Text(text) => Frame::message(text, OpCode::Data(OpData::Text), true),
+            Message::Binary(data) => Frame::message(data, OpCode::Data(OpData::Binary), true),
+            Message::Ping(data) => Frame::ping(data),
+            Message::Pong(data) => Frame::pong(data),
+            Message::Close(close) => Frame::close(close),
+            Message::Frame(frame) => frame,
+        };
+        frame.header_mut().mask = None;
+
+        let len = frame.payload().len() as u64;
+        let mut header = Vec::with_capacity(frame.header().len(len));
+        frame
+            .header()
+            .format(len, &mut header)
+            .expect("format frame header");
+
+        PreparedFrame {
+            header,
+            payload: frame.into_payload(),
+            message: msg,
+        }
+    }
+
+    /// The bytes left to write after `written` bytes of the frame.
+    fn remaining(&self, written: usize) -> [IoSlice<'_>; 2] {
+        match written.checked_sub(self.header.len()) {
+            Some(payload_written) => [
+                IoSlice::new(&[]),
+                IoSlice::new(&self.payload[payload_written..]),
+            ],
+            None => [
+                IoSlice::new(&self.header[written..]),
+                IoSlice::new(&self.payload),
+            ],
+        }
+    }
+
+    fn len(&self) -> usize {
+        self.header.len() + self.payload.len()
+    }
+}
+
+/// A prepared frame that is being written to the stream, see
+/// [`AllowStd::stage`](crate::compat::AllowStd::stage).
+#[derive(Debug)]
+pub(crate) struct Staged {
+    frame: PreparedFrame,
+    written: usize,
+}
+
+impl Staged {
+    pub(crate) fn new(frame: PreparedFrame) -> Self {
+        Staged { frame, written: 0 }
+    }
+
+    pub(crate) fn remaining(&self) -> [IoSlice<'_>; 2] {
+        self.frame.remaining(self.written)
+    }
+
+    /// Returns whether the frame was written completely after `n` more bytes.
+    pub(crate) fn advance(&mut self, n: usize) -> bool {
+        self.written += n;
+        self.written == self.frame.len()
+    }
+}
+
+impl<S> WebSocketStream<S>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    /// Sends a frame that was serialized before, e.g. one that is broadcast
+    /// to many clients.
+    ///
+    /// Pending data is flushed first. A server then writes the header and
+    /// the payload of the frame with a single vectored write if the
+    /// underlying stream supports it, instead of serializing the message
+    /// again for every connection.
+    ///
+    /// Clients have to mask every frame with a new key, so they send the
+    /// message like [`send`](Self::send) does. The same applies to close
+    /// messages, which change the state of the connection.
+    ///
+    /// Like with [`send`](Self::send), the
+    /// [send deadline](Self::set_send_deadline) applies, and dropping the
+    /// future after it started writing the frame doesn't corrupt the
+    /// connection: the rest of the frame is written before anything else on
+    /// the next write or flush.
+    pub async fn send_prepared(&mut self, frame: &PreparedFrame) -> Result<(), WsError> {
+        if self.role == Role::Client || frame.message.is_close() {
+            return self.send(frame.message.clone()).await;
+        }
+        if frame.message.is_text() || frame.message.is_binary() {
+            self.check_send_size(frame.payload.len())?;
+        }
+
+        std::future::poll_fn(|cx| self.poll_flush(cx)).await?;
+        if !self.inner.can_write() {
+            return Err(if self.closed_by_peer {
+                WsError::ConnectionClosed
+            } else {
+                WsError::Protocol(ProtocolError::SendAfterClosing)
+            });
+        }
+
+        // tungstenite's buffer is empty now, so nothing is written before the
+        // staged frame.
+        self.inner.get_mut().stage(frame.clone());
+        let sample = telemetry::MessageSample::new(&frame.message);
+        self.last_activity = self.clock.now();
+        sample.record(
+            telemetry::Direction::Sent,
+            &mut self.round_trip,
+            self.inner.get_ref().metrics(),
+        );
+        if frame.message.is_ping() {
+            self.ping_sent();
+        }
+        std::future::poll_fn(|cx| self.poll_flush(cx)).await
+    }
+
+    /// Sends `data` as a text or binary message without copying it, e.g. to
+    /// broadcast the same payload to many connections.
+    ///
+    /// A server writes the payload straight from `data` like
+    /// [`send_prepared`](Self::send_prepared), only the frame header is
+    /// serialized for every connection. Clients have to mask the payload, so
+    /// they copy it like [`send`](Self::send). Text is validated as UTF-8 on
+    /// every call.
+    pub async fn send_shared(&mut self, data: Arc<[u8]>, kind: FrameKind) -> Result<(), WsError> {
+        let data = Bytes::from_owner(data);
+        let msg = match kind {
+            FrameKind::Binary => Message::Binary(data),
+            FrameKind::Text => Message::Text(Utf8Bytes::try_from(data)?),
+        };
+        self.send_prepared(&PreparedFrame::new(msg)).await
+    }
+}
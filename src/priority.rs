@@ -0,0 +1,163 @@
+//! Prioritized sending for the [sender](WebSocketSender) part of a
+//! [websocket](crate::WebSocketStream).
+use std::cmp::Reverse;
+use std::collections::BTreeMap;
+use std::future::Future;
+use std::pin::Pin;
+use std::task::{Context, Poll, Waker};
+
+use futures_io::{AsyncRead, AsyncWrite};
+use tungstenite::{Error as WsError, Message};
+
+use crate::{send_helper, Shared, WebSocketSender};
+
+/// Position of a waiting message: higher priorities first, then in the order
+/// of arrival.
+type Ticket = (Reverse<u8>, u64);
+
+/// The priority of a message passed to
+/// [`WebSocketSender::send_priority`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub enum Priority {
+    /// Regular data, sent in the order of arrival.
+    #[default]
+    Normal,
+    /// Sent ahead of all waiting messages of [`Normal`](Self::Normal)
+    /// priority.
+    High,
+}
+
+/// Messages waiting for their turn to be sent.
+#[derive(Debug, Default)]
+pub(crate) struct Queue {
+    waiting: BTreeMap<Ticket, Option<Waker>>,
+    next: u64,
+}
+
+impl Queue {
+    fn enqueue(&mut self, priority: u8, waker: &Waker) -> Ticket {
+        let ticket = (Reverse(priority), self.next);
+        self.next += 1;
+        self.waiting.insert(ticket, Some(waker.clone()));
+        ticket
+    }
+
+    /// Whether `ticket` is next, otherwise `waker` is woken once it might be.
+    fn is_next(&mut self, ticket: Ticket, waker: &Waker) -> bool {
+        if self.waiting.keys().next() == Some(&ticket) {
+            return true;
+        }
+        if let Some(slot) = self.waiting.get_mut(&ticket) {
+            match slot {
+                Some(w) if w.will_wake(waker) => {}
+                _ => *slot = Some(waker.clone()),
+            }
+        }
+        false
+    }
+
+    fn remove(&mut self, ticket: Ticket) {
+        self.waiting.remove(&ticket);
+        if let Some(Some(waker)) = self.waiting.values().next() {
+            waker.wake_by_ref();
+        }
+    }
+}
+
+impl<S> WebSocketSender<S>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    /// Sends a message ahead of other prioritized messages with a lower
+    /// `priority`.
+    ///
+    /// While the connection can't accept more data, messages passed to this
+    /// method wait in a queue at this layer that is ordered by priority and
+    /// then by arrival, so the most important message is handed to the
+    /// connection first once it becomes writable. Messages are always
+    /// written as a whole, so a fragmented message is never interrupted.
+    ///
+    /// Messages passed to [`send`](Self::send) don't take part in the
+    /// ordering and are written as soon as possible, which makes it suitable
+    /// for urgent control frames.
+    pub async fn send_prioritized(&self, msg: Message, priority: u8) -> Result<(), WsError> {
+        let _permit = self.shared.acquire().await?;
+        SendPrioritized {
+            shared: &self.shared,
+            msg: Some(msg),
+            priority,
+            ticket: None,
+        }
+        .await
+    }
+
+    /// Sends a message ahead of waiting messages with a lower `priority`.
+    ///
+    /// Control frames, i.e. pings, pongs and close frames, are always sent
+    /// with [`Priority::High`] so they don't starve behind a flood of data.
+    /// This is the same as [`send_prioritized`](Self::send_prioritized) with
+    /// a priority of `0` for [`Priority::Normal`] and `u8::MAX` for
+    /// [`Priority::High`], so both can be mixed.
+    pub async fn send_priority(&self, msg: Message, priority: Priority) -> Result<(), WsError> {
+        let priority = if msg.is_ping() || msg.is_pong() || msg.is_close() {
+            Priority::High
+        } else {
+            priority
+        };
+        let priority = match priority {
+            Priority::Normal => 0,
+            Priority::High => u8::MAX,
+        };
+        self.send_prioritized(msg, priority).await
+    }
+}
+
+struct SendPrioritized<'a, S> {
+    shared: &'a Shared<S>,
+    msg: Option<Message>,
+    priority: u8,
+    ticket: Option<Ticket>,
+}
+
+impl<S> Future for SendPrioritized<'_, S>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    type Output = Result<(), WsError>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let me = self.get_mut();
+
+        if me.msg.is_some() {
+            let mut queue = me.shared.queue();
+            let priority = me.priority;
+            let ticket = *me
+                .ticket
+                .get_or_insert_with(|| queue.enqueue(priority, cx.waker()));
+            if !queue.is_next(ticket, cx.waker()) {
+                return Poll::Pending;
+            }
+        }
+
+        let mut ws = me.shared.lock();
+        let res = send_helper(&mut ws, &mut me.msg, cx);
+        drop(ws);
+
+        // Once the message was handed over, the next one may go ahead while
+        // this one is flushed.
+        if me.msg.is_none() || res.is_ready() {
+            if let Some(ticket) = me.ticket.take() {
+                me.shared.queue().remove(ticket);
+            }
+        }
+        res
+    }
+}
+
+impl<S> Drop for SendPrioritized<'_, S> {
+    fn drop(&mut self) {
+        if let Some(ticket) = self.ticket.take() {
+            self.shared.queue().remove(ticket);
+        }
+    }
+}
@@ -0,0 +1,121 @@
+//! Bridging of two [websockets](WebSocketStream), e.g. for reverse proxies.
+use std::future::{poll_fn, Future};
+use std::pin::Pin;
+use std::task::Poll;
+
+use futures_core::Stream;
+use futures_io::{AsyncRead, AsyncWrite};
+use tungstenite::protocol::CloseFrame;
+use tungstenite::{Error as WsError, Message};
+
+use crate::{WebSocketReceiver, WebSocketSender, WebSocketStream};
+
+/// One of the two websockets passed to [`proxy`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Side {
+    /// The first websocket.
+    A,
+    /// The second websocket.
+    B,
+}
+
+/// Forwards all messages between `a` and `b` until both are closed.
+///
+/// Text and binary messages are passed on unchanged. Pings and pongs are not
+/// forwarded, as each connection answers the pings of its own peer. Once one
+/// side sends a close frame or ends, the close is propagated to the other
+/// side and the close handshake of both connections is driven to completion.
+///
+/// Returns which side closed first together with its close frame, if any.
+/// If reading from one side fails, the other side is closed as well. Once
+/// both connections ended, the error of the side that ended first is
+/// returned, or else that of the other side, e.g. if it failed while its
+/// close handshake was driven to completion.
+pub async fn proxy<A, B>(
+    a: WebSocketStream<A>,
+    b: WebSocketStream<B>,
+) -> Result<(Side, Option<CloseFrame>), WsError>
+where
+    A: AsyncRead + AsyncWrite + Unpin,
+    B: AsyncRead + AsyncWrite + Unpin,
+{
+    let (a_tx, a_rx) = a.split();
+    let (b_tx, b_rx) = b.split();
+
+    let mut a_to_b = Box::pin(forward(a_rx, &b_tx));
+    let mut b_to_a = Box::pin(forward(b_rx, &a_tx));
+    let mut a_res = None;
+    let mut b_res = None;
+    let mut first = None;
+
+    poll_fn(|cx| {
+        if a_res.is_none() {
+            if let Poll::Ready(res) = a_to_b.as_mut().poll(cx) {
+                a_res = Some(res);
+                first.get_or_insert(Side::A);
+            }
+        }
+        if b_res.is_none() {
+            if let Poll::Ready(res) = b_to_a.as_mut().poll(cx) {
+                b_res = Some(res);
+                first.get_or_insert(Side::B);
+            }
+        }
+        if a_res.is_some() && b_res.is_some() {
+            Poll::Ready(())
+        } else {
+            Poll::Pending
+        }
+    })
+    .await;
+
+    let (a_res, b_res) = (a_res.expect("finished"), b_res.expect("finished"));
+    let first = first.expect("finished");
+    let (first_res, other_res) = match first {
+        Side::A => (a_res, b_res),
+        Side::B => (b_res, a_res),
+    };
+    let frame = first_res?;
+    other_res?;
+    Ok((first, frame))
+}
+
+/// Forwards the messages of `from` to `to` until `from` is closed and returns
+/// the close frame it received.
+async fn forward<S, T>(
+    mut from: WebSocketReceiver<S>,
+    to: &WebSocketSender<T>,
+) -> Result<Option<CloseFrame>, WsError>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+    T: AsyncRead + AsyncWrite + Unpin,
+{
+    loop {
+        let msg = match poll_fn(|cx| Pin::new(&mut from).poll_next(cx)).await {
+            Some(Ok(msg)) => msg,
+            Some(Err(err)) => {
+                let _ = to.close(None).await;
+                return Err(err);
+            }
+            None => {
+                let _ = to.close(None).await;
+                return Ok(None);
+            }
+        };
+
+        match msg {
+            Message::Text(_) | Message::Binary(_) => {
+                // A failing send means the other side is gone, which is
+                // handled by the other direction.
+                let _ = to.send(msg).await;
+            }
+            Message::Close(frame) => {
+                let _ = to.close(frame.clone()).await;
+                // Keep reading to complete the close handshake.
+                while let Some(Ok(_)) = poll_fn(|cx| Pin::new(&mut from).poll_next(cx)).await {}
+                return Ok(frame);
+            }
+            Message::Ping(_) | Message::Pong(_) | Message::Frame(_) => {}
+        }
+    }
+}
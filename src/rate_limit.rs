@@ -0,0 +1,227 @@
+//! Pacing of accepted connections and sent messages.
+use std::fmt;
+use std::future::Future;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use futures_io::{AsyncRead, AsyncWrite};
+use tungstenite::{Error as WsError, Message};
+
+#[cfg(feature = "test-clock")]
+use crate::clock::TestClock;
+use crate::{Clock, WebSocketSender};
+
+/// Limits the rate at which a server accepts connections.
+///
+/// This is coarse protection against connection storms, so that a flood of
+/// connections doesn't overwhelm the capacity for handshakes. Use
+/// [`pace`](Self::pace) before each `accept` to leave excess connections
+/// waiting in the operating system's backlog, or accept them and drop those
+/// for which [`try_admit`](Self::try_admit) returns `false`.
+///
+/// ```no_run
+/// # async fn test() {
+/// use async_std::net::TcpListener;
+/// use async_tungstenite::AcceptRateLimit;
+///
+/// let listener = TcpListener::bind("127.0.0.1:8080").await.unwrap();
+/// let mut limit = AcceptRateLimit::new(100);
+/// loop {
+///     limit.pace(async_std::task::sleep).await;
+///     let (connection, _) = listener.accept().await.unwrap();
+///     // ...
+/// }
+/// # }
+/// ```
+#[derive(Debug, Clone)]
+pub struct AcceptRateLimit {
+    interval: Duration,
+    next: Option<Instant>,
+    clock: Clock,
+}
+
+impl AcceptRateLimit {
+    /// Creates a limit of `per_sec` connections per second.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `per_sec` is `0`.
+    pub fn new(per_sec: u32) -> Self {
+        assert!(per_sec > 0, "rate must not be zero");
+        AcceptRateLimit {
+            interval: Duration::from_secs(1) / per_sec,
+            next: None,
+            clock: Clock::default(),
+        }
+    }
+
+    /// Waits until the next connection may be accepted.
+    ///
+    /// `sleep` returns a future of the runtime in use, see
+    /// [timers](crate#timers).
+    pub async fn pace<F, Fut>(&mut self, mut sleep: F)
+    where
+        F: FnMut(Duration) -> Fut,
+        Fut: Future<Output = ()>,
+    {
+        let now = self.clock.now();
+        let slot = match self.next {
+            Some(next) if next > now => next,
+            _ => now,
+        };
+        self.next = Some(slot + self.interval);
+        if slot > now {
+            sleep(slot - now).await;
+        }
+    }
+
+    /// Returns whether a connection may be accepted right now.
+    ///
+    /// Connections for which this returns `false` exceed the rate and should
+    /// be dropped.
+    pub fn try_admit(&mut self) -> bool {
+        let now = self.clock.now();
+        match self.next {
+            Some(next) if next > now => false,
+            _ => {
+                self.next = Some(now + self.interval);
+                true
+            }
+        }
+    }
+
+    /// Installs a [`TestClock`] as time source for this limit.
+    #[cfg(feature = "test-clock")]
+    pub fn set_clock(&mut self, clock: TestClock) {
+        self.clock = Clock::Test(clock);
+    }
+}
+
+/// A [`WebSocketSender`] that limits the rate of outgoing messages, see
+/// [`WebSocketSender::rate_limited`].
+///
+/// The limit is a token bucket: up to `burst` messages are sent right away,
+/// after that one message every `1 / per_sec` seconds. Clones share the
+/// bucket, so the limit applies to the connection as a whole.
+pub struct RateLimitedSender<S, F> {
+    sender: WebSocketSender<S>,
+    bucket: Arc<Mutex<Bucket>>,
+    sleep: F,
+}
+
+#[derive(Debug)]
+struct Bucket {
+    interval: Duration,
+    burst: u32,
+    // When the bucket is full again if no more messages are sent.
+    full_at: Option<Instant>,
+    clock: Clock,
+}
+
+impl Bucket {
+    /// Takes a token and returns how long to wait until it is available.
+    fn reserve(&mut self) -> Option<Duration> {
+        let now = self.clock.now();
+        let full_at = match self.full_at {
+            Some(full_at) if full_at > now => full_at,
+            _ => now,
+        };
+        self.full_at = Some(full_at + self.interval);
+        // The bucket holds `burst` tokens, so the token is available once it
+        // is at most `burst - 1` intervals away from being full.
+        let tolerance = self.interval * (self.burst - 1);
+        let wait = full_at.saturating_duration_since(now);
+        wait.checked_sub(tolerance).filter(|wait| !wait.is_zero())
+    }
+}
+
+impl<S> WebSocketSender<S> {
+    /// Limits the sender to `per_sec` messages per second.
+    ///
+    /// Only messages sent through the returned sender count towards the
+    /// limit. `sleep` returns a future of the runtime in use, see
+    /// [timers](crate#timers).
+    ///
+    /// # Panics
+    ///
+    /// Panics if `per_sec` is `0`.
+    pub fn rate_limited<F, Fut>(self, per_sec: u32, sleep: F) -> RateLimitedSender<S, F>
+    where
+        F: Fn(Duration) -> Fut,
+        Fut: Future<Output = ()>,
+    {
+        assert!(per_sec > 0, "rate must not be zero");
+        let clock = self.shared.lock().clock.clone();
+        RateLimitedSender {
+            sender: self,
+            bucket: Arc::new(Mutex::new(Bucket {
+                interval: Duration::from_secs(1) / per_sec,
+                burst: 1,
+                full_at: None,
+                clock,
+            })),
+            sleep,
+        }
+    }
+}
+
+impl<S, F, Fut> RateLimitedSender<S, F>
+where
+    F: Fn(Duration) -> Fut,
+    Fut: Future<Output = ()>,
+{
+    /// Allows sending up to `burst` messages at once after the sender was
+    /// idle. The default is `1`, i.e. messages are evenly spaced.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `burst` is `0`.
+    pub fn with_burst(self, burst: u32) -> Self {
+        assert!(burst > 0, "burst must not be zero");
+        self.bucket.lock().expect("lock rate limit").burst = burst;
+        self
+    }
+
+    /// Sends a message once the limit allows it, see
+    /// [`WebSocketSender::send`].
+    ///
+    /// This can be called concurrently, all calls share the limit.
+    pub async fn send(&self, msg: Message) -> Result<(), WsError>
+    where
+        S: AsyncRead + AsyncWrite + Unpin,
+    {
+        let wait = self.bucket.lock().expect("lock rate limit").reserve();
+        if let Some(wait) = wait {
+            (self.sleep)(wait).await;
+        }
+        self.sender.send(msg).await
+    }
+
+    /// Returns the underlying sender, which is not limited.
+    pub fn get_ref(&self) -> &WebSocketSender<S> {
+        &self.sender
+    }
+
+    /// Returns the underlying sender.
+    pub fn into_inner(self) -> WebSocketSender<S> {
+        self.sender
+    }
+}
+
+impl<S, F: Clone> Clone for RateLimitedSender<S, F> {
+    fn clone(&self) -> Self {
+        RateLimitedSender {
+            sender: self.sender.clone(),
+            bucket: self.bucket.clone(),
+            sleep: self.sleep.clone(),
+        }
+    }
+}
+
+impl<S, F> fmt::Debug for RateLimitedSender<S, F> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RateLimitedSender")
+            .field("bucket", &self.bucket)
+            .finish_non_exhaustive()
+    }
+}
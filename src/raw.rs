@@ -0,0 +1,64 @@
+//! Sending of hand-crafted frames for conformance testing.
+use std::pin::Pin;
+
+use futures_io::{AsyncRead, AsyncWrite};
+use tungstenite::protocol::frame::{
+    coding::{Control, OpCode},
+    FrameHeader,
+};
+use tungstenite::Error as WsError;
+
+use crate::WebSocketStream;
+
+impl<S> WebSocketStream<S>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    /// Writes a close frame with exactly the given `payload` and `mask`.
+    ///
+    /// Unlike [`close`](Self::close), the payload is not built from a close
+    /// code and reason, so it may be malformed on purpose, e.g. to check that
+    /// the peer rejects it. `mask` is applied as given, also if this is a
+    /// server or if it is `None` for a client.
+    ///
+    /// Pending data is flushed first and the frame is then written directly
+    /// to the underlying stream. The stream's own state is not changed, it
+    /// does not know that a close frame was sent.
+    pub async fn send_raw_close(
+        &mut self,
+        payload: &[u8],
+        mask: Option<[u8; 4]>,
+    ) -> Result<(), WsError> {
+        std::future::poll_fn(|cx| self.poll_flush(cx)).await?;
+
+        let header = FrameHeader {
+            opcode: OpCode::Control(Control::Close),
+            mask,
+            ..FrameHeader::default()
+        };
+        let mut frame = Vec::with_capacity(header.len(payload.len() as u64) + payload.len());
+        header.format(payload.len() as u64, &mut frame)?;
+        let start = frame.len();
+        frame.extend_from_slice(payload);
+        if let Some(mask) = mask {
+            for (i, byte) in frame[start..].iter_mut().enumerate() {
+                *byte ^= mask[i % 4];
+            }
+        }
+
+        let stream = self.inner.get_mut().get_mut();
+        let mut written = 0;
+        while written < frame.len() {
+            let n =
+                std::future::poll_fn(|cx| Pin::new(&mut *stream).poll_write(cx, &frame[written..]))
+                    .await?;
+            if n == 0 {
+                return Err(WsError::Io(std::io::ErrorKind::WriteZero.into()));
+            }
+            written += n;
+        }
+        std::future::poll_fn(|cx| Pin::new(&mut *stream).poll_flush(cx)).await?;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,138 @@
+//! Bounded read-ahead for the [receiver](WebSocketReceiver) part of a
+//! [websocket](crate::WebSocketStream).
+use std::collections::VecDeque;
+use std::fmt;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use futures_core::{FusedStream, Stream};
+use futures_io::{AsyncRead, AsyncWrite};
+use tungstenite::{Error as WsError, Message};
+
+use crate::WebSocketReceiver;
+
+/// A [`WebSocketReceiver`] that reads ahead into a buffer of fixed size.
+///
+/// Every poll reads as many messages as are available from the connection
+/// into the buffer until it holds `capacity` bytes. Each message is charged
+/// with its payload plus the size of a [`Message`], so that many small or
+/// empty messages fill the buffer as well. Once it is full, nothing is read
+/// from the connection until messages were taken out, so a fast peer is
+/// slowed down by TCP flow control instead of making the buffer grow.
+///
+/// The memory used for buffered messages is therefore bounded by `capacity`
+/// plus the size of the one message that crossed it. That message is only
+/// bounded by the
+/// [`max_message_size`](tungstenite::protocol::WebSocketConfig::max_message_size)
+/// of the connection, which should be lowered from its default of 64 MiB for
+/// a fixed memory budget.
+///
+/// Despite the name, the buffer is a queue of messages rather than a ring of
+/// bytes: tungstenite still allocates every message it reads, this only
+/// bounds how many of them are kept at a time.
+///
+/// Pings are only answered while messages are read, i.e. not while the
+/// buffer is full.
+pub struct RingReceiver<S> {
+    receiver: WebSocketReceiver<S>,
+    buffer: VecDeque<Message>,
+    capacity: usize,
+    buffered: usize,
+    /// How the receiver ended, yielded once the buffer is drained.
+    end: Option<Option<WsError>>,
+    terminated: bool,
+}
+
+impl<S> RingReceiver<S> {
+    /// Wraps `receiver` with a buffer of `capacity` bytes.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `capacity` is zero, as nothing could ever be read.
+    pub fn new(receiver: WebSocketReceiver<S>, capacity: usize) -> Self {
+        assert!(capacity > 0, "capacity must not be zero");
+        RingReceiver {
+            receiver,
+            buffer: VecDeque::new(),
+            capacity,
+            buffered: 0,
+            end: None,
+            terminated: false,
+        }
+    }
+
+    /// Returns the capacity of the buffer in bytes.
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// Returns the number of bytes the buffered messages are charged with.
+    pub fn buffered_bytes(&self) -> usize {
+        self.buffered
+    }
+
+    /// Whether the buffer is full and nothing is read from the connection.
+    pub fn is_full(&self) -> bool {
+        self.buffered >= self.capacity
+    }
+}
+
+/// Returns the number of bytes that `msg` is charged with in the buffer.
+fn charge(msg: &Message) -> usize {
+    msg.len() + std::mem::size_of::<Message>()
+}
+
+impl<S> Stream for RingReceiver<S>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    type Item = Result<Message, WsError>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let me = self.get_mut();
+        if me.terminated {
+            return Poll::Ready(None);
+        }
+        while !me.is_full() && me.end.is_none() {
+            match Pin::new(&mut me.receiver).poll_next(cx) {
+                Poll::Ready(Some(Ok(msg))) => {
+                    me.buffered += charge(&msg);
+                    me.buffer.push_back(msg);
+                }
+                Poll::Ready(Some(Err(err))) => me.end = Some(Some(err)),
+                Poll::Ready(None) => me.end = Some(None),
+                Poll::Pending => break,
+            }
+        }
+        if let Some(msg) = me.buffer.pop_front() {
+            me.buffered -= charge(&msg);
+            return Poll::Ready(Some(Ok(msg)));
+        }
+        match me.end.take() {
+            Some(end) => {
+                me.terminated = true;
+                Poll::Ready(end.map(Err))
+            }
+            None => Poll::Pending,
+        }
+    }
+}
+
+impl<S> FusedStream for RingReceiver<S>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    fn is_terminated(&self) -> bool {
+        self.terminated
+    }
+}
+
+impl<S> fmt::Debug for RingReceiver<S> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RingReceiver")
+            .field("capacity", &self.capacity)
+            .field("buffered", &self.buffered)
+            .field("messages", &self.buffer.len())
+            .finish_non_exhaustive()
+    }
+}
@@ -0,0 +1,412 @@
+//! `smol` integration.
+//!
+//! Connections are made with [`async_io::Async`], which `smol` re-exports as
+//! `smol::Async`, and host names are resolved on the thread pool of
+//! [`blocking`], the same building blocks `smol::net` uses.
+use std::future::Future;
+use std::net::{SocketAddr, TcpStream as StdTcpStream, ToSocketAddrs};
+
+use async_io::Async;
+use tungstenite::client::IntoClientRequest;
+use tungstenite::handshake::client::{Request, Response};
+use tungstenite::http::HeaderValue;
+use tungstenite::protocol::WebSocketConfig;
+use tungstenite::{Error, Message};
+
+use super::{
+    authorize_retry, domain, port, with_protocols, ConnectError, Heartbeat, HeartbeatHandle,
+    WebSocketSender, WebSocketStream,
+};
+
+use futures_io::{AsyncRead, AsyncWrite};
+
+#[cfg(not(any(feature = "async-tls", feature = "async-native-tls")))]
+pub(crate) mod dummy_tls {
+    use futures_io::{AsyncRead, AsyncWrite};
+
+    use tungstenite::client::{uri_mode, IntoClientRequest};
+    use tungstenite::handshake::client::Request;
+    use tungstenite::stream::Mode;
+    use tungstenite::Error;
+
+    use crate::{client_async_with_config, domain, Response, WebSocketConfig, WebSocketStream};
+
+    pub type AutoStream<S> = S;
+    type Connector = ();
+
+    async fn wrap_stream<S>(
+        socket: S,
+        _domain: String,
+        _connector: Option<()>,
+        mode: Mode,
+    ) -> Result<AutoStream<S>, Error>
+    where
+        S: 'static + AsyncRead + AsyncWrite + Unpin,
+    {
+        match mode {
+            Mode::Plain => Ok(socket),
+            Mode::Tls => Err(Error::Url(
+                tungstenite::error::UrlError::TlsFeatureNotEnabled,
+            )),
+        }
+    }
+
+    /// Creates a WebSocket handshake from a request and a stream,
+    /// upgrading the stream to TLS if required and using the given
+    /// connector and WebSocket configuration.
+    pub async fn client_async_tls_with_connector_and_config<R, S>(
+        request: R,
+        stream: S,
+        connector: Option<Connector>,
+        config: Option<WebSocketConfig>,
+    ) -> Result<(WebSocketStream<AutoStream<S>>, Response), Error>
+    where
+        R: IntoClientRequest + Unpin,
+        S: 'static + AsyncRead + AsyncWrite + Unpin,
+        AutoStream<S>: Unpin,
+    {
+        let request: Request = request.into_client_request()?;
+
+        let domain = domain(&request)?;
+
+        // Make sure we check domain and mode first. URL must be valid.
+        let mode = uri_mode(request.uri())?;
+
+        let stream = wrap_stream(stream, domain, connector, mode).await?;
+        client_async_with_config(request, stream, config).await
+    }
+}
+
+#[cfg(not(any(feature = "async-tls", feature = "async-native-tls")))]
+pub use self::dummy_tls::client_async_tls_with_connector_and_config;
+#[cfg(not(any(feature = "async-tls", feature = "async-native-tls")))]
+use self::dummy_tls::AutoStream;
+
+#[cfg(all(feature = "async-tls", not(feature = "async-native-tls")))]
+use crate::async_tls::AutoStream;
+#[cfg(all(feature = "async-tls", not(feature = "async-native-tls")))]
+pub use crate::async_tls::{client_async_tls_upgrade, client_async_tls_with_connector_and_config};
+#[cfg(all(feature = "async-tls", not(feature = "async-native-tls")))]
+type Connector = real_async_tls::TlsConnector;
+
+#[cfg(feature = "async-native-tls")]
+pub use crate::async_std::async_native_tls::{
+    client_async_tls_upgrade, client_async_tls_with_connector_and_config,
+};
+#[cfg(feature = "async-native-tls")]
+use crate::async_std::async_native_tls::{AutoStream, Connector};
+
+/// Type alias for the stream type of the `client_async()` functions.
+pub type ClientStream<S> = AutoStream<S>;
+
+#[cfg(feature = "async-native-tls")]
+/// Creates a WebSocket handshake from a request and a stream,
+/// upgrading the stream to TLS if required.
+pub async fn client_async_tls<R, S>(
+    request: R,
+    stream: S,
+) -> Result<(WebSocketStream<ClientStream<S>>, Response), Error>
+where
+    R: IntoClientRequest + Unpin,
+    S: 'static + AsyncRead + AsyncWrite + Unpin,
+    AutoStream<S>: Unpin,
+{
+    client_async_tls_with_connector_and_config(request, stream, None, None).await
+}
+
+#[cfg(feature = "async-native-tls")]
+/// Creates a WebSocket handshake from a request and a stream,
+/// upgrading the stream to TLS if required and using the given
+/// WebSocket configuration.
+pub async fn client_async_tls_with_config<R, S>(
+    request: R,
+    stream: S,
+    config: Option<WebSocketConfig>,
+) -> Result<(WebSocketStream<ClientStream<S>>, Response), Error>
+where
+    R: IntoClientRequest + Unpin,
+    S: 'static + AsyncRead + AsyncWrite + Unpin,
+    AutoStream<S>: Unpin,
+{
+    client_async_tls_with_connector_and_config(request, stream, None, config).await
+}
+
+#[cfg(feature = "async-native-tls")]
+/// Creates a WebSocket handshake from a request and a stream,
+/// upgrading the stream to TLS if required and using the given
+/// connector.
+pub async fn client_async_tls_with_connector<R, S>(
+    request: R,
+    stream: S,
+    connector: Option<Connector>,
+) -> Result<(WebSocketStream<ClientStream<S>>, Response), Error>
+where
+    R: IntoClientRequest + Unpin,
+    S: 'static + AsyncRead + AsyncWrite + Unpin,
+    AutoStream<S>: Unpin,
+{
+    client_async_tls_with_connector_and_config(request, stream, connector, None).await
+}
+
+/// Type alias for the stream type of the `connect_async()` functions.
+pub type ConnectStream = ClientStream<Async<StdTcpStream>>;
+
+/// Resolves `host` to the addresses to connect to.
+async fn resolve(host: &str, port: u16) -> Result<Vec<SocketAddr>, Error> {
+    let owned = host.to_owned();
+    let res = blocking::unblock(move || (owned.as_str(), port).to_socket_addrs()).await;
+    let addrs = match res {
+        Ok(addrs) => addrs.collect::<Vec<_>>(),
+        Err(source) => {
+            return Err(ConnectError::Dns {
+                host: host.to_owned(),
+                source,
+            }
+            .into())
+        }
+    };
+    if addrs.is_empty() {
+        return Err(ConnectError::Dns {
+            host: host.to_owned(),
+            source: std::io::ErrorKind::NotFound.into(),
+        }
+        .into());
+    }
+    Ok(addrs)
+}
+
+/// Resolves `host` and connects to the first address that accepts the connection.
+async fn connect_tcp(host: &str, port: u16) -> Result<Async<StdTcpStream>, Error> {
+    let mut last_err = None;
+    for addr in resolve(host, port).await? {
+        match Async::<StdTcpStream>::connect(addr).await {
+            Ok(stream) => return Ok(stream),
+            Err(err) => last_err = Some(err),
+        }
+    }
+    Err(ConnectError::Connect {
+        host: host.to_owned(),
+        port,
+        source: last_err.expect("no addresses"),
+    }
+    .into())
+}
+
+#[cfg(feature = "socket2")]
+/// Like `connect_tcp`, but creates the sockets itself and passes them to
+/// `configure` before connecting.
+async fn connect_tcp_with<F>(
+    host: &str,
+    port: u16,
+    configure: F,
+) -> Result<Async<StdTcpStream>, Error>
+where
+    F: Fn(&socket2::Socket) -> std::io::Result<()>,
+{
+    let mut last_err = None;
+    for addr in resolve(host, port).await? {
+        match connect_socket(addr, &configure).await {
+            Ok(stream) => return Ok(stream),
+            Err(err) => last_err = Some(err),
+        }
+    }
+    Err(ConnectError::Connect {
+        host: host.to_owned(),
+        port,
+        source: last_err.expect("no addresses"),
+    }
+    .into())
+}
+
+#[cfg(feature = "socket2")]
+async fn connect_socket<F>(addr: SocketAddr, configure: &F) -> std::io::Result<Async<StdTcpStream>>
+where
+    F: Fn(&socket2::Socket) -> std::io::Result<()>,
+{
+    let socket = socket2::Socket::new(
+        socket2::Domain::for_address(addr),
+        socket2::Type::STREAM,
+        Some(socket2::Protocol::TCP),
+    )?;
+    configure(&socket)?;
+    super::connect_nonblocking(socket, addr).await
+}
+
+/// Connect to a given URL.
+///
+/// Accepts any request that implements [`IntoClientRequest`], which is often just `&str`, but can
+/// be a variety of types such as `httparse::Request` or [`tungstenite::http::Request`] for more
+/// complex uses.
+///
+/// ```no_run
+/// # use tungstenite::client::IntoClientRequest;
+///
+/// # async fn test() {
+/// use tungstenite::http::{Method, Request};
+/// use async_tungstenite::smol::connect_async;
+///
+/// let mut request = "wss://api.example.com".into_client_request().unwrap();
+/// request.headers_mut().insert("api-key", "42".parse().unwrap());
+///
+/// let (stream, response) = connect_async(request).await.unwrap();
+/// # }
+/// ```
+pub async fn connect_async<R>(
+    request: R,
+) -> Result<(WebSocketStream<ConnectStream>, Response), Error>
+where
+    R: IntoClientRequest + Unpin,
+{
+    connect_async_with_config(request, None).await
+}
+
+/// Connect to a given URL with a given WebSocket configuration.
+pub async fn connect_async_with_config<R>(
+    request: R,
+    config: Option<WebSocketConfig>,
+) -> Result<(WebSocketStream<ConnectStream>, Response), Error>
+where
+    R: IntoClientRequest + Unpin,
+{
+    let request: Request = request.into_client_request()?;
+
+    let domain = domain(&request)?;
+    let port = port(&request)?;
+
+    let socket = connect_tcp(&domain, port).await?;
+    client_async_tls_with_connector_and_config(request, socket, None, config).await
+}
+
+#[cfg(feature = "socket2")]
+/// Connect to a given URL, letting `configure` set options on the socket
+/// before it connects.
+///
+/// `configure` is called with the socket of every address that is tried. This
+/// gives full control over the socket, e.g. to set the type of service or to
+/// bind to a local address, without dedicated options for each of them.
+pub async fn connect_async_with_socket_config<R, F>(
+    request: R,
+    config: Option<WebSocketConfig>,
+    configure: F,
+) -> Result<(WebSocketStream<ConnectStream>, Response), Error>
+where
+    R: IntoClientRequest + Unpin,
+    F: Fn(&socket2::Socket) -> std::io::Result<()>,
+{
+    let request: Request = request.into_client_request()?;
+
+    let domain = domain(&request)?;
+    let port = port(&request)?;
+
+    let socket = connect_tcp_with(&domain, port, configure).await?;
+    client_async_tls_with_connector_and_config(request, socket, None, config).await
+}
+
+/// Connect to a given URL, answering an authentication challenge of the server.
+///
+/// If the server rejects the upgrade with `401 Unauthorized`, `authorize` is called with its
+/// response, which carries the `WWW-Authenticate` challenge. If it returns a value for the
+/// `Authorization` header, the handshake is retried once on a new connection with that header
+/// added to the request. Otherwise the original error is returned.
+pub async fn connect_async_with_auth<R, F>(
+    request: R,
+    config: Option<WebSocketConfig>,
+    authorize: F,
+) -> Result<(WebSocketStream<ConnectStream>, Response), Error>
+where
+    R: IntoClientRequest + Unpin,
+    F: FnOnce(&Response) -> Option<HeaderValue>,
+{
+    let request: Request = request.into_client_request()?;
+
+    match connect_async_with_config(request.clone(), config).await {
+        Err(err) => match authorize_retry(&request, &err, authorize) {
+            Some(request) => connect_async_with_config(request, config).await,
+            None => Err(err),
+        },
+        res => res,
+    }
+}
+
+/// Connect to a given URL, offering the subprotocols `protocols` in order of
+/// preference.
+///
+/// The server's choice is available via
+/// [`WebSocketStream::selected_protocol`]. Any `Sec-WebSocket-Protocol` header
+/// of `request` is replaced.
+pub async fn connect_async_with_protocols<R>(
+    request: R,
+    protocols: &[&str],
+) -> Result<(WebSocketStream<ConnectStream>, Response), Error>
+where
+    R: IntoClientRequest + Unpin,
+{
+    let request = with_protocols(request.into_client_request()?, protocols)?;
+    connect_async_with_config(request, None).await
+}
+
+/// Connect to a given URL and send `initial` as the first message, e.g. to
+/// authenticate or subscribe.
+///
+/// If sending the message fails, the connection is closed before the error is
+/// returned.
+pub async fn connect_and_send<R>(
+    request: R,
+    initial: Message,
+) -> Result<(WebSocketStream<ConnectStream>, Response), Error>
+where
+    R: IntoClientRequest + Unpin,
+{
+    let (mut stream, response) = connect_async(request).await?;
+    if let Err(err) = stream.send(initial).await {
+        let _ = stream.close(None).await;
+        return Err(err);
+    }
+    Ok((stream, response))
+}
+
+/// Runs `heartbeat` on `sender` with the timer of `smol`.
+///
+/// See [`WebSocketSender::heartbeat`].
+pub fn heartbeat<S>(
+    sender: &WebSocketSender<S>,
+    heartbeat: Heartbeat,
+) -> (HeartbeatHandle, impl Future<Output = Result<(), Error>>)
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    sender.heartbeat(heartbeat, |interval| async move {
+        async_io::Timer::after(interval).await;
+    })
+}
+
+#[cfg(any(feature = "async-tls", feature = "async-native-tls"))]
+/// Connect to a given URL using the provided TLS connector.
+pub async fn connect_async_with_tls_connector<R>(
+    request: R,
+    connector: Option<Connector>,
+) -> Result<(WebSocketStream<ConnectStream>, Response), Error>
+where
+    R: IntoClientRequest + Unpin,
+{
+    connect_async_with_tls_connector_and_config(request, connector, None).await
+}
+
+#[cfg(any(feature = "async-tls", feature = "async-native-tls"))]
+/// Connect to a given URL using the provided TLS connector.
+pub async fn connect_async_with_tls_connector_and_config<R>(
+    request: R,
+    connector: Option<Connector>,
+    config: Option<WebSocketConfig>,
+) -> Result<(WebSocketStream<ConnectStream>, Response), Error>
+where
+    R: IntoClientRequest + Unpin,
+{
+    let request: Request = request.into_client_request()?;
+
+    let domain = domain(&request)?;
+    let port = port(&request)?;
+
+    let socket = connect_tcp(&domain, port).await?;
+    client_async_tls_with_connector_and_config(request, socket, connector, config).await
+}
@@ -0,0 +1,100 @@
+//! Socket level tuning of the underlying TCP connection via `socket2`.
+//!
+//! The functions in this module work on any stream that exposes its OS
+//! socket, e.g. the `TcpStream`s of the supported runtimes.
+use std::io;
+use std::time::Duration;
+
+use futures_io::{AsyncRead, AsyncWrite};
+use socket2::SockRef;
+pub use socket2::TcpKeepalive;
+use tungstenite::{Error as WsError, Message};
+
+use crate::WebSocketStream;
+
+/// Enables or disables `TCP_CORK` on the socket.
+///
+/// While corked, the kernel only sends full packets and coalesces the data of
+/// multiple writes. This is only supported on Linux, Android and Fuchsia and
+/// does nothing on other platforms.
+pub fn set_cork<S>(socket: &S, cork: bool) -> io::Result<()>
+where
+    for<'a> SockRef<'a>: From<&'a S>,
+{
+    #[cfg(any(target_os = "android", target_os = "fuchsia", target_os = "linux"))]
+    {
+        SockRef::from(socket).set_tcp_cork(cork)
+    }
+    #[cfg(not(any(target_os = "android", target_os = "fuchsia", target_os = "linux")))]
+    {
+        let _ = (socket, cork);
+        Ok(())
+    }
+}
+
+/// Whether `err` means that the operating system gave up on the connection,
+/// e.g. because the peer did not answer the probes enabled by
+/// [`WebSocketStream::set_tcp_keepalive`].
+///
+/// Unlike the errors of [deadlines](crate::DeadlineExceeded), which are of the
+/// same [`TimedOut`](io::ErrorKind::TimedOut) kind, these come straight from
+/// the socket.
+pub fn is_dead_peer(err: &WsError) -> bool {
+    match err {
+        WsError::Io(err) => err.kind() == io::ErrorKind::TimedOut && err.raw_os_error().is_some(),
+        _ => false,
+    }
+}
+
+impl<S> WebSocketStream<S>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+    for<'a> SockRef<'a>: From<&'a S>,
+{
+    /// Sends all messages with `TCP_CORK` enabled on the underlying socket.
+    ///
+    /// The socket is corked, all messages are queued and flushed once, and
+    /// the socket is uncorked again so that the remaining data is sent out.
+    /// The socket is also uncorked if sending fails.
+    ///
+    /// See [`set_cork`] for the supported platforms. On other platforms this
+    /// only batches the messages into a single flush.
+    pub async fn send_corked<I>(&mut self, msgs: I) -> Result<(), WsError>
+    where
+        I: IntoIterator<Item = Message>,
+    {
+        set_cork(self.get_ref(), true)?;
+        let res = self.send_batch(msgs).await;
+        let uncork = set_cork(self.get_ref(), false);
+        res?;
+        uncork.map_err(WsError::Io)
+    }
+
+    /// Makes dropping the stream reset the connection instead of closing it.
+    ///
+    /// This sets `SO_LINGER` to zero on the underlying socket, so that closing
+    /// it sends a TCP RST and skips the `TIME_WAIT` state. It is meant for
+    /// load-testing tools that open and close huge numbers of connections and
+    /// would otherwise run out of ports. Don't use it in production: data
+    /// that is still buffered is discarded and the peer sees an error instead
+    /// of a clean close.
+    pub fn set_abortive_close(&self) -> io::Result<()> {
+        SockRef::from(self.get_ref()).set_linger(Some(Duration::ZERO))
+    }
+
+    /// Enables TCP keepalive probes on the underlying socket with the
+    /// parameters of `keepalive`, or disables them with `None`.
+    ///
+    /// Unlike pings, the probes are answered by the peer's operating system
+    /// and detect dead peers and broken network paths even when the
+    /// application is idle and does not read. Once the probes go unanswered,
+    /// the next [`poll_next`](futures_core::Stream::poll_next) or flush of the
+    /// stream fails with an error for which [`is_dead_peer`] returns `true`.
+    pub fn set_tcp_keepalive(&self, keepalive: Option<&TcpKeepalive>) -> io::Result<()> {
+        let socket = SockRef::from(self.get_ref());
+        match keepalive {
+            Some(keepalive) => socket.set_tcp_keepalive(keepalive),
+            None => socket.set_keepalive(false),
+        }
+    }
+}
@@ -0,0 +1,83 @@
+//! Observing the raw bytes of a connection, see
+//! [`WebSocketStream::set_wire_tap`].
+use std::fmt;
+use std::io::IoSlice;
+
+use crate::exclusive::Exclusive;
+use crate::WebSocketStream;
+
+/// Whether the bytes passed to a [wire tap](WebSocketStream::set_wire_tap)
+/// were read or written.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TapDirection {
+    /// The bytes were read from the underlying stream.
+    Read,
+    /// The bytes were written to the underlying stream.
+    Write,
+}
+
+type Callback = Box<dyn FnMut(TapDirection, &[u8]) + Send>;
+
+/// The callback set with [`WebSocketStream::set_wire_tap`].
+pub(crate) struct Tap(Exclusive<Callback>);
+
+impl Tap {
+    pub(crate) fn read(&mut self, buf: &[u8]) {
+        self.call(TapDirection::Read, buf);
+    }
+
+    pub(crate) fn write(&mut self, buf: &[u8]) {
+        self.call(TapDirection::Write, buf);
+    }
+
+    fn call(&mut self, direction: TapDirection, buf: &[u8]) {
+        if !buf.is_empty() {
+            (self.0.get_mut())(direction, buf);
+        }
+    }
+
+    /// Reports the first `n` bytes of `bufs`, which were written at once.
+    pub(crate) fn write_vectored(&mut self, bufs: &[IoSlice<'_>], mut n: usize) {
+        for buf in bufs {
+            let len = std::cmp::min(buf.len(), n);
+            self.write(&buf[..len]);
+            n -= len;
+            if n == 0 {
+                break;
+            }
+        }
+    }
+}
+
+impl fmt::Debug for Tap {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Tap")
+    }
+}
+
+impl<S> WebSocketStream<S> {
+    /// Sets a callback that observes every byte read from or written to the
+    /// underlying stream, e.g. to debug or analyze the protocol.
+    ///
+    /// The callback is called with the bytes of every successful read and
+    /// write as they pass the stream, i.e. frames as they are on the wire,
+    /// before tungstenite parses them or after it serialized them. Reads and
+    /// writes that would block are not reported, and a write the stream only
+    /// partly accepted is reported up to where it stopped.
+    ///
+    /// The callback runs while the stream is read or written, so it should be
+    /// cheap. Without a tap there is no overhead.
+    pub fn set_wire_tap<F>(&mut self, tap: F)
+    where
+        F: FnMut(TapDirection, &[u8]) + Send + 'static,
+    {
+        self.inner
+            .get_mut()
+            .set_tap(Tap(Exclusive::new(Box::new(tap))));
+    }
+
+    /// Removes the callback set with [`set_wire_tap`](Self::set_wire_tap).
+    pub fn clear_wire_tap(&mut self) {
+        self.inner.get_mut().clear_tap();
+    }
+}
@@ -0,0 +1,145 @@
+//! Metrics emitted through the [`metrics`](https://crates.io/crates/metrics)
+//! facade with the `metrics` feature.
+//!
+//! Without the feature all types are empty and all functions compile to
+//! nothing.
+#![cfg_attr(not(feature = "metrics"), allow(unused_variables))]
+#[cfg(feature = "metrics")]
+use std::time::Instant;
+
+use tungstenite::{Error as WsError, Message};
+
+use crate::counters::ConnectionMetrics;
+
+/// Whether a message was received or sent.
+#[derive(Debug, Clone, Copy)]
+pub(crate) enum Direction {
+    Received,
+    Sent,
+}
+
+/// What is counted for a message, taken before the message is consumed.
+#[derive(Debug)]
+pub(crate) struct MessageSample {
+    kind: &'static str,
+    #[cfg(feature = "metrics")]
+    len: usize,
+}
+
+impl MessageSample {
+    pub(crate) fn new(msg: &Message) -> Self {
+        MessageSample {
+            kind: match msg {
+                Message::Text(_) => "text",
+                Message::Binary(_) => "binary",
+                Message::Ping(_) => "ping",
+                Message::Pong(_) => "pong",
+                Message::Close(_) => "close",
+                Message::Frame(_) => "frame",
+            },
+            #[cfg(feature = "metrics")]
+            len: msg.len(),
+        }
+    }
+
+    /// Counts the message and its payload bytes, and the message in the
+    /// counters of its connection.
+    ///
+    /// Pings that are sent and pongs that are received are passed on to
+    /// `round_trip`.
+    pub(crate) fn record(
+        self,
+        direction: Direction,
+        round_trip: &mut RoundTrip,
+        counters: &ConnectionMetrics,
+    ) {
+        counters.message(direction, self.kind);
+        #[cfg(feature = "metrics")]
+        {
+            let direction = match direction {
+                Direction::Received => {
+                    if self.kind == "pong" {
+                        round_trip.pong_received();
+                    }
+                    "received"
+                }
+                Direction::Sent => {
+                    if self.kind == "ping" {
+                        round_trip.ping_sent();
+                    }
+                    "sent"
+                }
+            };
+            let labels = [("direction", direction), ("kind", self.kind)];
+            metrics::counter!("websocket_messages_total", &labels).increment(1);
+            metrics::counter!("websocket_bytes_total", &labels).increment(self.len as u64);
+        }
+    }
+}
+
+/// Counts an error that was returned to the user.
+pub(crate) fn error(err: &WsError) {
+    #[cfg(feature = "metrics")]
+    {
+        let kind = match err {
+            WsError::ConnectionClosed | WsError::AlreadyClosed => "closed",
+            WsError::Io(_) => "io",
+            WsError::Tls(_) => "tls",
+            WsError::Capacity(_) => "capacity",
+            WsError::Protocol(_) => "protocol",
+            WsError::WriteBufferFull(_) => "write_buffer_full",
+            WsError::Utf8(_) => "utf8",
+            WsError::AttackAttempt => "attack_attempt",
+            WsError::Url(_) => "url",
+            // The HTTP errors, which only exist with the handshake feature.
+            _ => "http",
+        };
+        metrics::counter!("websocket_errors_total", "kind" => kind).increment(1);
+    }
+}
+
+/// Measures the duration of a handshake.
+#[cfg(feature = "handshake")]
+#[derive(Debug)]
+pub(crate) struct HandshakeTimer {
+    #[cfg(feature = "metrics")]
+    start: Instant,
+}
+
+#[cfg(feature = "handshake")]
+impl HandshakeTimer {
+    pub(crate) fn start() -> Self {
+        HandshakeTimer {
+            #[cfg(feature = "metrics")]
+            start: Instant::now(),
+        }
+    }
+
+    /// Records the duration of a successful handshake of `role`.
+    pub(crate) fn finish(self, role: &'static str) {
+        #[cfg(feature = "metrics")]
+        metrics::histogram!("websocket_handshake_duration_seconds", "role" => role)
+            .record(self.start.elapsed().as_secs_f64());
+    }
+}
+
+/// Measures the time between sending a ping and receiving a pong.
+#[derive(Debug, Default)]
+pub(crate) struct RoundTrip {
+    #[cfg(feature = "metrics")]
+    ping_sent: Option<Instant>,
+}
+
+#[cfg(feature = "metrics")]
+impl RoundTrip {
+    fn ping_sent(&mut self) {
+        self.ping_sent = Some(Instant::now());
+    }
+
+    fn pong_received(&mut self) {
+        if let Some(sent) = self.ping_sent.take() {
+            metrics::histogram!("websocket_round_trip_seconds")
+                .record(sent.elapsed().as_secs_f64());
+        }
+    }
+}
@@ -1,16 +1,37 @@
 //! `tokio` integration.
+use std::future::Future;
+
 use tungstenite::client::IntoClientRequest;
+use tungstenite::error::UrlError;
 use tungstenite::handshake::client::{Request, Response};
 use tungstenite::handshake::server::{Callback, NoCallback};
+use tungstenite::http::{self, HeaderValue, Uri};
 use tungstenite::protocol::WebSocketConfig;
-use tungstenite::Error;
+use tungstenite::{Error, Message};
 
 use tokio::net::TcpStream;
 
-use super::{domain, port, WebSocketStream};
+use super::{
+    authorize_retry, domain, port, with_protocols, ConnectError, Heartbeat, HeartbeatHandle,
+    WebSocketSender, WebSocketStream,
+};
 
 use futures_io::{AsyncRead, AsyncWrite};
 
+mod broadcast;
+pub use broadcast::LagPolicy;
+
+mod happy_eyeballs;
+pub use happy_eyeballs::{connect_happy_eyeballs, HAPPY_EYEBALLS_DELAY};
+
+mod proxy;
+pub use proxy::{ProxyAuth, ProxyConfig};
+
+#[cfg(feature = "__rustls-tls")]
+mod pinning;
+#[cfg(feature = "__rustls-tls")]
+pub use pinning::CertificatePins;
+
 #[cfg(feature = "tokio-native-tls")]
 #[path = "tokio/native_tls.rs"]
 mod tls;
@@ -70,7 +91,7 @@ mod tls;
     feature = "tokio-openssl",
     feature = "async-tls",
 ))]
-pub use self::tls::client_async_tls_with_connector_and_config;
+pub use self::tls::{client_async_tls_upgrade, client_async_tls_with_connector_and_config};
 #[cfg(any(
     feature = "tokio-native-tls",
     feature = "tokio-rustls-manual-roots",
@@ -112,7 +133,7 @@ use self::tls::AutoStream;
 ///
 /// This is typically used for clients who have already established, for
 /// example, a TCP connection to the remote server.
-pub async fn client_async<'a, R, S>(
+pub async fn client_async<R, S>(
     request: R,
     stream: S,
 ) -> Result<(WebSocketStream<TokioAdapter<S>>, Response), Error>
@@ -125,7 +146,7 @@ where
 
 /// The same as `client_async()` but the one can specify a websocket configuration.
 /// Please refer to `client_async()` for more details.
-pub async fn client_async_with_config<'a, R, S>(
+pub async fn client_async_with_config<R, S>(
     request: R,
     stream: S,
     config: Option<WebSocketConfig>,
@@ -361,6 +382,91 @@ where
 /// Type alias for the stream type of the `connect_async()` functions.
 pub type ConnectStream = ClientStream<TcpStream>;
 
+/// Resolves `host` to the addresses to connect to.
+async fn resolve(host: &str, port: u16) -> Result<Vec<std::net::SocketAddr>, Error> {
+    let addrs = match tokio::net::lookup_host((host, port)).await {
+        Ok(addrs) => addrs.collect::<Vec<_>>(),
+        Err(source) => {
+            return Err(ConnectError::Dns {
+                host: host.to_owned(),
+                source,
+            }
+            .into())
+        }
+    };
+    if addrs.is_empty() {
+        return Err(ConnectError::Dns {
+            host: host.to_owned(),
+            source: std::io::ErrorKind::NotFound.into(),
+        }
+        .into());
+    }
+    Ok(addrs)
+}
+
+/// Resolves `host` and connects to the first address that accepts the connection.
+async fn connect_tcp(host: &str, port: u16) -> Result<TcpStream, Error> {
+    connect_tcp_addr(host, port).await.map(|(stream, _)| stream)
+}
+
+/// Like `connect_tcp`, but also returns the address that accepted the connection.
+async fn connect_tcp_addr(
+    host: &str,
+    port: u16,
+) -> Result<(TcpStream, std::net::SocketAddr), Error> {
+    let mut last_err = None;
+    for addr in resolve(host, port).await? {
+        match TcpStream::connect(addr).await {
+            Ok(stream) => return Ok((stream, addr)),
+            Err(err) => last_err = Some(err),
+        }
+    }
+    Err(ConnectError::Connect {
+        host: host.to_owned(),
+        port,
+        source: last_err.expect("no addresses"),
+    }
+    .into())
+}
+
+#[cfg(feature = "socket2")]
+/// Like `connect_tcp`, but creates the sockets itself and passes them to
+/// `configure` before connecting.
+async fn connect_tcp_with<F>(host: &str, port: u16, configure: F) -> Result<TcpStream, Error>
+where
+    F: Fn(&socket2::Socket) -> std::io::Result<()>,
+{
+    let mut last_err = None;
+    for addr in resolve(host, port).await? {
+        match connect_socket(addr, &configure).await {
+            Ok(stream) => return Ok(stream),
+            Err(err) => last_err = Some(err),
+        }
+    }
+    Err(ConnectError::Connect {
+        host: host.to_owned(),
+        port,
+        source: last_err.expect("no addresses"),
+    }
+    .into())
+}
+
+#[cfg(feature = "socket2")]
+async fn connect_socket<F>(addr: std::net::SocketAddr, configure: &F) -> std::io::Result<TcpStream>
+where
+    F: Fn(&socket2::Socket) -> std::io::Result<()>,
+{
+    let socket = socket2::Socket::new(
+        socket2::Domain::for_address(addr),
+        socket2::Type::STREAM,
+        Some(socket2::Protocol::TCP),
+    )?;
+    configure(&socket)?;
+    socket.set_nonblocking(true)?;
+    let socket = tokio::net::TcpSocket::from_std_stream(socket.into());
+    socket.connect(addr).await
+}
+
 /// Connect to a given URL.
 ///
 /// Accepts any request that implements [`IntoClientRequest`], which is often just `&str`, but can
@@ -402,11 +508,268 @@ where
     let domain = domain(&request)?;
     let port = port(&request)?;
 
-    let try_socket = TcpStream::connect((domain.as_str(), port)).await;
-    let socket = try_socket.map_err(Error::Io)?;
+    let socket = connect_tcp(&domain, port).await?;
+    client_async_tls_with_connector_and_config(request, socket, None, config).await
+}
+
+/// Connect to a given URL and return the address that was connected to.
+///
+/// If the host resolves to several addresses, they are tried in order and
+/// the one that accepted the connection is returned, e.g. for logging.
+pub async fn connect_async_with_addr<R>(
+    request: R,
+) -> Result<
+    (
+        WebSocketStream<ConnectStream>,
+        Response,
+        std::net::SocketAddr,
+    ),
+    Error,
+>
+where
+    R: IntoClientRequest + Unpin,
+{
+    let request: Request = request.into_client_request()?;
+
+    let domain = domain(&request)?;
+    let port = port(&request)?;
+
+    let (socket, addr) = connect_tcp_addr(&domain, port).await?;
+    let (stream, response) =
+        client_async_tls_with_connector_and_config(request, socket, None, None).await?;
+    Ok((stream, response, addr))
+}
+
+/// Connect to a given URL, racing the connections to its addresses like
+/// described by RFC 8305 (Happy Eyeballs).
+///
+/// The host is resolved to all of its IPv6 and IPv4 addresses, see
+/// [`connect_happy_eyeballs`] for how they are tried. [`HAPPY_EYEBALLS_DELAY`]
+/// is a good default for `delay`.
+pub async fn connect_async_with_happy_eyeballs<R>(
+    request: R,
+    config: Option<WebSocketConfig>,
+    delay: std::time::Duration,
+) -> Result<(WebSocketStream<ConnectStream>, Response), Error>
+where
+    R: IntoClientRequest + Unpin,
+{
+    let request: Request = request.into_client_request()?;
+
+    let domain = domain(&request)?;
+    let port = port(&request)?;
+
+    let addrs = resolve(&domain, port).await?;
+    let (socket, _) = connect_happy_eyeballs(addrs, delay)
+        .await
+        .map_err(|source| ConnectError::Connect {
+            host: domain.clone(),
+            port,
+            source,
+        })?;
+    client_async_tls_with_connector_and_config(request, socket, None, config).await
+}
+
+#[cfg(feature = "socket2")]
+/// Connect to a given URL, letting `configure` set options on the socket
+/// before it connects.
+///
+/// `configure` is called with the socket of every address that is tried. This
+/// gives full control over the socket, e.g. to set the type of service or to
+/// bind to a local address, without dedicated options for each of them.
+///
+/// ```no_run
+/// # async fn test() {
+/// use async_tungstenite::tokio::connect_async_with_socket_config;
+///
+/// let (stream, _) = connect_async_with_socket_config("wss://api.example.com", None, |socket| {
+///     socket.set_tcp_nodelay(true)?;
+///     socket.set_tos_v4(0x10)
+/// })
+/// .await
+/// .unwrap();
+/// # }
+/// ```
+pub async fn connect_async_with_socket_config<R, F>(
+    request: R,
+    config: Option<WebSocketConfig>,
+    configure: F,
+) -> Result<(WebSocketStream<ConnectStream>, Response), Error>
+where
+    R: IntoClientRequest + Unpin,
+    F: Fn(&socket2::Socket) -> std::io::Result<()>,
+{
+    let request: Request = request.into_client_request()?;
+
+    let domain = domain(&request)?;
+    let port = port(&request)?;
+
+    let socket = connect_tcp_with(&domain, port, configure).await?;
     client_async_tls_with_connector_and_config(request, socket, None, config).await
 }
 
+/// Connect to a given URL through a proxy.
+///
+/// The tunnel to the host of `request` is established first, then the TLS and
+/// WebSocket handshakes are performed through it like with
+/// [`connect_async`]. The host name is resolved by the proxy.
+///
+/// ```no_run
+/// # async fn test() {
+/// use async_tungstenite::tokio::{connect_async_with_proxy, ProxyAuth, ProxyConfig};
+///
+/// let proxy = ProxyConfig::Socks5 {
+///     addr: "proxy.example.com:1080".into(),
+///     auth: Some(ProxyAuth::new("user", "secret")),
+/// };
+/// let (stream, _) = connect_async_with_proxy("wss://api.example.com", proxy)
+///     .await
+///     .unwrap();
+/// # }
+/// ```
+pub async fn connect_async_with_proxy<R>(
+    request: R,
+    proxy: ProxyConfig,
+) -> Result<(WebSocketStream<ConnectStream>, Response), Error>
+where
+    R: IntoClientRequest + Unpin,
+{
+    let request: Request = request.into_client_request()?;
+
+    let domain = domain(&request)?;
+    let port = port(&request)?;
+
+    let socket = proxy::connect(&proxy, &domain, port).await?;
+    client_async_tls_with_connector_and_config(request, socket, None, None).await
+}
+
+/// Connect to a given URL through the HTTP proxy at `proxy_uri`.
+///
+/// The tunnel is opened with a `CONNECT` request, authenticated with
+/// `proxy_auth` if given. Secure connections are then established through it
+/// like with [`connect_async`]. A proxy that does not respond with a `2xx`
+/// status fails the connection with an [`Error::Io`].
+///
+/// ```no_run
+/// # async fn test() {
+/// use async_tungstenite::tokio::connect_async_with_http_proxy;
+///
+/// let (stream, _) =
+///     connect_async_with_http_proxy("wss://api.example.com", "http://proxy.example.com:3128", None)
+///         .await
+///         .unwrap();
+/// # }
+/// ```
+pub async fn connect_async_with_http_proxy<R>(
+    request: R,
+    proxy_uri: &str,
+    proxy_auth: Option<ProxyAuth>,
+) -> Result<(WebSocketStream<ConnectStream>, Response), Error>
+where
+    R: IntoClientRequest + Unpin,
+{
+    let uri: Uri = proxy_uri
+        .parse()
+        .map_err(|err| Error::HttpFormat(http::Error::from(err)))?;
+    match uri.scheme_str() {
+        None | Some("http") => {}
+        Some(_) => return Err(Error::Url(UrlError::UnsupportedUrlScheme)),
+    }
+    let host = uri.host().ok_or(Error::Url(UrlError::NoHostName))?;
+    let proxy = ProxyConfig::Http {
+        addr: format!("{}:{}", host, uri.port_u16().unwrap_or(80)),
+        auth: proxy_auth,
+    };
+    connect_async_with_proxy(request, proxy).await
+}
+
+/// Connect to a given URL, answering an authentication challenge of the server.
+///
+/// If the server rejects the upgrade with `401 Unauthorized`, `authorize` is called with its
+/// response, which carries the `WWW-Authenticate` challenge. If it returns a value for the
+/// `Authorization` header, the handshake is retried once on a new connection with that header
+/// added to the request. Otherwise the original error is returned.
+pub async fn connect_async_with_auth<R, F>(
+    request: R,
+    config: Option<WebSocketConfig>,
+    authorize: F,
+) -> Result<(WebSocketStream<ConnectStream>, Response), Error>
+where
+    R: IntoClientRequest + Unpin,
+    F: FnOnce(&Response) -> Option<HeaderValue>,
+{
+    let request: Request = request.into_client_request()?;
+
+    match connect_async_with_config(request.clone(), config).await {
+        Err(err) => match authorize_retry(&request, &err, authorize) {
+            Some(request) => connect_async_with_config(request, config).await,
+            None => Err(err),
+        },
+        res => res,
+    }
+}
+
+/// Connect to a given URL, offering the subprotocols `protocols` in order of
+/// preference.
+///
+/// The server's choice is available via
+/// [`WebSocketStream::selected_protocol`]. Any `Sec-WebSocket-Protocol` header
+/// of `request` is replaced.
+///
+/// ```no_run
+/// # async fn test() {
+/// use async_tungstenite::tokio::connect_async_with_protocols;
+///
+/// let (stream, _) = connect_async_with_protocols("wss://api.example.com", &["v2", "v1"])
+///     .await
+///     .unwrap();
+/// println!("speaking {:?}", stream.selected_protocol());
+/// # }
+/// ```
+pub async fn connect_async_with_protocols<R>(
+    request: R,
+    protocols: &[&str],
+) -> Result<(WebSocketStream<ConnectStream>, Response), Error>
+where
+    R: IntoClientRequest + Unpin,
+{
+    let request = with_protocols(request.into_client_request()?, protocols)?;
+    connect_async_with_config(request, None).await
+}
+
+/// Connect to a given URL and send `initial` as the first message, e.g. to
+/// authenticate or subscribe.
+///
+/// If sending the message fails, the connection is closed before the error is
+/// returned.
+pub async fn connect_and_send<R>(
+    request: R,
+    initial: Message,
+) -> Result<(WebSocketStream<ConnectStream>, Response), Error>
+where
+    R: IntoClientRequest + Unpin,
+{
+    let (mut stream, response) = connect_async(request).await?;
+    if let Err(err) = stream.send(initial).await {
+        let _ = stream.close(None).await;
+        return Err(err);
+    }
+    Ok((stream, response))
+}
+
+/// Runs `heartbeat` on `sender` with the timer of `tokio`.
+///
+/// See [`WebSocketSender::heartbeat`].
+pub fn heartbeat<S>(
+    sender: &WebSocketSender<S>,
+    heartbeat: Heartbeat,
+) -> (HeartbeatHandle, impl Future<Output = Result<(), Error>>)
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    sender.heartbeat(heartbeat, tokio::time::sleep)
+}
+
 #[cfg(any(
     feature = "async-tls",
     feature = "tokio-native-tls",
@@ -448,11 +811,227 @@ where
     let domain = domain(&request)?;
     let port = port(&request)?;
 
-    let try_socket = TcpStream::connect((domain.as_str(), port)).await;
-    let socket = try_socket.map_err(Error::Io)?;
+    let socket = connect_tcp(&domain, port).await?;
     client_async_tls_with_connector_and_config(request, socket, connector, config).await
 }
 
+#[cfg(any(
+    feature = "async-tls",
+    feature = "tokio-native-tls",
+    feature = "tokio-rustls-manual-roots",
+    feature = "tokio-rustls-native-certs",
+    feature = "tokio-rustls-webpki-roots",
+    feature = "tokio-openssl"
+))]
+/// Connect to a given URL, using `server_name` instead of the URL's host
+/// for the TLS handshake of `wss://` URLs.
+///
+/// This allows e.g. connecting to an IP address while presenting and
+/// verifying a specific server name. The `Host` header still uses the URL's
+/// host. With `None` this behaves like
+/// [`connect_async_with_tls_connector_and_config`]. Whether SNI is sent at
+/// all is configured on the connector.
+pub async fn connect_async_with_server_name<R>(
+    request: R,
+    server_name: Option<&str>,
+    connector: Option<Connector>,
+    config: Option<WebSocketConfig>,
+) -> Result<(WebSocketStream<ConnectStream>, Response), Error>
+where
+    R: IntoClientRequest + Unpin,
+{
+    let request: Request = request.into_client_request()?;
+
+    let domain = domain(&request)?;
+    let port = port(&request)?;
+    let mode = tungstenite::client::uri_mode(request.uri())?;
+
+    let socket = connect_tcp(&domain, port).await?;
+    match (server_name, mode) {
+        (Some(server_name), tungstenite::stream::Mode::Tls) => {
+            client_async_tls_upgrade(socket, request, server_name, connector, config).await
+        }
+        _ => client_async_tls_with_connector_and_config(request, socket, connector, config).await,
+    }
+}
+
+#[cfg(all(
+    feature = "__rustls-tls",
+    not(feature = "tokio-native-tls"),
+    not(feature = "tokio-rustls-manual-roots")
+))]
+/// Connect to a given URL, accepting only the certificates in `pins` for
+/// `wss://` URLs.
+///
+/// The connection fails with a TLS error before the WebSocket handshake if
+/// the server's certificate doesn't match, see [`CertificatePins`]. Use
+/// [`CertificatePins::connector`] for other roots than the default ones.
+pub async fn connect_async_with_pins<R>(
+    request: R,
+    pins: &CertificatePins,
+) -> Result<(WebSocketStream<ConnectStream>, Response), Error>
+where
+    R: IntoClientRequest + Unpin,
+{
+    let connector = pins
+        .connector(tls::default_roots()?)
+        .map_err(ConnectError::tls)?;
+    connect_async_with_tls_connector(request, Some(connector)).await
+}
+
+#[cfg(any(
+    feature = "tokio-native-tls",
+    feature = "tokio-rustls-manual-roots",
+    feature = "tokio-rustls-native-certs",
+    feature = "tokio-rustls-webpki-roots",
+    feature = "tokio-openssl"
+))]
+/// A connection with TCP and, if required, TLS established but without the
+/// WebSocket handshake, returned by [`prewarm_tls`].
+pub struct WarmConnection {
+    stream: ConnectStream,
+}
+
+#[cfg(any(
+    feature = "tokio-native-tls",
+    feature = "tokio-rustls-manual-roots",
+    feature = "tokio-rustls-native-certs",
+    feature = "tokio-rustls-webpki-roots",
+    feature = "tokio-openssl"
+))]
+/// Connects to a given URL and performs the TLS handshake if the URL requires
+/// it, but not the WebSocket handshake.
+///
+/// The expensive part of connecting can be done ahead of time this way, e.g.
+/// to fill a pool of connections, and the WebSocket handshake is completed
+/// on demand with [`WarmConnection::upgrade`].
+pub async fn prewarm_tls<R>(
+    request: R,
+    connector: Option<Connector>,
+) -> Result<WarmConnection, Error>
+where
+    R: IntoClientRequest + Unpin,
+{
+    let request: Request = request.into_client_request()?;
+
+    let domain = domain(&request)?;
+    let port = port(&request)?;
+    let mode = tungstenite::client::uri_mode(request.uri())?;
+
+    let socket = connect_tcp(&domain, port).await?;
+    let stream = tls::wrap_stream(socket, domain, connector, mode)
+        .await
+        .map_err(ConnectError::tls)?;
+    Ok(WarmConnection { stream })
+}
+
+#[cfg(any(
+    feature = "tokio-native-tls",
+    feature = "tokio-rustls-manual-roots",
+    feature = "tokio-rustls-native-certs",
+    feature = "tokio-rustls-webpki-roots",
+    feature = "tokio-openssl"
+))]
+impl WarmConnection {
+    /// Performs the WebSocket handshake with `request`.
+    ///
+    /// `request` should be for the URL the connection was established for,
+    /// as it is not checked against it.
+    pub async fn upgrade<R>(
+        self,
+        request: R,
+    ) -> Result<(WebSocketStream<ConnectStream>, Response), Error>
+    where
+        R: IntoClientRequest + Unpin,
+    {
+        self.upgrade_with_config(request, None).await
+    }
+
+    /// Performs the WebSocket handshake with `request` and the given
+    /// WebSocket configuration.
+    pub async fn upgrade_with_config<R>(
+        self,
+        request: R,
+        config: Option<WebSocketConfig>,
+    ) -> Result<(WebSocketStream<ConnectStream>, Response), Error>
+    where
+        R: IntoClientRequest + Unpin,
+    {
+        crate::client_async_with_config(request, self.stream, config).await
+    }
+
+    /// Returns a shared reference to the established stream.
+    pub fn get_ref(&self) -> &ConnectStream {
+        &self.stream
+    }
+}
+
+#[cfg(any(
+    feature = "tokio-native-tls",
+    feature = "tokio-rustls-manual-roots",
+    feature = "tokio-rustls-native-certs",
+    feature = "tokio-rustls-webpki-roots",
+    feature = "tokio-openssl"
+))]
+/// A TLS connector that is built once and shared by many connections.
+///
+/// The functions without a connector, e.g. [`connect_async`], build a new
+/// one for every connection, which includes loading the root certificates.
+/// Cloning a `TokioConnector` is cheap.
+#[derive(Clone)]
+pub struct TokioConnector {
+    connector: tls::SharedConnector,
+}
+
+#[cfg(any(
+    feature = "tokio-native-tls",
+    feature = "tokio-rustls-manual-roots",
+    feature = "tokio-rustls-native-certs",
+    feature = "tokio-rustls-webpki-roots",
+    feature = "tokio-openssl"
+))]
+impl TokioConnector {
+    /// Builds a connector with the same settings as the functions without a
+    /// connector.
+    #[allow(clippy::result_large_err)]
+    pub fn new() -> Result<Self, Error> {
+        let connector = tls::default_shared_connector().map_err(ConnectError::tls)?;
+        Ok(TokioConnector { connector })
+    }
+
+    /// Connect to a given URL.
+    pub async fn connect_async<R>(
+        &self,
+        request: R,
+    ) -> Result<(WebSocketStream<ConnectStream>, Response), Error>
+    where
+        R: IntoClientRequest + Unpin,
+    {
+        self.connect_async_with_config(request, None).await
+    }
+
+    /// Connect to a given URL with a given WebSocket configuration.
+    pub async fn connect_async_with_config<R>(
+        &self,
+        request: R,
+        config: Option<WebSocketConfig>,
+    ) -> Result<(WebSocketStream<ConnectStream>, Response), Error>
+    where
+        R: IntoClientRequest + Unpin,
+    {
+        let connector = self.tls_connector()?;
+        connect_async_with_tls_connector_and_config(request, Some(connector), config).await
+    }
+
+    /// Returns the connector for a single connection, e.g. to pass it to
+    /// [`client_async_tls_with_connector_and_config`] with a stream that was
+    /// connected separately.
+    #[allow(clippy::result_large_err)]
+    pub fn tls_connector(&self) -> Result<Connector, Error> {
+        tls::configure(&self.connector).map_err(ConnectError::tls)
+    }
+}
+
 use std::pin::Pin;
 use std::task::{Context, Poll};
 
@@ -488,6 +1067,20 @@ impl<T> TokioAdapter<T> {
     }
 }
 
+#[cfg(unix)]
+impl<T: std::os::unix::io::AsFd> std::os::unix::io::AsFd for TokioAdapter<T> {
+    fn as_fd(&self) -> std::os::unix::io::BorrowedFd<'_> {
+        self.inner.as_fd()
+    }
+}
+
+#[cfg(windows)]
+impl<T: std::os::windows::io::AsSocket> std::os::windows::io::AsSocket for TokioAdapter<T> {
+    fn as_socket(&self) -> std::os::windows::io::BorrowedSocket<'_> {
+        self.inner.as_socket()
+    }
+}
+
 impl<T: tokio::io::AsyncRead> AsyncRead for TokioAdapter<T> {
     fn poll_read(
         self: Pin<&mut Self>,
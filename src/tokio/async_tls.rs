@@ -37,3 +37,29 @@ where
     )
     .await
 }
+
+/// Performs a TLS handshake on an already connected stream, with `domain` as
+/// the server name, and then the WebSocket handshake over it.
+///
+/// See [TLS over an existing stream](crate#tls-over-an-existing-stream).
+pub async fn client_async_tls_upgrade<R, S>(
+    stream: S,
+    request: R,
+    domain: &str,
+    connector: Option<Connector>,
+    config: Option<WebSocketConfig>,
+) -> Result<(WebSocketStream<AutoStream<S>>, Response), Error>
+where
+    R: IntoClientRequest + Unpin,
+    S: 'static + tokio::io::AsyncRead + tokio::io::AsyncWrite + Unpin,
+    AutoStream<S>: Unpin,
+{
+    crate::async_tls::client_async_tls_upgrade(
+        TokioAdapter::new(stream),
+        request,
+        domain,
+        connector,
+        config,
+    )
+    .await
+}
@@ -0,0 +1,74 @@
+//! Forwarding of [`broadcast`] channels to a connection.
+use futures_io::{AsyncRead, AsyncWrite};
+use log::*;
+use tokio::sync::broadcast::{self, error::RecvError};
+use tokio::task::JoinHandle;
+use tungstenite::error::{Error, ProtocolError};
+use tungstenite::protocol::{frame::coding::CloseCode, CloseFrame};
+use tungstenite::Message;
+
+use crate::WebSocketSender;
+
+/// What happens if a connection can't keep up with a broadcast channel.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum LagPolicy {
+    /// Skip the messages that were missed and continue with the oldest one
+    /// still in the channel.
+    #[default]
+    Skip,
+    /// Close the connection with code 1013 (try again later).
+    Close,
+}
+
+impl<S> WebSocketSender<S>
+where
+    S: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+{
+    /// Spawns a task that sends every message received from `rx` to this
+    /// connection.
+    ///
+    /// A broadcast channel only buffers a limited number of messages, so a
+    /// slow connection may miss some of them. `on_lag` decides whether these
+    /// are skipped or the connection is closed.
+    ///
+    /// The task finishes with `Ok(())` once the channel or the connection is
+    /// closed, or with the error that occurred while sending.
+    pub fn subscribe_to(
+        &self,
+        mut rx: broadcast::Receiver<Message>,
+        on_lag: LagPolicy,
+    ) -> JoinHandle<Result<(), Error>> {
+        let sender = self.clone();
+
+        tokio::spawn(async move {
+            loop {
+                let msg = match rx.recv().await {
+                    Ok(msg) => msg,
+                    Err(RecvError::Closed) => return Ok(()),
+                    Err(RecvError::Lagged(skipped)) => match on_lag {
+                        LagPolicy::Skip => {
+                            debug!("Skipped {} broadcast messages", skipped);
+                            continue;
+                        }
+                        LagPolicy::Close => Message::Close(Some(CloseFrame {
+                            code: CloseCode::Again,
+                            reason: "Too slow".into(),
+                        })),
+                    },
+                };
+
+                let close = msg.is_close();
+                match sender.send(msg).await {
+                    Ok(()) if close => return Ok(()),
+                    Ok(()) => {}
+                    Err(
+                        Error::AlreadyClosed
+                        | Error::ConnectionClosed
+                        | Error::Protocol(ProtocolError::SendAfterClosing),
+                    ) => return Ok(()),
+                    Err(err) => return Err(err),
+                }
+            }
+        })
+    }
+}
@@ -0,0 +1,111 @@
+//! Dual-stack connection establishment (Happy Eyeballs, RFC 8305).
+use std::future::Future;
+use std::io;
+use std::net::SocketAddr;
+use std::pin::Pin;
+use std::task::Poll;
+use std::time::Duration;
+
+use log::*;
+use tokio::net::TcpStream;
+use tokio::time::Sleep;
+
+/// The delay between two connection attempts that RFC 8305 recommends.
+pub const HAPPY_EYEBALLS_DELAY: Duration = Duration::from_millis(250);
+
+type Attempt = Pin<Box<dyn Future<Output = (SocketAddr, io::Result<TcpStream>)> + Send>>;
+
+/// Connects to the first of `addrs` that accepts the connection, racing the
+/// attempts like described by RFC 8305.
+///
+/// The addresses are reordered to alternate between IPv6 and IPv4, starting
+/// with the family of the first one. A new attempt is started whenever the
+/// previous one failed or didn't succeed within `delay`, while the earlier
+/// attempts keep running. The first connection that is established is
+/// returned together with its address and all other attempts are cancelled.
+/// If all of them fail, the error of the last one is returned.
+///
+/// This avoids waiting for a connect timeout if e.g. the IPv6 path of a
+/// dual-stack host is broken.
+pub async fn connect_happy_eyeballs<I>(
+    addrs: I,
+    delay: Duration,
+) -> io::Result<(TcpStream, SocketAddr)>
+where
+    I: IntoIterator<Item = SocketAddr>,
+{
+    let mut addrs = interleave(addrs).into_iter();
+    let mut attempts: Vec<Attempt> = Vec::new();
+    let mut timer: Option<Pin<Box<Sleep>>> = None;
+    let mut last_err = None;
+    let mut failed = false;
+
+    std::future::poll_fn(|cx| loop {
+        // Like RFC 8305 section 5 asks for, a failed attempt starts the next
+        // one right away instead of waiting for the delay to pass.
+        let start_next = attempts.is_empty()
+            || failed
+            || timer
+                .as_mut()
+                .map_or(false, |timer| timer.as_mut().poll(cx).is_ready());
+        if start_next {
+            timer = None;
+            match addrs.next() {
+                Some(addr) => {
+                    debug!("Connecting to {}", addr);
+                    attempts.push(Box::pin(
+                        async move { (addr, TcpStream::connect(addr).await) },
+                    ));
+                    timer = Some(Box::pin(tokio::time::sleep(delay)));
+                }
+                None if attempts.is_empty() => {
+                    return Poll::Ready(Err(last_err.take().unwrap_or_else(|| {
+                        io::Error::new(io::ErrorKind::InvalidInput, "no addresses to connect to")
+                    })));
+                }
+                None => {}
+            }
+        }
+
+        failed = false;
+        let mut i = 0;
+        while i < attempts.len() {
+            match attempts[i].as_mut().poll(cx) {
+                Poll::Ready((addr, Ok(stream))) => return Poll::Ready(Ok((stream, addr))),
+                Poll::Ready((addr, Err(err))) => {
+                    debug!("Connecting to {} failed: {}", addr, err);
+                    drop(attempts.swap_remove(i));
+                    last_err = Some(err);
+                    failed = true;
+                }
+                Poll::Pending => i += 1,
+            }
+        }
+        if !start_next && !failed {
+            return Poll::Pending;
+        }
+    })
+    .await
+}
+
+/// Alternates between the address families, starting with the family of the
+/// first address.
+fn interleave<I>(addrs: I) -> Vec<SocketAddr>
+where
+    I: IntoIterator<Item = SocketAddr>,
+{
+    let addrs: Vec<_> = addrs.into_iter().collect();
+    let first_is_v6 = addrs.first().map_or(false, SocketAddr::is_ipv6);
+    let (mut first, mut second): (Vec<_>, Vec<_>) = addrs
+        .into_iter()
+        .partition(|addr| addr.is_ipv6() == first_is_v6);
+    let mut interleaved = Vec::with_capacity(first.len() + second.len());
+    first.reverse();
+    second.reverse();
+    loop {
+        match (first.pop(), second.pop()) {
+            (None, None) => return interleaved,
+            (a, b) => interleaved.extend(a.into_iter().chain(b)),
+        }
+    }
+}
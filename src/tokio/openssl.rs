@@ -17,9 +17,53 @@ pub type MaybeTlsStream<S> =
 
 pub type AutoStream<S> = MaybeTlsStream<S>;
 
+impl<S> MaybeTlsStream<S> {
+    /// Returns the application protocol the server selected via ALPN.
+    ///
+    /// Returns `None` for plain connections and if no protocol was
+    /// negotiated. A server selecting anything other than `http/1.1`, e.g.
+    /// `h2`, can't upgrade the connection to a websocket.
+    pub fn alpn_protocol(&self) -> Option<Vec<u8>> {
+        match self {
+            StreamSwitcher::Plain(_) => None,
+            StreamSwitcher::Tls(stream) => stream
+                .get_ref()
+                .ssl()
+                .selected_alpn_protocol()
+                .map(Vec::from),
+        }
+    }
+}
+
 pub type Connector = ConnectConfiguration;
 
-async fn wrap_stream<S>(
+/// What a [`TokioConnector`](super::TokioConnector) keeps for all its
+/// connections. A `ConnectConfiguration` can only be used once.
+pub(super) type SharedConnector = SslConnector;
+
+/// Builds a connector that trusts the system's roots.
+#[allow(clippy::result_large_err)]
+pub(super) fn default_shared_connector() -> Result<SharedConnector, Error> {
+    Ok(SslConnector::builder(SslMethod::tls())
+        .map_err(|err| std::io::Error::new(std::io::ErrorKind::Other, err))?
+        .build())
+}
+
+/// Returns the connector for one connection.
+#[allow(clippy::result_large_err)]
+pub(super) fn configure(shared: &SharedConnector) -> Result<Connector, Error> {
+    Ok(shared
+        .configure()
+        .map_err(|err| std::io::Error::new(std::io::ErrorKind::Other, err))?)
+}
+
+impl From<SslConnector> for super::TokioConnector {
+    fn from(connector: SslConnector) -> Self {
+        super::TokioConnector { connector }
+    }
+}
+
+pub(super) async fn wrap_stream<S>(
     socket: S,
     domain: String,
     connector: Option<Connector>,
@@ -38,14 +82,9 @@ where
         Mode::Plain => Ok(StreamSwitcher::Plain(TokioAdapter::new(socket))),
         Mode::Tls => {
             let stream = {
-                let connector = if let Some(connector) = connector {
-                    connector
-                } else {
-                    SslConnector::builder(SslMethod::tls())
-                        .map_err(|err| std::io::Error::new(std::io::ErrorKind::Other, err))?
-                        .build()
-                        .configure()
-                        .map_err(|err| std::io::Error::new(std::io::ErrorKind::Other, err))?
+                let connector = match connector {
+                    Some(connector) => connector,
+                    None => configure(&default_shared_connector()?)?,
                 };
 
                 let ssl = connector
@@ -97,6 +136,36 @@ where
     // Make sure we check domain and mode first. URL must be valid.
     let mode = uri_mode(request.uri())?;
 
-    let stream = wrap_stream(stream, domain, connector, mode).await?;
+    let stream = wrap_stream(stream, domain, connector, mode)
+        .await
+        .map_err(crate::ConnectError::tls)?;
+    client_async_with_config(request, stream, config).await
+}
+
+/// Performs a TLS handshake on an already connected stream, with `domain` as
+/// the server name, and then the WebSocket handshake over it.
+///
+/// See [TLS over an existing stream](crate#tls-over-an-existing-stream).
+pub async fn client_async_tls_upgrade<R, S>(
+    stream: S,
+    request: R,
+    domain: &str,
+    connector: Option<Connector>,
+    config: Option<WebSocketConfig>,
+) -> Result<(WebSocketStream<AutoStream<S>>, Response), Error>
+where
+    R: IntoClientRequest + Unpin,
+    S: 'static
+        + tokio::io::AsyncRead
+        + tokio::io::AsyncWrite
+        + Unpin
+        + std::fmt::Debug
+        + Send
+        + Sync,
+    AutoStream<S>: Unpin,
+{
+    let stream = wrap_stream(stream, domain.to_owned(), connector, Mode::Tls)
+        .await
+        .map_err(crate::ConnectError::tls)?;
     client_async_with_config(request, stream, config).await
 }
@@ -0,0 +1,175 @@
+//! Certificate pinning for rustls connections.
+use std::convert::TryFrom;
+use std::fmt;
+use std::sync::Arc;
+
+use real_tokio_rustls::rustls::client::danger::{
+    HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier,
+};
+use real_tokio_rustls::rustls::client::WebPkiServerVerifier;
+use real_tokio_rustls::rustls::crypto::hash::{Hash, HashAlgorithm};
+use real_tokio_rustls::rustls::server::ParsedCertificate;
+use real_tokio_rustls::rustls::{
+    CertificateError, ClientConfig, DigitallySignedStruct, Error as RustlsError, RootCertStore,
+    SignatureScheme,
+};
+use real_tokio_rustls::TlsConnector;
+use rustls_pki_types::{CertificateDer, ServerName, UnixTime};
+use tungstenite::error::TlsError;
+use tungstenite::Error;
+
+type Callback = dyn Fn(&[CertificateDer<'_>]) -> bool + Send + Sync;
+
+/// The certificates a server may present, identified by the SHA-256 hash of
+/// their DER encoded SubjectPublicKeyInfo.
+///
+/// The certificate chain is verified as usual and the leaf certificate must
+/// additionally match one of the pins. Pinning both the current and the next
+/// key allows rotating the certificate without breaking clients.
+#[derive(Clone)]
+pub struct CertificatePins {
+    pins: Vec<[u8; 32]>,
+    callback: Option<Arc<Callback>>,
+}
+
+impl CertificatePins {
+    /// Accepts certificates whose SubjectPublicKeyInfo hashes to one of
+    /// `pins`.
+    pub fn new(pins: impl IntoIterator<Item = [u8; 32]>) -> Self {
+        CertificatePins {
+            pins: pins.into_iter().collect(),
+            callback: None,
+        }
+    }
+
+    /// Calls `callback` with the certificate chain of the server, starting
+    /// with the leaf, once it matched a pin. The connection fails if it
+    /// returns `false`.
+    pub fn with_callback(
+        mut self,
+        callback: impl Fn(&[CertificateDer<'_>]) -> bool + Send + Sync + 'static,
+    ) -> Self {
+        self.callback = Some(Arc::new(callback));
+        self
+    }
+
+    /// Builds a connector that trusts `roots` and only accepts pinned
+    /// certificates.
+    ///
+    /// Uses the process-wide default crypto provider of rustls.
+    #[allow(clippy::result_large_err)]
+    pub fn connector(&self, roots: RootCertStore) -> Result<TlsConnector, Error> {
+        let builder = ClientConfig::builder();
+        let provider = builder.crypto_provider().clone();
+        let sha256 = provider
+            .cipher_suites
+            .iter()
+            .filter_map(|suite| suite.tls13())
+            .map(|suite| suite.common.hash_provider)
+            .find(|hash| hash.algorithm() == HashAlgorithm::SHA256)
+            .ok_or_else(|| {
+                Error::Tls(TlsError::Rustls(RustlsError::General(
+                    "crypto provider does not support SHA-256".into(),
+                )))
+            })?;
+        let inner = WebPkiServerVerifier::builder_with_provider(Arc::new(roots), provider)
+            .build()
+            .map_err(|err| Error::Tls(TlsError::Rustls(RustlsError::General(err.to_string()))))?;
+
+        let config = builder
+            .dangerous()
+            .with_custom_certificate_verifier(Arc::new(PinnedVerifier {
+                inner,
+                sha256,
+                pins: self.clone(),
+            }))
+            .with_no_client_auth();
+        Ok(TlsConnector::from(Arc::new(config)))
+    }
+
+    fn matches(&self, hash: &[u8]) -> bool {
+        self.pins.iter().any(|pin| pin[..] == *hash)
+    }
+}
+
+impl fmt::Debug for CertificatePins {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("CertificatePins")
+            .field("pins", &self.pins)
+            .field("callback", &self.callback.is_some())
+            .finish()
+    }
+}
+
+/// Verifies certificates like rustls does and then checks the pins.
+struct PinnedVerifier {
+    inner: Arc<WebPkiServerVerifier>,
+    sha256: &'static dyn Hash,
+    pins: CertificatePins,
+}
+
+impl fmt::Debug for PinnedVerifier {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("PinnedVerifier")
+            .field("inner", &self.inner)
+            .field("pins", &self.pins)
+            .finish()
+    }
+}
+
+impl ServerCertVerifier for PinnedVerifier {
+    fn verify_server_cert(
+        &self,
+        end_entity: &CertificateDer<'_>,
+        intermediates: &[CertificateDer<'_>],
+        server_name: &ServerName<'_>,
+        ocsp_response: &[u8],
+        now: UnixTime,
+    ) -> Result<ServerCertVerified, RustlsError> {
+        let verified = self.inner.verify_server_cert(
+            end_entity,
+            intermediates,
+            server_name,
+            ocsp_response,
+            now,
+        )?;
+
+        let spki = ParsedCertificate::try_from(end_entity)?.subject_public_key_info();
+        if !self.pins.matches(self.sha256.hash(spki.as_ref()).as_ref()) {
+            log::debug!("Server certificate matches none of the pins");
+            return Err(CertificateError::ApplicationVerificationFailure.into());
+        }
+        if let Some(ref callback) = self.pins.callback {
+            let mut chain = Vec::with_capacity(1 + intermediates.len());
+            chain.push(end_entity.clone());
+            chain.extend_from_slice(intermediates);
+            if !callback(&chain) {
+                return Err(CertificateError::ApplicationVerificationFailure.into());
+            }
+        }
+
+        Ok(verified)
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, RustlsError> {
+        self.inner.verify_tls12_signature(message, cert, dss)
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, RustlsError> {
+        self.inner.verify_tls13_signature(message, cert, dss)
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        self.inner.supported_verify_schemes()
+    }
+}
@@ -0,0 +1,266 @@
+//! Tunneling connections through proxies.
+use std::convert::TryFrom;
+use std::io;
+use std::net::{Ipv4Addr, Ipv6Addr};
+
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+use tungstenite::Error;
+
+/// A proxy to connect through, see
+/// [`connect_async_with_proxy`](super::connect_async_with_proxy).
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum ProxyConfig {
+    /// A SOCKS5 proxy.
+    Socks5 {
+        /// The address of the proxy as `host:port`.
+        addr: String,
+        /// Credentials for username/password authentication, if the proxy
+        /// requires it.
+        auth: Option<ProxyAuth>,
+    },
+    /// An HTTP proxy that tunnels connections with `CONNECT` requests.
+    Http {
+        /// The address of the proxy as `host:port`.
+        addr: String,
+        /// Credentials for basic authentication, if the proxy requires it.
+        auth: Option<ProxyAuth>,
+    },
+}
+
+/// Credentials for a proxy.
+#[derive(Clone, PartialEq, Eq)]
+pub struct ProxyAuth {
+    /// The username.
+    pub username: String,
+    /// The password.
+    pub password: String,
+}
+
+impl ProxyAuth {
+    /// Creates credentials from `username` and `password`.
+    pub fn new(username: impl Into<String>, password: impl Into<String>) -> Self {
+        ProxyAuth {
+            username: username.into(),
+            password: password.into(),
+        }
+    }
+}
+
+impl std::fmt::Debug for ProxyAuth {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ProxyAuth")
+            .field("username", &self.username)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Connects to `host:port` through `proxy`.
+pub(super) async fn connect(
+    proxy: &ProxyConfig,
+    host: &str,
+    port: u16,
+) -> Result<TcpStream, Error> {
+    match proxy {
+        ProxyConfig::Socks5 { addr, auth } => {
+            let mut stream = TcpStream::connect(addr.as_str()).await?;
+            socks5_connect(&mut stream, auth.as_ref(), host, port).await?;
+            Ok(stream)
+        }
+        ProxyConfig::Http { addr, auth } => {
+            let mut stream = TcpStream::connect(addr.as_str()).await?;
+            http_connect(&mut stream, auth.as_ref(), host, port).await?;
+            Ok(stream)
+        }
+    }
+}
+
+fn proxy_error(msg: impl Into<String>) -> Error {
+    Error::Io(io::Error::new(io::ErrorKind::Other, msg.into()))
+}
+
+const SOCKS5: u8 = 0x05;
+const NO_AUTH: u8 = 0x00;
+const USERNAME_PASSWORD: u8 = 0x02;
+const NO_ACCEPTABLE_METHODS: u8 = 0xff;
+const CONNECT: u8 = 0x01;
+const IPV4: u8 = 0x01;
+const DOMAIN_NAME: u8 = 0x03;
+const IPV6: u8 = 0x04;
+
+/// Performs the SOCKS5 handshake of RFC 1928 to open a tunnel to `host:port`.
+async fn socks5_connect(
+    stream: &mut TcpStream,
+    auth: Option<&ProxyAuth>,
+    host: &str,
+    port: u16,
+) -> Result<(), Error> {
+    let greeting: &[u8] = match auth {
+        Some(_) => &[SOCKS5, 2, NO_AUTH, USERNAME_PASSWORD],
+        None => &[SOCKS5, 1, NO_AUTH],
+    };
+    stream.write_all(greeting).await?;
+
+    let mut choice = [0; 2];
+    stream.read_exact(&mut choice).await?;
+    if choice[0] != SOCKS5 {
+        return Err(proxy_error("not a SOCKS5 proxy"));
+    }
+    match (choice[1], auth) {
+        (NO_AUTH, _) => {}
+        (USERNAME_PASSWORD, Some(auth)) => socks5_authenticate(stream, auth).await?,
+        (NO_ACCEPTABLE_METHODS, _) => {
+            return Err(proxy_error(
+                "SOCKS5 proxy accepts none of the authentication methods",
+            ))
+        }
+        (method, _) => {
+            return Err(proxy_error(format!(
+                "SOCKS5 proxy chose unsupported authentication method {}",
+                method
+            )))
+        }
+    }
+
+    // `host` is an IP literal without brackets or a domain name.
+    let mut request = vec![SOCKS5, CONNECT, 0];
+    if let Ok(ip) = host.parse::<Ipv4Addr>() {
+        request.push(IPV4);
+        request.extend_from_slice(&ip.octets());
+    } else if let Ok(ip) = host.parse::<Ipv6Addr>() {
+        request.push(IPV6);
+        request.extend_from_slice(&ip.octets());
+    } else {
+        let len =
+            u8::try_from(host.len()).map_err(|_| proxy_error("host name too long for SOCKS5"))?;
+        request.push(DOMAIN_NAME);
+        request.push(len);
+        request.extend_from_slice(host.as_bytes());
+    }
+    request.extend_from_slice(&port.to_be_bytes());
+    stream.write_all(&request).await?;
+
+    let mut reply = [0; 4];
+    stream.read_exact(&mut reply).await?;
+    if reply[0] != SOCKS5 {
+        return Err(proxy_error("not a SOCKS5 proxy"));
+    }
+    if reply[1] != 0 {
+        return Err(proxy_error(format!(
+            "SOCKS5 proxy failed to connect: {}",
+            socks5_reply_reason(reply[1])
+        )));
+    }
+    // Skip the address the proxy bound for the tunnel.
+    let len = match reply[3] {
+        IPV4 => 4,
+        IPV6 => 16,
+        DOMAIN_NAME => stream.read_u8().await? as usize,
+        atyp => {
+            return Err(proxy_error(format!(
+                "SOCKS5 proxy replied with unknown address type {}",
+                atyp
+            )))
+        }
+    };
+    let mut bound = vec![0; len + 2];
+    stream.read_exact(&mut bound).await?;
+    Ok(())
+}
+
+/// Performs the username/password authentication of RFC 1929.
+async fn socks5_authenticate(stream: &mut TcpStream, auth: &ProxyAuth) -> Result<(), Error> {
+    let username =
+        u8::try_from(auth.username.len()).map_err(|_| proxy_error("SOCKS5 username too long"))?;
+    let password =
+        u8::try_from(auth.password.len()).map_err(|_| proxy_error("SOCKS5 password too long"))?;
+
+    let mut request = vec![0x01, username];
+    request.extend_from_slice(auth.username.as_bytes());
+    request.push(password);
+    request.extend_from_slice(auth.password.as_bytes());
+    stream.write_all(&request).await?;
+
+    let mut reply = [0; 2];
+    stream.read_exact(&mut reply).await?;
+    if reply[1] != 0 {
+        return Err(proxy_error("SOCKS5 proxy rejected the credentials"));
+    }
+    Ok(())
+}
+
+fn socks5_reply_reason(code: u8) -> &'static str {
+    match code {
+        0x01 => "general failure",
+        0x02 => "connection not allowed by ruleset",
+        0x03 => "network unreachable",
+        0x04 => "host unreachable",
+        0x05 => "connection refused",
+        0x06 => "TTL expired",
+        0x07 => "command not supported",
+        0x08 => "address type not supported",
+        _ => "unknown error",
+    }
+}
+
+/// The maximum size of the response to a `CONNECT` request.
+const MAX_HTTP_RESPONSE: usize = 8192;
+
+/// Sends a `CONNECT` request to open a tunnel to `host:port`.
+async fn http_connect(
+    stream: &mut TcpStream,
+    auth: Option<&ProxyAuth>,
+    host: &str,
+    port: u16,
+) -> Result<(), Error> {
+    let target = if host.contains(':') {
+        format!("[{}]:{}", host, port)
+    } else {
+        format!("{}:{}", host, port)
+    };
+    let mut request = format!("CONNECT {0} HTTP/1.1\r\nHost: {0}\r\n", target);
+    if let Some(auth) = auth {
+        let credentials = format!("{}:{}", auth.username, auth.password);
+        request.push_str("Proxy-Authorization: Basic ");
+        request.push_str(&data_encoding::BASE64.encode(credentials.as_bytes()));
+        request.push_str("\r\n");
+    }
+    request.push_str("\r\n");
+    stream.write_all(request.as_bytes()).await?;
+
+    // Read byte by byte so that nothing after the response is consumed.
+    let mut response = Vec::new();
+    while !response.ends_with(b"\r\n\r\n") {
+        if response.len() == MAX_HTTP_RESPONSE {
+            return Err(proxy_error("HTTP proxy response too long"));
+        }
+        response.push(stream.read_u8().await?);
+    }
+
+    let status_line = response
+        .split(|&b| b == b'\r')
+        .next()
+        .and_then(|line| std::str::from_utf8(line).ok())
+        .unwrap_or_default();
+    let mut parts = status_line.splitn(3, ' ');
+    let version = parts.next().unwrap_or_default();
+    let status = parts.next().and_then(|code| code.parse::<u16>().ok());
+    match status {
+        Some(status) if version.starts_with("HTTP/1.") => {
+            if (200..300).contains(&status) {
+                Ok(())
+            } else {
+                Err(proxy_error(format!(
+                    "HTTP proxy responded with {} {}",
+                    status,
+                    parts.next().unwrap_or_default()
+                )))
+            }
+        }
+        _ => Err(proxy_error(format!(
+            "invalid HTTP proxy response {:?}",
+            status_line
+        ))),
+    }
+}
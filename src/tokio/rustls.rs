@@ -9,6 +9,7 @@ use tungstenite::stream::Mode;
 use tungstenite::Error;
 
 use std::convert::TryFrom;
+use std::sync::Arc;
 
 use crate::stream::Stream as StreamSwitcher;
 use crate::{client_async_with_config, domain, Response, WebSocketConfig, WebSocketStream};
@@ -20,9 +21,90 @@ pub type MaybeTlsStream<S> = StreamSwitcher<TokioAdapter<S>, TokioAdapter<TlsStr
 
 pub type AutoStream<S> = MaybeTlsStream<S>;
 
+impl<S> MaybeTlsStream<S> {
+    /// Returns the application protocol the server selected via ALPN.
+    ///
+    /// Returns `None` for plain connections and if no protocol was
+    /// negotiated. A server selecting anything other than `http/1.1`, e.g.
+    /// `h2`, can't upgrade the connection to a websocket.
+    pub fn alpn_protocol(&self) -> Option<Vec<u8>> {
+        match self {
+            StreamSwitcher::Plain(_) => None,
+            StreamSwitcher::Tls(stream) => {
+                stream.get_ref().get_ref().1.alpn_protocol().map(Vec::from)
+            }
+        }
+    }
+}
+
 pub type Connector = TlsConnector;
 
-async fn wrap_stream<S>(
+/// What a [`TokioConnector`](super::TokioConnector) keeps for all its
+/// connections.
+pub(super) type SharedConnector = TlsConnector;
+
+/// Builds a connector that trusts the roots selected by the features.
+#[allow(clippy::result_large_err)]
+pub(super) fn default_shared_connector() -> Result<SharedConnector, Error> {
+    #[cfg(feature = "tokio-rustls-manual-roots")]
+    log::error!("tokio-rustls-manual-roots was selected, but no connector was provided! No certificates can be verified in this state.");
+
+    Ok(TlsConnector::from(Arc::new(
+        ClientConfig::builder()
+            .with_root_certificates(default_roots()?)
+            .with_no_client_auth(),
+    )))
+}
+
+/// Returns the roots selected by the features.
+#[allow(clippy::result_large_err)]
+pub(super) fn default_roots() -> Result<RootCertStore, Error> {
+    #[cfg(feature = "tokio-rustls-manual-roots")]
+    let root_store = RootCertStore::empty();
+    #[cfg(not(feature = "tokio-rustls-manual-roots"))]
+    let mut root_store = RootCertStore::empty();
+
+    #[cfg(feature = "tokio-rustls-native-certs")]
+    {
+        let mut native_certs = rustls_native_certs::load_native_certs();
+        if let Some(err) = native_certs.errors.drain(..).next() {
+            return Err(std::io::Error::new(std::io::ErrorKind::Other, err).into());
+        }
+        let native_certs = native_certs.certs;
+        let total_number = native_certs.len();
+        let (number_added, number_ignored) = root_store.add_parsable_certificates(native_certs);
+        log::debug!("Added {number_added}/{total_number} native root certificates (ignored {number_ignored})");
+    }
+    #[cfg(all(
+        feature = "tokio-rustls-webpki-roots",
+        not(feature = "tokio-rustls-native-certs"),
+        not(feature = "tokio-rustls-manual-roots")
+    ))]
+    {
+        root_store.extend(webpki_roots::TLS_SERVER_ROOTS.iter().cloned());
+    }
+    Ok(root_store)
+}
+
+/// Returns the connector for one connection.
+#[allow(clippy::result_large_err)]
+pub(super) fn configure(shared: &SharedConnector) -> Result<Connector, Error> {
+    Ok(shared.clone())
+}
+
+impl From<Connector> for super::TokioConnector {
+    fn from(connector: Connector) -> Self {
+        super::TokioConnector { connector }
+    }
+}
+
+impl From<Arc<ClientConfig>> for super::TokioConnector {
+    fn from(config: Arc<ClientConfig>) -> Self {
+        TlsConnector::from(config).into()
+    }
+}
+
+pub(super) async fn wrap_stream<S>(
     socket: S,
     domain: String,
     connector: Option<Connector>,
@@ -35,42 +117,9 @@ where
         Mode::Plain => Ok(StreamSwitcher::Plain(TokioAdapter::new(socket))),
         Mode::Tls => {
             let stream = {
-                let connector = if let Some(connector) = connector {
-                    connector
-                } else {
-                    #[cfg(feature = "tokio-rustls-manual-roots")]
-                    log::error!("tokio-rustls-manual-roots was selected, but no connector was provided! No certificates can be verified in this state.");
-
-                    #[cfg(feature = "tokio-rustls-manual-roots")]
-                    let root_store = RootCertStore::empty();
-                    #[cfg(not(feature = "tokio-rustls-manual-roots"))]
-                    let mut root_store = RootCertStore::empty();
-
-                    #[cfg(feature = "tokio-rustls-native-certs")]
-                    {
-                        let mut native_certs = rustls_native_certs::load_native_certs();
-                        if let Some(err) = native_certs.errors.drain(..).next() {
-                            return Err(std::io::Error::new(std::io::ErrorKind::Other, err).into());
-                        }
-                        let native_certs = native_certs.certs;
-                        let total_number = native_certs.len();
-                        let (number_added, number_ignored) =
-                            root_store.add_parsable_certificates(native_certs);
-                        log::debug!("Added {number_added}/{total_number} native root certificates (ignored {number_ignored})");
-                    }
-                    #[cfg(all(
-                        feature = "tokio-rustls-webpki-roots",
-                        not(feature = "tokio-rustls-native-certs"),
-                        not(feature = "tokio-rustls-manual-roots")
-                    ))]
-                    {
-                        root_store.extend(webpki_roots::TLS_SERVER_ROOTS.iter().cloned());
-                    }
-                    TlsConnector::from(std::sync::Arc::new(
-                        ClientConfig::builder()
-                            .with_root_certificates(root_store)
-                            .with_no_client_auth(),
-                    ))
+                let connector = match connector {
+                    Some(connector) => connector,
+                    None => configure(&default_shared_connector()?)?,
                 };
                 let domain = ServerName::try_from(domain)
                     .map_err(|_| Error::Tls(TlsError::InvalidDnsName))?;
@@ -102,6 +151,30 @@ where
     // Make sure we check domain and mode first. URL must be valid.
     let mode = uri_mode(request.uri())?;
 
-    let stream = wrap_stream(stream, domain, connector, mode).await?;
+    let stream = wrap_stream(stream, domain, connector, mode)
+        .await
+        .map_err(crate::ConnectError::tls)?;
+    client_async_with_config(request, stream, config).await
+}
+
+/// Performs a TLS handshake on an already connected stream, with `domain` as
+/// the server name, and then the WebSocket handshake over it.
+///
+/// See [TLS over an existing stream](crate#tls-over-an-existing-stream).
+pub async fn client_async_tls_upgrade<R, S>(
+    stream: S,
+    request: R,
+    domain: &str,
+    connector: Option<Connector>,
+    config: Option<WebSocketConfig>,
+) -> Result<(WebSocketStream<AutoStream<S>>, Response), Error>
+where
+    R: IntoClientRequest + Unpin,
+    S: 'static + tokio::io::AsyncRead + tokio::io::AsyncWrite + Unpin,
+    AutoStream<S>: Unpin,
+{
+    let stream = wrap_stream(stream, domain.to_owned(), connector, Mode::Tls)
+        .await
+        .map_err(crate::ConnectError::tls)?;
     client_async_with_config(request, stream, config).await
 }
@@ -0,0 +1,71 @@
+//! `tower` integration.
+//!
+//! [`AcceptService`] performs the server side of the WebSocket handshake on
+//! each connection it is called with. This allows using middleware like
+//! timeouts, concurrency limits or load shedding for accepting connections.
+//!
+//! ```no_run
+//! # async fn test() {
+//! use async_std::net::TcpListener;
+//! use async_tungstenite::tower::AcceptService;
+//! use tower_service::Service;
+//!
+//! let listener = TcpListener::bind("127.0.0.1:8080").await.unwrap();
+//! let mut service = AcceptService::new();
+//! while let Ok((connection, _)) = listener.accept().await {
+//!     let ws_stream = service.call(connection).await.unwrap();
+//!     // ...
+//! }
+//! # }
+//! ```
+use std::future::Future;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use futures_io::{AsyncRead, AsyncWrite};
+use tower_service::Service;
+use tungstenite::protocol::WebSocketConfig;
+use tungstenite::Error as WsError;
+
+use crate::{accept_async_with_config, WebSocketStream};
+
+/// A [`Service`] that accepts WebSocket connections.
+///
+/// Calling it with a stream performs the server handshake like
+/// [`accept_async_with_config`] and resolves to the resulting
+/// [`WebSocketStream`]. The service is always ready.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct AcceptService {
+    config: Option<WebSocketConfig>,
+}
+
+impl AcceptService {
+    /// Creates a service using the default configuration.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Creates a service using the given configuration for all connections.
+    pub fn with_config(config: WebSocketConfig) -> Self {
+        AcceptService {
+            config: Some(config),
+        }
+    }
+}
+
+impl<S> Service<S> for AcceptService
+where
+    S: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+{
+    type Response = WebSocketStream<S>;
+    type Error = WsError;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send>>;
+
+    fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, stream: S) -> Self::Future {
+        Box::pin(accept_async_with_config(stream, self.config))
+    }
+}
@@ -0,0 +1,72 @@
+#![cfg(feature = "handshake")]
+
+use std::time::Duration;
+
+use async_std::net::{TcpListener, TcpStream};
+use async_std::task;
+use async_tungstenite::ack::{self, AckError};
+use async_tungstenite::{accept_async, client_async};
+use futures::prelude::*;
+use tungstenite::Message;
+
+#[async_std::test]
+async fn send_acked() {
+    let _ = env_logger::try_init();
+
+    // Acknowledges every message except "skip" and closes on "bye".
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    task::spawn(async move {
+        let (connection, _) = listener.accept().await.unwrap();
+        let mut stream = accept_async(connection).await.unwrap();
+        stream.send(Message::text("hello")).await.unwrap();
+        while let Some(Ok(Message::Text(text))) = stream.next().await {
+            let (seq, payload) = text.split_once(' ').unwrap();
+            match payload {
+                "skip" => {}
+                "bye" => break,
+                _ => {
+                    let ack = format!("ack {}", seq);
+                    stream.send(Message::text(ack)).await.unwrap();
+                }
+            }
+        }
+    });
+
+    let tcp = TcpStream::connect(addr).await.unwrap();
+    let (stream, _) = client_async(format!("ws://{}/", addr), tcp).await.unwrap();
+    let (sender, mut receiver) = ack::split(
+        stream,
+        |seq, msg| Message::text(format!("{} {}", seq, msg.to_text().unwrap())),
+        |msg| msg.to_text().ok()?.strip_prefix("ack ")?.parse().ok(),
+    );
+    let received = task::spawn(async move {
+        let mut received = Vec::new();
+        while let Some(Ok(msg)) = receiver.next().await {
+            received.push(msg);
+        }
+        received
+    });
+
+    assert_eq!(sender.send(Message::text("one")).await.unwrap(), 0);
+    assert_eq!(sender.send(Message::text("two")).await.unwrap(), 1);
+
+    let res = sender
+        .send_with_timeout(
+            Message::text("skip"),
+            task::sleep(Duration::from_millis(50)),
+        )
+        .await;
+    assert!(matches!(res, Err(AckError::Timeout)));
+    assert_eq!(sender.outstanding(), 0);
+
+    // The server goes away without acknowledging.
+    let res = sender.send(Message::text("bye")).await;
+    assert!(matches!(res, Err(AckError::Closed)));
+    assert!(matches!(
+        sender.send(Message::text("late")).await,
+        Err(AckError::Closed)
+    ));
+
+    assert_eq!(received.await, vec![Message::text("hello")]);
+}
@@ -0,0 +1,77 @@
+#![cfg(feature = "tokio-runtime")]
+
+use async_tungstenite::tokio::{accept_async, client_async, LagPolicy, TokioAdapter};
+use async_tungstenite::WebSocketStream;
+use futures::prelude::*;
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::broadcast;
+use tungstenite::protocol::frame::coding::CloseCode;
+use tungstenite::Message;
+
+/// Subscribes the server side of a new connection to a channel that already
+/// lagged behind by two messages.
+async fn connect(
+    on_lag: LagPolicy,
+) -> (
+    WebSocketStream<TokioAdapter<TcpStream>>,
+    broadcast::Sender<Message>,
+) {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let (tx, _) = broadcast::channel(1);
+
+    let feed = tx.clone();
+    tokio::spawn(async move {
+        let (connection, _) = listener.accept().await.expect("No connections to accept");
+        let stream = accept_async(connection)
+            .await
+            .expect("Failed to handshake with connection");
+        let (sender, mut receiver) = stream.split();
+
+        let rx = feed.subscribe();
+        for i in 0..3 {
+            feed.send(Message::text(i.to_string())).unwrap();
+        }
+        let task = sender.subscribe_to(rx, on_lag);
+
+        while let Some(Ok(_)) = receiver.next().await {}
+        task.await.unwrap().expect("Forwarding failed");
+    });
+
+    let tcp = TcpStream::connect(addr).await.expect("Failed to connect");
+    let url = format!("ws://{}/", addr);
+    let (stream, _) = client_async(url, tcp)
+        .await
+        .expect("Client failed to connect");
+
+    (stream, tx)
+}
+
+#[tokio::test]
+async fn lag_skip() {
+    let _ = env_logger::try_init();
+
+    let (mut stream, tx) = connect(LagPolicy::Skip).await;
+
+    let message = stream.next().await.unwrap().unwrap();
+    assert_eq!(message, Message::text("2"));
+
+    tx.send(Message::text("3")).unwrap();
+    let message = stream.next().await.unwrap().unwrap();
+    assert_eq!(message, Message::text("3"));
+
+    stream.close(None).await.unwrap();
+}
+
+#[tokio::test]
+async fn lag_close() {
+    let _ = env_logger::try_init();
+
+    let (mut stream, _tx) = connect(LagPolicy::Close).await;
+
+    match stream.next().await {
+        Some(Ok(Message::Close(Some(frame)))) => assert_eq!(frame.code, CloseCode::Again),
+        other => panic!("Unexpected message {:?}", other),
+    }
+    assert!(stream.next().await.is_none());
+}
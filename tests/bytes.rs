@@ -0,0 +1,149 @@
+#![cfg(all(feature = "handshake", feature = "futures-03-sink"))]
+
+use async_std::net::{TcpListener, TcpStream};
+use async_std::task;
+use async_tungstenite::{
+    accept_async, client_async, ByteReader, ByteWriter, FrameKind, WebSocketStream,
+};
+use futures::prelude::*;
+use tungstenite::Message;
+
+/// Connects to a server that collects all data messages it receives.
+async fn connect() -> (WebSocketStream<TcpStream>, task::JoinHandle<Vec<Message>>) {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let server = task::spawn(async move {
+        let (connection, _) = listener.accept().await.unwrap();
+        let mut stream = accept_async(connection).await.unwrap();
+        let mut messages = vec![];
+        while let Some(Ok(msg)) = stream.next().await {
+            if msg.is_binary() || msg.is_text() {
+                messages.push(msg);
+            }
+        }
+        messages
+    });
+
+    let tcp = TcpStream::connect(addr).await.unwrap();
+    let (stream, _) = client_async(format!("ws://{}/", addr), tcp).await.unwrap();
+    (stream, server)
+}
+
+#[async_std::test]
+async fn byte_writer_coalesces_writes() {
+    let _ = env_logger::try_init();
+
+    const WRITES: usize = 1000;
+    const CHUNK: &[u8] = b"0123456789";
+
+    let (stream, server) = connect().await;
+    let mut writer = ByteWriter::new(stream);
+
+    for _ in 0..WRITES {
+        writer.write_all(CHUNK).await.unwrap();
+    }
+    assert_eq!(writer.buffered(), WRITES * CHUNK.len());
+    writer.flush().await.unwrap();
+    assert_eq!(writer.buffered(), 0);
+
+    // Data written before closing is sent as well.
+    writer.write_all(CHUNK).await.unwrap();
+    writer.close().await.unwrap();
+
+    let messages = server.await;
+    assert_eq!(messages.len(), 2);
+    assert_eq!(messages[0], Message::binary(CHUNK.repeat(WRITES)));
+    assert_eq!(messages[1], Message::binary(CHUNK));
+}
+
+#[async_std::test]
+async fn byte_writer_flush_threshold() {
+    let _ = env_logger::try_init();
+
+    let (stream, server) = connect().await;
+    let mut writer = ByteWriter::new(stream);
+    writer.set_flush_threshold(4);
+
+    // Full buffers are sent without flushing.
+    writer.write_all(b"0123456789").await.unwrap();
+    assert_eq!(writer.buffered(), 2);
+    writer.flush().await.unwrap();
+
+    // Text keeps an incomplete sequence for the next message.
+    writer.set_frame_kind(FrameKind::Text);
+    writer.write_all("abc\u{e4}d".as_bytes()).await.unwrap();
+    writer.close().await.unwrap();
+
+    assert_eq!(
+        server.await,
+        vec![
+            Message::binary(&b"0123"[..]),
+            Message::binary(&b"4567"[..]),
+            Message::binary(&b"89"[..]),
+            Message::text("abc"),
+            Message::text("\u{e4}d"),
+        ]
+    );
+}
+
+#[async_std::test]
+async fn byte_writer_text() {
+    let _ = env_logger::try_init();
+
+    let (stream, server) = connect().await;
+    let mut writer = ByteWriter::new_text(stream);
+
+    // "ä" is split across two writes.
+    writer.write_all(b"h\xc3").await.unwrap();
+    writer.write_all(b"\xa4llo").await.unwrap();
+    writer.flush().await.unwrap();
+
+    // An incomplete sequence fails on flush and is discarded.
+    writer.write_all(b"\xc3").await.unwrap();
+    let err = writer.flush().await.unwrap_err();
+    match err.into_inner().unwrap().downcast::<tungstenite::Error>() {
+        Ok(err) => assert!(matches!(*err, tungstenite::Error::Utf8(_)), "{:?}", err),
+        Err(err) => panic!("Unexpected error {:?}", err),
+    }
+    assert_eq!(writer.buffered(), 0);
+    writer.close().await.unwrap();
+
+    assert_eq!(server.await, vec![Message::text("h\u{e4}llo")]);
+}
+
+#[async_std::test]
+async fn byte_reader_message_boundaries() {
+    let _ = env_logger::try_init();
+
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let server = task::spawn(async move {
+        let (connection, _) = listener.accept().await.unwrap();
+        let mut stream = accept_async(connection).await.unwrap();
+        stream.send(Message::binary(&b"hello"[..])).await.unwrap();
+        stream
+            .send(Message::Ping(b"ping"[..].into()))
+            .await
+            .unwrap();
+        stream.send(Message::binary(&b"world"[..])).await.unwrap();
+        while stream.next().await.is_some() {}
+    });
+
+    let tcp = TcpStream::connect(addr).await.unwrap();
+    let (stream, _) = client_async(format!("ws://{}/", addr), tcp).await.unwrap();
+    let mut reader = ByteReader::new(stream).with_message_boundaries(true);
+
+    // Reads smaller than a message end with a boundary as well, and the ping
+    // in between doesn't add one.
+    let mut buf = [0; 3];
+    let mut reads = vec![];
+    for _ in 0..6 {
+        let n = reader.read(&mut buf).await.unwrap();
+        reads.push(buf[..n].to_vec());
+    }
+    let expected: [&[u8]; 6] = [b"hel", b"lo", b"", b"wor", b"ld", b""];
+    assert_eq!(reads, expected);
+
+    drop(reader);
+    server.await;
+}
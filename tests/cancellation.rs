@@ -0,0 +1,109 @@
+use std::io::{self, Cursor};
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+
+use async_tungstenite::WebSocketStream;
+use futures::prelude::*;
+use futures::task::noop_waker_ref;
+use tungstenite::protocol::{Role, WebSocket, WebSocketConfig};
+use tungstenite::Message;
+
+/// A stream that accepts writes until `budget` bytes were written and then
+/// blocks until the budget is raised again.
+#[derive(Clone, Default)]
+struct Budget {
+    data: Arc<Mutex<Vec<u8>>>,
+    budget: Arc<Mutex<usize>>,
+}
+
+impl Budget {
+    fn set(&self, budget: usize) {
+        *self.budget.lock().unwrap() = budget;
+    }
+
+    fn written(&self) -> usize {
+        self.data.lock().unwrap().len()
+    }
+}
+
+impl AsyncRead for Budget {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        _cx: &mut Context<'_>,
+        _buf: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+        Poll::Pending
+    }
+}
+
+impl AsyncWrite for Budget {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        _cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let mut budget = self.budget.lock().unwrap();
+        if *budget == 0 {
+            return Poll::Pending;
+        }
+        let n = buf.len().min(*budget);
+        *budget -= n;
+        self.data.lock().unwrap().extend_from_slice(&buf[..n]);
+        Poll::Ready(Ok(n))
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_close(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+}
+
+/// Polls `fut` once, without waiting for a wakeup.
+fn poll_once<F: Future + Unpin>(fut: &mut F) -> Poll<F::Output> {
+    Pin::new(fut).poll(&mut Context::from_waker(noop_waker_ref()))
+}
+
+#[async_std::test]
+async fn dropped_send() {
+    let _ = env_logger::try_init();
+
+    let msgs: Vec<_> = (0..4u8).map(|i| Message::binary(vec![i; 100])).collect();
+
+    let budget = Budget::default();
+    let config = WebSocketConfig::default().write_buffer_size(0);
+    let mut stream =
+        WebSocketStream::from_raw_socket(budget.clone(), Role::Server, Some(config)).await;
+
+    // Dropped after writing part of the frame.
+    budget.set(5);
+    assert!(poll_once(&mut Box::pin(stream.send(msgs[0].clone()))).is_pending());
+    assert_eq!(budget.written(), 5);
+
+    // Dropped while the rest of the first frame is pending, before the
+    // message was queued.
+    let mut send = stream.send_owned(msgs[1].clone());
+    assert!(poll_once(&mut send).is_pending());
+    assert_eq!(send.into_unsent(), Some(msgs[1].clone()));
+
+    // Dropped after queueing the message.
+    budget.set(usize::MAX);
+    stream.send(msgs[1].clone()).await.unwrap();
+    budget.set(5);
+    let mut send = stream.send_owned(msgs[2].clone());
+    assert!(poll_once(&mut send).is_pending());
+    assert_eq!(send.into_unsent(), None);
+
+    budget.set(usize::MAX);
+    stream.send(msgs[3].clone()).await.unwrap();
+
+    // All frames arrive intact and in order.
+    let data = budget.data.lock().unwrap().clone();
+    let mut ws = WebSocket::from_raw_socket(Cursor::new(data), Role::Client, None);
+    for msg in &msgs {
+        assert_eq!(ws.read().unwrap(), *msg);
+    }
+}
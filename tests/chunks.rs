@@ -0,0 +1,181 @@
+#![cfg(all(feature = "handshake", feature = "futures-03-sink"))]
+
+use std::io;
+
+use async_std::net::{TcpListener, TcpStream};
+use async_std::task;
+use async_tungstenite::{accept_async, client_async, FrameKind, MessageChunk, WebSocketStream};
+use futures::prelude::*;
+use tungstenite::error::ProtocolError;
+use tungstenite::protocol::frame::coding::{CloseCode, Data as OpData, OpCode};
+use tungstenite::protocol::frame::Frame;
+use tungstenite::Message;
+
+/// Connects to a server that streams incoming messages in chunks and returns
+/// what it read.
+async fn connect() -> (
+    WebSocketStream<TcpStream>,
+    task::JoinHandle<Vec<Result<MessageChunk, tungstenite::Error>>>,
+) {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let server = task::spawn(async move {
+        let (connection, _) = listener.accept().await.unwrap();
+        let stream = accept_async(connection).await.unwrap();
+        stream.chunks().collect().await
+    });
+
+    let tcp = TcpStream::connect(addr).await.unwrap();
+    let (stream, _) = client_async(format!("ws://{}/", addr), tcp).await.unwrap();
+    (stream, server)
+}
+
+fn data_frame(data: Vec<u8>, opcode: OpData, fin: bool) -> Message {
+    Message::Frame(Frame::message(data, OpCode::Data(opcode), fin))
+}
+
+#[async_std::test]
+async fn fragmented_message_chunks() {
+    let _ = env_logger::try_init();
+
+    const FRAME: usize = 256 * 1024;
+
+    let (mut stream, server) = connect().await;
+    let data: Vec<u8> = (0..2 * FRAME).map(|i| i as u8).collect();
+
+    // A Ping between the fragments is still answered.
+    stream
+        .send(data_frame(data[..FRAME].to_vec(), OpData::Binary, false))
+        .await
+        .unwrap();
+    stream.send(Message::Ping("ping".into())).await.unwrap();
+    stream
+        .send(data_frame(data[FRAME..].to_vec(), OpData::Continue, true))
+        .await
+        .unwrap();
+    // "ä" is split across two frames.
+    stream
+        .send(data_frame(b"h\xc3".to_vec(), OpData::Text, false))
+        .await
+        .unwrap();
+    stream
+        .send(data_frame(b"\xa4llo".to_vec(), OpData::Continue, true))
+        .await
+        .unwrap();
+    stream.close(None).await.unwrap();
+
+    let mut pongs = 0;
+    while let Some(msg) = stream.next().await {
+        if msg.unwrap() == Message::Pong("ping".into()) {
+            pongs += 1;
+        }
+    }
+    assert_eq!(pongs, 1);
+
+    let chunks: Vec<MessageChunk> = server.await.into_iter().map(Result::unwrap).collect();
+    let (binary, text): (Vec<_>, Vec<_>) = chunks
+        .iter()
+        .partition(|chunk| chunk.kind() == FrameKind::Binary);
+
+    // The binary message can't be read at once.
+    assert!(binary.len() > 2, "{} chunks", binary.len());
+    assert!(binary[..binary.len() - 1].iter().all(|c| !c.is_final()));
+    assert!(binary[binary.len() - 1].is_final());
+    let received: Vec<u8> = binary.iter().flat_map(|c| c.data().to_vec()).collect();
+    assert!(received == data);
+
+    assert!(text.last().unwrap().is_final());
+    let received: Vec<u8> = text.iter().flat_map(|c| c.data().to_vec()).collect();
+    assert_eq!(received, "h\u{e4}llo".as_bytes());
+}
+
+#[async_std::test]
+async fn interleaved_messages_rejected() {
+    let _ = env_logger::try_init();
+
+    let (mut stream, server) = connect().await;
+    stream
+        .send(data_frame(b"first".to_vec(), OpData::Binary, false))
+        .await
+        .unwrap();
+    stream
+        .send(data_frame(b"second".to_vec(), OpData::Text, true))
+        .await
+        .unwrap();
+
+    // The server fails the connection.
+    match stream.next().await {
+        Some(Ok(Message::Close(Some(frame)))) => assert_eq!(frame.code, CloseCode::Protocol),
+        other => panic!("Unexpected message {:?}", other),
+    }
+    drop(stream);
+
+    let chunks = server.await;
+    assert_eq!(chunks.len(), 2);
+    assert_eq!(chunks[0].as_ref().unwrap().data(), &b"first"[..]);
+    match chunks[1] {
+        Err(tungstenite::Error::Protocol(ProtocolError::ExpectedFragment(OpData::Text))) => {}
+        ref other => panic!("Unexpected result {:?}", other),
+    }
+}
+
+/// Accepts a client handshake with a hand written response, followed by
+/// `data` in the same write.
+async fn accept_raw(listener: TcpListener, data: &'static [u8]) {
+    let (mut connection, _) = listener.accept().await.unwrap();
+    let mut buf = [0; 1024];
+    let n = connection.read(&mut buf).await.unwrap();
+    let request = String::from_utf8_lossy(&buf[..n]).into_owned();
+    let key = request
+        .lines()
+        .filter_map(|line| line.split_once(": "))
+        .find(|(name, _)| name.eq_ignore_ascii_case("Sec-WebSocket-Key"))
+        .unwrap()
+        .1;
+    let response = format!(
+        "HTTP/1.1 101 Switching Protocols\r\nConnection: Upgrade\r\n\
+         Upgrade: websocket\r\nSec-WebSocket-Accept: {}\r\n\r\n",
+        tungstenite::handshake::derive_accept_key(key.as_bytes())
+    );
+    let mut response = response.into_bytes();
+    response.extend_from_slice(data);
+    connection.write_all(&response).await.unwrap();
+    while connection.read(&mut buf).await.unwrap_or(0) > 0 {}
+}
+
+async fn connect_raw(data: &'static [u8]) -> WebSocketStream<TcpStream> {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    task::spawn(accept_raw(listener, data));
+
+    let tcp = TcpStream::connect(addr).await.unwrap();
+    let (stream, _) = client_async(format!("ws://{}/", addr), tcp).await.unwrap();
+    stream
+}
+
+#[async_std::test]
+async fn client_chunks_with_handshake_response() {
+    let _ = env_logger::try_init();
+
+    // The message arrives together with the response.
+    let mut chunks = connect_raw(b"\x82\x05hello").await.chunks();
+    let chunk = chunks.next().await.unwrap().unwrap();
+    assert_eq!(chunk.kind(), FrameKind::Binary);
+    assert_eq!(chunk.data(), &b"hello"[..]);
+    assert!(chunk.is_final());
+}
+
+#[async_std::test]
+async fn chunks_after_read() {
+    let _ = env_logger::try_init();
+
+    let mut stream = connect_raw(b"\x81\x01a\x81\x01b").await;
+    assert_eq!(stream.next().await.unwrap().unwrap(), Message::text("a"));
+
+    // The second message is already buffered by tungstenite.
+    let mut chunks = stream.chunks();
+    match chunks.next().await {
+        Some(Err(tungstenite::Error::Io(err))) => assert_eq!(err.kind(), io::ErrorKind::Other),
+        other => panic!("Unexpected result {:?}", other),
+    }
+}
@@ -1,8 +1,12 @@
 #![cfg(feature = "handshake")]
 
+use std::io;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
 use async_std::net::{TcpListener, TcpStream};
 use async_std::task;
-use async_tungstenite::{accept_async, client_async, WebSocketStream};
+use async_tungstenite::{accept_async, client_async, ReuniteError, WebSocketStream};
 use futures::prelude::*;
 use log::*;
 use tungstenite::Message;
@@ -70,6 +74,14 @@ async fn communication() {
     assert_eq!(messages.len(), 10);
 }
 
+#[test]
+fn stream_is_sync() {
+    fn assert_sync<T: Send + Sync>() {}
+    assert_sync::<WebSocketStream<TcpStream>>();
+    assert_sync::<async_tungstenite::WebSocketSender<TcpStream>>();
+    assert_sync::<async_tungstenite::WebSocketReceiver<TcpStream>>();
+}
+
 #[async_std::test]
 async fn split_communication() {
     let _ = env_logger::try_init();
@@ -110,17 +122,91 @@ async fn split_communication() {
             .expect("Failed to send message");
     }
 
+    tx.send_vectored(&[b"head", b"-", b"body"])
+        .await
+        .expect("Failed to send message");
+
     tx.close(None).await.expect("Failed to close");
 
     info!("Waiting for response messages");
     let messages = msg_rx.await.expect("Failed to receive messages");
-    assert_eq!(messages.len(), 10);
+    assert_eq!(messages.len(), 11);
+    assert_eq!(messages[9], Message::binary(&b"head-body"[..]));
 
     assert!(tx.is_pair_of(&rx));
     assert!(rx.is_pair_of(&tx));
+    assert_eq!(tx.reference_count(), 2);
     WebSocketStream::reunite(tx, rx).expect("Failed to reunite the stream");
 }
 
+#[async_std::test]
+async fn cloned_sender() {
+    let _ = env_logger::try_init();
+
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let (msg_tx, msg_rx) = futures::channel::oneshot::channel();
+
+    task::spawn(async move {
+        let (connection, _) = listener.accept().await.expect("No connections to accept");
+        let stream = accept_async(connection)
+            .await
+            .expect("Failed to handshake with connection");
+        run_connection(stream, msg_tx).await;
+    });
+
+    let tcp = TcpStream::connect(addr).await.expect("Failed to connect");
+    let url = format!("ws://{}/", addr);
+    let (stream, _) = client_async(url, tcp)
+        .await
+        .expect("Client failed to connect");
+    let (tx, rx) = stream.split();
+
+    let clones = vec![tx.clone(), tx.clone()];
+    assert_eq!(tx.reference_count(), 4);
+    let tasks = clones.into_iter().enumerate().map(|(i, tx)| {
+        task::spawn(async move {
+            for j in 0..10 {
+                tx.send(Message::text(format!("{}-{}", i, j)))
+                    .await
+                    .expect("Failed to send message");
+            }
+        })
+    });
+    future::join_all(tasks).await;
+
+    // A clone that is still alive prevents reuniting.
+    let clone = tx.clone();
+    let (tx, rx) = match WebSocketStream::reunite(tx, rx) {
+        Err(err @ ReuniteError::OutstandingClones(..)) => {
+            assert_eq!(
+                err.to_string(),
+                "clones of the sender are still alive (1 outstanding)"
+            );
+            err.into_parts()
+        }
+        res => panic!("Unexpected result {:?}", res.map(|_| ())),
+    };
+    drop(clone);
+
+    // The clones were dropped with their tasks.
+    assert_eq!(tx.reference_count(), 2);
+    let mut stream = WebSocketStream::reunite(tx, rx).expect("Failed to reunite the stream");
+    stream.close(None).await.expect("Failed to close");
+
+    let messages = msg_rx.await.expect("Failed to receive messages");
+    assert_eq!(messages.len(), 21);
+    for i in 0..2 {
+        let sent: Vec<_> = messages
+            .iter()
+            .filter_map(|msg| msg.to_text().ok())
+            .filter(|text| text.starts_with(&format!("{}-", i)))
+            .collect();
+        let expected: Vec<_> = (0..10).map(|j| format!("{}-{}", i, j)).collect();
+        assert_eq!(sent, expected);
+    }
+}
+
 #[async_std::test]
 async fn concurrent_send() {
     let _ = env_logger::try_init();
@@ -170,3 +256,1890 @@ async fn concurrent_send() {
     let messages = msg_rx.await.expect("Failed to receive messages");
     assert_eq!(messages.len(), 10);
 }
+
+async fn deliver_after_close_test(deliver: bool) -> Vec<Message> {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let (sent_tx, sent_rx) = futures::channel::oneshot::channel();
+
+    task::spawn(async move {
+        let (connection, _) = listener.accept().await.expect("No connections to accept");
+        let mut stream = accept_async(connection)
+            .await
+            .expect("Failed to handshake with connection");
+        stream
+            .send(Message::text("late"))
+            .await
+            .expect("Failed to send message");
+        sent_tx.send(()).unwrap();
+        while let Some(message) = stream.next().await {
+            message.expect("Failed to get message");
+        }
+    });
+
+    let tcp = TcpStream::connect(addr).await.expect("Failed to connect");
+    let url = format!("ws://{}/", addr);
+    let (mut stream, _) = client_async(url, tcp)
+        .await
+        .expect("Client failed to connect");
+    stream.set_deliver_after_close(deliver);
+
+    sent_rx.await.expect("Server did not send");
+    stream.close(None).await.expect("Failed to close");
+
+    let mut messages = vec![];
+    while let Some(message) = stream.next().await {
+        messages.push(message.expect("Failed to get message"));
+    }
+    messages
+}
+
+#[async_std::test]
+async fn deliver_after_close() {
+    let _ = env_logger::try_init();
+
+    let messages = deliver_after_close_test(true).await;
+    assert_eq!(messages.len(), 2);
+    assert_eq!(messages[0], Message::text("late"));
+    assert!(messages[1].is_close());
+
+    let messages = deliver_after_close_test(false).await;
+    assert_eq!(messages.len(), 1);
+    assert!(messages[0].is_close());
+}
+
+#[async_std::test]
+async fn data_frames() {
+    let _ = env_logger::try_init();
+
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+
+    let server = task::spawn(async move {
+        let (connection, _) = listener.accept().await.expect("No connections to accept");
+        let mut stream = accept_async(connection)
+            .await
+            .expect("Failed to handshake with connection");
+        stream.send(Message::Ping("ping".into())).await.unwrap();
+        stream.send(Message::text("hello")).await.unwrap();
+        stream.close(None).await.unwrap();
+        let mut messages = vec![];
+        while let Some(message) = stream.next().await {
+            messages.push(message.expect("Failed to get message"));
+        }
+        messages
+    });
+
+    let tcp = TcpStream::connect(addr).await.expect("Failed to connect");
+    let url = format!("ws://{}/", addr);
+    let (stream, _) = client_async(url, tcp)
+        .await
+        .expect("Client failed to connect");
+
+    let messages: Vec<Message> = stream.data_frames().try_collect().await.unwrap();
+    assert_eq!(messages, vec![Message::text("hello")]);
+
+    // The Ping was answered and the close handshake completed.
+    let messages = server.await;
+    assert_eq!(messages.len(), 2);
+    assert_eq!(messages[0], Message::Pong("ping".into()));
+    assert!(messages[1].is_close());
+}
+
+#[async_std::test]
+async fn idle_duration_real_clock() {
+    use std::time::Duration;
+
+    let _ = env_logger::try_init();
+
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let (msg_tx, msg_rx) = futures::channel::oneshot::channel();
+
+    task::spawn(async move {
+        let (connection, _) = listener.accept().await.expect("No connections to accept");
+        let stream = accept_async(connection)
+            .await
+            .expect("Failed to handshake with connection");
+        run_connection(stream, msg_tx).await;
+    });
+
+    let tcp = TcpStream::connect(addr).await.expect("Failed to connect");
+    let url = format!("ws://{}/", addr);
+    let (mut stream, _) = client_async(url, tcp)
+        .await
+        .expect("Client failed to connect");
+
+    let idle = Duration::from_millis(50);
+    task::sleep(idle).await;
+    assert!(stream.idle_duration() >= idle);
+
+    stream
+        .send(Message::text("ping"))
+        .await
+        .expect("Failed to send message");
+    assert!(stream.idle_duration() < idle);
+
+    stream.close(None).await.expect("Failed to close");
+    let messages = msg_rx.await.expect("Failed to receive messages");
+    assert_eq!(messages.len(), 2);
+}
+
+#[cfg(feature = "test-clock")]
+#[async_std::test]
+async fn idle_duration() {
+    use async_tungstenite::TestClock;
+    use std::time::Duration;
+
+    let _ = env_logger::try_init();
+
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let (msg_tx, msg_rx) = futures::channel::oneshot::channel();
+
+    task::spawn(async move {
+        let (connection, _) = listener.accept().await.expect("No connections to accept");
+        let stream = accept_async(connection)
+            .await
+            .expect("Failed to handshake with connection");
+        run_connection(stream, msg_tx).await;
+    });
+
+    let tcp = TcpStream::connect(addr).await.expect("Failed to connect");
+    let url = format!("ws://{}/", addr);
+    let (mut stream, _) = client_async(url, tcp)
+        .await
+        .expect("Client failed to connect");
+
+    let clock = TestClock::new();
+    stream.set_clock(clock.clone());
+    assert_eq!(stream.idle_duration(), Duration::ZERO);
+
+    clock.advance(Duration::from_secs(5));
+    assert_eq!(stream.idle_duration(), Duration::from_secs(5));
+
+    stream
+        .send(Message::text("ping"))
+        .await
+        .expect("Failed to send message");
+    assert_eq!(stream.idle_duration(), Duration::ZERO);
+
+    stream.close(None).await.expect("Failed to close");
+    let messages = msg_rx.await.expect("Failed to receive messages");
+    assert_eq!(messages.len(), 2);
+}
+
+/// Reports the end of the stream as `UnexpectedEof`, like TLS streams do if
+/// the peer did not send `close_notify`.
+struct NoCloseNotify(TcpStream);
+
+impl AsyncRead for NoCloseNotify {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+        match Pin::new(&mut self.0).poll_read(cx, buf) {
+            Poll::Ready(Ok(0)) if !buf.is_empty() => {
+                Poll::Ready(Err(io::ErrorKind::UnexpectedEof.into()))
+            }
+            other => other,
+        }
+    }
+}
+
+impl AsyncWrite for NoCloseNotify {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.0).poll_write(cx, buf)
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.0).poll_flush(cx)
+    }
+
+    fn poll_close(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.0).poll_close(cx)
+    }
+}
+
+async fn close_without_close_notify(strict: bool) -> Option<tungstenite::Error> {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+
+    task::spawn(async move {
+        let (connection, _) = listener.accept().await.expect("No connections to accept");
+        let mut stream = accept_async(connection)
+            .await
+            .expect("Failed to handshake with connection");
+        stream.close(None).await.expect("Failed to close");
+        while stream.next().await.is_some() {}
+    });
+
+    let tcp = TcpStream::connect(addr).await.expect("Failed to connect");
+    let url = format!("ws://{}/", addr);
+    let (mut stream, _) = client_async(url, NoCloseNotify(tcp))
+        .await
+        .expect("Client failed to connect");
+    stream.set_strict_tls_close(strict);
+
+    let mut error = None;
+    while let Some(message) = stream.next().await {
+        if let Err(err) = message {
+            error = Some(err);
+        }
+    }
+    error
+}
+
+#[async_std::test]
+async fn strict_tls_close() {
+    let _ = env_logger::try_init();
+
+    assert!(close_without_close_notify(false).await.is_none());
+    match close_without_close_notify(true).await {
+        Some(tungstenite::Error::Io(err)) => {
+            assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof)
+        }
+        other => panic!("Unexpected result {:?}", other),
+    }
+}
+
+#[async_std::test]
+async fn concurrent_send_large() {
+    let _ = env_logger::try_init();
+
+    const SIZE: usize = 256 * 1024;
+
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let (msg_tx, msg_rx) = futures::channel::oneshot::channel();
+
+    task::spawn(async move {
+        let (connection, _) = listener.accept().await.expect("No connections to accept");
+        let stream = accept_async(connection)
+            .await
+            .expect("Failed to handshake with connection");
+        run_connection(stream, msg_tx).await;
+    });
+
+    let tcp = TcpStream::connect(addr).await.expect("Failed to connect");
+    let url = format!("ws://{}/", addr);
+    let (stream, _) = client_async(url, tcp)
+        .await
+        .expect("Client failed to connect");
+
+    let (tx, _rx) = stream.split();
+
+    let results =
+        futures::future::join_all((0..16u8).map(|i| tx.send(Message::binary(vec![i; SIZE])))).await;
+    assert!(results.iter().all(Result::is_ok));
+
+    tx.close(None).await.expect("Failed to close");
+
+    let messages = msg_rx.await.expect("Failed to receive messages");
+    assert_eq!(messages.len(), 17);
+
+    let mut seen = [false; 16];
+    for message in &messages[..16] {
+        let data = message.clone().into_data();
+        assert_eq!(data.len(), SIZE);
+        assert!(data.iter().all(|b| *b == data[0]), "message corrupted");
+        seen[data[0] as usize] = true;
+    }
+    assert!(seen.iter().all(|seen| *seen));
+}
+
+#[async_std::test]
+async fn max_fragments_per_message() {
+    use tungstenite::protocol::frame::coding::{CloseCode, Data, OpCode};
+    use tungstenite::protocol::frame::Frame;
+
+    let _ = env_logger::try_init();
+
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let (err_tx, err_rx) = futures::channel::oneshot::channel();
+
+    task::spawn(async move {
+        let (connection, _) = listener.accept().await.expect("No connections to accept");
+        let mut stream = accept_async(connection)
+            .await
+            .expect("Failed to handshake with connection");
+        stream.set_max_fragments_per_message(Some(4));
+
+        let message = stream.next().await.expect("Stream ended early");
+        assert_eq!(
+            message.expect("Failed to get message"),
+            Message::text("xxxx")
+        );
+        let err = stream
+            .next()
+            .await
+            .expect("Stream ended early")
+            .unwrap_err();
+        err_tx.send(err).unwrap();
+    });
+
+    let tcp = TcpStream::connect(addr).await.expect("Failed to connect");
+    let url = format!("ws://{}/", addr);
+    let (mut stream, _) = client_async(url, tcp)
+        .await
+        .expect("Client failed to connect");
+
+    // Exactly at the limit.
+    let fragment =
+        |opcode, fin| Message::Frame(Frame::message(vec![b'x'; 1], OpCode::Data(opcode), fin));
+    let mut fragments = vec![fragment(Data::Text, false)];
+    fragments.extend((0..2).map(|_| fragment(Data::Continue, false)));
+    fragments.push(fragment(Data::Continue, true));
+
+    fragments.push(fragment(Data::Binary, false));
+    fragments.extend((0..10).map(|_| fragment(Data::Continue, false)));
+    stream.send_batch(fragments).await.unwrap();
+
+    match err_rx.await.expect("Server task failed") {
+        tungstenite::Error::Io(err) => assert_eq!(err.kind(), io::ErrorKind::InvalidData),
+        other => panic!("Unexpected error {:?}", other),
+    }
+    match stream.next().await {
+        Some(Ok(Message::Close(Some(frame)))) => assert_eq!(frame.code, CloseCode::Size),
+        other => panic!("Unexpected message {:?}", other),
+    }
+}
+
+/// Accepts a client handshake with a hand written response, followed by
+/// `data` in the same write.
+async fn accept_raw(listener: TcpListener, data: &[u8]) -> TcpStream {
+    let (mut connection, _) = listener.accept().await.unwrap();
+    let mut buf = [0; 1024];
+    let n = connection.read(&mut buf).await.unwrap();
+    let request = String::from_utf8_lossy(&buf[..n]).into_owned();
+    let key = request
+        .lines()
+        .filter_map(|line| line.split_once(": "))
+        .find(|(name, _)| name.eq_ignore_ascii_case("Sec-WebSocket-Key"))
+        .unwrap()
+        .1;
+    let response = format!(
+        "HTTP/1.1 101 Switching Protocols\r\nConnection: Upgrade\r\n\
+         Upgrade: websocket\r\nSec-WebSocket-Accept: {}\r\n\r\n",
+        tungstenite::handshake::derive_accept_key(key.as_bytes())
+    );
+    let mut response = response.into_bytes();
+    response.extend_from_slice(data);
+    connection.write_all(&response).await.unwrap();
+    connection
+}
+
+fn frame_bytes(
+    data: &[u8],
+    opcode: tungstenite::protocol::frame::coding::Data,
+    fin: bool,
+) -> Vec<u8> {
+    use tungstenite::protocol::frame::coding::OpCode;
+    use tungstenite::protocol::frame::Frame;
+
+    let mut bytes = Vec::new();
+    Frame::message(data.to_vec(), OpCode::Data(opcode), fin)
+        .format(&mut bytes)
+        .unwrap();
+    bytes
+}
+
+#[async_std::test]
+async fn max_fragments_per_message_client() {
+    use tungstenite::protocol::frame::coding::Data;
+
+    let _ = env_logger::try_init();
+
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let (read_tx, read_rx) = futures::channel::oneshot::channel();
+
+    // A message and the first half of a large frame arrive together with
+    // the response.
+    let large = frame_bytes(&[0xff; 64 * 1024], Data::Binary, true);
+    let (first, second) = large.split_at(large.len() / 2);
+    let mut data = frame_bytes(b"a", Data::Text, true);
+    data.extend_from_slice(first);
+    let mut rest = second.to_vec();
+    rest.extend(frame_bytes(b"x", Data::Text, false));
+    for _ in 0..2 {
+        rest.extend(frame_bytes(b"x", Data::Continue, false));
+    }
+    task::spawn(async move {
+        let mut connection = accept_raw(listener, &data).await;
+        read_rx.await.unwrap();
+        connection.write_all(&rest).await.unwrap();
+        let mut buf = [0; 1024];
+        while connection.read(&mut buf).await.unwrap_or(0) > 0 {}
+    });
+
+    let tcp = TcpStream::connect(addr).await.expect("Failed to connect");
+    let url = format!("ws://{}/", addr);
+    let (mut stream, _) = client_async(url, tcp)
+        .await
+        .expect("Client failed to connect");
+    stream.set_max_fragments_per_message(Some(2));
+
+    // The rest of the large frame arrives once its start was read.
+    assert_eq!(stream.next().await.unwrap().unwrap(), Message::text("a"));
+    read_tx.send(()).unwrap();
+
+    assert_eq!(
+        stream.next().await.unwrap().unwrap(),
+        Message::binary(vec![0xff; 64 * 1024])
+    );
+    match stream.next().await.unwrap() {
+        Err(tungstenite::Error::Io(err)) => assert_eq!(err.kind(), io::ErrorKind::InvalidData),
+        other => panic!("Unexpected result {:?}", other),
+    }
+}
+
+#[async_std::test]
+async fn max_fragments_with_handshake_response() {
+    use tungstenite::protocol::frame::coding::Data;
+
+    let _ = env_logger::try_init();
+
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+
+    // The whole message arrives together with the response.
+    let mut data = frame_bytes(b"x", Data::Binary, false);
+    for _ in 0..2 {
+        data.extend(frame_bytes(b"x", Data::Continue, false));
+    }
+    task::spawn(async move {
+        let mut connection = accept_raw(listener, &data).await;
+        let mut buf = [0; 1024];
+        while connection.read(&mut buf).await.unwrap_or(0) > 0 {}
+    });
+
+    let tcp = TcpStream::connect(addr).await.expect("Failed to connect");
+    let url = format!("ws://{}/", addr);
+    let (mut stream, _) = client_async(url, tcp)
+        .await
+        .expect("Client failed to connect");
+    stream.set_max_fragments_per_message(Some(2));
+
+    match stream.next().await.unwrap() {
+        Err(tungstenite::Error::Io(err)) => assert_eq!(err.kind(), io::ErrorKind::InvalidData),
+        other => panic!("Unexpected result {:?}", other),
+    }
+}
+
+async fn orphan_continuation_test(ignore: bool) -> Result<Message, tungstenite::Error> {
+    use tungstenite::protocol::frame::coding::{Data, OpCode};
+    use tungstenite::protocol::frame::Frame;
+
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let (tx, rx) = futures::channel::oneshot::channel();
+
+    task::spawn(async move {
+        let (connection, _) = listener.accept().await.expect("No connections to accept");
+        let mut stream = accept_async(connection)
+            .await
+            .expect("Failed to handshake with connection");
+        stream.set_ignore_orphan_continuations(ignore);
+        let message = stream.next().await.expect("Stream ended early");
+        tx.send(message).unwrap();
+    });
+
+    let tcp = TcpStream::connect(addr).await.expect("Failed to connect");
+    let url = format!("ws://{}/", addr);
+    let (mut stream, _) = client_async(url, tcp)
+        .await
+        .expect("Client failed to connect");
+
+    let orphan = Frame::message(vec![b'x'], OpCode::Data(Data::Continue), true);
+    stream.send(Message::Frame(orphan)).await.unwrap();
+    stream.send(Message::text("after")).await.unwrap();
+
+    rx.await.expect("Server task failed")
+}
+
+#[async_std::test]
+async fn orphan_continuation() {
+    use tungstenite::error::ProtocolError;
+
+    let _ = env_logger::try_init();
+
+    match orphan_continuation_test(false).await {
+        Err(tungstenite::Error::Protocol(ProtocolError::UnexpectedContinueFrame)) => {}
+        other => panic!("Unexpected result {:?}", other),
+    }
+    assert_eq!(
+        orphan_continuation_test(true).await.unwrap(),
+        Message::text("after")
+    );
+}
+
+/// Splits the bytes written by a client into the first byte of every frame,
+/// i.e. the FIN bit and the opcode, and the payload length.
+fn written_frames(mut written: &[u8]) -> Vec<(u8, usize)> {
+    let mut frames = Vec::new();
+    while !written.is_empty() {
+        // Payloads are short and masked.
+        let len = (written[1] & 0x7f) as usize;
+        assert!(len < 126);
+        frames.push((written[0], len));
+        written = &written[2 + 4 + len..];
+    }
+    frames
+}
+
+#[async_std::test]
+async fn send_fragmented() {
+    use std::sync::{Arc, Mutex};
+
+    let _ = env_logger::try_init();
+
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let (tx, rx) = futures::channel::oneshot::channel();
+
+    task::spawn(async move {
+        let (connection, _) = listener.accept().await.expect("No connections to accept");
+        let stream = accept_async(connection)
+            .await
+            .expect("Failed to handshake with connection");
+        run_connection(stream, tx).await;
+    });
+
+    let tcp = TcpStream::connect(addr).await.expect("Failed to connect");
+    let url = format!("ws://{}/", addr);
+    let (mut stream, _) = client_async(url, tcp)
+        .await
+        .expect("Client failed to connect");
+    let written = Arc::new(Mutex::new(Vec::new()));
+    let tap = written.clone();
+    stream.set_wire_tap(move |direction, bytes| {
+        if direction == async_tungstenite::TapDirection::Write {
+            tap.lock().unwrap().extend_from_slice(bytes);
+        }
+    });
+    let (sender, receiver) = stream.split();
+
+    sender
+        .send_fragmented(Message::text("0123456789"), 4)
+        .await
+        .unwrap();
+    sender
+        .send_fragmented(Message::binary(vec![1, 2]), 4)
+        .await
+        .unwrap();
+    assert_eq!(
+        written_frames(&written.lock().unwrap()),
+        [(0x01, 4), (0x00, 4), (0x80, 2), (0x82, 2)]
+    );
+
+    // Concurrent sends never end up between the frames of a message.
+    written.lock().unwrap().clear();
+    let sends = (0..8).map(|i| {
+        let sender = sender.clone();
+        async move {
+            if i % 2 == 0 {
+                sender
+                    .send_fragmented(Message::binary(vec![0; 10]), 2)
+                    .await
+            } else {
+                sender.send(Message::text("x")).await
+            }
+        }
+    });
+    for res in future::join_all(sends).await {
+        res.unwrap();
+    }
+    let frames = written_frames(&written.lock().unwrap());
+    assert_eq!(frames.len(), 4 * 5 + 4);
+    let mut in_message = false;
+    for (first, _) in frames {
+        let (fin, opcode) = (first & 0x80 != 0, first & 0x0f);
+        assert_eq!(opcode == 0, in_message, "unexpected frame {:#x}", first);
+        in_message = !fin;
+    }
+
+    let mut stream = WebSocketStream::reunite(sender, receiver).unwrap();
+    stream.close(None).await.unwrap();
+    let messages = rx.await.expect("Server task failed");
+    assert_eq!(messages.len(), 2 + 8 + 1);
+    assert_eq!(messages[0], Message::text("0123456789"));
+    assert_eq!(messages[1], Message::binary(vec![1, 2]));
+}
+
+#[async_std::test]
+async fn send_chunks() {
+    use async_tungstenite::FrameKind;
+
+    let _ = env_logger::try_init();
+
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let (msg_tx, msg_rx) = futures::channel::oneshot::channel();
+
+    task::spawn(async move {
+        let (connection, _) = listener.accept().await.expect("No connections to accept");
+        let mut stream = accept_async(connection)
+            .await
+            .expect("Failed to handshake with connection");
+        // Three chunks and the final frame.
+        stream.set_max_fragments_per_message(Some(4));
+        run_connection(stream, msg_tx).await;
+    });
+
+    let tcp = TcpStream::connect(addr).await.expect("Failed to connect");
+    let url = format!("ws://{}/", addr);
+    let (mut stream, _) = client_async(url, tcp)
+        .await
+        .expect("Client failed to connect");
+
+    let chunks = stream::iter(vec![b"ab".to_vec(), b"cd".to_vec(), b"ef".to_vec()]);
+    stream.send_chunks(FrameKind::Binary, chunks).await.unwrap();
+    stream
+        .send_chunks(
+            FrameKind::Text,
+            stream::iter(vec![b"h\xc3".to_vec(), b"\xa4".to_vec()]),
+        )
+        .await
+        .unwrap();
+    stream
+        .send_chunks(FrameKind::Binary, stream::empty())
+        .await
+        .unwrap();
+    stream.close(None).await.unwrap();
+
+    let messages = msg_rx.await.expect("Failed to receive messages");
+    assert_eq!(messages.len(), 4);
+    assert_eq!(messages[0], Message::binary(&b"abcdef"[..]));
+    assert_eq!(messages[1], Message::text("h\u{e4}"));
+    assert_eq!(messages[2], Message::binary(vec![]));
+    assert!(messages[3].is_close());
+}
+
+#[async_std::test]
+async fn send_chunks_cancelled() {
+    use async_tungstenite::FrameKind;
+    use tungstenite::error::ProtocolError;
+    use tungstenite::protocol::frame::coding::Data;
+
+    let _ = env_logger::try_init();
+
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    task::spawn(async move {
+        let (connection, _) = listener.accept().await.expect("No connections to accept");
+        let mut stream = accept_async(connection)
+            .await
+            .expect("Failed to handshake with connection");
+        while let Some(Ok(_)) = stream.next().await {}
+    });
+
+    let tcp = TcpStream::connect(addr).await.expect("Failed to connect");
+    let url = format!("ws://{}/", addr);
+    let (mut stream, _) = client_async(url, tcp)
+        .await
+        .expect("Client failed to connect");
+
+    // The message is abandoned after its first chunk.
+    let chunks = stream::iter(vec![b"ab".to_vec()]).chain(stream::pending());
+    {
+        let mut send = Box::pin(stream.send_chunks(FrameKind::Binary, chunks));
+        for _ in 0..3 {
+            assert!(futures::poll!(&mut send).is_pending());
+        }
+    }
+
+    match stream.send(Message::text("next")).await {
+        Err(tungstenite::Error::Protocol(ProtocolError::ExpectedFragment(Data::Text))) => {}
+        other => panic!("Unexpected result {:?}", other),
+    }
+    let chunks = stream::iter(vec![b"cd".to_vec()]);
+    match stream.send_chunks(FrameKind::Binary, chunks).await {
+        Err(tungstenite::Error::Protocol(ProtocolError::ExpectedFragment(Data::Binary))) => {}
+        other => panic!("Unexpected result {:?}", other),
+    }
+}
+
+#[async_std::test]
+async fn ready_or_closed() {
+    use async_tungstenite::Readiness;
+
+    let _ = env_logger::try_init();
+
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let (close_tx, close_rx) = futures::channel::oneshot::channel::<()>();
+
+    task::spawn(async move {
+        let (connection, _) = listener.accept().await.expect("No connections to accept");
+        let mut stream = accept_async(connection)
+            .await
+            .expect("Failed to handshake with connection");
+        close_rx.await.unwrap();
+        stream.close(None).await.unwrap();
+        while let Some(Ok(_)) = stream.next().await {}
+    });
+
+    let tcp = TcpStream::connect(addr).await.expect("Failed to connect");
+    let url = format!("ws://{}/", addr);
+    let (stream, _) = client_async(url, tcp)
+        .await
+        .expect("Client failed to connect");
+    let (sender, mut receiver) = stream.split();
+
+    assert_eq!(sender.ready_or_closed().await.unwrap(), Readiness::Writable);
+
+    close_tx.send(()).unwrap();
+    assert!(matches!(receiver.next().await, Some(Ok(Message::Close(_)))));
+    assert_eq!(sender.ready_or_closed().await.unwrap(), Readiness::Closed);
+}
+
+#[async_std::test]
+async fn connection_state() {
+    use async_tungstenite::ConnectionState;
+
+    let _ = env_logger::try_init();
+
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+
+    task::spawn(async move {
+        let (connection, _) = listener.accept().await.expect("No connections to accept");
+        let mut stream = accept_async(connection)
+            .await
+            .expect("Failed to handshake with connection");
+        while let Some(Ok(_)) = stream.next().await {}
+    });
+
+    let tcp = TcpStream::connect(addr).await.expect("Failed to connect");
+    let url = format!("ws://{}/", addr);
+    let (stream, _) = client_async(url, tcp)
+        .await
+        .expect("Client failed to connect");
+    assert_eq!(stream.state(), ConnectionState::Open);
+    let (sender, mut receiver) = stream.split();
+    assert_eq!(sender.state(), ConnectionState::Open);
+
+    // Both halves see the same state.
+    sender.close(None).await.unwrap();
+    assert_eq!(sender.state(), ConnectionState::Closing);
+    assert_eq!(receiver.state(), ConnectionState::Closing);
+
+    assert!(matches!(receiver.next().await, Some(Ok(Message::Close(_)))));
+    assert!(receiver.next().await.is_none());
+    assert_eq!(sender.state(), ConnectionState::Closed);
+    assert_eq!(receiver.state(), ConnectionState::Closed);
+
+    let stream = WebSocketStream::reunite(sender, receiver).unwrap();
+    assert_eq!(stream.state(), ConnectionState::Closed);
+}
+
+#[async_std::test]
+async fn connection_metrics() {
+    let _ = env_logger::try_init();
+
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let (tx, rx) = futures::channel::oneshot::channel();
+
+    task::spawn(async move {
+        let (connection, _) = listener.accept().await.expect("No connections to accept");
+        let mut stream = accept_async(connection)
+            .await
+            .expect("Failed to handshake with connection");
+        let mut received = 0;
+        while let Some(Ok(msg)) = stream.next().await {
+            received += 1;
+            if msg.is_text() {
+                stream.send(msg).await.unwrap();
+            }
+            if received == 3 {
+                break;
+            }
+        }
+        tx.send(stream.metrics().snapshot()).unwrap();
+        while let Some(Ok(_)) = stream.next().await {}
+    });
+
+    let tcp = TcpStream::connect(addr).await.expect("Failed to connect");
+    let url = format!("ws://{}/", addr);
+    let (stream, _) = client_async(url, tcp)
+        .await
+        .expect("Client failed to connect");
+    // The handshake is counted as well.
+    let handshake = stream.metrics().snapshot();
+    assert!(handshake.bytes_sent > 0 && handshake.bytes_received > 0);
+    assert_eq!(handshake.messages_sent, 0);
+
+    let (sender, mut receiver) = stream.split();
+    sender.send(Message::text("hello")).await.unwrap();
+    sender.send(Message::text("world")).await.unwrap();
+    sender.send(Message::Ping("ping".into())).await.unwrap();
+    for _ in 0..3 {
+        receiver.next().await.unwrap().unwrap();
+    }
+
+    let client = sender.metrics().snapshot();
+    assert_eq!(client, receiver.metrics().snapshot());
+    assert_eq!(client.messages_sent, 2);
+    assert_eq!(client.messages_received, 2);
+    assert_eq!(client.pings_sent, 1);
+    assert_eq!(client.pongs_received, 1);
+    // Two masked text frames and a masked ping, each with 4 bytes of payload
+    // or more, and the unmasked replies.
+    assert_eq!(client.bytes_sent - handshake.bytes_sent, 11 + 11 + 10);
+    assert_eq!(client.bytes_received - handshake.bytes_received, 7 + 7 + 6);
+
+    let server = rx.await.unwrap();
+    assert_eq!(server.messages_received, 2);
+    assert_eq!(server.messages_sent, 2);
+    assert_eq!(server.pings_received, 1);
+    // The automatic reply isn't counted.
+    assert_eq!(server.pongs_sent, 0);
+    assert_eq!(server.bytes_received, client.bytes_sent);
+}
+
+#[async_std::test]
+async fn poll_close() {
+    use async_tungstenite::ConnectionState;
+
+    let _ = env_logger::try_init();
+
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+
+    let server = task::spawn(async move {
+        let (connection, _) = listener.accept().await.expect("No connections to accept");
+        let mut stream = accept_async(connection)
+            .await
+            .expect("Failed to handshake with connection");
+        let mut messages = vec![];
+        while let Some(Ok(msg)) = stream.next().await {
+            messages.push(msg);
+        }
+        messages
+    });
+
+    let tcp = TcpStream::connect(addr).await.expect("Failed to connect");
+    let url = format!("ws://{}/", addr);
+    let (mut stream, _) = client_async(url, tcp)
+        .await
+        .expect("Client failed to connect");
+
+    std::future::poll_fn(|cx| stream.poll_close(cx))
+        .await
+        .unwrap();
+    assert_eq!(stream.state(), ConnectionState::Closing);
+
+    // Closing again succeeds once the handshake completed.
+    assert!(matches!(
+        stream.next().await,
+        Some(Ok(Message::Close(None)))
+    ));
+    assert!(stream.next().await.is_none());
+    std::future::poll_fn(|cx| stream.poll_close(cx))
+        .await
+        .unwrap();
+
+    assert_eq!(server.await, vec![Message::Close(None)]);
+}
+
+#[async_std::test]
+async fn message_admission() {
+    use std::sync::{Arc, Mutex};
+    use tungstenite::protocol::frame::coding::CloseCode;
+
+    let _ = env_logger::try_init();
+
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let (tx, rx) = futures::channel::oneshot::channel();
+    let seen = Arc::new(Mutex::new(vec![]));
+
+    let seen_server = seen.clone();
+    task::spawn(async move {
+        let (connection, _) = listener.accept().await.expect("No connections to accept");
+        let mut stream = accept_async(connection)
+            .await
+            .expect("Failed to handshake with connection");
+        stream.set_message_admission(move |start| {
+            seen_server.lock().unwrap().push((
+                start.is_text(),
+                start.is_fragmented(),
+                start.frame_len(),
+            ));
+            start.frame_len() <= 100
+        });
+        let first = stream.next().await.expect("Stream ended early");
+        let second = stream.next().await.expect("Stream ended early");
+        tx.send((first, second)).unwrap();
+    });
+
+    let tcp = TcpStream::connect(addr).await.expect("Failed to connect");
+    let url = format!("ws://{}/", addr);
+    let (mut stream, _) = client_async(url, tcp)
+        .await
+        .expect("Client failed to connect");
+
+    stream.send(Message::text("small")).await.unwrap();
+    stream.send(Message::binary(vec![0; 1000])).await.unwrap();
+
+    let (first, second) = rx.await.expect("Server task failed");
+    assert_eq!(first.unwrap(), Message::text("small"));
+    match second {
+        Err(tungstenite::Error::Io(err)) => assert_eq!(err.kind(), io::ErrorKind::InvalidData),
+        other => panic!("Unexpected result {:?}", other),
+    }
+    match stream.next().await {
+        Some(Ok(Message::Close(Some(frame)))) => assert_eq!(frame.code, CloseCode::Policy),
+        other => panic!("Unexpected message {:?}", other),
+    }
+    assert_eq!(
+        *seen.lock().unwrap(),
+        vec![(true, false, 5), (false, false, 1000)]
+    );
+}
+
+/// Ends the server side of a connection either with a close handshake or a
+/// TCP reset and returns the client's last item.
+#[cfg(all(feature = "tokio-runtime", feature = "socket2"))]
+async fn disconnect_test(reset: bool) -> Option<Result<Message, tungstenite::Error>> {
+    use async_tungstenite::tokio::{accept_async, client_async};
+
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+
+    tokio::spawn(async move {
+        let (connection, _) = listener.accept().await.expect("No connections to accept");
+        let mut stream = accept_async(connection)
+            .await
+            .expect("Failed to handshake with connection");
+        stream.set_abortive_close().unwrap();
+        if !reset {
+            stream.close(None).await.unwrap();
+            while let Some(Ok(_)) = stream.next().await {}
+        }
+    });
+
+    let tcp = tokio::net::TcpStream::connect(addr)
+        .await
+        .expect("Failed to connect");
+    let url = format!("ws://{}/", addr);
+    let (mut stream, _) = client_async(url, tcp)
+        .await
+        .expect("Client failed to connect");
+
+    let mut last = None;
+    while let Some(item) = stream.next().await {
+        last = Some(item);
+    }
+    last
+}
+
+#[cfg(all(feature = "tokio-runtime", feature = "socket2"))]
+#[tokio::test]
+async fn abrupt_disconnect() {
+    use async_tungstenite::is_abrupt_disconnect;
+
+    let _ = env_logger::try_init();
+
+    match disconnect_test(false).await {
+        Some(Ok(Message::Close(_))) => {}
+        other => panic!("Unexpected result {:?}", other),
+    }
+    match disconnect_test(true).await {
+        Some(Err(err)) => assert!(is_abrupt_disconnect(&err), "{:?}", err),
+        other => panic!("Unexpected result {:?}", other),
+    }
+}
+
+#[cfg(all(feature = "tokio-runtime", feature = "socket2"))]
+#[tokio::test]
+async fn tcp_keepalive() {
+    use async_tungstenite::socket::{is_dead_peer, TcpKeepalive};
+    use async_tungstenite::tokio::{accept_async, client_async};
+    use std::time::Duration;
+
+    let _ = env_logger::try_init();
+
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move {
+        let (connection, _) = listener.accept().await.unwrap();
+        let _stream = accept_async(connection).await.unwrap();
+    });
+
+    let tcp = tokio::net::TcpStream::connect(addr).await.unwrap();
+    let (stream, _) = client_async(format!("ws://{}/", addr), tcp)
+        .await
+        .expect("Client failed to connect");
+
+    let keepalive = TcpKeepalive::new().with_time(Duration::from_secs(42));
+    stream.set_tcp_keepalive(Some(&keepalive)).unwrap();
+    let socket = socket2::SockRef::from(stream.get_ref());
+    assert!(socket.keepalive().unwrap());
+    assert_eq!(
+        socket.tcp_keepalive_time().unwrap(),
+        Duration::from_secs(42)
+    );
+
+    stream.set_tcp_keepalive(None).unwrap();
+    assert!(!socket.keepalive().unwrap());
+
+    let deadline = std::io::Error::new(std::io::ErrorKind::TimedOut, "deadline");
+    assert!(!is_dead_peer(&tungstenite::Error::Io(deadline)));
+
+    // What reading from the socket fails with once the keepalive probes went
+    // unanswered.
+    #[cfg(unix)]
+    {
+        let probes_failed = std::io::Error::from_raw_os_error(libc::ETIMEDOUT);
+        assert!(is_dead_peer(&tungstenite::Error::Io(probes_failed)));
+    }
+}
+
+/// Counts the writes to the socket and exposes it for socket options.
+#[cfg(all(unix, feature = "tokio-runtime", feature = "socket2"))]
+struct CountWrites(
+    tokio::net::TcpStream,
+    std::sync::Arc<std::sync::atomic::AtomicUsize>,
+);
+
+#[cfg(all(unix, feature = "tokio-runtime", feature = "socket2"))]
+impl std::os::fd::AsFd for CountWrites {
+    fn as_fd(&self) -> std::os::fd::BorrowedFd<'_> {
+        self.0.as_fd()
+    }
+}
+
+#[cfg(all(unix, feature = "tokio-runtime", feature = "socket2"))]
+impl tokio::io::AsyncRead for CountWrites {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut tokio::io::ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.0).poll_read(cx, buf)
+    }
+}
+
+#[cfg(all(unix, feature = "tokio-runtime", feature = "socket2"))]
+impl tokio::io::AsyncWrite for CountWrites {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        self.1.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+        Pin::new(&mut self.0).poll_write(cx, buf)
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.0).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.0).poll_shutdown(cx)
+    }
+}
+
+#[cfg(all(unix, feature = "tokio-runtime", feature = "socket2"))]
+#[tokio::test]
+async fn send_corked() {
+    use async_tungstenite::tokio::{accept_async, client_async};
+    use std::sync::atomic::Ordering;
+
+    const MESSAGES: usize = 10;
+
+    let _ = env_logger::try_init();
+
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let server = tokio::spawn(async move {
+        let (connection, _) = listener.accept().await.unwrap();
+        let mut stream = accept_async(connection).await.unwrap();
+        let mut messages = vec![];
+        while let Some(Ok(msg)) = stream.next().await {
+            if msg.is_text() {
+                messages.push(msg);
+            }
+        }
+        messages
+    });
+
+    let writes = std::sync::Arc::default();
+    let tcp = CountWrites(tokio::net::TcpStream::connect(addr).await.unwrap(), writes);
+    let writes = tcp.1.clone();
+    let (mut stream, _) = client_async(format!("ws://{}/", addr), tcp).await.unwrap();
+    let before = writes.load(Ordering::Relaxed);
+
+    // All messages go out with a single write.
+    let msgs: Vec<_> = (0..MESSAGES)
+        .map(|i| Message::text(i.to_string()))
+        .collect();
+    stream.send_corked(msgs.clone()).await.unwrap();
+    assert_eq!(writes.load(Ordering::Relaxed) - before, 1);
+    #[cfg(target_os = "linux")]
+    assert!(!socket2::SockRef::from(stream.get_ref()).tcp_cork().unwrap());
+
+    stream.close(None).await.unwrap();
+    while stream.next().await.is_some() {}
+    assert_eq!(server.await.unwrap(), msgs);
+}
+
+#[cfg(all(feature = "tokio-runtime", feature = "socket2"))]
+#[tokio::test]
+async fn connect_with_socket_config() {
+    use async_tungstenite::tokio::{accept_async, connect_async_with_socket_config};
+    use async_tungstenite::ConnectError;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    let _ = env_logger::try_init();
+
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move {
+        let (connection, _) = listener.accept().await.unwrap();
+        let mut stream = accept_async(connection).await.unwrap();
+        while let Some(Ok(_)) = stream.next().await {}
+    });
+
+    let configured = AtomicUsize::new(0);
+    let (mut stream, _) =
+        connect_async_with_socket_config(format!("ws://{}/", addr), None, |socket| {
+            configured.fetch_add(1, Ordering::SeqCst);
+            socket.set_tcp_nodelay(true)
+        })
+        .await
+        .expect("Client failed to connect");
+    assert_eq!(configured.load(Ordering::SeqCst), 1);
+    stream.send(Message::text("hello")).await.unwrap();
+
+    // Errors of the callback fail the connection attempt.
+    let err = connect_async_with_socket_config(format!("ws://{}/", addr), None, |_| {
+        Err(std::io::ErrorKind::PermissionDenied.into())
+    })
+    .await
+    .unwrap_err();
+    match ConnectError::from(err) {
+        ConnectError::Connect { source, .. } => {
+            assert_eq!(source.kind(), std::io::ErrorKind::PermissionDenied)
+        }
+        other => panic!("Unexpected error {:?}", other),
+    }
+}
+
+#[cfg(all(feature = "async-std-runtime", feature = "socket2"))]
+#[async_std::test]
+async fn connect_with_socket_config_async_std() {
+    use async_tungstenite::async_std::connect_async_with_socket_config;
+    use async_tungstenite::ConnectError;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    let _ = env_logger::try_init();
+
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    task::spawn(async move {
+        let (connection, _) = listener.accept().await.unwrap();
+        let mut stream = accept_async(connection).await.unwrap();
+        while let Some(Ok(_)) = stream.next().await {}
+    });
+
+    let configured = AtomicUsize::new(0);
+    let (mut stream, _) =
+        connect_async_with_socket_config(format!("ws://{}/", addr), None, |socket| {
+            configured.fetch_add(1, Ordering::SeqCst);
+            socket.set_tcp_nodelay(true)
+        })
+        .await
+        .expect("Client failed to connect");
+    assert_eq!(configured.load(Ordering::SeqCst), 1);
+    stream.send(Message::text("hello")).await.unwrap();
+
+    // A refused connection is reported once the connect completes.
+    let addr = TcpListener::bind("127.0.0.1:0")
+        .await
+        .unwrap()
+        .local_addr()
+        .unwrap();
+    let err = connect_async_with_socket_config(format!("ws://{}/", addr), None, |_| Ok(()))
+        .await
+        .unwrap_err();
+    match ConnectError::from(err) {
+        ConnectError::Connect { source, .. } => {
+            assert_eq!(source.kind(), std::io::ErrorKind::ConnectionRefused)
+        }
+        other => panic!("Unexpected error {:?}", other),
+    }
+}
+
+#[async_std::test]
+async fn try_recv() {
+    use futures::stream::FusedStream;
+    use std::time::Duration;
+
+    let _ = env_logger::try_init();
+
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let (done_tx, done_rx) = futures::channel::oneshot::channel::<()>();
+    task::spawn(async move {
+        let (connection, _) = listener.accept().await.unwrap();
+        let mut stream = accept_async(connection).await.unwrap();
+        stream.send(Message::text("one")).await.unwrap();
+        stream.send(Message::text("two")).await.unwrap();
+        let _ = done_rx.await;
+        stream.close(None).await.unwrap();
+    });
+
+    let tcp = TcpStream::connect(addr).await.unwrap();
+    let (stream, _) = client_async(format!("ws://{}/", addr), tcp).await.unwrap();
+    let (_tx, rx) = stream.split();
+
+    task::sleep(Duration::from_millis(50)).await;
+    assert_eq!(rx.try_recv().unwrap(), Some(Message::text("one")));
+    assert_eq!(rx.try_recv().unwrap(), Some(Message::text("two")));
+    assert_eq!(rx.try_recv().unwrap(), None);
+    assert!(!rx.is_terminated());
+
+    done_tx.send(()).unwrap();
+    task::sleep(Duration::from_millis(50)).await;
+    assert!(matches!(rx.try_recv(), Ok(Some(Message::Close(_)))));
+    assert!(matches!(
+        rx.try_recv(),
+        Err(tungstenite::Error::ConnectionClosed)
+    ));
+    assert!(rx.is_terminated());
+}
+
+#[async_std::test]
+async fn recv() {
+    let _ = env_logger::try_init();
+
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let server = task::spawn(async move {
+        let (connection, _) = listener.accept().await.unwrap();
+        let mut stream = accept_async(connection).await.unwrap();
+        let mut received = Vec::new();
+        while let Some(msg) = stream.recv().await.unwrap() {
+            received.push(msg);
+        }
+        received
+    });
+
+    let tcp = TcpStream::connect(addr).await.unwrap();
+    let (mut stream, _) = client_async(format!("ws://{}/", addr), tcp).await.unwrap();
+    stream.send(Message::Ping(vec![1].into())).await.unwrap();
+    stream.send(Message::text("hello")).await.unwrap();
+    stream.send(Message::Pong(vec![2].into())).await.unwrap();
+    assert_eq!(
+        stream.next().await.unwrap().unwrap(),
+        Message::Pong(vec![1].into())
+    );
+    stream.close(None).await.unwrap();
+    assert!(stream.next().await.unwrap().unwrap().is_close());
+
+    assert_eq!(server.await, vec![Message::text("hello")]);
+}
+
+#[async_std::test]
+async fn auto_pong() {
+    let _ = env_logger::try_init();
+
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let (msg_tx, msg_rx) = futures::channel::oneshot::channel();
+    let (done_tx, done_rx) = futures::channel::oneshot::channel::<()>();
+
+    // Waits for a single message and never reads again.
+    task::spawn(async move {
+        let (connection, _) = listener.accept().await.unwrap();
+        let mut stream = accept_async(connection).await.unwrap();
+        stream.set_auto_pong(true);
+        msg_tx.send(stream.next().await).unwrap();
+        let _ = done_rx.await;
+    });
+
+    let tcp = TcpStream::connect(addr).await.unwrap();
+    let (mut stream, _) = client_async(format!("ws://{}/", addr), tcp).await.unwrap();
+    stream.send(Message::Ping(vec![1].into())).await.unwrap();
+    assert_eq!(
+        stream.next().await.unwrap().unwrap(),
+        Message::Pong(vec![1].into())
+    );
+
+    // The Ping was not yielded to the application.
+    stream.send(Message::text("hello")).await.unwrap();
+    assert_eq!(
+        msg_rx.await.unwrap().unwrap().unwrap(),
+        Message::text("hello")
+    );
+    done_tx.send(()).unwrap();
+}
+
+#[async_std::test]
+async fn auto_pong_yield_pings() {
+    let _ = env_logger::try_init();
+
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let server = task::spawn(async move {
+        let (connection, _) = listener.accept().await.unwrap();
+        let mut stream = accept_async(connection).await.unwrap();
+        // Independent of the order of the setters.
+        stream.set_yield_pings(true);
+        stream.set_auto_pong(true);
+        stream.next().await.unwrap().unwrap()
+    });
+
+    let tcp = TcpStream::connect(addr).await.unwrap();
+    let (mut stream, _) = client_async(format!("ws://{}/", addr), tcp).await.unwrap();
+    stream.send(Message::Ping(vec![1].into())).await.unwrap();
+    assert_eq!(server.await, Message::Ping(vec![1].into()));
+}
+
+#[async_std::test]
+async fn send_races_peer_close() {
+    let _ = env_logger::try_init();
+
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    // Closes right away and collects the messages received until the close
+    // handshake completes.
+    let server = task::spawn(async move {
+        let (connection, _) = listener.accept().await.unwrap();
+        let mut stream = accept_async(connection).await.unwrap();
+        stream.close(None).await.unwrap();
+        let mut messages = vec![];
+        while let Some(Ok(msg)) = stream.next().await {
+            if msg.is_text() {
+                messages.push(msg);
+            }
+        }
+        messages
+    });
+
+    let tcp = TcpStream::connect(addr).await.unwrap();
+    let (mut stream, _) = client_async(format!("ws://{}/", addr), tcp).await.unwrap();
+    task::sleep(std::time::Duration::from_millis(10)).await;
+
+    // The Close frame arrived but was not processed yet, so the message is
+    // sent ahead of our reply.
+    stream.send(Message::text("before")).await.unwrap();
+    assert!(stream.next().await.unwrap().unwrap().is_close());
+    for _ in 0..2 {
+        match stream.send(Message::text("after")).await {
+            Err(tungstenite::Error::ConnectionClosed) => {}
+            other => panic!("Unexpected result {:?}", other),
+        }
+        // Also once the stream ended.
+        assert!(stream.next().await.is_none());
+    }
+    assert_eq!(server.await, vec![Message::text("before")]);
+}
+
+#[async_std::test]
+async fn split_send_races_peer_close() {
+    let _ = env_logger::try_init();
+
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    // Closes after the first message and counts all messages received.
+    let server = task::spawn(async move {
+        let (connection, _) = listener.accept().await.unwrap();
+        let mut stream = accept_async(connection).await.unwrap();
+        let mut received = 0;
+        while let Some(Ok(msg)) = stream.next().await {
+            if msg.is_text() {
+                received += 1;
+                if received == 1 {
+                    stream.close(None).await.unwrap();
+                }
+            }
+        }
+        received
+    });
+
+    let tcp = TcpStream::connect(addr).await.unwrap();
+    let (stream, _) = client_async(format!("ws://{}/", addr), tcp).await.unwrap();
+    let (tx, mut rx) = stream.split();
+    let reader = task::spawn(async move { while rx.next().await.is_some() {} });
+
+    let mut sent = 0;
+    loop {
+        match tx.send(Message::text("hello")).await {
+            Ok(()) => sent += 1,
+            Err(tungstenite::Error::ConnectionClosed) => break,
+            Err(err) => panic!("Unexpected error {:?}", err),
+        }
+        task::yield_now().await;
+    }
+    reader.await;
+
+    // Every message that was sent successfully arrived.
+    assert_eq!(server.await, sent);
+}
+
+/// Counts the flushes of a stream.
+struct CountFlushes(TcpStream, std::sync::Arc<std::sync::atomic::AtomicUsize>);
+
+impl AsyncRead for CountFlushes {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.0).poll_read(cx, buf)
+    }
+}
+
+impl AsyncWrite for CountFlushes {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.0).poll_write(cx, buf)
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        self.1.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+        Pin::new(&mut self.0).poll_flush(cx)
+    }
+
+    fn poll_close(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.0).poll_close(cx)
+    }
+}
+
+#[async_std::test]
+async fn send_batch() {
+    let _ = env_logger::try_init();
+
+    const MESSAGES: usize = 1000;
+
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    task::spawn(async move {
+        let (connection, _) = listener.accept().await.unwrap();
+        let mut stream = accept_async(connection).await.unwrap();
+        while let Some(Ok(msg)) = stream.next().await {
+            if msg.is_text() {
+                stream.send(msg).await.unwrap();
+            }
+        }
+    });
+
+    let flushes = std::sync::Arc::default();
+    let tcp = CountFlushes(TcpStream::connect(addr).await.unwrap(), flushes);
+    let flushes = tcp.1.clone();
+    let (mut stream, _) = client_async(format!("ws://{}/", addr), tcp).await.unwrap();
+    let before = flushes.load(std::sync::atomic::Ordering::Relaxed);
+
+    let msgs = (0..MESSAGES).map(|i| Message::text(i.to_string()));
+    stream.send_batch(msgs).await.unwrap();
+    let sent_flushes = flushes.load(std::sync::atomic::Ordering::Relaxed) - before;
+    assert!(sent_flushes < MESSAGES, "{} flushes", sent_flushes);
+
+    for i in 0..MESSAGES {
+        assert_eq!(
+            stream.next().await.unwrap().unwrap(),
+            Message::text(i.to_string())
+        );
+    }
+}
+
+#[async_std::test]
+async fn close_gracefully() {
+    use std::time::Duration;
+
+    let _ = env_logger::try_init();
+
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    // Sends data until it sees our Close frame.
+    let server = task::spawn(async move {
+        let (connection, _) = listener.accept().await.unwrap();
+        let mut stream = accept_async(connection).await.unwrap();
+        for i in 0..100 {
+            stream.send(Message::text(i.to_string())).await.unwrap();
+        }
+        let mut last = None;
+        while let Some(res) = stream.next().await {
+            last = Some(res);
+        }
+        last
+    });
+
+    let tcp = TcpStream::connect(addr).await.unwrap();
+    let (mut stream, _) = client_async(format!("ws://{}/", addr), tcp).await.unwrap();
+    assert!(stream.next().await.unwrap().unwrap().is_text());
+    stream
+        .close_gracefully(None, task::sleep(Duration::from_secs(10)))
+        .await
+        .expect("Failed to close");
+    assert!(stream.next().await.is_none());
+    match server.await {
+        Some(Ok(Message::Close(_))) => {}
+        other => panic!("Unexpected result {:?}", other),
+    }
+
+    // A peer that never answers.
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let (done_tx, done_rx) = futures::channel::oneshot::channel::<()>();
+    task::spawn(async move {
+        let (connection, _) = listener.accept().await.unwrap();
+        let _stream = accept_async(connection).await.unwrap();
+        let _ = done_rx.await;
+    });
+    let tcp = TcpStream::connect(addr).await.unwrap();
+    let (mut stream, _) = client_async(format!("ws://{}/", addr), tcp).await.unwrap();
+    match stream
+        .close_gracefully(None, task::sleep(Duration::from_millis(10)))
+        .await
+    {
+        Err(tungstenite::Error::Io(err)) => assert_eq!(err.kind(), io::ErrorKind::TimedOut),
+        other => panic!("Unexpected result {:?}", other),
+    }
+    done_tx.send(()).unwrap();
+}
+
+#[async_std::test]
+async fn peek() {
+    use async_tungstenite::client_async_with_config;
+    use futures::stream::FusedStream;
+    use tungstenite::protocol::WebSocketConfig;
+
+    let _ = env_logger::try_init();
+
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let (done_tx, done_rx) = futures::channel::oneshot::channel::<()>();
+    task::spawn(async move {
+        let (connection, _) = listener.accept().await.unwrap();
+        let mut stream = accept_async(connection).await.unwrap();
+        stream.send(Message::text("hello")).await.unwrap();
+        stream.send(Message::binary(vec![0; 100])).await.unwrap();
+        let _ = done_rx.await;
+    });
+
+    let tcp = TcpStream::connect(addr).await.unwrap();
+    let config = WebSocketConfig::default()
+        .max_message_size(Some(50))
+        .max_frame_size(Some(50));
+    let (mut stream, _) = client_async_with_config(format!("ws://{}/", addr), tcp, Some(config))
+        .await
+        .unwrap();
+
+    for _ in 0..2 {
+        assert_eq!(
+            stream.peek().await.unwrap().unwrap(),
+            &Message::text("hello")
+        );
+    }
+    assert_eq!(
+        stream.next().await.unwrap().unwrap(),
+        Message::text("hello")
+    );
+
+    // The binary message is too large, the error is only returned once.
+    match stream.peek().await {
+        Some(Err(tungstenite::Error::Capacity(_))) => {}
+        other => panic!("Unexpected result {:?}", other),
+    }
+    assert!(stream.is_terminated());
+    assert!(stream.peek().await.is_none());
+    assert!(stream.next().await.is_none());
+    done_tx.send(()).unwrap();
+}
+
+#[async_std::test]
+async fn pong_deadline() {
+    let _ = env_logger::try_init();
+
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let (pong_tx, pong_rx) = futures::channel::oneshot::channel::<()>();
+    let (done_tx, done_rx) = futures::channel::oneshot::channel::<()>();
+
+    // Never reads, so no ping is ever answered.
+    task::spawn(async move {
+        let (connection, _) = listener.accept().await.unwrap();
+        let mut stream = accept_async(connection).await.unwrap();
+        pong_rx.await.unwrap();
+        stream.send(Message::Pong(vec![].into())).await.unwrap();
+        let _ = done_rx.await;
+    });
+
+    let tcp = TcpStream::connect(addr).await.unwrap();
+    let (mut stream, _) = client_async(format!("ws://{}/", addr), tcp).await.unwrap();
+    stream.set_pong_deadline(2);
+    for _ in 0..2 {
+        stream.send(Message::Ping(vec![].into())).await.unwrap();
+    }
+
+    // An unsolicited Pong resets the count.
+    pong_tx.send(()).unwrap();
+    assert!(stream.next().await.unwrap().unwrap().is_pong());
+
+    let (tx, mut rx) = stream.split();
+    let reader = task::spawn(async move { rx.next().await });
+    for _ in 0..3 {
+        tx.send(Message::Ping(vec![].into())).await.unwrap();
+    }
+    match reader.await {
+        Some(Err(tungstenite::Error::Io(err))) => {
+            assert_eq!(err.kind(), io::ErrorKind::TimedOut)
+        }
+        other => panic!("Unexpected result {:?}", other),
+    }
+    done_tx.send(()).unwrap();
+}
+
+#[async_std::test]
+async fn ping_flood() {
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
+    use tungstenite::protocol::frame::coding::CloseCode;
+
+    let _ = env_logger::try_init();
+
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let (err_tx, err_rx) = futures::channel::oneshot::channel();
+    let written = Arc::new(Mutex::new(Vec::new()));
+    let tap = written.clone();
+
+    task::spawn(async move {
+        let (connection, _) = listener.accept().await.expect("No connections to accept");
+        let mut stream = accept_async(connection)
+            .await
+            .expect("Failed to handshake with connection");
+        stream.set_ping_limit(Some((5, Duration::from_secs(60))));
+        stream.set_wire_tap(move |direction, bytes| {
+            if direction == async_tungstenite::TapDirection::Write {
+                tap.lock().unwrap().extend_from_slice(bytes);
+            }
+        });
+
+        for _ in 0..5 {
+            let message = stream.next().await.expect("Stream ended early");
+            assert!(message.expect("Failed to get message").is_ping());
+        }
+        let err = stream
+            .next()
+            .await
+            .expect("Stream ended early")
+            .unwrap_err();
+        err_tx.send(err).unwrap();
+    });
+
+    let tcp = TcpStream::connect(addr).await.expect("Failed to connect");
+    let url = format!("ws://{}/", addr);
+    let (mut stream, _) = client_async(url, tcp)
+        .await
+        .expect("Client failed to connect");
+
+    stream
+        .send_batch((0..20).map(|_| Message::Ping(Default::default())))
+        .await
+        .unwrap();
+
+    match err_rx.await.expect("Server task failed") {
+        tungstenite::Error::Io(err) => assert_eq!(err.kind(), io::ErrorKind::InvalidData),
+        other => panic!("Unexpected error {:?}", other),
+    }
+    for _ in 0..5 {
+        match stream.next().await {
+            Some(Ok(Message::Pong(_))) => {}
+            other => panic!("Unexpected message {:?}", other),
+        }
+    }
+    match stream.next().await {
+        Some(Ok(Message::Close(Some(frame)))) => assert_eq!(frame.code, CloseCode::Policy),
+        other => panic!("Unexpected message {:?}", other),
+    }
+
+    // Exactly five Pongs and the Close frame went out, in that order.
+    let mut expected = b"\x8a\x00".repeat(5);
+    expected.extend_from_slice(b"\x88\x10\x03\xf0Too many pings");
+    assert_eq!(*written.lock().unwrap(), expected);
+}
+
+#[async_std::test]
+async fn request_headers() {
+    use tungstenite::http::HeaderMap;
+
+    let _ = env_logger::try_init();
+
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+
+    let server = task::spawn(async move {
+        let (connection, _) = listener.accept().await.expect("No connections to accept");
+        let stream = accept_async(connection)
+            .await
+            .expect("Failed to handshake with connection");
+        stream.request_headers().cloned()
+    });
+
+    let tcp = TcpStream::connect(addr).await.expect("Failed to connect");
+    let url = format!("ws://{}/", addr);
+    let (stream, _) = client_async(url, tcp)
+        .await
+        .expect("Client failed to connect");
+    assert!(stream.request_headers().is_none());
+
+    let headers = server.await.expect("No request headers");
+    assert_eq!(headers["Host"], addr.to_string().as_str());
+
+    let mut headers = HeaderMap::new();
+    headers.insert("Authorization", "Bearer secret".parse().unwrap());
+    let stream = WebSocketStream::from_raw_socket_with_request_headers(
+        futures::io::Cursor::new(Vec::new()),
+        headers,
+        None,
+    )
+    .await;
+    assert_eq!(
+        stream.request_headers().unwrap()["Authorization"],
+        "Bearer secret"
+    );
+}
+
+#[async_std::test]
+async fn finalize() {
+    use std::time::Duration;
+
+    let _ = env_logger::try_init();
+
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+
+    let server = task::spawn(async move {
+        let (connection, _) = listener.accept().await.expect("No connections to accept");
+        let mut stream = accept_async(connection)
+            .await
+            .expect("Failed to handshake with connection");
+        let mut messages = vec![];
+        while let Some(message) = stream.next().await {
+            messages.push(message.expect("Failed to get message"));
+        }
+        messages
+    });
+
+    let tcp = TcpStream::connect(addr).await.expect("Failed to connect");
+    let url = format!("ws://{}/", addr);
+    let (mut stream, _) = client_async(url, tcp)
+        .await
+        .expect("Client failed to connect");
+    stream.send(Message::text("hello")).await.unwrap();
+    stream.finalize(task::sleep(Duration::from_secs(10))).await;
+
+    assert_eq!(
+        server.await,
+        vec![Message::text("hello"), Message::Close(None)]
+    );
+}
+
+#[async_std::test]
+async fn with_raw() {
+    let _ = env_logger::try_init();
+
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let server = task::spawn(async move {
+        let (connection, _) = listener.accept().await.unwrap();
+        let mut stream = accept_async(connection).await.unwrap();
+        stream.next().await.unwrap().unwrap()
+    });
+
+    let tcp = TcpStream::connect(addr).await.unwrap();
+    let (mut stream, _) = client_async(format!("ws://{}/", addr), tcp).await.unwrap();
+
+    // Queue a message with tungstenite's `write()`, which doesn't flush.
+    let queued = stream.with_raw(|ws| {
+        assert!(ws.can_read() && ws.can_write());
+        ws.write(Message::text("raw")).is_ok()
+    });
+    assert!(queued);
+    stream.flush_with_progress(|_| {}).await.unwrap();
+
+    assert_eq!(server.await, Message::text("raw"));
+}
+
+#[async_std::test]
+async fn set_config() {
+    let _ = env_logger::try_init();
+
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let server = task::spawn(async move {
+        let (connection, _) = listener.accept().await.unwrap();
+        let mut stream = accept_async(connection).await.unwrap();
+        let first = stream.next().await.unwrap();
+        stream.set_config(|config| config.max_message_size = Some(50));
+        let second = stream.next().await.unwrap();
+        (first, second)
+    });
+
+    let tcp = TcpStream::connect(addr).await.unwrap();
+    let (mut stream, _) = client_async(format!("ws://{}/", addr), tcp).await.unwrap();
+    stream.send(Message::binary(vec![0; 100])).await.unwrap();
+    stream.send(Message::binary(vec![0; 100])).await.unwrap();
+
+    let (first, second) = server.await;
+    assert_eq!(first.unwrap(), Message::binary(vec![0; 100]));
+    match second {
+        Err(tungstenite::Error::Capacity(_)) => {}
+        other => panic!("Unexpected result {:?}", other),
+    }
+}
+
+#[async_std::test]
+async fn close_frame() {
+    use tungstenite::protocol::frame::coding::CloseCode;
+    use tungstenite::protocol::CloseFrame;
+
+    let _ = env_logger::try_init();
+
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    task::spawn(async move {
+        // Closes the first connection with a Close frame, drops the second.
+        let (connection, _) = listener.accept().await.unwrap();
+        let mut stream = accept_async(connection).await.unwrap();
+        let frame = CloseFrame {
+            code: CloseCode::Error,
+            reason: "boom".into(),
+        };
+        stream.close(Some(frame)).await.unwrap();
+        while stream.next().await.is_some() {}
+        drop(stream);
+
+        let (connection, _) = listener.accept().await.unwrap();
+        drop(accept_async(connection).await.unwrap());
+    });
+
+    let tcp = TcpStream::connect(addr).await.unwrap();
+    let (mut stream, _) = client_async(format!("ws://{}/", addr), tcp).await.unwrap();
+    assert!(stream.close_frame().is_none());
+    while stream.next().await.is_some() {}
+    let frame = stream.close_frame().expect("No close frame");
+    assert_eq!(frame.code, CloseCode::Error);
+    assert_eq!(frame.reason, "boom");
+
+    let tcp = TcpStream::connect(addr).await.unwrap();
+    let (mut stream, _) = client_async(format!("ws://{}/", addr), tcp).await.unwrap();
+    while stream.next().await.is_some() {}
+    assert!(stream.close_frame().is_none());
+}
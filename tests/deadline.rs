@@ -0,0 +1,72 @@
+use std::io;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::time::Duration;
+
+use async_tungstenite::{DeadlineExceeded, WebSocketStream};
+use futures::prelude::*;
+use tungstenite::protocol::{Role, WebSocketConfig};
+use tungstenite::Message;
+
+/// A stream that never makes progress.
+struct Stalled;
+
+impl AsyncRead for Stalled {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        _cx: &mut Context<'_>,
+        _buf: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+        Poll::Pending
+    }
+}
+
+impl AsyncWrite for Stalled {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        _cx: &mut Context<'_>,
+        _buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        Poll::Pending
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Poll::Pending
+    }
+
+    fn poll_close(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Poll::Pending
+    }
+}
+
+#[async_std::test]
+async fn recv_deadline() {
+    let mut stream = WebSocketStream::from_raw_socket(Stalled, Role::Client, None).await;
+    stream.set_recv_deadline(async_std::task::sleep(Duration::from_millis(50)));
+
+    let err = stream.next().await.unwrap().unwrap_err();
+    assert_eq!(DeadlineExceeded::of(&err), Some(DeadlineExceeded::Recv));
+
+    // The deadline was cleared, so the stream waits again.
+    let next = async_std::future::timeout(Duration::from_millis(50), stream.next()).await;
+    assert!(next.is_err());
+}
+
+#[async_std::test]
+async fn send_deadline() {
+    let config = WebSocketConfig::default().write_buffer_size(0);
+    let mut stream = WebSocketStream::from_raw_socket(Stalled, Role::Client, Some(config)).await;
+    stream.set_send_deadline(async_std::task::sleep(Duration::from_millis(50)));
+
+    let err = stream.send(Message::text("hello")).await.unwrap_err();
+    assert_eq!(DeadlineExceeded::of(&err), Some(DeadlineExceeded::Send));
+    assert!(DeadlineExceeded::of(&tungstenite::Error::ConnectionClosed).is_none());
+}
+
+#[async_std::test]
+async fn finalize_timeout() {
+    let stream = WebSocketStream::from_raw_socket(Stalled, Role::Client, None).await;
+    stream
+        .finalize(async_std::task::sleep(Duration::from_millis(50)))
+        .await;
+}
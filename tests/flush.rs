@@ -0,0 +1,124 @@
+#![cfg(feature = "futures-03-sink")]
+
+use std::io;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+
+use async_tungstenite::WebSocketStream;
+use futures::prelude::*;
+use tungstenite::protocol::Role;
+use tungstenite::Message;
+
+/// A stream that accepts at most 100 bytes per write and only every other
+/// write.
+#[derive(Clone, Default)]
+struct Trickle {
+    written: Arc<Mutex<usize>>,
+    blocked: bool,
+}
+
+impl AsyncRead for Trickle {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        _cx: &mut Context<'_>,
+        _buf: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+        Poll::Pending
+    }
+}
+
+impl AsyncWrite for Trickle {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        self.blocked = !self.blocked;
+        if !self.blocked {
+            cx.waker().wake_by_ref();
+            return Poll::Pending;
+        }
+        let n = buf.len().min(100);
+        *self.written.lock().unwrap() += n;
+        Poll::Ready(Ok(n))
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_close(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+}
+
+#[async_std::test]
+async fn flush_with_progress() {
+    let _ = env_logger::try_init();
+
+    let trickle = Trickle::default();
+    let mut stream = WebSocketStream::from_raw_socket(trickle.clone(), Role::Server, None).await;
+
+    stream.feed(Message::binary(vec![0; 1000])).await.unwrap();
+    let mut reports = vec![];
+    stream
+        .flush_with_progress(|remaining| reports.push(remaining))
+        .await
+        .expect("Failed to flush");
+
+    // 1000 bytes of payload and a 4 byte header.
+    assert_eq!(*trickle.written.lock().unwrap(), 1004);
+    assert!(reports.len() > 2, "{:?}", reports);
+    assert!(reports.windows(2).all(|w| w[0] > w[1]), "{:?}", reports);
+    assert_eq!(reports.last(), Some(&0));
+}
+
+#[async_std::test]
+async fn write_buffer_occupancy() {
+    let _ = env_logger::try_init();
+
+    let config = async_tungstenite::ConfigBuilder::new()
+        .write_buffer_size(0)
+        .max_write_buffer_size(4520)
+        .build()
+        .unwrap();
+    let mut stream =
+        WebSocketStream::from_raw_socket(Trickle::default(), Role::Server, Some(config)).await;
+    assert_eq!(stream.write_buffer_occupancy(), 0.0);
+
+    // 100 of the 1004 bytes are written before the stream blocks.
+    stream.feed(Message::binary(vec![0; 1000])).await.unwrap();
+    assert_eq!(stream.write_buffer_occupancy(), 0.2);
+
+    stream.flush().await.unwrap();
+    assert_eq!(stream.write_buffer_occupancy(), 0.0);
+}
+
+#[async_std::test]
+async fn write_buffer_occupancy_counts_held_back_bytes() {
+    let _ = env_logger::try_init();
+
+    // The default `write_buffer_size` holds small messages back without
+    // writing them.
+    let config = async_tungstenite::ConfigBuilder::new()
+        .max_write_buffer_size(1 << 18)
+        .build()
+        .unwrap();
+    let trickle = Trickle::default();
+    let mut stream =
+        WebSocketStream::from_raw_socket(trickle.clone(), Role::Server, Some(config)).await;
+
+    for i in 1..=4 {
+        stream.feed(Message::binary(vec![0; 1000])).await.unwrap();
+        assert_eq!(*trickle.written.lock().unwrap(), 0);
+        assert_eq!(
+            stream.write_buffer_occupancy(),
+            (i * 1004) as f32 / 262_144.0
+        );
+    }
+
+    stream.flush().await.unwrap();
+    assert_eq!(*trickle.written.lock().unwrap(), 4016);
+    assert_eq!(stream.write_buffer_occupancy(), 0.0);
+}
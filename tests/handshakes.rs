@@ -28,3 +28,508 @@ async fn handshakes() {
         .await
         .expect("Client failed to connect");
 }
+
+#[cfg(feature = "async-std-runtime")]
+#[async_std::test]
+async fn auth_challenge() {
+    use async_tungstenite::accept_hdr_async;
+    use async_tungstenite::async_std::connect_async_with_auth;
+    use tungstenite::handshake::server::{ErrorResponse, Request, Response};
+    use tungstenite::http::StatusCode;
+
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+
+    task::spawn(async move {
+        while let Ok((connection, _)) = listener.accept().await {
+            #[allow(clippy::result_large_err)]
+            let callback = |request: &Request, response: Response| match request
+                .headers()
+                .get("Authorization")
+            {
+                Some(value) if value == "Bearer secret" => Ok(response),
+                _ => {
+                    let mut response = ErrorResponse::new(None);
+                    *response.status_mut() = StatusCode::UNAUTHORIZED;
+                    response
+                        .headers_mut()
+                        .insert("WWW-Authenticate", "Bearer".parse().unwrap());
+                    Err(response)
+                }
+            };
+            let _ = accept_hdr_async(connection, callback).await;
+        }
+    });
+
+    let url = format!("ws://{}/", addr);
+    let mut challenge = None;
+    let (_stream, response) = connect_async_with_auth(&url, None, |response| {
+        challenge = response.headers().get("WWW-Authenticate").cloned();
+        Some("Bearer secret".parse().unwrap())
+    })
+    .await
+    .expect("Client failed to connect");
+    assert_eq!(response.status(), StatusCode::SWITCHING_PROTOCOLS);
+    assert_eq!(challenge.unwrap(), "Bearer");
+
+    match connect_async_with_auth(&url, None, |_| None).await {
+        Err(tungstenite::Error::Http(response)) => {
+            assert_eq!(response.status(), StatusCode::UNAUTHORIZED)
+        }
+        other => panic!("Unexpected result {:?}", other.map(|(_, r)| r)),
+    }
+}
+
+#[cfg(feature = "async-std-runtime")]
+#[async_std::test]
+async fn connect_errors() {
+    use async_tungstenite::async_std::connect_async;
+    use async_tungstenite::ConnectError;
+    use tungstenite::http::StatusCode;
+
+    // Nothing listens on the port anymore.
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let port = listener.local_addr().unwrap().port();
+    drop(listener);
+    let err = connect_async(format!("ws://127.0.0.1:{}/", port))
+        .await
+        .unwrap_err();
+    match ConnectError::from(err) {
+        ConnectError::Connect { host, port: p, .. } => {
+            assert_eq!(host, "127.0.0.1");
+            assert_eq!(p, port);
+        }
+        other => panic!("Unexpected error {:?}", other),
+    }
+
+    // A plain HTTP server.
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    task::spawn(async move {
+        use async_std::io::prelude::*;
+
+        let (mut connection, _) = listener.accept().await.unwrap();
+        let mut buf = [0; 1024];
+        let _ = connection.read(&mut buf).await.unwrap();
+        connection
+            .write_all(b"HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\n\r\n")
+            .await
+            .unwrap();
+    });
+    let err = connect_async(format!("ws://{}/", addr)).await.unwrap_err();
+    match ConnectError::from(err) {
+        ConnectError::Status(response) => assert_eq!(response.status(), StatusCode::NOT_FOUND),
+        other => panic!("Unexpected error {:?}", other),
+    }
+}
+
+#[cfg(feature = "async-std-runtime")]
+#[async_std::test]
+async fn error_body() {
+    use async_tungstenite::async_std::connect_async;
+    use async_tungstenite::ConnectError;
+
+    // Rejects the upgrade with an endless body.
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    task::spawn(async move {
+        use async_std::io::prelude::*;
+
+        let (mut connection, _) = listener.accept().await.unwrap();
+        let mut buf = [0; 1024];
+        let _ = connection.read(&mut buf).await.unwrap();
+        connection
+            .write_all(b"HTTP/1.1 503 Service Unavailable\r\n\r\noverloaded")
+            .await
+            .unwrap();
+        while connection.write_all(&[b'x'; 4096]).await.is_ok() {}
+    });
+
+    let err = connect_async(format!("ws://{}/", addr)).await.unwrap_err();
+    let mut err = ConnectError::from(err);
+    let body = err.body().expect("No body");
+    assert!(body.starts_with(b"overloaded"));
+    assert!(body.len() <= 64 * 1024);
+
+    err.truncate_body(4);
+    assert_eq!(err.body(), Some(&b"over"[..]));
+}
+
+#[cfg(feature = "async-std-runtime")]
+#[async_std::test]
+async fn connect_and_send() {
+    use async_tungstenite::async_std::connect_and_send;
+    use futures::prelude::*;
+    use tungstenite::Message;
+
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let (tx, rx) = futures::channel::oneshot::channel();
+
+    task::spawn(async move {
+        let (connection, _) = listener.accept().await.unwrap();
+        let mut stream = accept_async(connection).await.unwrap();
+        tx.send(stream.next().await.unwrap().unwrap()).unwrap();
+    });
+
+    let (_stream, _) = connect_and_send(format!("ws://{}/", addr), Message::text("subscribe"))
+        .await
+        .expect("Client failed to connect");
+    assert_eq!(rx.await.unwrap(), Message::text("subscribe"));
+}
+
+#[cfg(feature = "async-std-runtime")]
+#[async_std::test]
+async fn connect_with_addr() {
+    use async_tungstenite::async_std::connect_async_with_addr;
+
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+
+    task::spawn(async move {
+        let (connection, _) = listener.accept().await.unwrap();
+        accept_async(connection).await.unwrap();
+    });
+
+    // "localhost" may resolve to an IPv6 address first, where nothing listens.
+    let url = format!("ws://localhost:{}/", addr.port());
+    let (_stream, _, peer) = connect_async_with_addr(url)
+        .await
+        .expect("Client failed to connect");
+    assert_eq!(peer, addr);
+}
+
+#[async_std::test]
+async fn client_hook() {
+    use async_tungstenite::{accept_hdr_async, client_async_with_hook};
+    use tungstenite::handshake::server::{Request, Response};
+
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let (tx, rx) = futures::channel::oneshot::channel();
+
+    task::spawn(async move {
+        let (connection, _) = listener.accept().await.unwrap();
+        #[allow(clippy::result_large_err)]
+        let callback = |request: &Request, response: Response| {
+            let key = request.headers()["Sec-WebSocket-Key"].clone();
+            tx.send((key, request.headers()["X-Nonce"].clone()))
+                .unwrap();
+            Ok(response)
+        };
+        accept_hdr_async(connection, callback).await.unwrap();
+    });
+
+    let tcp = TcpStream::connect(addr).await.unwrap();
+    client_async_with_hook(format!("ws://{}/", addr), tcp, |request| {
+        let key = request.headers()["Sec-WebSocket-Key"].clone();
+        request.headers_mut().insert("X-Nonce", key);
+    })
+    .await
+    .expect("Client failed to connect");
+
+    let (key, nonce) = rx.await.unwrap();
+    assert_eq!(key, nonce);
+}
+
+#[async_std::test]
+async fn peer_max_message_size() {
+    use async_tungstenite::accept_hdr_async;
+    use futures::prelude::*;
+    use tungstenite::error::{CapacityError, Error};
+    use tungstenite::handshake::server::{Request, Response};
+    use tungstenite::Message;
+
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+
+    let server = task::spawn(async move {
+        let (connection, _) = listener.accept().await.unwrap();
+        #[allow(clippy::result_large_err)]
+        let callback = |_: &Request, mut response: Response| {
+            response
+                .headers_mut()
+                .insert("X-Max-Message-Size", "16".parse().unwrap());
+            Ok(response)
+        };
+        let mut stream = accept_hdr_async(connection, callback).await.unwrap();
+        stream.next().await.unwrap().unwrap()
+    });
+
+    let tcp = TcpStream::connect(addr).await.unwrap();
+    let (mut stream, response) = client_async(format!("ws://{}/", addr), tcp)
+        .await
+        .expect("Client failed to connect");
+    assert_eq!(
+        stream.respect_peer_max_message_size(&response, "X-Missing"),
+        None
+    );
+    assert_eq!(
+        stream.respect_peer_max_message_size(&response, "X-Max-Message-Size"),
+        Some(16)
+    );
+    assert_eq!(stream.get_config().max_message_size, Some(16));
+
+    match stream.send(Message::text("x".repeat(17))).await {
+        Err(Error::Capacity(CapacityError::MessageTooLong {
+            size: 17,
+            max_size: 16,
+        })) => {}
+        other => panic!("Unexpected result {:?}", other),
+    }
+    stream.send(Message::text("x".repeat(16))).await.unwrap();
+    assert_eq!(server.await, Message::text("x".repeat(16)));
+}
+
+#[async_std::test]
+async fn cancel_handshake() {
+    use async_std::io::ReadExt;
+    use std::time::Duration;
+
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+
+    // Reads the request but never responds.
+    let server = task::spawn(async move {
+        let (mut connection, _) = listener.accept().await.unwrap();
+        let mut buf = [0; 1024];
+        let mut request = Vec::new();
+        loop {
+            let n = connection.read(&mut buf).await.unwrap();
+            if n == 0 {
+                return request;
+            }
+            request.extend_from_slice(&buf[..n]);
+        }
+    });
+
+    let tcp = TcpStream::connect(addr).await.unwrap();
+    let res = async_std::future::timeout(
+        Duration::from_millis(100),
+        client_async(format!("ws://{}/", addr), tcp),
+    )
+    .await;
+    assert!(res.is_err());
+
+    // The server sees the end of the connection once the future is dropped.
+    let request = async_std::future::timeout(Duration::from_secs(5), server)
+        .await
+        .expect("Socket was not closed");
+    assert!(request.starts_with(b"GET / HTTP/1.1\r\n"));
+}
+
+#[cfg(feature = "async-std-runtime")]
+#[async_std::test]
+async fn protocols() {
+    use async_tungstenite::accept_hdr_async;
+    use async_tungstenite::async_std::connect_async_with_protocols;
+    use tungstenite::handshake::server::{Request, Response};
+
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let (tx, rx) = futures::channel::oneshot::channel();
+
+    // Selects the least preferred protocol.
+    let server = task::spawn(async move {
+        let (connection, _) = listener.accept().await.unwrap();
+        #[allow(clippy::result_large_err)]
+        let callback = |request: &Request, mut response: Response| {
+            let offered = request.headers()["Sec-WebSocket-Protocol"].clone();
+            response
+                .headers_mut()
+                .insert("Sec-WebSocket-Protocol", "chat.v1".parse().unwrap());
+            tx.send(offered).unwrap();
+            Ok(response)
+        };
+        let stream = accept_hdr_async(connection, callback).await.unwrap();
+        stream.selected_protocol().map(str::to_owned)
+    });
+
+    let (stream, _) = connect_async_with_protocols(
+        format!("ws://{}/", addr),
+        &["chat.v3", "chat.v2", "chat.v1"],
+    )
+    .await
+    .expect("Client failed to connect");
+    assert_eq!(rx.await.unwrap(), "chat.v3, chat.v2, chat.v1");
+    assert_eq!(stream.selected_protocol(), Some("chat.v1"));
+    assert_eq!(server.await.as_deref(), Some("chat.v1"));
+}
+
+#[async_std::test]
+async fn handshake_timeout() {
+    use async_tungstenite::{accept_async_with_timeout, client_async_with_timeout};
+    use std::io::ErrorKind;
+    use std::time::Duration;
+
+    // Accepts TCP connections but never takes part in the handshake.
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let silent = task::spawn(async move {
+        let (connection, _) = listener.accept().await.unwrap();
+        connection
+    });
+
+    let tcp = TcpStream::connect(addr).await.unwrap();
+    let err = client_async_with_timeout(
+        format!("ws://{}/", addr),
+        tcp,
+        task::sleep(Duration::from_millis(50)),
+    )
+    .await
+    .unwrap_err();
+    match err {
+        tungstenite::Error::Io(err) => assert_eq!(err.kind(), ErrorKind::TimedOut),
+        other => panic!("Unexpected error {:?}", other),
+    }
+
+    drop(silent.await);
+
+    // A server facing a client that never sends its request.
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let _tcp = TcpStream::connect(listener.local_addr().unwrap())
+        .await
+        .unwrap();
+    let (connection, _) = listener.accept().await.unwrap();
+    let err = accept_async_with_timeout(connection, task::sleep(Duration::from_millis(50)))
+        .await
+        .unwrap_err();
+    match err {
+        tungstenite::Error::Io(err) => assert_eq!(err.kind(), ErrorKind::TimedOut),
+        other => panic!("Unexpected error {:?}", other),
+    }
+}
+
+#[async_std::test]
+async fn raw_response() {
+    use async_tungstenite::client_async_with_raw_response;
+    use futures::prelude::*;
+    use tungstenite::Message;
+
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let (tx, rx) = futures::channel::oneshot::channel();
+
+    // Answers with a hand written response followed by a message in the
+    // same write.
+    task::spawn(async move {
+        let (mut connection, _) = listener.accept().await.unwrap();
+        let mut buf = [0; 1024];
+        let n = connection.read(&mut buf).await.unwrap();
+        let request = String::from_utf8_lossy(&buf[..n]).into_owned();
+        let key = request
+            .lines()
+            .filter_map(|line| line.split_once(": "))
+            .find(|(name, _)| name.eq_ignore_ascii_case("Sec-WebSocket-Key"))
+            .unwrap()
+            .1;
+        let accept = tungstenite::handshake::derive_accept_key(key.as_bytes());
+        let response = format!(
+            "HTTP/1.1 101 Switching Protocols\r\nConnection: Upgrade\r\n\
+             Upgrade: websocket\r\nX-Cache: hit\r\nSec-WebSocket-Accept: {}\r\n\r\n",
+            accept
+        );
+        let mut data = response.clone().into_bytes();
+        data.extend_from_slice(b"\x81\x02hi");
+        connection.write_all(&data).await.unwrap();
+        tx.send(response).unwrap();
+        let _ = connection.read(&mut buf).await;
+    });
+
+    let tcp = TcpStream::connect(addr).await.unwrap();
+    let (mut stream, _, raw) = client_async_with_raw_response(format!("ws://{}/", addr), tcp)
+        .await
+        .expect("Client failed to connect");
+    assert_eq!(raw, rx.await.unwrap().into_bytes());
+    assert_eq!(stream.next().await.unwrap().unwrap(), Message::text("hi"));
+}
+
+#[async_std::test]
+async fn redirects() {
+    use async_tungstenite::{accept_hdr_async, client_async_with_redirects};
+    use tungstenite::client::IntoClientRequest;
+    use tungstenite::handshake::server::{ErrorResponse, Request, Response};
+    use tungstenite::http::StatusCode;
+
+    let _ = env_logger::try_init();
+
+    // Redirects `/old` to `/new` and `/loop` to itself.
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    task::spawn(async move {
+        while let Ok((connection, _)) = listener.accept().await {
+            #[allow(clippy::result_large_err)]
+            let callback = |request: &Request, response: Response| {
+                let location = match request.uri().path() {
+                    "/new" => {
+                        assert_eq!(request.headers()["X-Token"], "secret");
+                        return Ok(response);
+                    }
+                    "/old" => "/new",
+                    _ => "/loop",
+                };
+                let mut response = ErrorResponse::new(None);
+                *response.status_mut() = StatusCode::FOUND;
+                response
+                    .headers_mut()
+                    .insert("Location", location.parse().unwrap());
+                Err(response)
+            };
+            let _ = accept_hdr_async(connection, callback).await;
+        }
+    });
+
+    let connects = std::cell::Cell::new(0);
+    let factory = |uri: &tungstenite::http::Uri| {
+        assert_eq!(uri.port_u16(), Some(addr.port()));
+        connects.set(connects.get() + 1);
+        async move { Ok(TcpStream::connect(addr).await?) }
+    };
+
+    let mut request = format!("ws://{}/old", addr).into_client_request().unwrap();
+    request
+        .headers_mut()
+        .insert("X-Token", "secret".parse().unwrap());
+    let (_, response) = client_async_with_redirects(request, factory, 5)
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::SWITCHING_PROTOCOLS);
+    assert_eq!(connects.get(), 2);
+
+    connects.set(0);
+    match client_async_with_redirects(format!("ws://{}/loop", addr), factory, 3).await {
+        Err(tungstenite::Error::Http(response)) => {
+            assert_eq!(response.status(), StatusCode::FOUND)
+        }
+        other => panic!("Unexpected result {:?}", other.map(|_| ())),
+    }
+    assert_eq!(connects.get(), 4);
+}
+
+#[async_std::test]
+async fn accept_with_request() {
+    use async_tungstenite::accept_async_with_request;
+    use tungstenite::client::IntoClientRequest;
+
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let server = task::spawn(async move {
+        let (connection, _) = listener.accept().await.unwrap();
+        let (_stream, request) = accept_async_with_request(connection).await.unwrap();
+        request
+    });
+
+    let mut request = format!("ws://{}/chat?room=1", addr)
+        .into_client_request()
+        .unwrap();
+    request
+        .headers_mut()
+        .insert("Origin", "https://example.com".parse().unwrap());
+    let tcp = TcpStream::connect(addr).await.unwrap();
+    let _stream = client_async(request, tcp).await.unwrap();
+
+    let request = server.await;
+    assert_eq!(request.uri().path(), "/chat");
+    assert_eq!(request.uri().query(), Some("room=1"));
+    assert_eq!(request.headers()["Origin"], "https://example.com");
+}
@@ -0,0 +1,208 @@
+#![cfg(feature = "handshake")]
+
+use std::time::{Duration, Instant};
+
+use async_std::net::{TcpListener, TcpStream};
+use async_std::task;
+use async_tungstenite::{accept_async, client_async, Heartbeat, KeepAliveMode, WebSocketStream};
+use futures::prelude::*;
+use tungstenite::Message;
+
+async fn connect() -> (
+    WebSocketStream<TcpStream>,
+    futures::channel::oneshot::Receiver<Vec<Message>>,
+) {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let (msg_tx, msg_rx) = futures::channel::oneshot::channel();
+
+    task::spawn(async move {
+        let (connection, _) = listener.accept().await.expect("No connections to accept");
+        let mut stream = accept_async(connection)
+            .await
+            .expect("Failed to handshake with connection");
+        let mut messages = vec![];
+        while let Some(message) = stream.next().await {
+            messages.push(message.expect("Failed to get message"));
+        }
+        msg_tx.send(messages).expect("Failed to send results");
+    });
+
+    let tcp = TcpStream::connect(addr).await.expect("Failed to connect");
+    let url = format!("ws://{}/", addr);
+    let (stream, _) = client_async(url, tcp)
+        .await
+        .expect("Client failed to connect");
+
+    (stream, msg_rx)
+}
+
+fn count_pings(messages: &[Message]) -> usize {
+    messages.iter().filter(|msg| msg.is_ping()).count()
+}
+
+#[async_std::test]
+async fn heartbeat_always() {
+    let _ = env_logger::try_init();
+
+    let (stream, msg_rx) = connect().await;
+    let (tx, mut rx) = stream.split();
+
+    let (_handle, heartbeat) =
+        tx.heartbeat(Heartbeat::ping(Duration::from_millis(20)), task::sleep);
+    let traffic = async {
+        for i in 0..10 {
+            tx.send(Message::text(format!("{}", i)))
+                .await
+                .expect("Failed to send message");
+            task::sleep(Duration::from_millis(10)).await;
+        }
+        tx.close(None).await.expect("Failed to close");
+        while rx.next().await.is_some() {}
+    };
+
+    let (res, ()) = future::join(heartbeat, traffic).await;
+    res.expect("Heartbeat failed");
+
+    let messages = msg_rx.await.expect("Failed to receive messages");
+    assert!(count_pings(&messages) >= 2);
+}
+
+#[async_std::test]
+async fn heartbeat_on_idle() {
+    let _ = env_logger::try_init();
+
+    let (stream, msg_rx) = connect().await;
+    let (tx, mut rx) = stream.split();
+
+    let heartbeat = Heartbeat::ping(Duration::from_millis(200)).mode(KeepAliveMode::OnIdle);
+    let (_handle, heartbeat) = tx.heartbeat(heartbeat, task::sleep);
+    let traffic = async {
+        for i in 0..10 {
+            tx.send(Message::text(format!("{}", i)))
+                .await
+                .expect("Failed to send message");
+            task::sleep(Duration::from_millis(10)).await;
+        }
+        tx.close(None).await.expect("Failed to close");
+        while rx.next().await.is_some() {}
+    };
+
+    let (res, ()) = future::join(heartbeat, traffic).await;
+    res.expect("Heartbeat failed");
+
+    let messages = msg_rx.await.expect("Failed to receive messages");
+    assert_eq!(count_pings(&messages), 0);
+}
+
+#[async_std::test]
+async fn heartbeat_replies() {
+    let _ = env_logger::try_init();
+
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let server = task::spawn(async move {
+        let (connection, _) = listener.accept().await.unwrap();
+        let mut stream = accept_async(connection).await.unwrap();
+        let mut pings = vec![];
+        while let Some(Ok(msg)) = stream.next().await {
+            if msg.is_ping() {
+                pings.push(Instant::now());
+            }
+        }
+        pings
+    });
+
+    let tcp = TcpStream::connect(addr).await.unwrap();
+    let (stream, _) = client_async(format!("ws://{}/", addr), tcp).await.unwrap();
+    let (tx, mut rx) = stream.split();
+
+    let interval = Duration::from_millis(20);
+    let (handle, heartbeat) = tx.heartbeat(Heartbeat::ping(interval), task::sleep);
+    let heartbeat = task::spawn(heartbeat);
+    // Processes the pongs while the heartbeat runs.
+    let receive = async { while let Some(Ok(_)) = rx.next().await {} };
+    future::select(receive.boxed(), task::sleep(interval * 5).boxed()).await;
+    assert!(handle.reply_seen());
+    assert_eq!(handle.missed_replies(), 0);
+
+    drop(handle);
+    heartbeat.await.expect("Heartbeat failed");
+    tx.close(None).await.unwrap();
+    while rx.next().await.is_some() {}
+
+    let pings = server.await;
+    assert!(pings.len() >= 3, "{} pings", pings.len());
+    for pair in pings.windows(2) {
+        assert!(pair[1] - pair[0] >= interval / 2, "{:?}", pair);
+    }
+}
+
+#[cfg(feature = "async-std-runtime")]
+#[async_std::test]
+async fn message_heartbeat() {
+    use async_tungstenite::async_std::heartbeat;
+    use tungstenite::protocol::frame::coding::CloseCode;
+
+    let _ = env_logger::try_init();
+
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    // Answers the first two heartbeats only.
+    let server = task::spawn(async move {
+        let (connection, _) = listener.accept().await.unwrap();
+        let mut stream = accept_async(connection).await.unwrap();
+        let mut heartbeats = 0;
+        while let Some(Ok(msg)) = stream.next().await {
+            match msg {
+                Message::Text(text) if text == r#"{"op":"ping"}"# => {
+                    heartbeats += 1;
+                    if heartbeats <= 2 {
+                        stream
+                            .send(Message::text(r#"{"op":"pong"}"#))
+                            .await
+                            .unwrap();
+                    }
+                }
+                Message::Close(frame) => return (heartbeats, frame),
+                _ => {}
+            }
+        }
+        panic!("Connection ended without Close frame");
+    });
+
+    let tcp = TcpStream::connect(addr).await.unwrap();
+    let (stream, _) = client_async(format!("ws://{}/", addr), tcp).await.unwrap();
+    let (tx, mut rx) = stream.split();
+
+    let config = Heartbeat::message(Duration::from_millis(20), || {
+        Message::text(r#"{"op":"ping"}"#)
+    })
+    .expect_reply(|msg| {
+        msg.to_text()
+            .map_or(false, |text| text == r#"{"op":"pong"}"#)
+    })
+    .max_missed(1);
+    let (_handle, heartbeat) = heartbeat(&tx, config);
+    let receive = async {
+        let mut replies = 0;
+        while let Some(Ok(msg)) = rx.next().await {
+            if msg.is_text() {
+                replies += 1;
+            }
+        }
+        replies
+    };
+    let (res, replies) = future::join(heartbeat, receive).await;
+
+    let err = res.unwrap_err();
+    match err {
+        tungstenite::Error::Io(err) => assert_eq!(err.kind(), std::io::ErrorKind::TimedOut),
+        other => panic!("Unexpected error {:?}", other),
+    }
+    // Replies are passed on to the application.
+    assert_eq!(replies, 2);
+    let (heartbeats, frame) = server.await;
+    assert_eq!(heartbeats, 3);
+    assert_eq!(frame.unwrap().code, CloseCode::Away);
+}
@@ -0,0 +1,120 @@
+#![cfg(all(feature = "metrics", feature = "handshake"))]
+
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+
+use async_std::net::{TcpListener, TcpStream};
+use async_std::task;
+use async_tungstenite::{accept_async, client_async};
+use futures::prelude::*;
+use metrics::{
+    Counter, Gauge, Histogram, HistogramFn, Key, KeyName, Metadata, Recorder, SharedString, Unit,
+};
+use tungstenite::Message;
+
+/// Records counters and the number of histogram samples by key.
+#[derive(Default)]
+struct TestRecorder {
+    counters: Mutex<HashMap<String, Arc<AtomicU64>>>,
+    histograms: Mutex<HashMap<String, Arc<AtomicU64>>>,
+}
+
+struct Samples(Arc<AtomicU64>);
+
+impl HistogramFn for Samples {
+    fn record(&self, _value: f64) {
+        self.0.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+impl TestRecorder {
+    fn counter(&self, key: &str) -> u64 {
+        self.counters
+            .lock()
+            .unwrap()
+            .get(key)
+            .map_or(0, |c| c.load(Ordering::Relaxed))
+    }
+
+    fn samples(&self, key: &str) -> u64 {
+        self.histograms
+            .lock()
+            .unwrap()
+            .get(key)
+            .map_or(0, |c| c.load(Ordering::Relaxed))
+    }
+}
+
+impl Recorder for TestRecorder {
+    fn describe_counter(&self, _: KeyName, _: Option<Unit>, _: SharedString) {}
+    fn describe_gauge(&self, _: KeyName, _: Option<Unit>, _: SharedString) {}
+    fn describe_histogram(&self, _: KeyName, _: Option<Unit>, _: SharedString) {}
+
+    fn register_counter(&self, key: &Key, _: &Metadata<'_>) -> Counter {
+        let mut counters = self.counters.lock().unwrap();
+        Counter::from_arc(counters.entry(key.to_string()).or_default().clone())
+    }
+
+    fn register_gauge(&self, _: &Key, _: &Metadata<'_>) -> Gauge {
+        Gauge::noop()
+    }
+
+    fn register_histogram(&self, key: &Key, _: &Metadata<'_>) -> Histogram {
+        let mut histograms = self.histograms.lock().unwrap();
+        let samples = histograms.entry(key.to_string()).or_default().clone();
+        Histogram::from_arc(Arc::new(Samples(samples)))
+    }
+}
+
+#[async_std::test]
+async fn metrics() {
+    let _ = env_logger::try_init();
+
+    let recorder: &'static TestRecorder = Box::leak(Box::default());
+    metrics::set_global_recorder(recorder).unwrap();
+
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let server = task::spawn(async move {
+        let (connection, _) = listener.accept().await.unwrap();
+        let mut stream = accept_async(connection).await.unwrap();
+        while let Some(Ok(msg)) = stream.next().await {
+            if msg.is_text() {
+                stream.send(msg).await.unwrap();
+            }
+        }
+    });
+
+    let tcp = TcpStream::connect(addr).await.unwrap();
+    let (mut stream, _) = client_async(format!("ws://{}/", addr), tcp).await.unwrap();
+    stream.send(Message::text("hello")).await.unwrap();
+    assert_eq!(
+        stream.next().await.unwrap().unwrap(),
+        Message::text("hello")
+    );
+    stream.send(Message::Ping(vec![1].into())).await.unwrap();
+    assert!(stream.next().await.unwrap().unwrap().is_pong());
+    stream.close(None).await.unwrap();
+    while stream.next().await.is_some() {}
+    server.await;
+
+    let sent = "Key(websocket_messages_total, [direction = sent, kind = text])";
+    let received = "Key(websocket_messages_total, [direction = received, kind = text])";
+    // Both the client and the server sent and received the text message.
+    assert_eq!(recorder.counter(sent), 2);
+    assert_eq!(recorder.counter(received), 2);
+    assert_eq!(
+        recorder.counter("Key(websocket_bytes_total, [direction = sent, kind = text])"),
+        10
+    );
+    assert_eq!(
+        recorder.samples("Key(websocket_handshake_duration_seconds, [role = client])"),
+        1
+    );
+    assert_eq!(
+        recorder.samples("Key(websocket_handshake_duration_seconds, [role = server])"),
+        1
+    );
+    assert_eq!(recorder.samples("Key(websocket_round_trip_seconds)"), 1);
+}
@@ -0,0 +1,198 @@
+use std::io::{self, IoSlice};
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+
+use async_tungstenite::{FrameKind, PreparedFrame, WebSocketStream};
+use futures::prelude::*;
+use tungstenite::protocol::Role;
+use tungstenite::Message;
+
+/// A stream that records the bytes, the buffers they were written from and
+/// the number of writes. If `limit` is set, only every other write is
+/// accepted and with at most `limit` bytes.
+#[derive(Clone, Default)]
+struct Recorder {
+    data: Arc<Mutex<Vec<u8>>>,
+    buffers: Arc<Mutex<Vec<*const u8>>>,
+    writes: Arc<Mutex<usize>>,
+    vectored_writes: Arc<Mutex<usize>>,
+    limit: Option<usize>,
+    blocked: bool,
+}
+
+impl Recorder {
+    fn accept(&mut self, cx: &mut Context<'_>, bufs: &[IoSlice<'_>]) -> Poll<io::Result<usize>> {
+        self.buffers
+            .lock()
+            .unwrap()
+            .extend(bufs.iter().map(|buf| buf.as_ptr()));
+        if let Some(limit) = self.limit {
+            self.blocked = !self.blocked;
+            if self.blocked {
+                cx.waker().wake_by_ref();
+                return Poll::Pending;
+            }
+            let mut data = self.data.lock().unwrap();
+            let start = data.len();
+            for buf in bufs {
+                let n = buf.len().min(limit - (data.len() - start));
+                data.extend_from_slice(&buf[..n]);
+            }
+            return Poll::Ready(Ok(data.len() - start));
+        }
+        let mut data = self.data.lock().unwrap();
+        let start = data.len();
+        for buf in bufs {
+            data.extend_from_slice(buf);
+        }
+        Poll::Ready(Ok(data.len() - start))
+    }
+}
+
+impl AsyncRead for Recorder {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        _cx: &mut Context<'_>,
+        _buf: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+        Poll::Pending
+    }
+}
+
+impl AsyncWrite for Recorder {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        *self.writes.lock().unwrap() += 1;
+        self.accept(cx, &[IoSlice::new(buf)])
+    }
+
+    fn poll_write_vectored(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        bufs: &[IoSlice<'_>],
+    ) -> Poll<io::Result<usize>> {
+        *self.vectored_writes.lock().unwrap() += 1;
+        self.accept(cx, bufs)
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_close(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+}
+
+#[async_std::test]
+async fn send_prepared() {
+    let _ = env_logger::try_init();
+
+    let msg = Message::binary(vec![7; 1000]);
+
+    let sent = Recorder::default();
+    let mut stream = WebSocketStream::from_raw_socket(sent.clone(), Role::Server, None).await;
+    stream.send(msg.clone()).await.unwrap();
+    assert_eq!(*sent.writes.lock().unwrap(), 1);
+    assert_eq!(*sent.vectored_writes.lock().unwrap(), 0);
+
+    // The same bytes go out with a single vectored write.
+    let frame = PreparedFrame::new(msg.clone());
+    let prepared = Recorder::default();
+    let mut stream = WebSocketStream::from_raw_socket(prepared.clone(), Role::Server, None).await;
+    stream.send_prepared(&frame).await.unwrap();
+    assert_eq!(*prepared.writes.lock().unwrap(), 0);
+    assert_eq!(*prepared.vectored_writes.lock().unwrap(), 1);
+    assert_eq!(*prepared.data.lock().unwrap(), *sent.data.lock().unwrap());
+
+    // Partial and blocked writes continue where they stopped.
+    let trickle = Recorder {
+        limit: Some(3),
+        ..Recorder::default()
+    };
+    let mut stream = WebSocketStream::from_raw_socket(trickle.clone(), Role::Server, None).await;
+    stream.send_prepared(&frame).await.unwrap();
+    stream.send_prepared(&frame).await.unwrap();
+    assert_eq!(*trickle.data.lock().unwrap(), {
+        let mut twice = sent.data.lock().unwrap().clone();
+        twice.extend_from_slice(&sent.data.lock().unwrap());
+        twice
+    });
+
+    // Clients mask every frame.
+    let client = Recorder::default();
+    let mut stream = WebSocketStream::from_raw_socket(client.clone(), Role::Client, None).await;
+    stream.send_prepared(&frame).await.unwrap();
+    assert_eq!(*client.vectored_writes.lock().unwrap(), 0);
+    assert_ne!(*client.data.lock().unwrap(), *sent.data.lock().unwrap());
+}
+
+#[async_std::test]
+async fn send_prepared_cancelled() {
+    let _ = env_logger::try_init();
+
+    let msg = Message::binary(vec![7; 1000]);
+    let expected = Recorder::default();
+    let mut stream = WebSocketStream::from_raw_socket(expected.clone(), Role::Server, None).await;
+    stream.send(msg.clone()).await.unwrap();
+    stream.send(Message::text("after")).await.unwrap();
+
+    // The future is dropped after the frame was written in part.
+    let frame = PreparedFrame::new(msg);
+    let trickle = Recorder {
+        limit: Some(3),
+        ..Recorder::default()
+    };
+    let mut stream = WebSocketStream::from_raw_socket(trickle.clone(), Role::Server, None).await;
+    {
+        let mut send = Box::pin(stream.send_prepared(&frame));
+        for _ in 0..5 {
+            assert!(futures::poll!(&mut send).is_pending());
+        }
+    }
+    assert!(!trickle.data.lock().unwrap().is_empty());
+
+    // The rest of the frame goes out before the next message.
+    stream.send(Message::text("after")).await.unwrap();
+    assert_eq!(
+        *trickle.data.lock().unwrap(),
+        *expected.data.lock().unwrap()
+    );
+}
+
+#[async_std::test]
+async fn send_shared() {
+    let _ = env_logger::try_init();
+
+    let data: Arc<[u8]> = vec![7; 1000].into();
+    let peers: Vec<_> = (0..3).map(|_| Recorder::default()).collect();
+    for peer in &peers {
+        let mut stream = WebSocketStream::from_raw_socket(peer.clone(), Role::Server, None).await;
+        stream
+            .send_shared(data.clone(), FrameKind::Binary)
+            .await
+            .unwrap();
+    }
+    assert_eq!(Arc::strong_count(&data), 1);
+
+    let expected = Recorder::default();
+    let mut stream = WebSocketStream::from_raw_socket(expected.clone(), Role::Server, None).await;
+    stream.send(Message::binary(vec![7; 1000])).await.unwrap();
+    for peer in &peers {
+        assert_eq!(*peer.data.lock().unwrap(), *expected.data.lock().unwrap());
+        // The payload was written from the shared buffer itself.
+        assert!(peer.buffers.lock().unwrap().contains(&data.as_ptr()));
+    }
+
+    let mut stream =
+        WebSocketStream::from_raw_socket(Recorder::default(), Role::Server, None).await;
+    let err = stream
+        .send_shared(b"\xc3"[..].into(), FrameKind::Text)
+        .await
+        .unwrap_err();
+    assert!(matches!(err, tungstenite::Error::Utf8(_)), "{:?}", err);
+}
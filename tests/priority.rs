@@ -0,0 +1,235 @@
+use std::io;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll, Waker};
+use std::time::Duration;
+
+use async_tungstenite::{Priority, WebSocketStream};
+use futures::prelude::*;
+use tungstenite::protocol::{Role, WebSocketConfig};
+use tungstenite::Message;
+
+#[derive(Default)]
+struct State {
+    blocked: bool,
+    written: Vec<u8>,
+    waker: Option<Waker>,
+}
+
+/// A stream that collects everything written to it and can block writes.
+#[derive(Clone, Default)]
+struct Gate(Arc<Mutex<State>>);
+
+impl Gate {
+    fn set_blocked(&self, blocked: bool) {
+        let mut state = self.0.lock().unwrap();
+        state.blocked = blocked;
+        if let Some(waker) = state.waker.take() {
+            waker.wake();
+        }
+    }
+
+    fn find(&self, needle: &[u8]) -> Option<usize> {
+        let state = self.0.lock().unwrap();
+        state
+            .written
+            .windows(needle.len())
+            .position(|w| w == needle)
+    }
+
+    fn position(&self, needle: &[u8]) -> usize {
+        self.find(needle).expect("message not written")
+    }
+}
+
+impl AsyncRead for Gate {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        _cx: &mut Context<'_>,
+        _buf: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+        Poll::Pending
+    }
+}
+
+impl AsyncWrite for Gate {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let mut state = self.0.lock().unwrap();
+        if state.blocked {
+            state.waker = Some(cx.waker().clone());
+            return Poll::Pending;
+        }
+        state.written.extend_from_slice(buf);
+        Poll::Ready(Ok(buf.len()))
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_close(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+}
+
+#[async_std::test]
+async fn send_prioritized() {
+    let _ = env_logger::try_init();
+
+    let gate = Gate::default();
+    gate.set_blocked(true);
+
+    // Make every message go straight to the blocked stream.
+    let config = WebSocketConfig::default().write_buffer_size(0);
+    let stream = WebSocketStream::from_raw_socket(gate.clone(), Role::Server, Some(config)).await;
+    let (tx, _rx) = stream.split();
+
+    // The first message is queued right away, the other two have to wait
+    // until the stream is writable again.
+    let sends = future::try_join3(
+        tx.send_prioritized(Message::text("low"), 1),
+        tx.send_prioritized(Message::text("mid"), 5),
+        tx.send_prioritized(Message::text("high"), 9),
+    );
+    let unblock = async { gate.set_blocked(false) };
+    let (res, ()) = future::join(sends, unblock).await;
+    res.expect("Failed to send");
+
+    let low = gate.position(b"low");
+    let mid = gate.position(b"mid");
+    let high = gate.position(b"high");
+    assert!(low < high && high < mid, "{} {} {}", low, high, mid);
+}
+
+#[async_std::test]
+async fn send_priority() {
+    let _ = env_logger::try_init();
+
+    let gate = Gate::default();
+    gate.set_blocked(true);
+
+    let config = WebSocketConfig::default().write_buffer_size(0);
+    let stream = WebSocketStream::from_raw_socket(gate.clone(), Role::Server, Some(config)).await;
+    let (tx, _rx) = stream.split();
+
+    // The ping and the urgent message are passed last but overtake the
+    // flood of normal data that is still waiting.
+    let flood =
+        (0..20).map(|i| tx.send_priority(Message::text(format!("data{:02}", i)), Priority::Normal));
+    let sends = future::try_join3(
+        future::try_join_all(flood),
+        tx.send_priority(Message::Ping(b"ping".to_vec().into()), Priority::Normal),
+        tx.send_priority(Message::text("urgent"), Priority::High),
+    );
+    let unblock = async { gate.set_blocked(false) };
+    let (res, ()) = future::join(sends, unblock).await;
+    res.expect("Failed to send");
+
+    let ping = gate.position(b"ping");
+    let urgent = gate.position(b"urgent");
+    assert!(gate.position(b"data00") < ping);
+    assert!(ping < urgent, "{} {}", ping, urgent);
+    assert!(urgent < gate.position(b"data01"));
+    assert!(gate.position(b"data18") < gate.position(b"data19"));
+}
+
+#[async_std::test]
+async fn send_with_ttl() {
+    let _ = env_logger::try_init();
+
+    let gate = Gate::default();
+    gate.set_blocked(true);
+
+    let config = WebSocketConfig::default().write_buffer_size(0);
+    let stream = WebSocketStream::from_raw_socket(gate.clone(), Role::Server, Some(config)).await;
+    let (tx, _rx) = stream.split();
+
+    // The first message blocks the stream, so the others wait until it is
+    // unblocked, by which time only the stale one expired.
+    let sends = future::try_join3(
+        tx.send(Message::text("first")),
+        tx.send_with_ttl(Message::text("stale"), Duration::from_millis(10)),
+        tx.send_with_ttl(Message::text("fresh"), Duration::from_secs(60)),
+    );
+    let unblock = async {
+        async_std::task::sleep(Duration::from_millis(50)).await;
+        gate.set_blocked(false)
+    };
+    let (res, ()) = future::join(sends, unblock).await;
+    let ((), stale, fresh) = res.expect("Failed to send");
+
+    assert!(!stale);
+    assert!(fresh);
+    assert!(gate.find(b"stale").is_none());
+    assert!(gate.position(b"first") < gate.position(b"fresh"));
+}
+
+#[async_std::test]
+async fn split_with_capacity() {
+    let _ = env_logger::try_init();
+
+    let gate = Gate::default();
+    gate.set_blocked(true);
+
+    let config = WebSocketConfig::default().write_buffer_size(0);
+    let stream = WebSocketStream::from_raw_socket(gate.clone(), Role::Server, Some(config)).await;
+    let (tx, rx) = stream.split_with_capacity(1);
+
+    let mut first = Box::pin(tx.send(Message::text("first")));
+    let mut second = Box::pin(tx.send(Message::text("second")));
+    assert!(futures::poll!(first.as_mut()).is_pending());
+    assert!(futures::poll!(second.as_mut()).is_pending());
+
+    // The second message waits for capacity instead of the connection, so it
+    // is not written along with the first one.
+    gate.set_blocked(false);
+    first.await.unwrap();
+    assert!(gate.find(b"second").is_none());
+    second.await.unwrap();
+    assert!(gate.position(b"first") < gate.position(b"second"));
+
+    // Dropping the receiver fails sends that wait for capacity.
+    gate.set_blocked(true);
+    let mut third = Box::pin(tx.send(Message::text("third")));
+    let mut fourth = Box::pin(tx.send(Message::text("fourth")));
+    assert!(futures::poll!(third.as_mut()).is_pending());
+    assert!(futures::poll!(fourth.as_mut()).is_pending());
+    drop(rx);
+    match fourth.await {
+        Err(tungstenite::Error::Io(err)) => assert_eq!(err.kind(), io::ErrorKind::BrokenPipe),
+        other => panic!("Unexpected result {:?}", other),
+    }
+    gate.set_blocked(false);
+    third.await.unwrap();
+}
+
+#[cfg(feature = "futures-03-sink")]
+#[async_std::test]
+async fn split_with_capacity_all_sends() {
+    let _ = env_logger::try_init();
+
+    let config = WebSocketConfig::default().write_buffer_size(0);
+    let stream =
+        WebSocketStream::from_raw_socket(Gate::default(), Role::Server, Some(config)).await;
+    let (tx, _rx) = stream.split_with_capacity(1);
+
+    // The sink holds the capacity from accepting a message until flushing.
+    let mut sink = tx.clone();
+    sink.feed(Message::text("sink")).await.unwrap();
+
+    let mut priority = Box::pin(tx.send_priority(Message::text("priority"), Priority::High));
+    let mut ttl = Box::pin(tx.send_with_ttl(Message::text("ttl"), Duration::from_secs(60)));
+    let mut fragmented = Box::pin(tx.send_fragmented(Message::text("fragmented"), 4));
+    assert!(futures::poll!(priority.as_mut()).is_pending());
+    assert!(futures::poll!(ttl.as_mut()).is_pending());
+    assert!(futures::poll!(fragmented.as_mut()).is_pending());
+
+    sink.flush().await.unwrap();
+    priority.await.unwrap();
+    assert!(ttl.await.unwrap());
+    fragmented.await.unwrap();
+}
@@ -0,0 +1,117 @@
+#![cfg(feature = "handshake")]
+
+use async_std::net::{TcpListener, TcpStream};
+use async_std::task;
+use async_tungstenite::proxy::{proxy, Side};
+use async_tungstenite::{accept_async, client_async};
+use futures::prelude::*;
+use tungstenite::protocol::{frame::coding::CloseCode, CloseFrame};
+use tungstenite::Message;
+
+#[async_std::test]
+async fn proxy_echo() {
+    let _ = env_logger::try_init();
+
+    // The backend echoes everything and reports the close frame it got.
+    let backend = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let backend_addr = backend.local_addr().unwrap();
+    let (close_tx, close_rx) = futures::channel::oneshot::channel();
+    task::spawn(async move {
+        let (connection, _) = backend.accept().await.unwrap();
+        let mut stream = accept_async(connection).await.unwrap();
+        while let Some(msg) = stream.next().await {
+            match msg.unwrap() {
+                Message::Close(frame) => {
+                    close_tx.send(frame).unwrap();
+                    // Complete the close handshake.
+                    while let Some(Ok(_)) = stream.next().await {}
+                    break;
+                }
+                msg => stream.send(msg).await.unwrap(),
+            }
+        }
+    });
+
+    let front = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let front_addr = front.local_addr().unwrap();
+    let proxied = task::spawn(async move {
+        let (connection, _) = front.accept().await.unwrap();
+        let a = accept_async(connection).await.unwrap();
+        let tcp = TcpStream::connect(backend_addr).await.unwrap();
+        let (b, _) = client_async(format!("ws://{}/", backend_addr), tcp)
+            .await
+            .unwrap();
+        proxy(a, b).await
+    });
+
+    let tcp = TcpStream::connect(front_addr).await.unwrap();
+    let (mut client, _) = client_async(format!("ws://{}/", front_addr), tcp)
+        .await
+        .unwrap();
+
+    client.send(Message::text("hello")).await.unwrap();
+    client.send(Message::binary(vec![1, 2, 3])).await.unwrap();
+    assert_eq!(
+        client.next().await.unwrap().unwrap(),
+        Message::text("hello")
+    );
+    assert_eq!(
+        client.next().await.unwrap().unwrap(),
+        Message::binary(vec![1, 2, 3])
+    );
+
+    // Answered by the proxy itself.
+    client.send(Message::Ping(vec![4].into())).await.unwrap();
+    assert_eq!(
+        client.next().await.unwrap().unwrap(),
+        Message::Pong(vec![4].into())
+    );
+
+    let frame = CloseFrame {
+        code: CloseCode::Normal,
+        reason: "bye".into(),
+    };
+    client.close(Some(frame.clone())).await.unwrap();
+    while let Some(Ok(_)) = client.next().await {}
+
+    let (side, close) = proxied.await.expect("Proxy failed");
+    assert_eq!(side, Side::A);
+    assert_eq!(close, Some(frame.clone()));
+    assert_eq!(close_rx.await.unwrap(), Some(frame));
+}
+
+#[async_std::test]
+async fn proxy_reports_error_of_other_side() {
+    let _ = env_logger::try_init();
+
+    // The backend drops the connection without a close handshake.
+    let backend = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let backend_addr = backend.local_addr().unwrap();
+    task::spawn(async move {
+        let (connection, _) = backend.accept().await.unwrap();
+        let _stream = accept_async(connection).await.unwrap();
+        task::sleep(std::time::Duration::from_millis(200)).await;
+    });
+
+    let front = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let front_addr = front.local_addr().unwrap();
+    let proxied = task::spawn(async move {
+        let (connection, _) = front.accept().await.unwrap();
+        let a = accept_async(connection).await.unwrap();
+        let tcp = TcpStream::connect(backend_addr).await.unwrap();
+        let (b, _) = client_async(format!("ws://{}/", backend_addr), tcp)
+            .await
+            .unwrap();
+        proxy(a, b).await
+    });
+
+    let tcp = TcpStream::connect(front_addr).await.unwrap();
+    let (mut client, _) = client_async(format!("ws://{}/", front_addr), tcp)
+        .await
+        .unwrap();
+    client.close(None).await.unwrap();
+    while let Some(Ok(_)) = client.next().await {}
+
+    // The client closed cleanly first, but the backend failed afterwards.
+    assert!(proxied.await.is_err());
+}
@@ -0,0 +1,87 @@
+#![cfg(feature = "test-clock")]
+
+use std::time::Duration;
+
+use async_tungstenite::{AcceptRateLimit, TestClock, WebSocketStream};
+use futures::io::Cursor;
+use tungstenite::protocol::Role;
+use tungstenite::Message;
+
+#[test]
+fn try_admit() {
+    let clock = TestClock::new();
+    let mut limit = AcceptRateLimit::new(10);
+    limit.set_clock(clock.clone());
+
+    assert!(limit.try_admit());
+    assert!(!limit.try_admit());
+    clock.advance(Duration::from_millis(50));
+    assert!(!limit.try_admit());
+    clock.advance(Duration::from_millis(50));
+    assert!(limit.try_admit());
+    assert!(!limit.try_admit());
+}
+
+#[async_std::test]
+async fn pace() {
+    let clock = TestClock::new();
+    let mut limit = AcceptRateLimit::new(10);
+    limit.set_clock(clock.clone());
+
+    let mut slept = vec![];
+    for _ in 0..3 {
+        limit
+            .pace(|duration| {
+                slept.push(duration);
+                async {}
+            })
+            .await;
+    }
+    // Sleeping doesn't advance the clock here, so the waits add up.
+    assert_eq!(
+        slept,
+        vec![Duration::from_millis(100), Duration::from_millis(200)]
+    );
+
+    // A quiet period doesn't allow a burst afterwards.
+    clock.advance(Duration::from_secs(10));
+    limit.pace(|_| async { unreachable!() }).await;
+    let mut slept = vec![];
+    limit
+        .pace(|duration| {
+            slept.push(duration);
+            async {}
+        })
+        .await;
+    assert_eq!(slept, vec![Duration::from_millis(100)]);
+}
+
+#[async_std::test]
+async fn rate_limited_sender() {
+    let clock = TestClock::new();
+    let mut stream =
+        WebSocketStream::from_raw_socket(Cursor::new(Vec::new()), Role::Server, None).await;
+    stream.set_clock(clock.clone());
+    let (sender, _receiver) = stream.split();
+
+    let start = clock.now();
+    let sender = sender
+        .rate_limited(10, |duration| {
+            clock.advance(duration);
+            async {}
+        })
+        .with_burst(10);
+    for i in 0..20 {
+        sender.send(Message::text(i.to_string())).await.unwrap();
+    }
+    // The first 10 messages are a burst, the others are 100ms apart.
+    assert_eq!(clock.now() - start, Duration::from_secs(1));
+
+    // The bucket refills while the sender is idle.
+    clock.advance(Duration::from_millis(350));
+    let start = clock.now();
+    for i in 0..4 {
+        sender.send(Message::text(i.to_string())).await.unwrap();
+    }
+    assert_eq!(clock.now() - start, Duration::from_millis(50));
+}
@@ -0,0 +1,96 @@
+#![cfg(feature = "test-frames")]
+
+use async_std::net::{TcpListener, TcpStream};
+use async_std::task;
+use async_tungstenite::{accept_async, client_async};
+use futures::prelude::*;
+use tungstenite::error::ProtocolError;
+use tungstenite::protocol::frame::coding::CloseCode;
+use tungstenite::Message;
+
+/// Sends a raw close frame from a client and returns what the server reads.
+async fn send_raw_close(payload: &'static [u8]) -> Result<Message, tungstenite::Error> {
+    send_raw_close_with(payload, false).await.0
+}
+
+/// Like `send_raw_close`, but also returns what the client reads afterwards.
+async fn send_raw_close_with(
+    payload: &'static [u8],
+    lossy_close_reasons: bool,
+) -> (
+    Result<Message, tungstenite::Error>,
+    Option<Result<Message, tungstenite::Error>>,
+) {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let (tx, rx) = futures::channel::oneshot::channel();
+
+    task::spawn(async move {
+        let (connection, _) = listener.accept().await.expect("No connections to accept");
+        let mut stream = accept_async(connection)
+            .await
+            .expect("Failed to handshake with connection");
+        stream.set_lossy_close_reasons(lossy_close_reasons);
+        let message = stream.next().await.expect("Stream ended early");
+        if message.is_ok() {
+            assert!(stream.next().await.is_none());
+        }
+        tx.send(message).unwrap();
+    });
+
+    let tcp = TcpStream::connect(addr).await.expect("Failed to connect");
+    let url = format!("ws://{}/", addr);
+    let (mut stream, _) = client_async(url, tcp)
+        .await
+        .expect("Client failed to connect");
+    stream
+        .send_raw_close(payload, Some([1, 2, 3, 4]))
+        .await
+        .expect("Failed to send close");
+
+    let message = rx.await.expect("Server task failed");
+    (message, stream.next().await)
+}
+
+#[async_std::test]
+async fn raw_close() {
+    let _ = env_logger::try_init();
+
+    match send_raw_close(b"\x03\xe8bye").await {
+        Ok(Message::Close(Some(frame))) => {
+            assert_eq!(frame.code, CloseCode::Normal);
+            assert_eq!(frame.reason, "bye");
+        }
+        other => panic!("Unexpected result {:?}", other),
+    }
+
+    // A close payload of a single byte is invalid.
+    match send_raw_close(b"\x03").await {
+        Err(tungstenite::Error::Protocol(ProtocolError::InvalidCloseSequence)) => {}
+        other => panic!("Unexpected result {:?}", other),
+    }
+}
+
+#[async_std::test]
+async fn lossy_close_reason() {
+    let _ = env_logger::try_init();
+
+    let payload = b"\x03\xe8b\xffe";
+    match send_raw_close(payload).await {
+        Err(tungstenite::Error::Utf8(_)) => {}
+        other => panic!("Unexpected result {:?}", other),
+    }
+
+    let (message, reply) = send_raw_close_with(payload, true).await;
+    match message {
+        Ok(Message::Close(Some(frame))) => {
+            assert_eq!(frame.code, CloseCode::Normal);
+            assert_eq!(frame.reason, "b\u{fffd}e");
+        }
+        other => panic!("Unexpected result {:?}", other),
+    }
+    match reply {
+        Some(Ok(Message::Close(Some(frame)))) => assert_eq!(frame.code, CloseCode::Normal),
+        other => panic!("Unexpected reply {:?}", other),
+    }
+}
@@ -0,0 +1,92 @@
+#![cfg(feature = "handshake")]
+
+use async_std::net::{TcpListener, TcpStream};
+use async_std::task;
+use async_tungstenite::{accept_async, client_async, RingReceiver};
+use futures::prelude::*;
+use tungstenite::Message;
+
+#[async_std::test]
+async fn ring_receiver() {
+    let _ = env_logger::try_init();
+
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    task::spawn(async move {
+        let (connection, _) = listener.accept().await.unwrap();
+        let mut stream = accept_async(connection).await.unwrap();
+        let msgs = (0..20).map(|i| Message::binary(vec![i; 10]));
+        stream.send_batch(msgs).await.unwrap();
+        stream.close(None).await.unwrap();
+    });
+
+    let tcp = TcpStream::connect(addr).await.unwrap();
+    let (stream, _) = client_async(format!("ws://{}/", addr), tcp).await.unwrap();
+    let (_sender, receiver) = stream.split();
+    let size = std::mem::size_of::<Message>();
+    let mut ring = RingReceiver::new(receiver, 5 * (size + 10));
+
+    // Give the server time to send everything.
+    task::sleep(std::time::Duration::from_millis(50)).await;
+
+    let mut received = Vec::new();
+    while let Some(msg) = ring.next().await {
+        // At most one message crosses the capacity.
+        assert!(ring.buffered_bytes() < ring.capacity() + size + 10);
+        match msg.unwrap() {
+            Message::Binary(data) => received.push(data[0]),
+            Message::Close(_) => {}
+            other => panic!("Unexpected message {:?}", other),
+        }
+    }
+    assert_eq!(received, (0..20).collect::<Vec<_>>());
+    assert_eq!(ring.buffered_bytes(), 0);
+}
+
+#[async_std::test]
+async fn ring_receiver_empty_messages() {
+    let _ = env_logger::try_init();
+
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    task::spawn(async move {
+        let (connection, _) = listener.accept().await.unwrap();
+        let mut stream = accept_async(connection).await.unwrap();
+        let msgs = (0..20).map(|_| Message::binary(Vec::new()));
+        stream.send_batch(msgs).await.unwrap();
+        stream.close(None).await.unwrap();
+    });
+
+    let tcp = TcpStream::connect(addr).await.unwrap();
+    let (stream, _) = client_async(format!("ws://{}/", addr), tcp).await.unwrap();
+    let (_sender, receiver) = stream.split();
+    let mut ring = RingReceiver::new(receiver, 5 * std::mem::size_of::<Message>());
+
+    // Give the server time to send everything.
+    task::sleep(std::time::Duration::from_millis(50)).await;
+
+    // Empty messages are charged as well, so reading stops after five.
+    assert!(ring.next().await.unwrap().unwrap().is_binary());
+    assert!(format!("{:?}", ring).contains("messages: 4"));
+
+    let mut received = 1;
+    while let Some(msg) = ring.next().await {
+        if msg.unwrap().is_binary() {
+            received += 1;
+        }
+    }
+    assert_eq!(received, 20);
+}
+
+#[async_std::test]
+#[should_panic(expected = "capacity must not be zero")]
+async fn ring_receiver_zero_capacity() {
+    use async_tungstenite::WebSocketStream;
+    use tungstenite::protocol::Role;
+
+    let stream =
+        WebSocketStream::from_raw_socket(futures::io::Cursor::new(Vec::new()), Role::Client, None)
+            .await;
+    let (_sender, receiver) = stream.split();
+    RingReceiver::new(receiver, 0);
+}
@@ -0,0 +1,41 @@
+#![cfg(feature = "smol-runtime")]
+
+use std::net::TcpListener;
+
+use async_io::Async;
+use async_tungstenite::accept_async;
+use async_tungstenite::smol::connect_async;
+use futures::prelude::*;
+use tungstenite::Message;
+
+#[test]
+fn smol_echo() {
+    let _ = env_logger::try_init();
+
+    async_io::block_on(async {
+        let listener = Async::<TcpListener>::bind(([127, 0, 0, 1], 0)).unwrap();
+        let addr = listener.get_ref().local_addr().unwrap();
+
+        let server = async {
+            let (connection, _) = listener.accept().await.unwrap();
+            let mut stream = accept_async(connection).await.unwrap();
+            while let Some(Ok(msg)) = stream.next().await {
+                if msg.is_text() {
+                    stream.send(msg).await.unwrap();
+                }
+            }
+        };
+
+        let client = async {
+            let (mut stream, _) = connect_async(format!("ws://{}/", addr)).await.unwrap();
+            stream.send(Message::text("hello")).await.unwrap();
+            assert_eq!(
+                stream.next().await.unwrap().unwrap(),
+                Message::text("hello")
+            );
+            stream.close(None).await.unwrap();
+        };
+
+        future::join(server, client).await;
+    });
+}
@@ -0,0 +1,61 @@
+#![cfg(all(feature = "handshake", feature = "futures-03-sink"))]
+
+use std::sync::{Arc, Mutex};
+
+use async_std::net::{TcpListener, TcpStream};
+use async_std::task;
+use async_tungstenite::{accept_async, client_async, TapDirection};
+use futures::prelude::*;
+use tungstenite::Message;
+
+#[async_std::test]
+async fn wire_tap() {
+    let _ = env_logger::try_init();
+
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+
+    task::spawn(async move {
+        let (connection, _) = listener.accept().await.unwrap();
+        let mut stream = accept_async(connection).await.unwrap();
+        while let Some(Ok(msg)) = stream.next().await {
+            if msg.is_text() {
+                stream.send(msg).await.unwrap();
+            }
+        }
+    });
+
+    let tcp = TcpStream::connect(addr).await.unwrap();
+    let (mut stream, _) = client_async(format!("ws://{}/", addr), tcp).await.unwrap();
+
+    let read = Arc::new(Mutex::new(Vec::new()));
+    let written = Arc::new(Mutex::new(Vec::new()));
+    let (tap_read, tap_written) = (read.clone(), written.clone());
+    stream.set_wire_tap(move |direction, bytes| match direction {
+        TapDirection::Read => tap_read.lock().unwrap().extend_from_slice(bytes),
+        TapDirection::Write => tap_written.lock().unwrap().extend_from_slice(bytes),
+    });
+
+    stream.send(Message::text("hello")).await.unwrap();
+    assert_eq!(
+        stream.next().await.unwrap().unwrap(),
+        Message::text("hello")
+    );
+
+    // A masked text frame with 5 bytes, followed by the masking key and the
+    // masked payload.
+    let written = written.lock().unwrap().clone();
+    assert_eq!(written.len(), 2 + 4 + 5);
+    assert_eq!(written[..2], [0x81, 0x80 | 5]);
+
+    // The server's frame is not masked.
+    assert_eq!(*read.lock().unwrap(), b"\x81\x05hello");
+
+    stream.clear_wire_tap();
+    stream.send(Message::text("again")).await.unwrap();
+    assert_eq!(
+        stream.next().await.unwrap().unwrap(),
+        Message::text("again")
+    );
+    assert_eq!(*read.lock().unwrap(), b"\x81\x05hello");
+}
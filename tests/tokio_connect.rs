@@ -0,0 +1,76 @@
+#![cfg(feature = "tokio-runtime")]
+
+use std::net::SocketAddr;
+use std::time::{Duration, Instant};
+
+use async_tungstenite::tokio::{
+    accept_async, connect_async_with_happy_eyeballs, connect_happy_eyeballs, HAPPY_EYEBALLS_DELAY,
+};
+use tokio::net::TcpListener;
+
+#[tokio::test]
+async fn happy_eyeballs() {
+    let _ = env_logger::try_init();
+
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move {
+        loop {
+            let (connection, _) = listener.accept().await.unwrap();
+            tokio::spawn(accept_async(connection));
+        }
+    });
+
+    // Addresses of the discard prefix (RFC 6666) are never reachable, the
+    // attempts either fail right away or hang until they are cancelled.
+    let unreachable: SocketAddr = format!("[100::1]:{}", addr.port()).parse().unwrap();
+    let start = Instant::now();
+    let (_, peer) = connect_happy_eyeballs(vec![unreachable, addr], Duration::from_millis(100))
+        .await
+        .expect("Failed to connect");
+    assert_eq!(peer, addr);
+    assert!(
+        start.elapsed() < Duration::from_secs(1),
+        "{:?}",
+        start.elapsed()
+    );
+
+    connect_async_with_happy_eyeballs(
+        format!("ws://localhost:{}/", addr.port()),
+        None,
+        HAPPY_EYEBALLS_DELAY,
+    )
+    .await
+    .expect("Client failed to connect");
+}
+
+#[tokio::test]
+async fn happy_eyeballs_failure_starts_next_attempt() {
+    let _ = env_logger::try_init();
+
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move {
+        loop {
+            let _ = listener.accept().await.unwrap();
+        }
+    });
+    let refused = TcpListener::bind("127.0.0.1:0")
+        .await
+        .unwrap()
+        .local_addr()
+        .unwrap();
+
+    // Attempted in the order `unreachable`, `refused`, `loopback`, `addr`:
+    // once `refused` failed, the remaining attempts must not wait for the
+    // delay again.
+    let unreachable: SocketAddr = format!("[100::1]:{}", addr.port()).parse().unwrap();
+    let loopback: SocketAddr = format!("[::1]:{}", refused.port()).parse().unwrap();
+    let delay = Duration::from_millis(500);
+    let start = Instant::now();
+    let (_, peer) = connect_happy_eyeballs(vec![unreachable, loopback, refused, addr], delay)
+        .await
+        .expect("Failed to connect");
+    assert_eq!(peer, addr);
+    assert!(start.elapsed() < delay * 2, "{:?}", start.elapsed());
+}
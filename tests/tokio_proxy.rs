@@ -0,0 +1,197 @@
+#![cfg(feature = "tokio-runtime")]
+
+use std::net::SocketAddr;
+
+use async_tungstenite::tokio::{
+    accept_async, connect_async_with_http_proxy, connect_async_with_proxy, ProxyAuth, ProxyConfig,
+};
+use futures::prelude::*;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tungstenite::Message;
+
+/// Starts a websocket server that echoes one message.
+async fn echo_server(addr: &str) -> SocketAddr {
+    let listener = TcpListener::bind(addr).await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move {
+        let (connection, _) = listener.accept().await.unwrap();
+        let mut stream = accept_async(connection).await.unwrap();
+        let msg = stream.next().await.unwrap().unwrap();
+        stream.send(msg).await.unwrap();
+    });
+    addr
+}
+
+/// Starts a SOCKS5 proxy that requires `user:secret` and accepts CONNECT
+/// requests to IP addresses only.
+async fn socks5_server() -> SocketAddr {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move {
+        let (mut client, _) = listener.accept().await.unwrap();
+
+        let mut greeting = [0; 2];
+        client.read_exact(&mut greeting).await.unwrap();
+        let mut methods = vec![0; greeting[1] as usize];
+        client.read_exact(&mut methods).await.unwrap();
+        assert!(methods.contains(&0x02));
+        client.write_all(&[0x05, 0x02]).await.unwrap();
+
+        let mut auth = [0; 1 + 1 + 4 + 1 + 6];
+        client.read_exact(&mut auth).await.unwrap();
+        assert_eq!(&auth, b"\x01\x04user\x06secret");
+        client.write_all(&[0x01, 0x00]).await.unwrap();
+
+        let mut request = [0; 4];
+        client.read_exact(&mut request).await.unwrap();
+        assert_eq!(&request[..3], &[0x05, 0x01, 0x00]);
+        let ip: std::net::IpAddr = match request[3] {
+            0x01 => {
+                let mut ip = [0; 4];
+                client.read_exact(&mut ip).await.unwrap();
+                ip.into()
+            }
+            0x04 => {
+                let mut ip = [0; 16];
+                client.read_exact(&mut ip).await.unwrap();
+                ip.into()
+            }
+            atyp => panic!("Unexpected address type {}", atyp),
+        };
+        let port = client.read_u16().await.unwrap();
+
+        let mut target = TcpStream::connect((ip, port)).await.unwrap();
+        client
+            .write_all(&[0x05, 0x00, 0x00, 0x01, 0, 0, 0, 0, 0, 0])
+            .await
+            .unwrap();
+        let _ = tokio::io::copy_bidirectional(&mut client, &mut target).await;
+    });
+    addr
+}
+
+#[tokio::test]
+async fn socks5_proxy() {
+    let _ = env_logger::try_init();
+
+    for host in ["127.0.0.1:0", "[::1]:0"] {
+        let target = echo_server(host).await;
+        let proxy = ProxyConfig::Socks5 {
+            addr: socks5_server().await.to_string(),
+            auth: Some(ProxyAuth::new("user", "secret")),
+        };
+
+        let (mut stream, _) = connect_async_with_proxy(format!("ws://{}/", target), proxy)
+            .await
+            .unwrap();
+        stream.send(Message::text("hello")).await.unwrap();
+        assert_eq!(
+            stream.next().await.unwrap().unwrap(),
+            Message::text("hello")
+        );
+    }
+}
+
+#[tokio::test]
+async fn socks5_proxy_refused() {
+    let _ = env_logger::try_init();
+
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move {
+        let (mut client, _) = listener.accept().await.unwrap();
+        let mut greeting = [0; 3];
+        client.read_exact(&mut greeting).await.unwrap();
+        client.write_all(&[0x05, 0x00]).await.unwrap();
+        let mut request = [0; 4 + 4 + 2];
+        client.read_exact(&mut request).await.unwrap();
+        client
+            .write_all(&[0x05, 0x05, 0x00, 0x01, 0, 0, 0, 0, 0, 0])
+            .await
+            .unwrap();
+    });
+
+    let proxy = ProxyConfig::Socks5 {
+        addr: addr.to_string(),
+        auth: None,
+    };
+    match connect_async_with_proxy("ws://127.0.0.1:1/", proxy).await {
+        Err(tungstenite::Error::Io(err)) => {
+            assert_eq!(
+                err.to_string(),
+                "SOCKS5 proxy failed to connect: connection refused"
+            );
+        }
+        other => panic!("Unexpected result {:?}", other.map(|_| ())),
+    }
+}
+
+/// Starts an HTTP proxy that answers one `CONNECT` request with `status`.
+async fn http_proxy_server(status: &'static str) -> SocketAddr {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move {
+        let (mut client, _) = listener.accept().await.unwrap();
+
+        let mut request = Vec::new();
+        while !request.ends_with(b"\r\n\r\n") {
+            request.push(client.read_u8().await.unwrap());
+        }
+        let request = String::from_utf8(request).unwrap();
+        let target = request
+            .strip_prefix("CONNECT ")
+            .and_then(|rest| rest.split(' ').next())
+            .unwrap()
+            .to_owned();
+        assert!(request.contains("\r\nProxy-Authorization: Basic dXNlcjpzZWNyZXQ=\r\n"));
+
+        let mut target = TcpStream::connect(target).await.unwrap();
+        let response = format!("HTTP/1.1 {}\r\n\r\n", status);
+        client.write_all(response.as_bytes()).await.unwrap();
+        let _ = tokio::io::copy_bidirectional(&mut client, &mut target).await;
+    });
+    addr
+}
+
+#[tokio::test]
+async fn http_proxy() {
+    let _ = env_logger::try_init();
+
+    let target = echo_server("[::1]:0").await;
+    let proxy = http_proxy_server("200 Connection established").await;
+
+    let (mut stream, _) = connect_async_with_http_proxy(
+        format!("ws://{}/", target),
+        &format!("http://{}", proxy),
+        Some(ProxyAuth::new("user", "secret")),
+    )
+    .await
+    .unwrap();
+    stream.send(Message::text("hello")).await.unwrap();
+    assert_eq!(
+        stream.next().await.unwrap().unwrap(),
+        Message::text("hello")
+    );
+}
+
+#[tokio::test]
+async fn http_proxy_rejected() {
+    let _ = env_logger::try_init();
+
+    let target = echo_server("127.0.0.1:0").await;
+    let proxy = http_proxy_server("403 Forbidden").await;
+
+    match connect_async_with_http_proxy(
+        format!("ws://{}/", target),
+        &proxy.to_string(),
+        Some(ProxyAuth::new("user", "secret")),
+    )
+    .await
+    {
+        Err(tungstenite::Error::Io(err)) => {
+            assert_eq!(err.to_string(), "HTTP proxy responded with 403 Forbidden");
+        }
+        other => panic!("Unexpected result {:?}", other.map(|_| ())),
+    }
+}
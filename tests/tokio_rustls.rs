@@ -0,0 +1,331 @@
+#![cfg(all(feature = "__rustls-tls", not(feature = "tokio-native-tls")))]
+
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+
+use async_tungstenite::stream::Stream;
+use async_tungstenite::tokio::{
+    accept_async, accept_hdr_async, client_async_tls_upgrade, connect_async,
+    connect_async_with_server_name, connect_async_with_tls_connector, prewarm_tls, CertificatePins,
+    TokioConnector,
+};
+use async_tungstenite::ConnectError;
+use futures::prelude::*;
+use real_tokio_rustls::rustls::{self, ClientConfig, RootCertStore, ServerConfig};
+use real_tokio_rustls::{TlsAcceptor, TlsConnector};
+use rustls::pki_types::{CertificateDer, PrivateKeyDer, PrivatePkcs8KeyDer};
+use rustls::server::{ClientHello, ResolvesServerCert};
+use rustls::sign::CertifiedKey;
+use tokio::net::TcpListener;
+use tungstenite::handshake::server::{Request, Response};
+use tungstenite::Message;
+
+/// Self-signed certificate for `localhost`.
+const CERT: &[u8] = include_bytes!("certs/localhost.der");
+const KEY: &[u8] = include_bytes!("certs/localhost.key.der");
+/// SHA-256 hash of the SubjectPublicKeyInfo of `CERT`.
+const PIN: [u8; 32] = [
+    0x1b, 0x19, 0x6d, 0x99, 0xa4, 0x53, 0x5e, 0x8c, 0xb6, 0x9a, 0x97, 0xa7, 0x79, 0x9c, 0x89, 0x8e,
+    0x4f, 0x1b, 0x9e, 0x3b, 0xfc, 0xe9, 0x59, 0x17, 0x53, 0x04, 0x27, 0xf0, 0xf2, 0x13, 0xa8, 0x6a,
+];
+const OTHER_PIN: [u8; 32] = [0x42; 32];
+
+/// Starts a TLS websocket server that echoes messages and offers the ALPN
+/// protocols `alpn`.
+async fn echo_server(alpn: &[&[u8]]) -> SocketAddr {
+    let _ = rustls::crypto::ring::default_provider().install_default();
+
+    let mut config = ServerConfig::builder()
+        .with_no_client_auth()
+        .with_single_cert(
+            vec![CertificateDer::from(CERT)],
+            PrivateKeyDer::Pkcs8(PrivatePkcs8KeyDer::from(KEY)),
+        )
+        .unwrap();
+    config.alpn_protocols = alpn.iter().map(|p| p.to_vec()).collect();
+    let acceptor = TlsAcceptor::from(Arc::new(config));
+
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move {
+        loop {
+            let (connection, _) = listener.accept().await.unwrap();
+            let acceptor = acceptor.clone();
+            tokio::spawn(async move {
+                let tls = match acceptor.accept(connection).await {
+                    Ok(tls) => tls,
+                    Err(_) => return,
+                };
+                let mut stream = accept_async(tls).await.unwrap();
+                while let Some(Ok(msg)) = stream.next().await {
+                    if msg.is_text() {
+                        stream.send(msg).await.unwrap();
+                    }
+                }
+            });
+        }
+    });
+    addr
+}
+
+fn roots() -> RootCertStore {
+    let mut roots = RootCertStore::empty();
+    roots.add(CertificateDer::from(CERT)).unwrap();
+    roots
+}
+
+#[tokio::test]
+async fn pinned_certificate() {
+    let _ = env_logger::try_init();
+
+    let addr = echo_server(&[]).await;
+    let url = format!("wss://localhost:{}/", addr.port());
+
+    // The old pin is still listed during a rotation.
+    let pins = CertificatePins::new([OTHER_PIN, PIN]);
+    let connector = pins.connector(roots()).unwrap();
+    let (mut stream, _) = connect_async_with_tls_connector(url.as_str(), Some(connector))
+        .await
+        .unwrap();
+    stream.send(Message::text("hello")).await.unwrap();
+    assert_eq!(
+        stream.next().await.unwrap().unwrap(),
+        Message::text("hello")
+    );
+}
+
+#[tokio::test]
+async fn unpinned_certificate() {
+    let _ = env_logger::try_init();
+
+    let addr = echo_server(&[]).await;
+    let url = format!("wss://localhost:{}/", addr.port());
+
+    let pins = CertificatePins::new([OTHER_PIN]);
+    let connector = pins.connector(roots()).unwrap();
+    let err = connect_async_with_tls_connector(url.as_str(), Some(connector))
+        .await
+        .map(|_| ())
+        .unwrap_err();
+    assert!(matches!(ConnectError::from(err), ConnectError::Tls(_)));
+
+    // The callback sees the chain and can reject it as well.
+    let pins = CertificatePins::new([PIN]).with_callback(|chain| {
+        assert_eq!(chain, [CertificateDer::from(CERT)]);
+        false
+    });
+    let connector = pins.connector(roots()).unwrap();
+    let err = connect_async_with_tls_connector(url.as_str(), Some(connector))
+        .await
+        .map(|_| ())
+        .unwrap_err();
+    assert!(matches!(ConnectError::from(err), ConnectError::Tls(_)));
+}
+
+#[tokio::test]
+async fn alpn_protocol() {
+    let _ = env_logger::try_init();
+
+    let addr = echo_server(&[b"h2", b"http/1.1"]).await;
+    let url = format!("wss://localhost:{}/", addr.port());
+
+    let mut config = ClientConfig::builder()
+        .with_root_certificates(roots())
+        .with_no_client_auth();
+    config.alpn_protocols = vec![b"http/1.1".to_vec()];
+    let connector = TlsConnector::from(Arc::new(config));
+    let (stream, _) = connect_async_with_tls_connector(url.as_str(), Some(connector))
+        .await
+        .unwrap();
+    assert_eq!(stream.get_ref().alpn_protocol(), Some(b"http/1.1".to_vec()));
+
+    // Nothing is negotiated if the client doesn't offer any protocol.
+    let connector = TlsConnector::from(Arc::new(
+        ClientConfig::builder()
+            .with_root_certificates(roots())
+            .with_no_client_auth(),
+    ));
+    let (stream, _) = connect_async_with_tls_connector(url.as_str(), Some(connector))
+        .await
+        .unwrap();
+    assert_eq!(stream.get_ref().alpn_protocol(), None);
+
+    // Plain connections have no ALPN.
+    let plain = {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            let (connection, _) = listener.accept().await.unwrap();
+            let mut stream = accept_async(connection).await.unwrap();
+            while stream.next().await.is_some() {}
+        });
+        addr
+    };
+    let (stream, _) = connect_async(format!("ws://{}/", plain)).await.unwrap();
+    assert_eq!(stream.get_ref().alpn_protocol(), None);
+}
+
+fn connector() -> TlsConnector {
+    TlsConnector::from(Arc::new(
+        ClientConfig::builder()
+            .with_root_certificates(roots())
+            .with_no_client_auth(),
+    ))
+}
+
+#[tokio::test]
+async fn shared_tls_connector() {
+    let _ = env_logger::try_init();
+
+    let addr = echo_server(&[]).await;
+    let url = format!("wss://localhost:{}/", addr.port());
+
+    let connector = TokioConnector::from(connector());
+    let (mut first, _) = connector.connect_async(url.as_str()).await.unwrap();
+    let (mut second, _) = connector.connect_async(url.as_str()).await.unwrap();
+    for (stream, text) in [(&mut first, "first"), (&mut second, "second")] {
+        assert!(matches!(stream.get_ref(), Stream::Tls(_)));
+        stream.send(Message::text(text)).await.unwrap();
+        assert_eq!(stream.next().await.unwrap().unwrap(), Message::text(text));
+    }
+}
+
+#[tokio::test]
+async fn prewarm() {
+    let _ = env_logger::try_init();
+
+    let addr = echo_server(&[]).await;
+    let url = format!("wss://localhost:{}/", addr.port());
+
+    // The TLS handshake is done before the upgrade.
+    let warm = prewarm_tls(url.as_str(), Some(connector())).await.unwrap();
+    assert!(matches!(warm.get_ref(), Stream::Tls(_)));
+    let (mut stream, _) = warm.upgrade(url).await.unwrap();
+    stream.send(Message::text("hello")).await.unwrap();
+    assert_eq!(
+        stream.next().await.unwrap().unwrap(),
+        Message::text("hello")
+    );
+
+    // Certificates that can't be verified fail before the upgrade.
+    let untrusted = TlsConnector::from(Arc::new(
+        ClientConfig::builder()
+            .with_root_certificates(RootCertStore::empty())
+            .with_no_client_auth(),
+    ));
+    let err = prewarm_tls(format!("wss://localhost:{}/", addr.port()), Some(untrusted))
+        .await
+        .map(|_| ())
+        .unwrap_err();
+    assert!(matches!(ConnectError::from(err), ConnectError::Tls(_)));
+}
+
+#[tokio::test]
+async fn tls_upgrade() {
+    let _ = env_logger::try_init();
+
+    let addr = echo_server(&[]).await;
+
+    // TLS is used even for a ws:// URL, and the certificate is checked
+    // against `domain` rather than the host of the URL.
+    let tcp = tokio::net::TcpStream::connect(addr).await.unwrap();
+    let url = format!("ws://127.0.0.1:{}/", addr.port());
+    let (mut stream, _) =
+        client_async_tls_upgrade(tcp, url.as_str(), "localhost", Some(connector()), None)
+            .await
+            .unwrap();
+    assert!(matches!(stream.get_ref(), Stream::Tls(_)));
+    stream.send(Message::text("hello")).await.unwrap();
+    assert_eq!(
+        stream.next().await.unwrap().unwrap(),
+        Message::text("hello")
+    );
+
+    let tcp = tokio::net::TcpStream::connect(addr).await.unwrap();
+    let err = client_async_tls_upgrade(tcp, url.as_str(), "example.com", Some(connector()), None)
+        .await
+        .map(|_| ())
+        .unwrap_err();
+    assert!(matches!(ConnectError::from(err), ConnectError::Tls(_)));
+}
+
+/// Serves `CERT` and records the server names the clients sent.
+#[derive(Debug)]
+struct RecordServerName {
+    key: Arc<CertifiedKey>,
+    names: Mutex<Vec<Option<String>>>,
+}
+
+impl ResolvesServerCert for RecordServerName {
+    fn resolve(&self, client_hello: ClientHello<'_>) -> Option<Arc<CertifiedKey>> {
+        let name = client_hello.server_name().map(str::to_owned);
+        self.names.lock().unwrap().push(name);
+        Some(self.key.clone())
+    }
+}
+
+#[tokio::test]
+async fn server_name() {
+    let _ = env_logger::try_init();
+    let _ = rustls::crypto::ring::default_provider().install_default();
+
+    let key = rustls::crypto::ring::sign::any_supported_type(&PrivateKeyDer::Pkcs8(
+        PrivatePkcs8KeyDer::from(KEY),
+    ))
+    .unwrap();
+    let resolver = Arc::new(RecordServerName {
+        key: Arc::new(CertifiedKey::new(vec![CertificateDer::from(CERT)], key)),
+        names: Mutex::new(Vec::new()),
+    });
+    let config = ServerConfig::builder()
+        .with_no_client_auth()
+        .with_cert_resolver(resolver.clone());
+    let acceptor = TlsAcceptor::from(Arc::new(config));
+
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let hosts = Arc::new(Mutex::new(Vec::new()));
+    let server_hosts = hosts.clone();
+    tokio::spawn(async move {
+        loop {
+            let (connection, _) = listener.accept().await.unwrap();
+            let acceptor = acceptor.clone();
+            let hosts = server_hosts.clone();
+            tokio::spawn(async move {
+                let tls = match acceptor.accept(connection).await {
+                    Ok(tls) => tls,
+                    Err(_) => return,
+                };
+                #[allow(clippy::result_large_err)]
+                let record_host = move |request: &Request, response: Response| {
+                    let host = request.headers()["Host"].to_str().unwrap().to_owned();
+                    hosts.lock().unwrap().push(host);
+                    Ok(response)
+                };
+                let mut stream = accept_hdr_async(tls, record_host).await.unwrap();
+                while stream.next().await.is_some() {}
+            });
+        }
+    });
+    // The certificate is for `localhost`, so it is only accepted for the IP
+    // address with the override.
+    let url = format!("wss://127.0.0.1:{}/", addr.port());
+    let (_stream, _) =
+        connect_async_with_server_name(url.as_str(), Some("localhost"), Some(connector()), None)
+            .await
+            .unwrap();
+    let err = connect_async_with_server_name(url.as_str(), None, Some(connector()), None)
+        .await
+        .map(|_| ())
+        .unwrap_err();
+    assert!(matches!(ConnectError::from(err), ConnectError::Tls(_)));
+
+    assert_eq!(
+        *resolver.names.lock().unwrap(),
+        [Some("localhost".to_owned()), None]
+    );
+    assert_eq!(
+        *hosts.lock().unwrap(),
+        [format!("127.0.0.1:{}", addr.port())]
+    );
+}
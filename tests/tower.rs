@@ -0,0 +1,42 @@
+#![cfg(feature = "tower")]
+
+use async_std::net::{TcpListener, TcpStream};
+use async_std::task;
+use async_tungstenite::client_async;
+use async_tungstenite::tower::AcceptService;
+use futures::prelude::*;
+use tower_service::Service;
+use tungstenite::Message;
+
+#[async_std::test]
+async fn accept_service() {
+    let _ = env_logger::try_init();
+
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+
+    task::spawn(async move {
+        let mut service = AcceptService::new();
+        let (connection, _) = listener.accept().await.expect("No connections to accept");
+        future::poll_fn(|cx| Service::<TcpStream>::poll_ready(&mut service, cx))
+            .await
+            .unwrap();
+        let mut stream = service
+            .call(connection)
+            .await
+            .expect("Failed to handshake with connection");
+        let message = stream.next().await.unwrap().unwrap();
+        stream.send(message).await.unwrap();
+    });
+
+    let tcp = TcpStream::connect(addr).await.expect("Failed to connect");
+    let url = format!("ws://{}/", addr);
+    let (mut stream, _) = client_async(url, tcp)
+        .await
+        .expect("Client failed to connect");
+    stream.send(Message::text("hello")).await.unwrap();
+    assert_eq!(
+        stream.next().await.unwrap().unwrap(),
+        Message::text("hello")
+    );
+}